@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE offboarding_events;
+ALTER TABLE users DROP COLUMN end_date;
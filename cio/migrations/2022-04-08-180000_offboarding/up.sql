@@ -0,0 +1,19 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN end_date DATE NOT NULL DEFAULT '1970-01-01';
+
+CREATE TABLE offboarding_events (
+    id SERIAL PRIMARY KEY,
+    offboarding_id VARCHAR NOT NULL,
+    username VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    reason VARCHAR NOT NULL,
+    step VARCHAR NOT NULL,
+    status VARCHAR NOT NULL,
+    message TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    cio_company_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+);
+
+ALTER TABLE offboarding_events ADD FOREIGN KEY (cio_company_id) REFERENCES companys(id) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE INDEX IF NOT EXISTS idx_offboarding_events ON offboarding_events(cio_company_id,username);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users DROP COLUMN pronouns;
+ALTER TABLE users DROP COLUMN title;
+ALTER TABLE users DROP COLUMN slack_id;
+ALTER TABLE groups DROP COLUMN slack_channels;
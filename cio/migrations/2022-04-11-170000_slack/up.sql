@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN pronouns VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN title VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN slack_id VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE groups ADD COLUMN slack_channels TEXT [] NOT NULL DEFAULT '{}';
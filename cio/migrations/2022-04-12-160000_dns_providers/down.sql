@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE companys DROP COLUMN dns_provider;
+ALTER TABLE companys DROP COLUMN dns_server;
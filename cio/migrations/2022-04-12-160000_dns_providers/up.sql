@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE companys ADD COLUMN dns_provider VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE companys ADD COLUMN dns_server VARCHAR NOT NULL DEFAULT '';
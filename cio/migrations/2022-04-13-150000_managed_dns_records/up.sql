@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE managed_dns_records (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    content VARCHAR NOT NULL,
+    source VARCHAR NOT NULL,
+    last_ensured_at TIMESTAMPTZ NOT NULL,
+    cio_company_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+);
+
+ALTER TABLE managed_dns_records ADD FOREIGN KEY (cio_company_id) REFERENCES companys(id) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_managed_dns_records ON managed_dns_records(cio_company_id,name);
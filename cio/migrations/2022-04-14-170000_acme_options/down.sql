@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE certificates DROP COLUMN alt_names;
+ALTER TABLE certificates DROP COLUMN key_type;
+ALTER TABLE companys DROP COLUMN acme_directory_url;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE certificates ADD COLUMN alt_names TEXT [] NOT NULL DEFAULT '{}';
+ALTER TABLE certificates ADD COLUMN key_type VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE companys ADD COLUMN acme_directory_url VARCHAR NOT NULL DEFAULT '';
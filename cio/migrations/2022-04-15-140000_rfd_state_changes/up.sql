@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE rfd_state_changes (
+    id SERIAL PRIMARY KEY,
+    rfd_number INTEGER NOT NULL,
+    from_state VARCHAR NOT NULL,
+    to_state VARCHAR NOT NULL,
+    actor VARCHAR NOT NULL,
+    commit_sha VARCHAR NOT NULL,
+    pull_request_number INTEGER NOT NULL DEFAULT 0,
+    changed_at TIMESTAMPTZ NOT NULL,
+    cio_company_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+);
+
+ALTER TABLE rfd_state_changes ADD FOREIGN KEY (cio_company_id) REFERENCES companys(id) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_rfd_state_changes ON rfd_state_changes(cio_company_id,rfd_number,commit_sha,to_state);
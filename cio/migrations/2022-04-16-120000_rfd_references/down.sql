@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE rfd_references;
+
+ALTER TABLE rfds DROP COLUMN cites;
+ALTER TABLE rfds DROP COLUMN cited_by;
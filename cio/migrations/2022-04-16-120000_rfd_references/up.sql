@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE rfd_references (
+    id SERIAL PRIMARY KEY,
+    rfd_number INTEGER NOT NULL,
+    referenced_rfd_number INTEGER NOT NULL,
+    cio_company_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+);
+
+ALTER TABLE rfd_references ADD FOREIGN KEY (cio_company_id) REFERENCES companys(id) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_rfd_references ON rfd_references(cio_company_id,rfd_number,referenced_rfd_number);
+
+ALTER TABLE rfds ADD COLUMN cites TEXT [] NOT NULL DEFAULT '{}';
+ALTER TABLE rfds ADD COLUMN cited_by TEXT [] NOT NULL DEFAULT '{}';
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE rfd_changelog_subscriptions (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR NOT NULL,
+    cadence VARCHAR NOT NULL DEFAULT '',
+    channel VARCHAR NOT NULL DEFAULT '',
+    rfd_numbers INTEGER [] NOT NULL DEFAULT '{}',
+    states TEXT [] NOT NULL DEFAULT '{}',
+    relevant_components TEXT [] NOT NULL DEFAULT '{}',
+    last_sent_at TIMESTAMPTZ,
+    cio_company_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+);
+
+ALTER TABLE rfd_changelog_subscriptions ADD FOREIGN KEY (cio_company_id) REFERENCES companys(id) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_rfd_changelog_subscriptions ON rfd_changelog_subscriptions(cio_company_id,email,channel);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE rfd_reservations (
+    id SERIAL PRIMARY KEY,
+    number INTEGER NOT NULL,
+    title VARCHAR NOT NULL,
+    reserved_by VARCHAR NOT NULL DEFAULT '',
+    branch VARCHAR NOT NULL DEFAULT '',
+    link VARCHAR NOT NULL DEFAULT '',
+    pull_request_number INTEGER NOT NULL DEFAULT 0,
+    pull_request_url VARCHAR NOT NULL DEFAULT '',
+    reserved_at TIMESTAMPTZ NOT NULL,
+    cio_company_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+);
+
+ALTER TABLE rfd_reservations ADD FOREIGN KEY (cio_company_id) REFERENCES companys(id) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_rfd_reservations ON rfd_reservations(cio_company_id,number);
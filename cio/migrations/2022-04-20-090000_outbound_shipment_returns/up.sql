@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE outbound_shipments ADD COLUMN is_return BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE outbound_shipments DROP COLUMN parcels;
+ALTER TABLE outbound_shipments DROP COLUMN commercial_invoice_link;
+
+ALTER TABLE swag_items DROP COLUMN hs_code;
+ALTER TABLE swag_items DROP COLUMN customs_value;
+ALTER TABLE swag_items DROP COLUMN weight;
+ALTER TABLE swag_items DROP COLUMN origin_country;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE outbound_shipments ADD COLUMN parcels TEXT [] NOT NULL DEFAULT '{}';
+ALTER TABLE outbound_shipments ADD COLUMN commercial_invoice_link VARCHAR NOT NULL DEFAULT '';
+
+ALTER TABLE swag_items ADD COLUMN hs_code VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE swag_items ADD COLUMN customs_value REAL NOT NULL DEFAULT 0;
+ALTER TABLE swag_items ADD COLUMN weight REAL NOT NULL DEFAULT 0;
+ALTER TABLE swag_items ADD COLUMN origin_country VARCHAR NOT NULL DEFAULT '';
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE holidays;
+
+ALTER TABLE buildings DROP COLUMN timezone;
+ALTER TABLE buildings DROP COLUMN holidays_ical_url;
+ALTER TABLE buildings DROP COLUMN pickup_window_start;
+ALTER TABLE buildings DROP COLUMN pickup_window_end;
+ALTER TABLE buildings DROP COLUMN carrier_cutoffs;
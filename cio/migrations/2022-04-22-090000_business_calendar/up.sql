@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE holidays (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    date DATE NOT NULL,
+    building VARCHAR NOT NULL DEFAULT '',
+    cio_company_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+);
+
+ALTER TABLE holidays ADD FOREIGN KEY (cio_company_id) REFERENCES companys(id) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_holidays ON holidays(cio_company_id,date,building);
+
+ALTER TABLE buildings ADD COLUMN timezone VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE buildings ADD COLUMN holidays_ical_url VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE buildings ADD COLUMN pickup_window_start VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE buildings ADD COLUMN pickup_window_end VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE buildings ADD COLUMN carrier_cutoffs TEXT [] NOT NULL DEFAULT '{}';
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE shipment_tracking_events (
+    id SERIAL PRIMARY KEY,
+    carrier VARCHAR NOT NULL,
+    tracking_number VARCHAR NOT NULL,
+    provider VARCHAR NOT NULL DEFAULT '',
+    status VARCHAR NOT NULL,
+    status_details VARCHAR NOT NULL DEFAULT '',
+    location VARCHAR NOT NULL DEFAULT '',
+    status_date TIMESTAMPTZ NOT NULL,
+    cio_company_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+);
+
+ALTER TABLE shipment_tracking_events ADD FOREIGN KEY (cio_company_id) REFERENCES companys(id) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_shipment_tracking_events ON shipment_tracking_events(carrier,tracking_number,status,status_date);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE swag_inventory_reservations;
+
+ALTER TABLE swag_inventory_items DROP COLUMN reorder_threshold;
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+ALTER TABLE swag_inventory_items ADD COLUMN reorder_threshold INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE swag_inventory_reservations (
+    id SERIAL PRIMARY KEY,
+    swag_inventory_item_id INTEGER NOT NULL,
+    outbound_shipment_id INTEGER NOT NULL,
+    item VARCHAR NOT NULL,
+    size VARCHAR NOT NULL DEFAULT '',
+    quantity INTEGER NOT NULL,
+    status VARCHAR NOT NULL DEFAULT '',
+    reserved_time TIMESTAMPTZ NOT NULL,
+    released_time TIMESTAMPTZ,
+    cio_company_id INTEGER NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+);
+
+ALTER TABLE swag_inventory_reservations ADD FOREIGN KEY (cio_company_id) REFERENCES companys(id) ON DELETE CASCADE ON UPDATE CASCADE;
+ALTER TABLE swag_inventory_reservations ADD FOREIGN KEY (swag_inventory_item_id) REFERENCES swag_inventory_items(id) ON DELETE CASCADE ON UPDATE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_swag_inventory_reservations ON swag_inventory_reservations(outbound_shipment_id,swag_inventory_item_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users DROP COLUMN address_validation;
+ALTER TABLE outbound_shipments DROP COLUMN address_validation;
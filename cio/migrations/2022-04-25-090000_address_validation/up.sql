@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN address_validation VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE outbound_shipments ADD COLUMN address_validation VARCHAR NOT NULL DEFAULT '';
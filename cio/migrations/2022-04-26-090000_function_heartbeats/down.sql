@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE functions DROP COLUMN owner;
+ALTER TABLE functions DROP COLUMN heartbeat_at;
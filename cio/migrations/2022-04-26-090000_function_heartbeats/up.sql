@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE functions ADD COLUMN owner VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE functions ADD COLUMN heartbeat_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE offboarding_events DROP COLUMN start_date;
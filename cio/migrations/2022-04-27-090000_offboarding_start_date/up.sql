@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE offboarding_events ADD COLUMN start_date DATE NOT NULL DEFAULT '1970-01-01';
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE rfd_changelog_subscriptions DROP COLUMN confirmed;
+ALTER TABLE rfd_changelog_subscriptions DROP COLUMN token;
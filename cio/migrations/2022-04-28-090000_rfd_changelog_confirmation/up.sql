@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE rfd_changelog_subscriptions ADD COLUMN token VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE rfd_changelog_subscriptions ADD COLUMN confirmed BOOLEAN NOT NULL DEFAULT false;
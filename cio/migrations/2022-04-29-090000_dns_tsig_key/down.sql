@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE companys DROP COLUMN dns_tsig_key;
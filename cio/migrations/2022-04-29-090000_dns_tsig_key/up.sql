@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE companys ADD COLUMN dns_tsig_key VARCHAR NOT NULL DEFAULT '';
@@ -0,0 +1,418 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use google_geocode::{Geocode, LocationType, Reply};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// What we found out about an address from the geocoder.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressVerdict {
+    /// The address matches a single street address.
+    Valid,
+    /// The address matches a single street address, but parts of it are wrong.
+    Corrected,
+    /// The address matches more than one place.
+    Ambiguous,
+    /// The address does not match any street address.
+    Undeliverable,
+}
+
+impl Default for AddressVerdict {
+    fn default() -> Self {
+        AddressVerdict::Valid
+    }
+}
+
+impl ToString for AddressVerdict {
+    fn to_string(&self) -> String {
+        match self {
+            AddressVerdict::Valid => "valid".to_string(),
+            AddressVerdict::Corrected => "corrected".to_string(),
+            AddressVerdict::Ambiguous => "ambiguous".to_string(),
+            AddressVerdict::Undeliverable => "undeliverable".to_string(),
+        }
+    }
+}
+
+impl FromStr for AddressVerdict {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "valid" => Ok(AddressVerdict::Valid),
+            "corrected" => Ok(AddressVerdict::Corrected),
+            "ambiguous" => Ok(AddressVerdict::Ambiguous),
+            "undeliverable" => Ok(AddressVerdict::Undeliverable),
+            _ => bail!("unknown address verdict `{}`", s),
+        }
+    }
+}
+
+/// The parts of a postal address.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PostalAddress {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub street_1: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub street_2: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub city: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub state: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub zipcode: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub country: String,
+}
+
+impl ToString for PostalAddress {
+    fn to_string(&self) -> String {
+        let state_zipcode = format!("{} {}", self.state, self.zipcode);
+
+        [
+            self.street_1.as_str(),
+            self.street_2.as_str(),
+            self.city.as_str(),
+            state_zipcode.as_str(),
+            self.country.as_str(),
+        ]
+        .iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>()
+        .join(", ")
+    }
+}
+
+/// The result of validating an address. We cache this as JSON in the
+/// `address_validation` of the record so we only ask the geocoder again when the
+/// address changes.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct AddressValidation {
+    /// The address as it was entered. We key the cache on this, so the address
+    /// we normalize it to is only ever in the `suggestion`.
+    pub address: String,
+    pub verdict: AddressVerdict,
+    /// The address as the geocoder has it, with the street, city and zipcode
+    /// normalized, and the state and country as their short codes.
+    #[serde(default)]
+    pub suggestion: PostalAddress,
+    /// Why the address is not valid.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<String>,
+    #[serde(default)]
+    pub latitude: f32,
+    #[serde(default)]
+    pub longitude: f32,
+    /// Someone told us in Slack to ship to the address as it was entered.
+    #[serde(default)]
+    pub confirmed: bool,
+}
+
+impl AddressValidation {
+    /// Get the validation for an address from the cache, if the address did not
+    /// change since. Once we are shipping to the suggested address, it is found for
+    /// that too.
+    pub fn from_cache(cache: &str, address: &PostalAddress) -> Option<Self> {
+        match serde_json::from_str::<AddressValidation>(cache) {
+            Ok(v) if v.address == address.to_string() => Some(v),
+            Ok(v) if v.is_deliverable() && v.suggestion.to_string() == address.to_string() => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn to_cache(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Returns if we can buy a label for the address.
+    pub fn is_deliverable(&self) -> bool {
+        self.verdict == AddressVerdict::Valid || self.confirmed
+    }
+
+    /// Accept the suggested address. The validation stays keyed on the address as
+    /// it was entered.
+    pub fn accept_suggestion(&self) -> Self {
+        AddressValidation {
+            address: self.address.to_string(),
+            verdict: AddressVerdict::Valid,
+            suggestion: self.suggestion.clone(),
+            messages: Default::default(),
+            latitude: self.latitude,
+            longitude: self.longitude,
+            confirmed: true,
+        }
+    }
+}
+
+/// Validate an address with the Google geocoder, unless the cache has the
+/// validation for it already.
+pub async fn validate_address(address: &PostalAddress, cache: &str) -> Result<AddressValidation> {
+    if let Some(v) = AddressValidation::from_cache(cache, address) {
+        return Ok(v);
+    }
+
+    let geocode = Geocode::new_from_env();
+    let replies = match geocode.get_all(&address.to_string()).await {
+        Ok(replies) => replies,
+        Err(e) if e.status_code == StatusCode::NOT_FOUND => Default::default(),
+        Err(e) => bail!("geocoding address `{}` failed: {}", address.to_string(), e),
+    };
+
+    Ok(validate_replies(address, &replies))
+}
+
+fn normalize(s: &str) -> String {
+    s.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Get the long and short names of the first component of a type.
+fn component<'a>(reply: &'a Reply, types: &[&str]) -> Option<(&'a str, &'a str)> {
+    types.iter().find_map(|t| {
+        reply
+            .address_components
+            .iter()
+            .find(|c| c.types.iter().any(|ct| ct == t))
+            .map(|c| (c.long_name.as_str(), c.short_name.as_str()))
+    })
+}
+
+/// Returns if a part of an address matches either name of a component.
+fn matches(s: &str, names: (&str, &str)) -> bool {
+    let s = normalize(s);
+    s == normalize(names.0) || s == normalize(names.1)
+}
+
+fn country_code(country: &str) -> String {
+    match normalize(country).as_str() {
+        "united states" | "usa" => "US".to_string(),
+        "great britain" | "great britian" | "uk" => "GB".to_string(),
+        _ => country.trim().to_string(),
+    }
+}
+
+/// Validate an address from the places the geocoder matched it to.
+pub fn validate_replies(address: &PostalAddress, replies: &[Reply]) -> AddressValidation {
+    let mut validation = AddressValidation {
+        address: address.to_string(),
+        ..Default::default()
+    };
+
+    let reply = match replies.first() {
+        Some(reply) => reply,
+        None => {
+            validation.verdict = AddressVerdict::Undeliverable;
+            validation.messages.push("the address could not be found".to_string());
+            return validation;
+        }
+    };
+    validation.latitude = reply.geometry.location.lat as f32;
+    validation.longitude = reply.geometry.location.lng as f32;
+
+    // Build the suggestion, keeping what the geocoder does not know about.
+    let street_number = component(reply, &["street_number"]);
+    let route = component(reply, &["route"]);
+    let country = component(reply, &["country"]);
+    let mut suggestion = address.clone();
+    if let (Some((number, _)), Some((_, route))) = (street_number, route) {
+        suggestion.street_1 = match country.map(|c| c.1) {
+            Some("US") | Some("CA") | Some("GB") | Some("IE") | Some("AU") | Some("NZ") | None => {
+                format!("{} {}", number, route)
+            }
+            _ => format!("{} {}", route, number),
+        };
+    }
+    if let Some((subpremise, _)) = component(reply, &["subpremise"]) {
+        if !normalize(&address.street_2).contains(&normalize(subpremise)) {
+            suggestion.street_2 = format!("#{}", subpremise);
+        }
+    }
+    let city = component(
+        reply,
+        &["locality", "postal_town", "sublocality", "administrative_area_level_3"],
+    );
+    if let Some((long, _)) = city {
+        suggestion.city = long.to_string();
+    }
+    let state = component(reply, &["administrative_area_level_1"]);
+    if let Some((_, short)) = state {
+        suggestion.state = short.to_string();
+    }
+    if let Some((_, short)) = country {
+        suggestion.country = short.to_string();
+    }
+    let zipcode = component(reply, &["postal_code"]);
+    if let Some((long, _)) = zipcode {
+        // Keep the ZIP+4 suffix, the geocoder does not have it.
+        if !normalize(&address.zipcode).starts_with(&normalize(long)) {
+            suggestion.zipcode = long.to_string();
+        }
+    }
+    validation.suggestion = suggestion;
+
+    if street_number.is_none() || route.is_none() || reply.geometry.location_type == LocationType::Approximate {
+        validation.verdict = AddressVerdict::Undeliverable;
+        validation.messages.push(format!(
+            "the address only matches `{}`, not a street address",
+            reply.formatted_address
+        ));
+        return validation;
+    }
+
+    if replies.len() > 1 {
+        validation.verdict = AddressVerdict::Ambiguous;
+        validation.messages.push(format!(
+            "the address matches {} places, like `{}`",
+            replies.len(),
+            reply.formatted_address
+        ));
+        return validation;
+    }
+
+    // Find the parts of the address that are wrong.
+    let street = normalize(&address.street_1);
+    if let (Some((number, _)), Some(route)) = (street_number, route) {
+        let has_number = street.split(' ').any(|w| w == normalize(number));
+        let has_route = street.contains(&normalize(route.0)) || street.contains(&normalize(route.1));
+        if !has_number || !has_route {
+            validation
+                .messages
+                .push(format!("the street is `{}`", validation.suggestion.street_1));
+        }
+    }
+    if let Some(names) = city {
+        if !matches(&address.city, names) {
+            validation.messages.push(format!("the city is `{}`", names.0));
+        }
+    }
+    if let Some(names) = state {
+        if !address.state.is_empty() && !matches(&address.state, names) {
+            validation.messages.push(format!("the state is `{}`", names.1));
+        }
+    }
+    if zipcode.is_some() && validation.suggestion.zipcode != address.zipcode {
+        validation
+            .messages
+            .push(format!("the zipcode is `{}`", validation.suggestion.zipcode));
+    }
+    if let Some(names) = country {
+        if !address.country.is_empty()
+            && !matches(&country_code(&address.country), names)
+            && !matches(&address.country, names)
+        {
+            validation.messages.push(format!("the country is `{}`", names.0));
+        }
+    }
+
+    if !validation.messages.is_empty() {
+        validation.verdict = AddressVerdict::Corrected;
+    } else if reply.partial_match {
+        // The geocoder matched the address without the parts it did not know,
+        // like a missing apartment, but everything we checked is right.
+        validation
+            .messages
+            .push(format!("the address partially matches `{}`", reply.formatted_address));
+        validation.verdict = AddressVerdict::Ambiguous;
+    }
+
+    validation
+}
+
+#[cfg(test)]
+mod tests {
+    use google_geocode::Reply;
+    use serde_json::json;
+
+    use super::{validate_replies, AddressValidation, AddressVerdict, PostalAddress};
+
+    fn reply(zipcode: &str, street_number: bool) -> Reply {
+        let mut components = vec![
+            json!({"long_name": "Main Street", "short_name": "Main St", "types": ["route"]}),
+            json!({"long_name": "Emeryville", "short_name": "Emeryville", "types": ["locality", "political"]}),
+            json!({"long_name": "California", "short_name": "CA", "types": ["administrative_area_level_1"]}),
+            json!({"long_name": "United States", "short_name": "US", "types": ["country", "political"]}),
+            json!({"long_name": zipcode, "short_name": zipcode, "types": ["postal_code"]}),
+        ];
+        if street_number {
+            components.push(json!({"long_name": "1251", "short_name": "1251", "types": ["street_number"]}));
+        }
+
+        serde_json::from_value(json!({
+            "address_components": components,
+            "formatted_address": "1251 Main St, Emeryville, CA 94608, USA",
+            "geometry": {
+                "location": {"lat": 37.8, "lng": -122.2},
+                "location_type": "ROOFTOP",
+                "viewport": {
+                    "northeast": {"lat": 37.9, "lng": -122.1},
+                    "southwest": {"lat": 37.7, "lng": -122.3},
+                },
+            },
+            "place_id": "abc",
+            "types": ["street_address"],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_replies() {
+        let address = PostalAddress {
+            street_1: "1251 main street".to_string(),
+            street_2: "".to_string(),
+            city: "emeryville".to_string(),
+            state: "California".to_string(),
+            zipcode: "94608-1234".to_string(),
+            country: "United States".to_string(),
+        };
+
+        let v = validate_replies(&address, &[reply("94608", true)]);
+        assert_eq!(v.verdict, AddressVerdict::Valid, "{:?}", v.messages);
+        assert_eq!(v.suggestion.street_1, "1251 Main St");
+        assert_eq!(v.suggestion.city, "Emeryville");
+        assert_eq!(v.suggestion.state, "CA");
+        assert_eq!(v.suggestion.country, "US");
+
+        let v = validate_replies(&address, &[reply("94609", true)]);
+        assert_eq!(v.verdict, AddressVerdict::Corrected);
+        assert_eq!(v.messages, vec!["the zipcode is `94609`".to_string()]);
+        assert!(!v.is_deliverable());
+        assert!(v.accept_suggestion().is_deliverable());
+
+        let v = validate_replies(&address, &[reply("94608", true), reply("94608", true)]);
+        assert_eq!(v.verdict, AddressVerdict::Ambiguous);
+
+        let v = validate_replies(&address, &[reply("94608", false)]);
+        assert_eq!(v.verdict, AddressVerdict::Undeliverable);
+        assert_eq!(validate_replies(&address, &[]).verdict, AddressVerdict::Undeliverable);
+
+        // The cache is only used for the same address.
+        let cache = v.to_cache();
+        assert!(AddressValidation::from_cache(&cache, &address).is_some());
+        assert!(AddressValidation::from_cache(&cache, &PostalAddress::default()).is_none());
+        assert!(AddressValidation::from_cache("🔵 eyJpIjoi", &address).is_none());
+
+        // The cache is keyed on the address as entered, and once we normalize the
+        // address to the suggestion it is found for that too.
+        let v = validate_replies(&address, &[reply("94608", true)]);
+        assert_eq!(v.address, address.to_string());
+        let cache = v.to_cache();
+        assert!(AddressValidation::from_cache(&cache, &address).is_some());
+        assert!(AddressValidation::from_cache(&cache, &v.suggestion).is_some());
+
+        // But only if we would ship to the suggestion.
+        let v = validate_replies(&address, &[reply("94609", true)]);
+        assert!(AddressValidation::from_cache(&v.to_cache(), &v.suggestion).is_none());
+        let accepted = v.accept_suggestion();
+        assert_eq!(accepted.address, address.to_string());
+        assert!(AddressValidation::from_cache(&accepted.to_cache(), &address).is_some());
+        assert!(AddressValidation::from_cache(&accepted.to_cache(), &v.suggestion).is_some());
+    }
+}
@@ -6,18 +6,25 @@ pub static AIRTABLE_AUTH_USER_LOGINS_TABLE: &str = "Auth User Logins";
 pub static AIRTABLE_PAGE_VIEWS_TABLE: &str = "Page Views";
 
 pub static AIRTABLE_EMPLOYEES_TABLE: &str = "Employees";
+pub static AIRTABLE_OFFBOARDING_EVENTS_TABLE: &str = "Offboarding Events";
 pub static AIRTABLE_GROUPS_TABLE: &str = "Groups";
 pub static AIRTABLE_BUILDINGS_TABLE: &str = "Buildings";
 pub static AIRTABLE_CONFERENCE_ROOMS_TABLE: &str = "Conference Rooms";
+pub static AIRTABLE_HOLIDAYS_TABLE: &str = "Holidays";
 pub static AIRTABLE_LINKS_TABLE: &str = "Links";
 
 pub static AIRTABLE_CERTIFICATES_TABLE: &str = "Certificates";
+pub static AIRTABLE_MANAGED_DNS_RECORDS_TABLE: &str = "Managed DNS Records";
 pub static AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE: &str = "Journal Club Meetings";
 pub static AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE: &str = "Journal Club Papers";
 pub static AIRTABLE_GITHUB_REPOS_TABLE: &str = "GitHub Repos";
 pub static AIRTABLE_RECORDED_MEETINGS_TABLE: &str = "Recorded Meetings";
 
 pub static AIRTABLE_RFD_TABLE: &str = "RFDs";
+pub static AIRTABLE_RFD_STATE_CHANGES_TABLE: &str = "RFD State Changes";
+pub static AIRTABLE_RFD_REFERENCES_TABLE: &str = "RFD References";
+pub static AIRTABLE_RFD_CHANGELOG_SUBSCRIPTIONS_TABLE: &str = "RFD Changelog Subscriptions";
+pub static AIRTABLE_RFD_RESERVATIONS_TABLE: &str = "RFD Reservations";
 
 pub static AIRTABLE_APPLICATIONS_TABLE: &str = "Applicants";
 pub static AIRTABLE_INTERVIEWS_TABLE: &str = "Interviews";
@@ -30,6 +37,7 @@ pub static AIRTABLE_MEETING_SCHEDULE_TABLE: &str = "Meeting schedule";
 pub static AIRTABLE_OUTBOUND_TABLE: &str = "Outbound";
 pub static AIRTABLE_INBOUND_TABLE: &str = "Inbound";
 pub static AIRTABLE_PACKAGE_PICKUPS_TABLE: &str = "Package Pickups";
+pub static AIRTABLE_SHIPMENT_TRACKING_EVENTS_TABLE: &str = "Tracking Events";
 
 pub static AIRTABLE_SOFTWARE_VENDORS_TABLE: &str = "Vendors";
 pub static AIRTABLE_CREDIT_CARD_TRANSACTIONS_TABLE: &str = "Credit Card Transactions";
@@ -39,6 +47,7 @@ pub static AIRTABLE_EXPENSED_ITEMS_TABLE: &str = "Expensed Items";
 pub static AIRTABLE_SWAG_INVENTORY_ITEMS_TABLE: &str = "Inventory";
 pub static AIRTABLE_BARCODE_SCANS_TABLE: &str = "Barcode Scans";
 pub static AIRTABLE_SWAG_ITEMS_TABLE: &str = "Items";
+pub static AIRTABLE_SWAG_INVENTORY_RESERVATIONS_TABLE: &str = "Reservations";
 
 pub static AIRTABLE_ASSET_ITEMS_TABLE: &str = "Items";
 
@@ -0,0 +1,1764 @@
+//! A renderer for the subset of AsciiDoc our RFDs use, so we don't need to shell out to
+//! `asciidoctor`. The output mirrors the embedded HTML `asciidoctor --no-header-footer`
+//! produces, so the RFD site styles keep working.
+//!
+//! Supported: the document header and attributes, sections, paragraphs, admonitions,
+//! ordered, unordered and description lists, tables, listing, source, literal, example,
+//! sidebar, quote, open and passthrough blocks, block and inline images (including
+//! inline SVGs), links, cross references, anchors, footnotes and the basic inline
+//! formatting.
+use std::collections::BTreeMap;
+
+use regex::{Captures, Regex};
+
+/// The types of admonitions, the prefix of the paragraph and the label we show.
+const ADMONITIONS: &[(&str, &str)] = &[
+    ("NOTE", "Note"),
+    ("TIP", "Tip"),
+    ("IMPORTANT", "Important"),
+    ("WARNING", "Warning"),
+    ("CAUTION", "Caution"),
+];
+
+/// How many times attribute values can reference other attributes, so a value that
+/// references itself does not loop forever.
+const MAX_ATTRIBUTE_DEPTH: usize = 8;
+
+/// How deep inline formatting can be nested.
+const MAX_NESTING_DEPTH: usize = 16;
+
+/// Render AsciiDoc content as HTML.
+///
+/// `inline_images` maps the path of an SVG, relative to the document, to its contents.
+/// Images with `opts=inline` are embedded with these instead of being linked to.
+pub fn render(content: &str, inline_images: &BTreeMap<String, String>) -> String {
+    let content = content.replace("\r\n", "\n");
+    let lines: Vec<&str> = content.lines().collect();
+
+    let mut renderer = Renderer {
+        attributes: Default::default(),
+        ids: Default::default(),
+        section_ids: Default::default(),
+        next_section: 0,
+        footnotes: Default::default(),
+        open_sections: Default::default(),
+        counters: Default::default(),
+        depth: 0,
+        inline_images,
+    };
+
+    let (title, body) = renderer.parse_header(&lines);
+    renderer.collect_ids(&lines[body..]);
+
+    let mut html = renderer.render_document(&lines[body..], title.is_some());
+    if !renderer.footnotes.is_empty() {
+        let footnotes: Vec<String> = renderer
+            .footnotes
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                format!(
+                    "<div class=\"footnote\" id=\"_footnotedef_{n}\">\n<a href=\"#_footnoteref_{n}\">{n}</a>. {}\n</div>",
+                    text,
+                    n = i + 1
+                )
+            })
+            .collect();
+        html += &format!("\n<div id=\"footnotes\">\n<hr>\n{}\n</div>", footnotes.join("\n"));
+    }
+
+    html
+}
+
+/// Get the title of an AsciiDoc document, from its `= Title` header line.
+pub fn title(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find(|l| !l.trim().is_empty() && !l.starts_with("//") && !l.starts_with(':'))
+        .and_then(|l| l.strip_prefix("= "))
+        .map(|t| t.trim().to_string())
+}
+
+struct Renderer<'a> {
+    attributes: BTreeMap<String, String>,
+    /// The ids we can cross reference, with the title of the section if there is one.
+    ids: BTreeMap<String, Option<String>>,
+    /// The ids of the sections, in the order they appear.
+    section_ids: Vec<String>,
+    next_section: usize,
+    footnotes: Vec<String>,
+    /// The levels of the sections we are in.
+    open_sections: Vec<usize>,
+    /// The number of titled tables, figures and examples so far, for their captions.
+    counters: BTreeMap<&'static str, usize>,
+    depth: usize,
+    inline_images: &'a BTreeMap<String, String>,
+}
+
+/// The attributes from a `[...]` line before a block.
+#[derive(Debug, Default, Clone)]
+struct BlockAttributes {
+    style: Option<String>,
+    positional: Vec<String>,
+    named: BTreeMap<String, String>,
+    options: Vec<String>,
+    id: Option<String>,
+    roles: Vec<String>,
+}
+
+impl BlockAttributes {
+    fn parse(s: &str) -> Self {
+        let mut attrs = BlockAttributes::default();
+
+        for (i, part) in split_attributes(s).into_iter().enumerate() {
+            if let Some((key, value)) = part.split_once('=') {
+                let key = key.trim().to_string();
+                let value = unquote(value.trim()).to_string();
+                if key == "opts" || key == "options" {
+                    attrs.options.extend(value.split(',').map(|o| o.trim().to_string()));
+                } else if key == "id" {
+                    attrs.id = Some(value);
+                } else if key == "role" {
+                    attrs.roles.extend(value.split_whitespace().map(|r| r.to_string()));
+                } else {
+                    attrs.named.insert(key, value);
+                }
+                continue;
+            }
+
+            let part = unquote(part.trim()).to_string();
+            if i == 0 {
+                // The first positional attribute is the style, with shorthands for the id,
+                // roles and options, like `source#id.role%option`.
+                let mut style = String::new();
+                let mut current: Option<char> = None;
+                let mut value = String::new();
+                for c in part.chars().chain(std::iter::once('\0')) {
+                    if c == '#' || c == '.' || c == '%' || c == '\0' {
+                        match current {
+                            Some('#') => attrs.id = Some(value.to_string()),
+                            Some('.') => attrs.roles.push(value.to_string()),
+                            Some('%') => attrs.options.push(value.to_string()),
+                            _ => style = value.to_string(),
+                        }
+                        current = Some(c);
+                        value = String::new();
+                    } else {
+                        value.push(c);
+                    }
+                }
+                if !style.is_empty() {
+                    attrs.style = Some(style);
+                }
+            } else {
+                attrs.positional.push(part);
+            }
+        }
+
+        attrs
+    }
+
+    fn has_option(&self, option: &str) -> bool {
+        self.options.iter().any(|o| o == option)
+    }
+}
+
+/// The things that can come before a block and change how it is rendered.
+#[derive(Debug, Default, Clone)]
+struct PendingBlock {
+    attributes: BlockAttributes,
+    title: Option<String>,
+    id: Option<String>,
+}
+
+impl PendingBlock {
+    fn id(&self) -> Option<String> {
+        self.id.clone().or_else(|| self.attributes.id.clone())
+    }
+}
+
+impl<'a> Renderer<'a> {
+    /// Parse the document header, return the title and the index of the first line of
+    /// the body.
+    fn parse_header(&mut self, lines: &[&str]) -> (Option<String>, usize) {
+        // Comments and attribute entries can come before the title.
+        let mut i = 0;
+        while i < lines.len()
+            && (lines[i].trim().is_empty() || is_line_comment(lines[i]) || self.set_attribute(lines[i]))
+        {
+            i += 1;
+        }
+
+        let title = match lines.get(i).and_then(|l| l.strip_prefix("= ")) {
+            Some(title) => title.trim().to_string(),
+            None => return (None, 0),
+        };
+        i += 1;
+
+        // The header runs until the first blank line, it has the author and revision
+        // lines and attribute entries.
+        while i < lines.len() && !lines[i].trim().is_empty() {
+            self.set_attribute(lines[i]);
+            i += 1;
+        }
+
+        (Some(title), i)
+    }
+
+    /// Find the ids of all the sections and anchors before rendering, so cross
+    /// references work no matter where they are.
+    fn collect_ids(&mut self, lines: &[&str]) {
+        let mut delimiter: Option<&str> = None;
+        let mut anchor: Option<String> = None;
+
+        for line in lines {
+            let t = line.trim_end();
+            if let Some(d) = delimiter {
+                if t == d {
+                    delimiter = None;
+                }
+                continue;
+            }
+            if is_delimiter(t) {
+                delimiter = Some(t);
+                anchor = None;
+                continue;
+            }
+
+            if let Some(id) = block_anchor(t) {
+                self.ids.insert(id.to_string(), None);
+                anchor = Some(id);
+            } else if let Some((_, title)) = section_title(t) {
+                let title = self.substitute_attributes(title, 0);
+                let id = match anchor.take() {
+                    Some(id) => id,
+                    None => self.generate_id(&title),
+                };
+                self.ids.insert(id.to_string(), Some(title));
+                self.section_ids.push(id);
+            } else if t.starts_with(':') {
+                self.set_attribute(t);
+            } else if !t.is_empty() && !t.starts_with('[') {
+                anchor = None;
+            }
+        }
+    }
+
+    /// Generate an id for a section, the same way asciidoctor does.
+    fn generate_id(&self, title: &str) -> String {
+        let mut id = String::from("_");
+        let mut separated = false;
+        for c in strip_inline_markup(title).to_lowercase().chars() {
+            if c.is_alphanumeric() || c == '_' {
+                id.push(c);
+                separated = false;
+            } else if (c == ' ' || c == '-' || c == '.') && !separated {
+                id.push('_');
+                separated = true;
+            }
+        }
+        if id.len() > 1 && id.ends_with('_') {
+            id.pop();
+        }
+
+        // Make sure the id is unique.
+        let mut unique = id.to_string();
+        let mut n = 2;
+        while self.ids.contains_key(&unique) {
+            unique = format!("{}_{}", id, n);
+            n += 1;
+        }
+
+        unique
+    }
+
+    /// Set or unset an attribute from an attribute entry line like `:name: value`.
+    /// Returns false if the line is not an attribute entry.
+    fn set_attribute(&mut self, line: &str) -> bool {
+        let rest = match line.strip_prefix(':') {
+            Some(rest) => rest,
+            None => return false,
+        };
+        let (name, value) = match rest.split_once(':') {
+            Some(v) => v,
+            None => return false,
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return false;
+        }
+
+        if let Some(name) = name.strip_suffix('!') {
+            self.attributes.remove(name);
+        } else if let Some(name) = name.strip_prefix('!') {
+            self.attributes.remove(name);
+        } else {
+            self.attributes.insert(name.to_string(), value.trim().to_string());
+        }
+
+        true
+    }
+
+    fn render_document(&mut self, lines: &[&str], has_title: bool) -> String {
+        let mut parts = self.render_blocks(lines, true, has_title);
+
+        // Close any sections that are still open.
+        while let Some(level) = self.open_sections.pop() {
+            parts.push(close_section(level));
+        }
+
+        parts.join("\n")
+    }
+
+    /// Render a list of blocks. Only the top level of the document can have sections.
+    fn render_blocks(&mut self, lines: &[&str], top_level: bool, has_title: bool) -> Vec<String> {
+        let mut parts: Vec<String> = Default::default();
+        let mut pending = PendingBlock::default();
+        let mut seen_section = false;
+        let mut i = 0;
+
+        while i < lines.len() {
+            let line = lines[i];
+            let t = line.trim_end();
+
+            if t.is_empty() {
+                i += 1;
+                continue;
+            }
+
+            // Comments.
+            if t == "////" {
+                i = find_closing(lines, i, t).map(|j| j + 1).unwrap_or(lines.len());
+                continue;
+            }
+            if is_line_comment(t) {
+                i += 1;
+                continue;
+            }
+
+            // Things that change the next block.
+            if let Some(id) = block_anchor(t) {
+                pending.id = Some(id);
+                i += 1;
+                continue;
+            }
+            if t.starts_with('[') && t.ends_with(']') && !t.starts_with("[[") {
+                pending.attributes = BlockAttributes::parse(&t[1..t.len() - 1]);
+                i += 1;
+                continue;
+            }
+            if is_block_title(t) {
+                pending.title = Some(t[1..].to_string());
+                i += 1;
+                continue;
+            }
+            if t.starts_with(':') && self.set_attribute(t) {
+                i += 1;
+                continue;
+            }
+
+            // Things we don't render.
+            if t == "<<<" || t.starts_with("toc::[") || t.starts_with("include::") {
+                i += 1;
+                continue;
+            }
+
+            if top_level {
+                if let Some((level, title)) = section_title(t) {
+                    // Everything before the first section is the preamble.
+                    if !seen_section && has_title && !parts.is_empty() {
+                        parts = vec![format!(
+                            "<div id=\"preamble\">\n<div class=\"sectionbody\">\n{}\n</div>\n</div>",
+                            parts.join("\n")
+                        )];
+                    }
+                    seen_section = true;
+
+                    while let Some(open) = self.open_sections.last() {
+                        if *open < level {
+                            break;
+                        }
+                        parts.push(close_section(*open));
+                        self.open_sections.pop();
+                    }
+
+                    let id = self.section_ids.get(self.next_section).cloned().unwrap_or_default();
+                    self.next_section += 1;
+
+                    let title = self.inline(title);
+                    if level == 1 {
+                        parts.push(format!(
+                            "<div class=\"sect1\">\n<h2 id=\"{}\">{}</h2>\n<div class=\"sectionbody\">",
+                            id, title
+                        ));
+                    } else {
+                        parts.push(format!(
+                            "<div class=\"sect{}\">\n<h{} id=\"{}\">{}</h{}>",
+                            level,
+                            level + 1,
+                            id,
+                            title,
+                            level + 1
+                        ));
+                    }
+                    self.open_sections.push(level);
+
+                    pending = PendingBlock::default();
+                    i += 1;
+                    continue;
+                }
+            }
+
+            if t == "'''" {
+                parts.push("<hr>".to_string());
+                pending = PendingBlock::default();
+                i += 1;
+                continue;
+            }
+
+            if let Some(rest) = t.strip_prefix("image::") {
+                parts.push(self.render_image_block(rest, &pending));
+                pending = PendingBlock::default();
+                i += 1;
+                continue;
+            }
+
+            if t == "|===" {
+                let end = find_closing(lines, i, t).unwrap_or(lines.len());
+                parts.push(self.render_table(&lines[i + 1..end], &pending));
+                pending = PendingBlock::default();
+                i = end + 1;
+                continue;
+            }
+
+            if is_delimiter(t) {
+                let end = find_closing(lines, i, t).unwrap_or(lines.len());
+                parts.push(self.render_delimited_block(t, &lines[i + 1..end], &pending));
+                pending = PendingBlock::default();
+                i = end + 1;
+                continue;
+            }
+
+            if list_marker(t).is_some() {
+                let marker = list_marker(t).unwrap().0;
+                parts.push(self.render_list(lines, &mut i, &marker, &[], &pending));
+                pending = PendingBlock::default();
+                continue;
+            }
+
+            if dlist_term(t).is_some() {
+                parts.push(self.render_dlist(lines, &mut i, &pending));
+                pending = PendingBlock::default();
+                continue;
+            }
+
+            // Otherwise we have a paragraph, it runs until a blank line or a block.
+            let start = i;
+            while i < lines.len() {
+                let t = lines[i].trim_end();
+                if t.is_empty() || (i > start && (is_delimiter(t) || t == "|===" || is_line_comment(t))) {
+                    break;
+                }
+                i += 1;
+            }
+            parts.push(self.render_paragraph(&lines[start..i], &pending));
+            pending = PendingBlock::default();
+        }
+
+        parts
+    }
+
+    fn render_paragraph(&mut self, lines: &[&str], pending: &PendingBlock) -> String {
+        let style = pending.attributes.style.as_deref().unwrap_or_default();
+
+        // An indented paragraph is literal.
+        if lines[0].starts_with(' ') || lines[0].starts_with('\t') || style == "literal" {
+            return self.render_verbatim("literalblock", &dedent(lines), pending, None);
+        }
+        if style == "source" || style == "listing" {
+            return self.render_verbatim("listingblock", &lines.join("\n"), pending, None);
+        }
+
+        let text = lines.iter().map(|l| l.trim_end()).collect::<Vec<&str>>().join("\n");
+
+        // Admonitions can be a style or a prefix of the paragraph.
+        for (name, label) in ADMONITIONS {
+            if style == *name {
+                let content = self.inline(&text);
+                return self.render_admonition(name, label, &content, pending);
+            }
+            if let Some(rest) = text.strip_prefix(&format!("{}: ", name)) {
+                let content = self.inline(rest);
+                return self.render_admonition(name, label, &content, pending);
+            }
+        }
+
+        if style == "quote" {
+            let content = format!("<div class=\"paragraph\">\n<p>{}</p>\n</div>", self.inline(&text));
+            return self.render_quote(&content, pending);
+        }
+
+        let title = self.render_title(pending, None);
+        format!(
+            "<div{} class=\"{}\">\n{}<p>{}</p>\n</div>",
+            id_attribute(pending),
+            classes("paragraph", pending),
+            title,
+            self.inline(&text)
+        )
+    }
+
+    fn render_admonition(&mut self, name: &str, label: &str, content: &str, pending: &PendingBlock) -> String {
+        let title = self.render_title(pending, None);
+        format!(
+            "<div{} class=\"{}\">\n<table>\n<tr>\n<td class=\"icon\">\n<div class=\"title\">{}</div>\n</td>\n<td class=\"content\">\n{}{}\n</td>\n</tr>\n</table>\n</div>",
+            id_attribute(pending),
+            classes(&format!("admonitionblock {}", name.to_lowercase()), pending),
+            label,
+            title,
+            content
+        )
+    }
+
+    fn render_quote(&mut self, content: &str, pending: &PendingBlock) -> String {
+        let title = self.render_title(pending, None);
+        let mut attribution = String::new();
+        if let Some(author) = pending.attributes.positional.first() {
+            attribution = format!("\n<div class=\"attribution\">\n&#8212; {}", self.inline(author));
+            if let Some(citation) = pending.attributes.positional.get(1) {
+                attribution += &format!("<br>\n<cite>{}</cite>", self.inline(citation));
+            }
+            attribution += "\n</div>";
+        }
+
+        format!(
+            "<div{} class=\"{}\">\n{}<blockquote>\n{}\n</blockquote>{}\n</div>",
+            id_attribute(pending),
+            classes("quoteblock", pending),
+            title,
+            content,
+            attribution
+        )
+    }
+
+    fn render_delimited_block(&mut self, delimiter: &str, lines: &[&str], pending: &PendingBlock) -> String {
+        let style = pending.attributes.style.clone().unwrap_or_default();
+
+        match delimiter.chars().next().unwrap_or_default() {
+            '-' if delimiter.len() >= 4 => {
+                if style == "literal" || style == "mermaid" {
+                    self.render_verbatim("literalblock", &lines.join("\n"), pending, Some(&style))
+                } else {
+                    self.render_verbatim("listingblock", &lines.join("\n"), pending, None)
+                }
+            }
+            '.' => {
+                if style == "source" || style == "listing" {
+                    self.render_verbatim("listingblock", &lines.join("\n"), pending, None)
+                } else {
+                    self.render_verbatim("literalblock", &lines.join("\n"), pending, Some(&style))
+                }
+            }
+            '+' => lines.join("\n"),
+            '=' => {
+                let content = self.render_blocks(lines, false, false).join("\n");
+                for (name, label) in ADMONITIONS {
+                    if style == *name {
+                        return self.render_admonition(name, label, &content, pending);
+                    }
+                }
+
+                let title = self.render_title(pending, Some("Example"));
+                format!(
+                    "<div{} class=\"{}\">\n{}<div class=\"content\">\n{}\n</div>\n</div>",
+                    id_attribute(pending),
+                    classes("exampleblock", pending),
+                    title,
+                    content
+                )
+            }
+            '*' => {
+                let content = self.render_blocks(lines, false, false).join("\n");
+                let title = self.render_title(pending, None);
+                format!(
+                    "<div{} class=\"{}\">\n<div class=\"content\">\n{}{}\n</div>\n</div>",
+                    id_attribute(pending),
+                    classes("sidebarblock", pending),
+                    title,
+                    content
+                )
+            }
+            '_' => {
+                let content = self.render_blocks(lines, false, false).join("\n");
+                self.render_quote(&content, pending)
+            }
+            _ => {
+                // An open block, `--`.
+                let content = self.render_blocks(lines, false, false).join("\n");
+                for (name, label) in ADMONITIONS {
+                    if style == *name {
+                        return self.render_admonition(name, label, &content, pending);
+                    }
+                }
+
+                let title = self.render_title(pending, None);
+                format!(
+                    "<div{} class=\"{}\">\n{}<div class=\"content\">\n{}\n</div>\n</div>",
+                    id_attribute(pending),
+                    classes("openblock", pending),
+                    title,
+                    content
+                )
+            }
+        }
+    }
+
+    /// Render a listing or literal block, these are not formatted.
+    fn render_verbatim(&mut self, class: &str, text: &str, pending: &PendingBlock, style: Option<&str>) -> String {
+        let text = escape(text.trim_matches('\n'));
+        let title = self.render_title(pending, None);
+
+        let pre = if class == "listingblock" {
+            match pending.attributes.style.as_deref() {
+                Some("source") => match pending.attributes.positional.first() {
+                    Some(lang) => format!(
+                        "<pre class=\"highlight\"><code class=\"language-{lang}\" data-lang=\"{lang}\">{}</code></pre>",
+                        text,
+                        lang = lang
+                    ),
+                    None => format!("<pre class=\"highlight\"><code>{}</code></pre>", text),
+                },
+                _ => format!("<pre>{}</pre>", text),
+            }
+        } else {
+            match style {
+                Some(style) if !style.is_empty() && style != "literal" => {
+                    format!("<pre class=\"{}\">{}</pre>", style, text)
+                }
+                _ => format!("<pre>{}</pre>", text),
+            }
+        };
+
+        let class = match style {
+            Some(style) if !style.is_empty() && style != "literal" => format!("{} {}", class, style),
+            _ => class.to_string(),
+        };
+
+        format!(
+            "<div{} class=\"{}\">\n{}<div class=\"content\">\n{}\n</div>\n</div>",
+            id_attribute(pending),
+            classes(&class, pending),
+            title,
+            pre
+        )
+    }
+
+    /// Render the title of a block, if it has one. Blocks that are numbered get a
+    /// caption like `Figure 1.` in front of their title.
+    fn render_title(&mut self, pending: &PendingBlock, caption: Option<&'static str>) -> String {
+        match &pending.title {
+            Some(title) => {
+                let title = self.inline(title);
+                match caption {
+                    Some(caption) => {
+                        let n = self.counters.entry(caption).or_default();
+                        *n += 1;
+                        format!("<div class=\"title\">{} {}. {}</div>\n", caption, n, title)
+                    }
+                    None => format!("<div class=\"title\">{}</div>\n", title),
+                }
+            }
+            None => String::new(),
+        }
+    }
+
+    fn render_image_block(&mut self, macro_rest: &str, pending: &PendingBlock) -> String {
+        let (target, attrs) = match parse_macro(macro_rest) {
+            Some(v) => v,
+            None => return format!("<div class=\"paragraph\">\n<p>{}</p>\n</div>", escape(macro_rest)),
+        };
+        let attrs = BlockAttributes::parse(&attrs);
+
+        let mut image = String::new();
+        if attrs.has_option("inline") || pending.attributes.has_option("inline") {
+            if let Some(svg) = self.inline_images.get(target.trim_start_matches("./")) {
+                image = strip_xml_prolog(svg).to_string();
+            }
+        }
+        if image.is_empty() {
+            image = img_tag(&target, &attrs);
+        }
+
+        // The title can be set in the macro as well.
+        let mut pending = pending.clone();
+        if let Some(title) = attrs.named.get("title") {
+            pending.title = Some(title.to_string());
+        }
+        let title = self.render_title(&pending, Some("Figure"));
+
+        format!(
+            "<div{} class=\"{}\">\n<div class=\"content\">\n{}\n</div>\n{}</div>",
+            id_attribute(&pending),
+            classes("imageblock", &pending),
+            image,
+            title
+        )
+    }
+
+    fn render_table(&mut self, lines: &[&str], pending: &PendingBlock) -> String {
+        // Figure out the columns and their widths.
+        let mut widths: Vec<f64> = match pending.attributes.named.get("cols") {
+            Some(cols) => {
+                if let Some((n, _)) = cols.split_once('*') {
+                    vec![1.0; n.trim().parse::<usize>().unwrap_or(1)]
+                } else {
+                    cols.split(',')
+                        .map(|c| {
+                            let digits: String = c.chars().filter(|c| c.is_ascii_digit()).collect();
+                            digits.parse::<f64>().unwrap_or(1.0).max(1.0)
+                        })
+                        .collect()
+                }
+            }
+            None => {
+                let first = lines.iter().find(|l| !l.trim().is_empty()).copied().unwrap_or_default();
+                vec![1.0; split_cells(first).len().max(1)]
+            }
+        };
+        if widths.is_empty() {
+            widths.push(1.0);
+        }
+        let columns = widths.len();
+
+        // The first row is a header if we are told it is, or if it is on one line
+        // followed by a blank line.
+        let first_line = lines.iter().position(|l| !l.trim().is_empty());
+        let implicit_header = match first_line {
+            Some(i) => {
+                split_cells(lines[i]).len() == columns && lines.get(i + 1).map(|l| l.trim().is_empty()).unwrap_or(false)
+            }
+            None => false,
+        };
+        let has_header =
+            pending.attributes.has_option("header") || (implicit_header && !pending.attributes.has_option("noheader"));
+
+        // Get all the cells, a cell can continue on the following lines.
+        let mut cells: Vec<String> = Default::default();
+        for line in lines {
+            if line.trim_start().starts_with('|') {
+                cells.extend(split_cells(line));
+            } else if let Some(last) = cells.last_mut() {
+                last.push('\n');
+                last.push_str(line.trim_end());
+            }
+        }
+
+        let mut html = format!(
+            "<table{} class=\"{}\">\n",
+            id_attribute(pending),
+            classes("tableblock frame-all grid-all stretch", pending)
+        );
+        if let Some(title) = &pending.title {
+            let n = self.counters.entry("Table").or_default();
+            *n += 1;
+            let n = *n;
+            html += &format!(
+                "<caption class=\"title\">Table {}. {}</caption>\n",
+                n,
+                self.inline(title)
+            );
+        }
+
+        html += "<colgroup>\n";
+        let total: f64 = widths.iter().sum();
+        let mut used = 0.0;
+        for (i, w) in widths.iter().enumerate() {
+            let percent = if i == columns - 1 {
+                100.0 - used
+            } else {
+                ((w / total * 100.0) * 10000.0).round() / 10000.0
+            };
+            used += percent;
+            html += &format!("<col style=\"width: {}%;\">\n", format_percent(percent));
+        }
+        html += "</colgroup>\n";
+
+        let rows: Vec<&[String]> = cells.chunks(columns).collect();
+        for (r, row) in rows.iter().enumerate() {
+            let header = r == 0 && has_header;
+            if header {
+                html += "<thead>\n";
+            } else if r == 0 || (r == 1 && has_header) {
+                html += "<tbody>\n";
+            }
+
+            html += "<tr>\n";
+            for cell in row.iter() {
+                let cell = cell.trim();
+                if header {
+                    html += &format!(
+                        "<th class=\"tableblock halign-left valign-top\">{}</th>\n",
+                        self.inline(cell)
+                    );
+                } else {
+                    let paragraphs: Vec<String> = cell
+                        .split("\n\n")
+                        .filter(|p| !p.trim().is_empty())
+                        .map(|p| format!("<p class=\"tableblock\">{}</p>", self.inline(p.trim())))
+                        .collect();
+                    html += &format!(
+                        "<td class=\"tableblock halign-left valign-top\">{}</td>\n",
+                        paragraphs.join("\n")
+                    );
+                }
+            }
+            html += "</tr>\n";
+
+            if header {
+                html += "</thead>\n";
+            }
+        }
+        if rows.len() > usize::from(has_header) {
+            html += "</tbody>\n";
+        }
+        html += "</table>";
+
+        html
+    }
+
+    /// Render a list starting at `lines[*i]`, this moves `i` past the end of the list.
+    fn render_list(
+        &mut self,
+        lines: &[&str],
+        i: &mut usize,
+        marker: &str,
+        parents: &[String],
+        pending: &PendingBlock,
+    ) -> String {
+        let ordered = marker.starts_with('.');
+        let mut items: Vec<String> = Default::default();
+
+        let mut parents_and_marker = parents.to_vec();
+        parents_and_marker.push(marker.to_string());
+
+        while *i < lines.len() {
+            // Skip blank lines, but only keep going if there is another item after them.
+            let mut j = *i;
+            while j < lines.len() && lines[j].trim().is_empty() {
+                j += 1;
+            }
+            let (item_marker, text) = match lines.get(j).and_then(|l| list_marker(l.trim_end())) {
+                Some(v) => v,
+                None => break,
+            };
+
+            if item_marker != marker {
+                if parents.contains(&item_marker) || items.is_empty() {
+                    // The item belongs to a parent list.
+                    break;
+                }
+
+                // A nested list, it belongs to the last item.
+                *i = j;
+                let nested = self.render_list(lines, i, &item_marker, &parents_and_marker, &PendingBlock::default());
+                let last = items.last_mut().unwrap();
+                last.push('\n');
+                last.push_str(&nested);
+                continue;
+            }
+            *i = j + 1;
+
+            // The text of the item can continue on the following lines.
+            let mut text_lines = vec![text];
+            while *i < lines.len() {
+                let t = lines[*i].trim();
+                if t.is_empty()
+                    || t == "+"
+                    || list_marker(t).is_some()
+                    || is_delimiter(t)
+                    || (t.starts_with('[') && t.ends_with(']'))
+                {
+                    break;
+                }
+                text_lines.push(t);
+                *i += 1;
+            }
+            let mut item = format!("<li>\n<p>{}</p>", self.inline(&text_lines.join("\n")));
+
+            // A `+` attaches the next block to the item.
+            while *i < lines.len() && lines[*i].trim() == "+" {
+                *i += 1;
+                let start = *i;
+                // Keep any block attributes and titles with the block.
+                while *i < lines.len() && {
+                    let t = lines[*i].trim_end();
+                    (t.starts_with('[') && t.ends_with(']')) || is_block_title(t)
+                } {
+                    *i += 1;
+                }
+                let t = lines.get(*i).map(|l| l.trim_end()).unwrap_or_default();
+                if is_delimiter(t) || t == "|===" {
+                    *i = find_closing(lines, *i, t).map(|j| j + 1).unwrap_or(lines.len());
+                } else {
+                    while *i < lines.len() && !lines[*i].trim().is_empty() && lines[*i].trim() != "+" {
+                        *i += 1;
+                    }
+                }
+                let block = self.render_blocks(&lines[start..*i], false, false).join("\n");
+                item.push('\n');
+                item.push_str(&block);
+            }
+
+            items.push(item);
+        }
+
+        let items: Vec<String> = items.into_iter().map(|item| format!("{}\n</li>", item)).collect();
+        let title = self.render_title(pending, None);
+        if ordered {
+            let depth = parents_and_marker.iter().filter(|m| m.starts_with('.')).count();
+            let (style, type_) = match depth {
+                1 => ("arabic", ""),
+                2 => ("loweralpha", " type=\"a\""),
+                3 => ("lowerroman", " type=\"i\""),
+                4 => ("upperalpha", " type=\"A\""),
+                _ => ("upperroman", " type=\"I\""),
+            };
+            format!(
+                "<div{} class=\"{}\">\n{}<ol class=\"{}\"{}>\n{}\n</ol>\n</div>",
+                id_attribute(pending),
+                classes(&format!("olist {}", style), pending),
+                title,
+                style,
+                type_,
+                items.join("\n")
+            )
+        } else {
+            format!(
+                "<div{} class=\"{}\">\n{}<ul>\n{}\n</ul>\n</div>",
+                id_attribute(pending),
+                classes("ulist", pending),
+                title,
+                items.join("\n")
+            )
+        }
+    }
+
+    /// Render a description list starting at `lines[*i]`, this moves `i` past the end
+    /// of the list.
+    fn render_dlist(&mut self, lines: &[&str], i: &mut usize, pending: &PendingBlock) -> String {
+        let mut entries: Vec<String> = Default::default();
+
+        while *i < lines.len() {
+            let mut j = *i;
+            while j < lines.len() && lines[j].trim().is_empty() {
+                j += 1;
+            }
+            let (term, text) = match lines.get(j).and_then(|l| dlist_term(l.trim_end())) {
+                Some(v) => v,
+                None => break,
+            };
+            *i = j + 1;
+
+            // The description can start on the same line or the next ones.
+            let mut text_lines: Vec<&str> = Default::default();
+            if !text.is_empty() {
+                text_lines.push(text);
+            }
+            while *i < lines.len() {
+                let t = lines[*i].trim();
+                if t.is_empty() && !text_lines.is_empty() {
+                    break;
+                }
+                if dlist_term(t).is_some() || is_delimiter(t) {
+                    break;
+                }
+                if !t.is_empty() {
+                    text_lines.push(t);
+                }
+                *i += 1;
+            }
+
+            let mut entry = format!("<dt class=\"hdlist1\">{}</dt>", self.inline(term));
+            if !text_lines.is_empty() {
+                entry += &format!("\n<dd>\n<p>{}</p>\n</dd>", self.inline(&text_lines.join("\n")));
+            }
+            entries.push(entry);
+        }
+
+        let title = self.render_title(pending, None);
+        format!(
+            "<div{} class=\"{}\">\n{}<dl>\n{}\n</dl>\n</div>",
+            id_attribute(pending),
+            classes("dlist", pending),
+            title,
+            entries.join("\n")
+        )
+    }
+
+    /// Replace `{name}` with the value of the attribute, leaving anything else alone.
+    fn substitute_attributes(&self, text: &str, depth: usize) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            match after.find('}') {
+                Some(end) if is_attribute_name(&after[..end]) => {
+                    match self.attributes.get(&after[..end]) {
+                        Some(value) if depth < MAX_ATTRIBUTE_DEPTH => {
+                            out.push_str(&self.substitute_attributes(value, depth + 1))
+                        }
+                        _ => out.push_str(&rest[start..start + end + 2]),
+                    }
+                    rest = &after[end + 1..];
+                }
+                _ => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+
+        out
+    }
+
+    /// Render the inline formatting of some text.
+    fn inline(&mut self, text: &str) -> String {
+        if self.depth > MAX_NESTING_DEPTH {
+            return escape(text);
+        }
+
+        // Attributes are replaced first, so their values get formatted too.
+        let text = if self.depth == 0 {
+            self.substitute_attributes(text, 0)
+        } else {
+            text.to_string()
+        };
+
+        self.depth += 1;
+        let html = self.inline_inner(&text);
+        self.depth -= 1;
+
+        html
+    }
+
+    fn inline_inner(&mut self, s: &str) -> String {
+        let mut out = String::new();
+        let mut i = 0;
+
+        while i < s.len() {
+            let rest = &s[i..];
+            let prev = s[..i].chars().next_back();
+            let c = rest.chars().next().unwrap();
+
+            // Hard line breaks.
+            if rest == " +" || rest.starts_with(" +\n") {
+                out.push_str("<br>");
+                i += 2;
+                continue;
+            }
+
+            // Escaped markup.
+            if c == '\\' {
+                if let Some(next) = rest[1..].chars().next() {
+                    if "*_`{<[+#\\".contains(next) || rest[1..].starts_with("http") {
+                        out.push_str(&escape(&next.to_string()));
+                        i += 1 + next.len_utf8();
+                        continue;
+                    }
+                }
+            }
+
+            // Passthroughs.
+            if let Some(inner) = rest.strip_prefix("+++") {
+                if let Some(end) = inner.find("+++") {
+                    out.push_str(&inner[..end]);
+                    i += end + 6;
+                    continue;
+                }
+            }
+            if let Some(inner) = rest.strip_prefix("pass:[") {
+                if let Some(end) = inner.find(']') {
+                    out.push_str(&inner[..end]);
+                    i += end + 7;
+                    continue;
+                }
+            }
+
+            // Monospace, the contents are not formatted.
+            if let Some(end) = unconstrained(rest, "``") {
+                out.push_str(&format!("<code>{}</code>", escape(&rest[2..end])));
+                i += end + 2;
+                continue;
+            }
+            if let Some(end) = constrained(rest, prev, '`') {
+                out.push_str(&format!("<code>{}</code>", escape(&rest[1..end])));
+                i += end + 1;
+                continue;
+            }
+
+            // Bold and italic.
+            if let Some(end) = unconstrained(rest, "**") {
+                let inner = self.inline(&rest[2..end]);
+                out.push_str(&format!("<strong>{}</strong>", inner));
+                i += end + 2;
+                continue;
+            }
+            if let Some(end) = constrained(rest, prev, '*') {
+                let inner = self.inline(&rest[1..end]);
+                out.push_str(&format!("<strong>{}</strong>", inner));
+                i += end + 1;
+                continue;
+            }
+            if let Some(end) = unconstrained(rest, "__") {
+                let inner = self.inline(&rest[2..end]);
+                out.push_str(&format!("<em>{}</em>", inner));
+                i += end + 2;
+                continue;
+            }
+            if let Some(end) = constrained(rest, prev, '_') {
+                let inner = self.inline(&rest[1..end]);
+                out.push_str(&format!("<em>{}</em>", inner));
+                i += end + 1;
+                continue;
+            }
+
+            // Cross references.
+            if let Some(inner) = rest.strip_prefix("<<") {
+                if let Some(end) = inner.find(">>") {
+                    let (target, text) = match inner[..end].split_once(',') {
+                        Some((target, text)) => (target.trim(), Some(text.trim())),
+                        None => (inner[..end].trim(), None),
+                    };
+                    if !target.is_empty() && !target.contains(char::is_whitespace) {
+                        out.push_str(&self.xref(target, text));
+                        i += end + 4;
+                        continue;
+                    }
+                }
+            }
+            if let Some(inner) = rest.strip_prefix("xref:") {
+                if let Some((target, text)) = parse_macro(inner) {
+                    let text = if text.is_empty() { None } else { Some(text.as_str()) };
+                    out.push_str(&self.xref(&target, text));
+                    i += 5 + target.len() + text.map(|t| t.len()).unwrap_or_default() + 2;
+                    continue;
+                }
+            }
+
+            // Inline anchors.
+            if let Some(inner) = rest.strip_prefix("[[") {
+                if let Some(end) = inner.find("]]") {
+                    let id = &inner[..end];
+                    if is_id(id) {
+                        out.push_str(&format!("<a id=\"{}\"></a>", id));
+                        i += end + 4;
+                        continue;
+                    }
+                }
+            }
+
+            // Footnotes.
+            if let Some(inner) = rest.strip_prefix("footnote:[") {
+                if let Some(end) = closing_bracket(inner) {
+                    let text = self.inline(&inner[..end]);
+                    self.footnotes.push(text);
+                    let n = self.footnotes.len();
+                    out.push_str(&format!(
+                        "<sup class=\"footnote\">[<a id=\"_footnoteref_{n}\" class=\"footnote\" href=\"#_footnotedef_{n}\" title=\"View footnote.\">{n}</a>]</sup>",
+                        n = n
+                    ));
+                    i += end + 11;
+                    continue;
+                }
+            }
+
+            // Inline images.
+            if let Some(inner) = rest.strip_prefix("image:") {
+                if !inner.starts_with(':') {
+                    if let Some((target, attrs)) = parse_macro(inner) {
+                        let len = target.len() + attrs.len() + 2;
+                        let attrs = BlockAttributes::parse(&attrs);
+                        out.push_str(&format!("<span class=\"image\">{}</span>", img_tag(&target, &attrs)));
+                        i += 6 + len;
+                        continue;
+                    }
+                }
+            }
+
+            // Links.
+            if let Some(inner) = rest.strip_prefix("link:") {
+                if let Some((target, text)) = parse_macro(inner) {
+                    out.push_str(&self.link(&target, &text, false));
+                    i += 5 + target.len() + text.len() + 2;
+                    continue;
+                }
+            }
+            if (rest.starts_with("https://") || rest.starts_with("http://") || rest.starts_with("mailto:"))
+                && !prev
+                    .map(|p| p.is_alphanumeric() || p == '"' || p == '\'' || p == '=')
+                    .unwrap_or(false)
+            {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '[' || c == '<' || c == '>')
+                    .unwrap_or(rest.len());
+                let mut url = &rest[..end];
+                if rest[end..].starts_with('[') {
+                    if let Some(close) = closing_bracket(&rest[end + 1..]) {
+                        let text = &rest[end + 1..end + 1 + close];
+                        out.push_str(&self.link(url, text, false));
+                        i += end + close + 2;
+                        continue;
+                    }
+                }
+                // Trailing punctuation is not part of a bare URL.
+                url = url.trim_end_matches(|c: char| ".,;:!?)".contains(c));
+                if url.contains("://") && url.len() > "https://".len() || url.starts_with("mailto:") {
+                    out.push_str(&self.link(url, "", true));
+                    i += url.len();
+                    continue;
+                }
+            }
+
+            // Typographic replacements.
+            if rest.starts_with(" -- ") {
+                out.push_str("&#8201;&#8212;&#8201;");
+                i += 4;
+                continue;
+            }
+            if rest.starts_with("--")
+                && prev.map(|p| p.is_alphanumeric()).unwrap_or(false)
+                && rest[2..].chars().next().map(|n| n.is_alphanumeric()).unwrap_or(false)
+            {
+                out.push_str("&#8212;&#8203;");
+                i += 2;
+                continue;
+            }
+            let replacements: &[(&str, &str)] = &[
+                ("...", "&#8230;&#8203;"),
+                ("->", "&#8594;"),
+                ("=>", "&#8658;"),
+                ("<-", "&#8592;"),
+                ("<=", "&#8656;"),
+                ("(C)", "&#169;"),
+                ("(R)", "&#174;"),
+                ("(TM)", "&#8482;"),
+            ];
+            if let Some((from, to)) = replacements.iter().find(|(from, _)| rest.starts_with(from)) {
+                out.push_str(to);
+                i += from.len();
+                continue;
+            }
+            if c == '\''
+                && prev.map(|p| p.is_alphanumeric()).unwrap_or(false)
+                && rest[1..].chars().next().map(|n| n.is_alphanumeric()).unwrap_or(false)
+            {
+                out.push_str("&#8217;");
+                i += 1;
+                continue;
+            }
+
+            out.push_str(&escape(&c.to_string()));
+            i += c.len_utf8();
+        }
+
+        out
+    }
+
+    fn xref(&mut self, target: &str, text: Option<&str>) -> String {
+        // References to other documents look like `other.adoc#id`.
+        let (doc, id) = match target.split_once('#') {
+            Some((doc, id)) => (doc, id),
+            None => ("", target),
+        };
+
+        // A reference can use the title of a section instead of its id.
+        let id = if doc.is_empty() && !self.ids.contains_key(id) {
+            self.ids
+                .iter()
+                .find(|(_, title)| title.as_deref() == Some(id))
+                .map(|(id, _)| id.to_string())
+                .unwrap_or_else(|| id.to_string())
+        } else {
+            id.to_string()
+        };
+
+        let href = if doc.is_empty() {
+            format!("#{}", id)
+        } else {
+            format!("{}#{}", doc.replace(".adoc", ".html"), id)
+        };
+
+        let text = match text {
+            Some(text) => self.inline(text),
+            None => match self.ids.get(&id).cloned().flatten() {
+                Some(title) => self.inline(&title),
+                None => format!("[{}]", escape(&id)),
+            },
+        };
+
+        format!("<a href=\"{}\">{}</a>", escape(&href), text)
+    }
+
+    fn link(&mut self, target: &str, text: &str, bare: bool) -> String {
+        let mut text = text.trim();
+        let mut attrs = String::new();
+
+        // A `^` at the end of the text, or `window=_blank`, opens the link in a new window.
+        let mut new_window = false;
+        if let Some(t) = text.strip_suffix('^') {
+            text = t;
+            new_window = true;
+        }
+        if let Some((t, rest)) = text.split_once(",window=") {
+            text = t;
+            new_window = rest.trim_matches('"') == "_blank";
+        }
+        if new_window {
+            attrs = " target=\"_blank\" rel=\"noopener\"".to_string();
+        }
+
+        let text = unquote(text);
+        if text.is_empty() {
+            let display = target.strip_prefix("mailto:").unwrap_or(target);
+            format!(
+                "<a href=\"{}\" class=\"bare\"{}>{}</a>",
+                escape(target),
+                attrs,
+                escape(if bare { target } else { display })
+            )
+        } else {
+            format!("<a href=\"{}\"{}>{}</a>", escape(target), attrs, self.inline(text))
+        }
+    }
+}
+
+fn close_section(level: usize) -> String {
+    if level == 1 {
+        "</div>\n</div>".to_string()
+    } else {
+        "</div>".to_string()
+    }
+}
+
+fn id_attribute(pending: &PendingBlock) -> String {
+    match pending.id() {
+        Some(id) => format!(" id=\"{}\"", escape(&id)),
+        None => String::new(),
+    }
+}
+
+fn classes(class: &str, pending: &PendingBlock) -> String {
+    let mut classes = vec![class.to_string()];
+    classes.extend(pending.attributes.roles.iter().cloned());
+    classes.join(" ")
+}
+
+fn img_tag(target: &str, attrs: &BlockAttributes) -> String {
+    // The alt text defaults to the file name without the extension.
+    let alt = attrs.style.clone().unwrap_or_else(|| {
+        let name = target.rsplit('/').next().unwrap_or(target);
+        let stem = name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name);
+        stem.replace(['-', '_'], " ")
+    });
+
+    let mut tag = format!("<img src=\"{}\" alt=\"{}\"", escape(target), escape(&alt));
+    let width = attrs.named.get("width").or_else(|| attrs.positional.first());
+    if let Some(width) = width {
+        tag += &format!(" width=\"{}\"", escape(width));
+    }
+    let height = attrs.named.get("height").or_else(|| attrs.positional.get(1));
+    if let Some(height) = height {
+        tag += &format!(" height=\"{}\"", escape(height));
+    }
+    tag.push('>');
+
+    tag
+}
+
+fn strip_xml_prolog(svg: &str) -> &str {
+    match svg.find("<svg") {
+        Some(start) => svg[start..].trim_end(),
+        None => svg.trim(),
+    }
+}
+
+/// Get the source of the mermaid diagrams in rendered HTML, in order. We render
+/// them as literal blocks, so there is something to read when they can't be drawn.
+pub fn mermaid_diagrams(html: &str) -> Vec<String> {
+    let re = Regex::new(r#"(?s)<pre class="mermaid">(.*?)</pre>"#).unwrap();
+    re.captures_iter(html).map(|cap| unescape(&cap[1])).collect()
+}
+
+/// Replace the mermaid diagrams in rendered HTML with the images they were drawn
+/// as, in the order of `mermaid_diagrams`. Diagrams without an image stay as they are.
+pub fn replace_mermaid_diagrams(html: &str, images: &[Option<String>]) -> String {
+    let re = Regex::new(r#"(?s)<pre class="mermaid">(.*?)</pre>"#).unwrap();
+    let mut i = 0;
+    re.replace_all(html, |cap: &Captures| {
+        let replacement = match images.get(i) {
+            Some(Some(image)) => format!("<img src=\"{}\" alt=\"Diagram\">", escape(image)),
+            _ => cap[0].to_string(),
+        };
+        i += 1;
+        replacement
+    })
+    .to_string()
+}
+
+/// Put the language of source blocks on their `pre` too, like `<pre class="highlight
+/// rust">`. That is the only place pandoc looks for it when it highlights them.
+pub fn with_source_languages(html: &str) -> String {
+    let re = Regex::new(r#"<pre class="highlight"><code class="language-([^"]+)""#).unwrap();
+    re.replace_all(html, r#"<pre class="highlight $1"><code class="language-$1""#)
+        .to_string()
+}
+
+/// Escape the characters that are special in HTML.
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+/// Strip the characters used for inline formatting, for generating ids.
+fn strip_inline_markup(s: &str) -> String {
+    s.chars().filter(|c| !"*_`#".contains(*c)).collect()
+}
+
+fn unquote(s: &str) -> &str {
+    if s.len() >= 2 && ((s.starts_with('"') && s.ends_with('"')) || (s.starts_with('\'') && s.ends_with('\''))) {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    }
+}
+
+/// Split the attributes in a `[...]` on commas that are not in quotes.
+fn split_attributes(s: &str) -> Vec<String> {
+    let mut parts: Vec<String> = Default::default();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+
+    for c in s.chars() {
+        match quote {
+            Some(q) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            Some(_) => current.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                current.push(c);
+            }
+            None if c == ',' => {
+                parts.push(current.trim().to_string());
+                current = String::new();
+            }
+            None => current.push(c),
+        }
+    }
+    if !current.trim().is_empty() || !parts.is_empty() {
+        parts.push(current.trim().to_string());
+    }
+
+    parts
+}
+
+/// Parse a macro like `target[text]`, returning the target and the text.
+fn parse_macro(s: &str) -> Option<(String, String)> {
+    let open = s.find('[')?;
+    let target = &s[..open];
+    if target.is_empty() || target.contains(char::is_whitespace) {
+        return None;
+    }
+    let close = closing_bracket(&s[open + 1..])?;
+
+    Some((target.to_string(), s[open + 1..open + 1 + close].to_string()))
+}
+
+/// Find the `]` that closes the text, skipping over nested brackets.
+fn closing_bracket(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => return Some(i),
+            ']' => depth -= 1,
+            '\n' if depth == 0 && s[i + 1..].starts_with('\n') => return None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Find the end of constrained formatting like `*bold*` at the start of `s`. The mark
+/// has to be at the start of a word and the closing mark at the end of one.
+fn constrained(s: &str, prev: Option<char>, mark: char) -> Option<usize> {
+    if !s.starts_with(mark) {
+        return None;
+    }
+    if let Some(p) = prev {
+        if p.is_alphanumeric() || p == mark || ";:}".contains(p) {
+            return None;
+        }
+    }
+    let first = s[1..].chars().next()?;
+    if first.is_whitespace() || first == mark {
+        return None;
+    }
+
+    for (j, c) in s.char_indices().skip(1) {
+        if c == '\n' && s[j + 1..].starts_with('\n') {
+            return None;
+        }
+        if c != mark || j < 2 {
+            continue;
+        }
+        let before = s[..j].chars().next_back()?;
+        let after = s[j + 1..].chars().next();
+        if !before.is_whitespace() && !after.map(|a| a.is_alphanumeric() || a == mark).unwrap_or(false) {
+            return Some(j);
+        }
+    }
+
+    None
+}
+
+/// Find the end of unconstrained formatting like `**bold**` at the start of `s`.
+fn unconstrained(s: &str, mark: &str) -> Option<usize> {
+    let inner = s.strip_prefix(mark)?;
+    let end = inner.find(mark)?;
+    if end == 0 {
+        return None;
+    }
+
+    Some(end + mark.len())
+}
+
+fn is_attribute_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+fn is_id(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.' || c == ':')
+}
+
+fn is_line_comment(line: &str) -> bool {
+    line.starts_with("//") && !line.starts_with("///")
+}
+
+fn is_block_title(line: &str) -> bool {
+    line.len() > 1 && line.starts_with('.') && !line[1..].starts_with(|c: char| c == '.' || c.is_whitespace())
+}
+
+/// Get the id from a block anchor line, either `[[id]]` or `[#id]`.
+fn block_anchor(line: &str) -> Option<String> {
+    if let Some(inner) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+        let id = inner.split(',').next().unwrap_or_default();
+        if is_id(id) {
+            return Some(id.to_string());
+        }
+    }
+    if let Some(inner) = line.strip_prefix("[#").and_then(|l| l.strip_suffix(']')) {
+        let id = inner.split(['.', '%', ',']).next().unwrap_or_default();
+        if is_id(id) {
+            return Some(id.to_string());
+        }
+    }
+
+    None
+}
+
+/// Get the level and title of a section title line like `== Title`.
+fn section_title(line: &str) -> Option<(usize, &str)> {
+    let marks = line.chars().take_while(|c| *c == '=').count();
+    if !(2..=6).contains(&marks) {
+        return None;
+    }
+    let title = line[marks..].strip_prefix(' ')?.trim();
+    if title.is_empty() {
+        return None;
+    }
+
+    Some((marks - 1, title))
+}
+
+/// Check if the line opens a delimited block.
+fn is_delimiter(line: &str) -> bool {
+    if line == "--" {
+        return true;
+    }
+    if line.len() < 4 {
+        return false;
+    }
+    let first = line.chars().next().unwrap();
+    "-.=*_+/".contains(first) && line.chars().all(|c| c == first)
+}
+
+fn find_closing(lines: &[&str], start: usize, delimiter: &str) -> Option<usize> {
+    (start + 1..lines.len()).find(|j| lines[*j].trim_end() == delimiter)
+}
+
+/// Get the marker and the text of a list item, `**` for a nested unordered list or `.`
+/// for an ordered one.
+fn list_marker(line: &str) -> Option<(String, &str)> {
+    let t = line.trim_start();
+
+    if let Some(text) = t.strip_prefix("- ") {
+        return Some(("-".to_string(), text.trim()));
+    }
+    for mark in &['*', '.'] {
+        let n = t.chars().take_while(|c| c == mark).count();
+        if (1..=5).contains(&n) {
+            if let Some(text) = t[n..].strip_prefix(' ') {
+                if !text.trim().is_empty() {
+                    return Some((mark.to_string().repeat(n), text.trim()));
+                }
+            }
+        }
+    }
+
+    // Explicitly numbered items, like `1. `, are the same as `.`.
+    let digits = t.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        if let Some(text) = t[digits..].strip_prefix(". ") {
+            return Some((".".to_string(), text.trim()));
+        }
+    }
+
+    None
+}
+
+/// Get the term and the text after it for a description list line like `term:: text`.
+fn dlist_term(line: &str) -> Option<(&str, &str)> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let idx = line.find("::")?;
+    let term = &line[..idx];
+    let rest = &line[idx + 2..];
+    if term.trim().is_empty() || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+
+    Some((term.trim(), rest.trim()))
+}
+
+/// Split a table row into its cells, a `\|` is part of the cell.
+fn split_cells(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = match line.strip_prefix('|') {
+        Some(line) => line,
+        None => return vec![],
+    };
+
+    let mut cells = vec![String::new()];
+    let mut escaped = false;
+    for c in line.chars() {
+        if escaped {
+            if c != '|' {
+                cells.last_mut().unwrap().push('\\');
+            }
+            cells.last_mut().unwrap().push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '|' {
+            cells.push(String::new());
+        } else {
+            cells.last_mut().unwrap().push(c);
+        }
+    }
+    if escaped {
+        cells.last_mut().unwrap().push('\\');
+    }
+
+    cells.into_iter().map(|c| c.trim().to_string()).collect()
+}
+
+/// Remove the common indentation from lines.
+fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or_default();
+
+    lines
+        .iter()
+        .map(|l| {
+            if l.len() >= indent {
+                &l[indent..]
+            } else {
+                l.trim_start()
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+fn format_percent(p: f64) -> String {
+    let s = format!("{:.4}", p);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_str(content: &str) -> String {
+        render(content, &Default::default())
+    }
+
+    #[test]
+    fn test_inline_formatting() {
+        assert_eq!(
+            render_str("Some *bold*, _italic_ and `mono_space*` text."),
+            "<div class=\"paragraph\">\n<p>Some <strong>bold</strong>, <em>italic</em> and <code>mono_space*</code> text.</p>\n</div>"
+        );
+        assert_eq!(
+            render_str("snake_case_name and 2*3*4 stay & <as is>"),
+            "<div class=\"paragraph\">\n<p>snake_case_name and 2*3*4 stay &amp; &lt;as is&gt;</p>\n</div>"
+        );
+    }
+
+    #[test]
+    fn test_title() {
+        assert_eq!(title("No title here.\n"), None);
+        assert_eq!(
+            title(":showtitle:\n= RFD 1 Requests for Discussion\n{authors}\n"),
+            Some("RFD 1 Requests for Discussion".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mermaid_diagrams() {
+        let html = render_str("[mermaid]\n----\ngraph TD; A-->B;\n----\n\n[mermaid]\n----\ngraph LR; C-->D;\n----\n");
+        assert_eq!(
+            mermaid_diagrams(&html),
+            vec!["graph TD; A-->B;".to_string(), "graph LR; C-->D;".to_string()]
+        );
+
+        // The diagrams we could not draw keep their source.
+        let replaced = replace_mermaid_diagrams(&html, &[None, Some("mermaid-1.svg".to_string())]);
+        assert!(replaced.contains("<pre class=\"mermaid\">graph TD; A--&gt;B;</pre>"));
+        assert!(replaced.contains("<img src=\"mermaid-1.svg\" alt=\"Diagram\">"));
+        assert!(!replaced.contains("C--&gt;D"));
+    }
+
+    #[test]
+    fn test_with_source_languages() {
+        let html = render_str("[source,rust]\n----\nfn main() {}\n----\n\n----\nplain\n----\n");
+        let html = with_source_languages(&html);
+        assert!(html.contains("<pre class=\"highlight rust\"><code class=\"language-rust\" data-lang=\"rust\">"));
+        assert!(html.contains("<pre>plain</pre>"));
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use async_trait::async_trait;
+use chrono::{naive::NaiveDate, DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use log::{info, warn};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    airtable::AIRTABLE_HOLIDAYS_TABLE,
+    companies::Company,
+    configs::{Building, Buildings},
+    core::UpdateAirtableRecord,
+    db::Database,
+    schema::holidays,
+    shipments::clean_carrier_name,
+};
+
+/// The timezone of a building that does not set one.
+const DEFAULT_TIMEZONE: &str = "America/Los_Angeles";
+
+/// The pickup window of a building that does not set one.
+const DEFAULT_PICKUP_WINDOW_START: &str = "09:00";
+const DEFAULT_PICKUP_WINDOW_END: &str = "17:00";
+
+/// A day we do not ship or pick up packages.
+#[db {
+    new_struct_name = "Holiday",
+    airtable_base = "directory",
+    airtable_table = "AIRTABLE_HOLIDAYS_TABLE",
+    match_on = {
+        "cio_company_id" = "i32",
+        "date" = "NaiveDate",
+        "building" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[diesel(table_name = holidays)]
+pub struct NewHoliday {
+    pub name: String,
+    pub date: NaiveDate,
+    /// The building the holiday is for, or empty if it is for all of the company's
+    /// buildings.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub building: String,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// Implement updating the Airtable record for a Holiday.
+#[async_trait]
+impl UpdateAirtableRecord<Holiday> for Holiday {
+    async fn update_airtable_record(&mut self, _record: Holiday) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Parse a time of day like `15:00`.
+fn parse_time(s: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").map_err(|e| anyhow!("invalid time `{}`: {}", s, e))
+}
+
+/// Parse a carrier cutoff like `USPS 15:00`, the last time we can hand packages to
+/// the carrier for them to ship that day.
+pub fn parse_carrier_cutoff(s: &str) -> Result<(String, NaiveTime)> {
+    let (carrier, time) = match s.trim().rsplit_once(' ') {
+        Some(v) => v,
+        None => bail!("carrier cutoff `{}` should look like `USPS 15:00`", s),
+    };
+
+    Ok((clean_carrier_name(carrier.trim()), parse_time(time)?))
+}
+
+/// Parse the dates of the all day events in an iCalendar feed.
+pub fn parse_ical_dates(ical: &str) -> Vec<(NaiveDate, String)> {
+    let mut dates: Vec<(NaiveDate, String)> = Default::default();
+
+    let mut date: Option<NaiveDate> = None;
+    let mut summary = String::new();
+    // Lines that start with a space continue the line before, unfold them first.
+    for line in ical.replace("\r\n", "\n").replace("\n ", "").lines() {
+        if line == "BEGIN:VEVENT" {
+            date = None;
+            summary = String::new();
+        } else if line == "END:VEVENT" {
+            if let Some(d) = date.take() {
+                dates.push((d, summary.to_string()));
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            if key.starts_with("DTSTART") {
+                // Only all day events are holidays, those are just a date.
+                let value = value.trim();
+                if value.len() == 8 {
+                    date = NaiveDate::parse_from_str(value, "%Y%m%d").ok();
+                }
+            } else if key == "SUMMARY" {
+                summary = value.trim().replace("\\,", ",");
+            }
+        }
+    }
+
+    dates
+}
+
+/// The days and times a building ships and receives packages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusinessCalendar {
+    pub timezone: Tz,
+    pub holidays: BTreeSet<NaiveDate>,
+    pub pickup_window_start: NaiveTime,
+    pub pickup_window_end: NaiveTime,
+    /// The last time, for each carrier, we can hand them packages to ship that day.
+    pub carrier_cutoffs: BTreeMap<String, NaiveTime>,
+}
+
+impl Default for BusinessCalendar {
+    fn default() -> Self {
+        BusinessCalendar {
+            timezone: chrono_tz::US::Pacific,
+            holidays: Default::default(),
+            pickup_window_start: parse_time(DEFAULT_PICKUP_WINDOW_START).unwrap(),
+            pickup_window_end: parse_time(DEFAULT_PICKUP_WINDOW_END).unwrap(),
+            carrier_cutoffs: Default::default(),
+        }
+    }
+}
+
+impl BusinessCalendar {
+    /// Create the calendar for a building from its config and the holidays.
+    pub fn new(building: &Building, holidays: &[Holiday]) -> Result<Self> {
+        let or_default = |s: &str, default: &str| {
+            if s.trim().is_empty() {
+                default.to_string()
+            } else {
+                s.to_string()
+            }
+        };
+
+        let timezone: Tz = or_default(&building.timezone, DEFAULT_TIMEZONE)
+            .parse()
+            .map_err(|e| anyhow!("invalid timezone for building `{}`: {}", building.name, e))?;
+
+        let mut carrier_cutoffs: BTreeMap<String, NaiveTime> = Default::default();
+        for cutoff in &building.carrier_cutoffs {
+            let (carrier, time) = parse_carrier_cutoff(cutoff)?;
+            carrier_cutoffs.insert(carrier, time);
+        }
+
+        Ok(BusinessCalendar {
+            timezone,
+            holidays: holidays
+                .iter()
+                .filter(|h| h.building.is_empty() || h.building == building.name)
+                .map(|h| h.date)
+                .collect(),
+            pickup_window_start: parse_time(&or_default(&building.pickup_window_start, DEFAULT_PICKUP_WINDOW_START))?,
+            pickup_window_end: parse_time(&or_default(&building.pickup_window_end, DEFAULT_PICKUP_WINDOW_END))?,
+            carrier_cutoffs,
+        })
+    }
+
+    /// Get the calendar for the company's HQ, where we ship from.
+    pub async fn for_hq(db: &Database, company: &Company) -> Result<Self> {
+        let building = company.hq_building(db).await?;
+        let holidays: Vec<Holiday> = Holidays::get_from_db(db, company.id).await?.into();
+
+        BusinessCalendar::new(&building, &holidays)
+    }
+
+    /// Returns if we ship packages on a day.
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        date.weekday() != Weekday::Sat && date.weekday() != Weekday::Sun && !self.holidays.contains(&date)
+    }
+
+    /// The first business day after a day.
+    pub fn next_business_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut next = date + Duration::days(1);
+        while !self.is_business_day(next) {
+            next += Duration::days(1);
+        }
+
+        next
+    }
+
+    /// The business day a number of business days after a day.
+    pub fn add_business_days(&self, date: NaiveDate, days: i64) -> NaiveDate {
+        let mut date = date;
+        for _ in 0..days {
+            date = self.next_business_day(date);
+        }
+
+        date
+    }
+
+    /// The day it is now for the building.
+    pub fn today(&self, now: DateTime<Utc>) -> NaiveDate {
+        now.with_timezone(&self.timezone).date().naive_local()
+    }
+
+    fn at(&self, date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+        // Around daylight saving changes a local time can be ambiguous, pick the
+        // earliest.
+        self.timezone
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .unwrap_or_else(|| self.timezone.from_utc_datetime(&date.and_time(time)))
+            .with_timezone(&Utc)
+    }
+
+    /// The pickup window on the next business day.
+    pub fn next_pickup_window(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let day = self.next_business_day(self.today(now));
+
+        (
+            self.at(day, self.pickup_window_start),
+            self.at(day, self.pickup_window_end),
+        )
+    }
+
+    /// The day a package for a carrier ships, today if it is a business day and it
+    /// is before the carrier's cutoff, otherwise the next business day.
+    pub fn ship_date(&self, carrier: &str, now: DateTime<Utc>) -> NaiveDate {
+        let today = self.today(now);
+        let cutoff = self
+            .carrier_cutoffs
+            .get(&clean_carrier_name(carrier))
+            .copied()
+            .unwrap_or(self.pickup_window_end);
+
+        if self.is_business_day(today) && now < self.at(today, cutoff) {
+            today
+        } else {
+            self.next_business_day(today)
+        }
+    }
+
+    /// Estimate when a package will be delivered, from the number of business days
+    /// the carrier takes to deliver it.
+    pub fn estimate_delivery(&self, carrier: &str, now: DateTime<Utc>, transit_days: i64) -> DateTime<Utc> {
+        let day = self.add_business_days(self.ship_date(carrier, now), transit_days);
+
+        self.at(day, self.pickup_window_end)
+    }
+}
+
+/// Sync the holidays from the configs repo, and the iCalendar feeds of the buildings.
+pub async fn sync_holidays(db: &Database, holidays: BTreeMap<String, NewHoliday>, company: &Company) -> Result<()> {
+    let mut all: Vec<NewHoliday> = holidays.into_values().collect();
+
+    let buildings: Vec<Building> = Buildings::get_from_db(db, company.id).await?.into();
+    for building in buildings {
+        if building.holidays_ical_url.is_empty() {
+            continue;
+        }
+
+        let ical = match get_ical_feed(&building.holidays_ical_url).await {
+            Ok(ical) => ical,
+            Err(e) => {
+                // Keep the holidays we already have, rather than removing them.
+                warn!(
+                    "getting the holidays feed for building `{}` failed: {}",
+                    building.name, e
+                );
+                for h in Holidays::get_from_db(db, company.id).await? {
+                    if h.building == building.name {
+                        all.push(h.into());
+                    }
+                }
+                continue;
+            }
+        };
+
+        for (date, name) in parse_ical_dates(&ical) {
+            all.push(NewHoliday {
+                name,
+                date,
+                building: building.name.to_string(),
+                cio_company_id: company.id,
+            });
+        }
+    }
+
+    // Get all the holidays.
+    let mut holiday_map: BTreeMap<(NaiveDate, String), Holiday> = Default::default();
+    for h in Holidays::get_from_db(db, company.id).await? {
+        holiday_map.insert((h.date, h.building.to_string()), h);
+    }
+
+    // Sync holidays.
+    for mut holiday in all {
+        holiday.cio_company_id = company.id;
+        holiday.upsert(db).await?;
+
+        // Remove the holiday from the BTreeMap.
+        holiday_map.remove(&(holiday.date, holiday.building.to_string()));
+    }
+
+    // Remove any holidays that should no longer be in the database.
+    for (_, holiday) in holiday_map {
+        info!("deleting holiday {} {} from the database", holiday.date, holiday.name);
+        holiday.delete(db).await?;
+    }
+    info!("updated configs holidays in the database");
+
+    // Update holidays in airtable.
+    Holidays::get_from_db(db, company.id).await?.update_airtable(db).await?;
+
+    Ok(())
+}
+
+async fn get_ical_feed(url: &str) -> Result<String> {
+    let resp = reqwest::get(url).await?;
+    match resp.status() {
+        reqwest::StatusCode::OK => Ok(resp.text().await?),
+        s => bail!("getting `{}` status_code: {}", url, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::{parse_carrier_cutoff, parse_ical_dates, parse_time, BusinessCalendar};
+
+    #[test]
+    fn test_business_calendar() {
+        let mut calendar = BusinessCalendar::default();
+        // Thanksgiving and the day after.
+        calendar.holidays.insert(NaiveDate::from_ymd(2021, 11, 25));
+        calendar.holidays.insert(NaiveDate::from_ymd(2021, 11, 26));
+        let (carrier, cutoff) = parse_carrier_cutoff("usps 15:00").unwrap();
+        assert_eq!(carrier, "USPS");
+        calendar.carrier_cutoffs.insert(carrier, cutoff);
+
+        // Wednesday afternoon in California, the next pickup skips the holidays and
+        // the weekend.
+        let now = Utc.ymd(2021, 11, 24).and_hms(23, 30, 0);
+        let (start, end) = calendar.next_pickup_window(now);
+        assert_eq!(start, Utc.ymd(2021, 11, 29).and_hms(17, 0, 0));
+        assert_eq!(end, Utc.ymd(2021, 11, 30).and_hms(1, 0, 0));
+
+        // It is after the USPS cutoff, but before the end of the day for UPS.
+        assert_eq!(calendar.ship_date("USPS", now), NaiveDate::from_ymd(2021, 11, 29));
+        assert_eq!(calendar.ship_date("UPS", now), NaiveDate::from_ymd(2021, 11, 24));
+        assert_eq!(
+            calendar.estimate_delivery("UPS", now, 2),
+            Utc.ymd(2021, 12, 1).and_hms(1, 0, 0)
+        );
+
+        assert!(parse_time("25:00").is_err());
+        assert!(parse_carrier_cutoff("USPS").is_err());
+    }
+
+    #[test]
+    fn test_parse_ical_dates() {
+        let ical = [
+            "BEGIN:VCALENDAR",
+            "BEGIN:VEVENT",
+            "DTSTART;VALUE=DATE:20211225",
+            "SUMMARY:Christmas Day",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "DTSTART:20211231T090000Z",
+            "SUMMARY:Party",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "DTSTART;VALUE=DATE:20220101",
+            "SUMMARY:New Year\\, ",
+            " again",
+            "END:VEVENT",
+            "END:VCALENDAR",
+        ]
+        .join("\r\n");
+
+        assert_eq!(
+            parse_ical_dates(&ical),
+            vec![
+                (NaiveDate::from_ymd(2021, 12, 25), "Christmas Day".to_string()),
+                (NaiveDate::from_ymd(2022, 1, 1), "New Year, again".to_string()),
+            ]
+        );
+    }
+}
@@ -3,20 +3,22 @@ use std::{
     collections::BTreeMap,
     env,
     path::{Path, PathBuf},
-    str::from_utf8,
+    str::{from_utf8, FromStr},
     time,
 };
 
-use acme_lib::{create_p384_key, persist::FilePersist, Directory, DirectoryUrl};
-use anyhow::Result;
+use acme_lib::{create_p256_key, create_p384_key, persist::FilePersist, Directory, DirectoryUrl};
+use anyhow::{bail, Result};
 use async_bb8_diesel::AsyncRunQueryDsl;
 use async_trait::async_trait;
 use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use chrono_humanize::HumanTime;
-use cloudflare::endpoints::dns;
 use log::info;
 use macros::db;
-use openssl::x509::X509;
+use openssl::{
+    pkey::{PKey, Private},
+    x509::X509,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use slack_chat_api::{
@@ -29,11 +31,56 @@ use crate::{
     companies::Company,
     core::UpdateAirtableRecord,
     db::Database,
-    dns_providers::DNSProviderOps,
+    dns_providers::{wait_for_record, DnsContent},
     schema::certificates,
     utils::{create_or_update_file_in_github_repo, get_file_content_from_repo},
 };
 
+/// How long to wait for the challenge TXT record to be on all the authoritative name
+/// servers for a domain before giving up.
+const DNS_PROPAGATION_TIMEOUT: time::Duration = time::Duration::from_secs(300);
+
+/// The types of private keys we can create for a certificate.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CertificateKeyType {
+    /// ECDSA with the P-256 curve.
+    P256,
+    /// ECDSA with the P-384 curve.
+    P384,
+}
+
+impl ToString for CertificateKeyType {
+    fn to_string(&self) -> String {
+        match self {
+            CertificateKeyType::P256 => "p256".to_string(),
+            CertificateKeyType::P384 => "p384".to_string(),
+        }
+    }
+}
+
+impl FromStr for CertificateKeyType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            // Certificates that were created before we supported other keys use P-384.
+            "" | "p384" => Ok(CertificateKeyType::P384),
+            "p256" => Ok(CertificateKeyType::P256),
+            _ => bail!("unknown certificate key type `{}`", s),
+        }
+    }
+}
+
+impl CertificateKeyType {
+    /// Create a new private key of this type.
+    pub fn create_key(&self) -> PKey<Private> {
+        match self {
+            CertificateKeyType::P256 => create_p256_key(),
+            CertificateKeyType::P384 => create_p384_key(),
+        }
+    }
+}
+
 /// A data type to hold the values of a let's encrypt certificate for a domain.
 #[db {
     new_struct_name = "Certificate",
@@ -48,6 +95,14 @@ use crate::{
 #[diesel(table_name = certificates)]
 pub struct NewCertificate {
     pub domain: String,
+    /// Other domains the certificate is also valid for, these are requested in the same
+    /// order as `domain`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alt_names: Vec<String>,
+    /// The type of private key for the certificate, either `p256` or `p384`.
+    /// Defaults to `p384`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub key_type: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub certificate: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
@@ -169,25 +224,34 @@ impl NewCertificate {
         Ok(())
     }
 
-    /// Creates a Let's Encrypt SSL certificate for a domain by using a DNS challenge.
-    /// The DNS Challenge TXT record is added to Cloudflare automatically.
+    /// Creates a Let's Encrypt SSL certificate for a domain, and its alt names, by using a
+    /// DNS challenge. The DNS Challenge TXT records are added with the company's DNS provider
+    /// automatically.
     pub async fn create_cert(&mut self, company: &Company) -> Result<()> {
-        let api_client = company.authenticate_cloudflare()?;
+        let dns = company.authenticate_dns()?;
+        let key_type = CertificateKeyType::from_str(&self.key_type)?;
 
         // Save/load keys and certificates to a temporary directory, we will re-save elsewhere.
         let persist = FilePersist::new(env::temp_dir());
 
         // Create a directory entrypoint.
-        // Use DirectoryUrl::LetsEncrypStaging for dev/testing.
-        let dir = Directory::from_url(persist, DirectoryUrl::LetsEncrypt)?;
+        // Companies can point this at another ACME server, like a local Pebble server for
+        // testing.
+        let url = if company.acme_directory_url.is_empty() {
+            DirectoryUrl::LetsEncrypt
+        } else {
+            DirectoryUrl::Other(&company.acme_directory_url)
+        };
+        let dir = Directory::from_url(persist, url)?;
 
         // Reads the private account key from persistence, or
         // creates a new one before accessing the API to establish
         // that it's there.
         let acc = dir.account(&company.gsuite_subject)?;
 
-        // Order a new TLS certificate for a domain.
-        let mut ord_new = acc.new_order(&self.domain, &[])?;
+        // Order a new TLS certificate for all the domains.
+        let alt_names: Vec<&str> = self.alt_names.iter().map(|n| n.as_str()).collect();
+        let mut ord_new = acc.new_order(&self.domain, &alt_names)?;
 
         // If the ownership of the domain(s) have already been
         // authorized in a previous order, you might be able to
@@ -198,49 +262,53 @@ impl NewCertificate {
                 break ord_csr;
             }
 
-            // Get the possible authorizations (for a single domain
-            // this will only be one element).
-            let auths = ord_new.authorizations()?;
-
-            // Get the proff we need for the TXT record:
-            // _acme-challenge.<domain-to-be-proven>.  TXT  <proof>
-            let challenge = auths[0].dns_challenge();
-
-            // Create a TXT record for _acme-challenge.{domain} with the value of
-            // the proof.
-            // Use the Cloudflare API for this.
-            let record_name = format!("_acme-challenge.{}", &self.domain.replace("*.", ""));
-
-            let content = dns::DnsContent::TXT {
-                content: challenge.dns_proof(),
-            };
-
-            // Ensure our DNS record exists.
-            api_client.ensure_record(&record_name, content).await?;
-
-            // TODO: make this less awful than a sleep.
-            info!("validating the proof...");
-            let dur = time::Duration::from_secs(10);
-            tokio::time::sleep(dur).await;
-
-            // After the TXT record is accessible, the calls
-            // this to tell the ACME API to start checking the
-            // existence of the proof.
-            //
-            // The order at ACME will change status to either
-            // confirm ownership of the domain, or fail due to the
-            // not finding the proof. To see the change, we poll
-            // the API with 5000 milliseconds wait between.
-            challenge.validate(5000)?;
+            // Get the possible authorizations, there is one for each domain.
+            // We do them one at a time since a domain and its wildcard share the same
+            // TXT record.
+            for auth in ord_new.authorizations()? {
+                if !auth.need_challenge() {
+                    continue;
+                }
+
+                // Get the proof we need for the TXT record:
+                // _acme-challenge.<domain-to-be-proven>.  TXT  <proof>
+                let challenge = auth.dns_challenge();
+
+                // Create a TXT record for _acme-challenge.{domain} with the value of
+                // the proof.
+                let record_name = format!("_acme-challenge.{}", auth.domain_name().replace("*.", ""));
+
+                let content = DnsContent::TXT {
+                    content: challenge.dns_proof(),
+                };
+
+                // Ensure our DNS record exists.
+                dns.ensure_record(&record_name, content.clone()).await?;
+
+                // Wait for the record to be on all the authoritative name servers,
+                // otherwise the ACME server might look before it is there.
+                info!("waiting for the proof for `{}` to propagate...", auth.domain_name());
+                let nameservers = dns.nameservers(&record_name).await?;
+                wait_for_record(&nameservers, &record_name, &content, DNS_PROPAGATION_TIMEOUT).await?;
+
+                // After the TXT record is accessible, the calls
+                // this to tell the ACME API to start checking the
+                // existence of the proof.
+                //
+                // The order at ACME will change status to either
+                // confirm ownership of the domain, or fail due to the
+                // not finding the proof. To see the change, we poll
+                // the API with 5000 milliseconds wait between.
+                challenge.validate(5000)?;
+            }
 
             // Update the state against the ACME API.
             ord_new.refresh()?;
         };
 
         // Ownership is proven. Create a private key for
-        // the certificate. These are provided for convenience, you
-        // can provide your own keypair instead if you want.
-        let pkey_pri = create_p384_key();
+        // the certificate.
+        let pkey_pri = key_type.create_key();
 
         // Submit the CSR. This causes the ACME provider to enter a
         // state of "processing" that must be polled until the
@@ -262,7 +330,7 @@ impl NewCertificate {
     }
 
     /// For a certificate struct, populate the certificate fields for the domain.
-    /// This will create the cert from Let's Encrypt and update Cloudflare TXT records for the
+    /// This will create the cert from Let's Encrypt and update the DNS provider's TXT records for the
     /// verification.
     pub async fn populate(&mut self, company: &Company) -> Result<()> {
         self.create_cert(company).await?;
@@ -502,3 +570,68 @@ impl Certificate {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificate_key_type_from_str() {
+        assert_eq!(CertificateKeyType::from_str("").unwrap(), CertificateKeyType::P384);
+        assert_eq!(CertificateKeyType::from_str("P256").unwrap(), CertificateKeyType::P256);
+        assert!(CertificateKeyType::from_str("rsa2048").is_err());
+    }
+
+    /// Runs the whole DNS challenge flow against a local Pebble server.
+    ///
+    /// Pebble has to be started with `-dnsserver` set to a name server that accepts
+    /// RFC 2136 updates for `PEBBLE_DOMAIN` (for example BIND with `allow-update`), and
+    /// its HTTPS certificate has to be trusted, since the ACME client can't be told to
+    /// skip verification. Set `PEBBLE_DNS_TSIG_KEY` if the name server wants signed
+    /// updates.
+    ///
+    ///   PEBBLE_DIRECTORY_URL=https://localhost:14000/dir \
+    ///   PEBBLE_DNS_SERVER=127.0.0.1:5353 \
+    ///   PEBBLE_DOMAIN=example.test \
+    ///   cargo test -p cio test_create_cert_pebble -- --ignored
+    #[ignore]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_create_cert_pebble() {
+        crate::utils::setup_logger();
+
+        let domain = env::var("PEBBLE_DOMAIN").unwrap_or_else(|_| "example.test".to_string());
+        let company: Company = serde_json::from_value(serde_json::json!({
+            "name": "Pebble",
+            "gsuite_subject": format!("admin@{}", domain),
+            "dns_provider": "rfc2136",
+            "dns_server": env::var("PEBBLE_DNS_SERVER").unwrap_or_else(|_| "127.0.0.1:5353".to_string()),
+            "dns_tsig_key": env::var("PEBBLE_DNS_TSIG_KEY").unwrap_or_default(),
+            "acme_directory_url": env::var("PEBBLE_DIRECTORY_URL")
+                .unwrap_or_else(|_| "https://localhost:14000/dir".to_string()),
+        }))
+        .unwrap();
+
+        for key_type in ["p256", "p384"] {
+            let mut cert: NewCertificate = serde_json::from_value(serde_json::json!({
+                "domain": format!("*.{}", domain),
+                "alt_names": [domain.to_string()],
+                "key_type": key_type,
+            }))
+            .unwrap();
+
+            cert.create_cert(&company).await.unwrap();
+
+            assert!(cert.private_key.contains("PRIVATE KEY"));
+            let x509 = X509::from_pem(cert.certificate.as_bytes()).unwrap();
+            let names: Vec<String> = x509
+                .subject_alt_names()
+                .unwrap()
+                .iter()
+                .filter_map(|n| n.dnsname().map(|n| n.to_string()))
+                .collect();
+            assert!(names.contains(&format!("*.{}", domain)));
+            assert!(names.contains(&domain));
+            assert!(cert.valid_days_left > 0);
+        }
+    }
+}
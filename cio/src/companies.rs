@@ -1,4 +1,4 @@
-use std::{convert::TryInto, env};
+use std::{convert::TryInto, env, str::FromStr};
 
 use airtable_api::Airtable;
 use anyhow::{anyhow, bail, Result};
@@ -42,6 +42,7 @@ use crate::{
     configs::{Building, Buildings},
     core::UpdateAirtableRecord,
     db::Database,
+    dns_providers::{DNSProvider, DNSProviderOps, DynamicUpdateClient, TsigKey},
     schema::{api_tokens, companys},
 };
 
@@ -82,6 +83,22 @@ pub struct NewCompany {
     pub github_app_installation_id: i32,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub cloudflare_api_key: String,
+    /// The DNS provider to manage records with, this is `cloudflare` (the default)
+    /// or `rfc2136`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub dns_provider: String,
+    /// The `host:port` of the name server to send dynamic updates to, when using
+    /// the `rfc2136` DNS provider.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub dns_server: String,
+    /// The TSIG key to sign dynamic updates and zone transfers with, in the same
+    /// `[algorithm:]name:secret` format as `nsupdate -y`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub dns_tsig_key: String,
+    /// The directory URL of the ACME server to get certificates from, defaults to
+    /// Let's Encrypt. This can point at a local Pebble server for testing.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub acme_directory_url: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub checkr_api_key: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
@@ -160,12 +177,20 @@ impl UpdateAirtableRecord<Company> for Company {
 impl Company {
     /// Returns the shippo data structure for the address at the office
     /// for the company.
-    pub async fn hq_shipping_address(&self, db: &Database) -> Result<shippo::Address> {
+    /// Get the building we ship packages from.
+    pub async fn hq_building(&self, db: &Database) -> Result<Building> {
         // Get the buildings from the company.
         let buildings: Vec<Building> = Buildings::get_from_db(db, self.cio_company_id).await?.into();
         // Get the first one.
         // TODO: when there is more than one building, figure this out.
-        let building = buildings.get(0).unwrap();
+        match buildings.into_iter().next() {
+            Some(building) => Ok(building),
+            None => bail!("company `{}` has no buildings", self.name),
+        }
+    }
+
+    pub async fn hq_shipping_address(&self, db: &Database) -> Result<shippo::Address> {
+        let building = self.hq_building(db).await?;
 
         Ok(shippo::Address {
             company: self.name.to_string(),
@@ -298,6 +323,26 @@ impl Company {
         Ok(api_client)
     }
 
+    /// Get the client for the DNS provider the company uses.
+    pub fn authenticate_dns(&self) -> Result<Box<dyn DNSProviderOps + Send + Sync>> {
+        match DNSProvider::from_str(&self.dns_provider)? {
+            DNSProvider::Cloudflare => Ok(Box::new(self.authenticate_cloudflare()?)),
+            DNSProvider::DynamicUpdate => {
+                if self.dns_server.is_empty() {
+                    bail!("no dns server set for the `rfc2136` dns provider");
+                }
+
+                let key = if self.dns_tsig_key.is_empty() {
+                    None
+                } else {
+                    Some(TsigKey::from_str(&self.dns_tsig_key)?)
+                };
+
+                Ok(Box::new(DynamicUpdateClient::new(&self.dns_server, key)?))
+            }
+        }
+    }
+
     /// Authenticate with Checkr.
     pub fn authenticate_checkr(&self) -> Option<Checkr> {
         if self.checkr_api_key.is_empty() {
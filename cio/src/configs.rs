@@ -7,9 +7,11 @@ use std::{
 use anyhow::{bail, Result};
 use async_bb8_diesel::AsyncRunQueryDsl;
 use async_trait::async_trait;
-use chrono::naive::NaiveDate;
-use google_calendar::types::{Event, EventAttendee, EventDateTime};
-use google_geocode::Geocode;
+use chrono::{naive::NaiveDate, Utc};
+use google_calendar::{
+    types::{Event, EventAttendee, EventDateTime},
+    Client as GoogleCalendar,
+};
 use gsuite_api::types::{
     Building as GSuiteBuilding, CalendarResource as GSuiteCalendarResource, Group as GSuiteGroup, User as GSuiteUser,
 };
@@ -19,22 +21,26 @@ use macros::db;
 use schemars::JsonSchema;
 use sendgrid_api::{traits::MailOps, Client as SendGrid};
 use serde::{Deserialize, Serialize};
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
 use zoom_api::Client as Zoom;
 
 use crate::{
+    address_validation::{validate_address, AddressValidation, AddressVerdict, PostalAddress},
     airtable::{
         AIRTABLE_BUILDINGS_TABLE, AIRTABLE_CONFERENCE_ROOMS_TABLE, AIRTABLE_EMPLOYEES_TABLE, AIRTABLE_GROUPS_TABLE,
         AIRTABLE_LINKS_TABLE,
     },
     applicants::Applicant,
+    business_days::{sync_holidays, NewHoliday},
     certs::{Certificate, Certificates, NewCertificate},
     companies::Company,
     core::UpdateAirtableRecord,
     db::Database,
     gsuite::{update_gsuite_building, update_gsuite_calendar_resource},
+    offboarding::OffboardingReason,
     providers::ProviderOps,
     schema::{applicants, buildings, conference_rooms, groups, links, users},
-    shipments::NewOutboundShipment,
+    shipments::{NewOutboundShipment, OutboundShipment},
     utils::{get_file_content_from_repo, get_github_user_public_ssh_keys},
 };
 
@@ -63,6 +69,9 @@ pub struct Config {
 
     #[serde(default)]
     pub certificates: BTreeMap<String, NewCertificate>,
+
+    #[serde(default)]
+    pub holidays: BTreeMap<String, NewHoliday>,
 }
 
 /// The data type for a user.
@@ -91,6 +100,8 @@ pub struct UserConfig {
     pub recovery_phone: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub gender: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pronouns: String,
 
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub chat: String,
@@ -101,6 +112,9 @@ pub struct UserConfig {
 
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub department: String,
+    /// The user's job title, this is synced to their Slack profile.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub manager: String,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -170,6 +184,13 @@ pub struct UserConfig {
         serialize_with = "null_date_format::serialize"
     )]
     pub start_date: NaiveDate,
+    /// The user's last day. Once it has passed the user is offboarded from all our
+    /// providers, even if they are still in the config files.
+    #[serde(
+        default = "crate::utils::default_date",
+        serialize_with = "null_date_format::serialize"
+    )]
+    pub end_date: NaiveDate,
     /// Birthday (automatically populated by Gusto)
     #[serde(
         default = "crate::utils::default_date",
@@ -203,6 +224,8 @@ pub struct UserConfig {
     pub ramp_id: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub zoom_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub slack_id: String,
 
     /// This field is used by Airtable for mapping the location data.
     #[serde(default, skip_serializing_if = "String::is_empty")]
@@ -211,6 +234,11 @@ pub struct UserConfig {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub working_on: Vec<String>,
 
+    /// The validation of the home address, as JSON, so we only validate it again
+    /// when it changes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub address_validation: String,
+
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
@@ -269,7 +297,9 @@ pub mod null_date_format {
 }
 
 impl UserConfig {
-    /// Sync a user from the config file with the services.
+    /// Sync a user from the config file with the services. Returns true if the user
+    /// still needs to be onboarded, in which case they are only saved to the
+    /// database, the onboarding saga sets up their accounts.
     #[allow(clippy::too_many_arguments)]
     pub async fn sync(
         &mut self,
@@ -279,11 +309,12 @@ impl UserConfig {
         gsuite_users_map: &BTreeMap<String, GSuiteUser>,
         okta_users: &HashMap<String, okta::types::User>,
         ramp_users: &HashMap<String, ramp_api::types::User>,
+        slack_users: &HashMap<String, slack_chat_api::User>,
         zoom_users: &HashMap<String, zoom_api::types::UsersResponse>,
         zoom_users_pending: &HashMap<String, zoom_api::types::UsersResponse>,
         gusto_users: &HashMap<String, gusto_api::types::Employee>,
         gusto_users_by_id: &HashMap<String, gusto_api::types::Employee>,
-    ) -> Result<()> {
+    ) -> Result<bool> {
         // Get everything we need to authenticate with GSuite.
         // Initialize the GSuite client.
         let gsuite = company.authenticate_google_admin(db).await?;
@@ -300,6 +331,9 @@ impl UserConfig {
         // Initialize the Ramp client.
         let ramp_auth = company.authenticate_ramp(db).await;
 
+        // Initialize the Slack client.
+        let slack_auth = company.authenticate_slack(db).await;
+
         // Initialize the Zoom client.
         let zoom_auth = company.authenticate_zoom(db).await;
 
@@ -312,6 +346,9 @@ impl UserConfig {
         // Update or create the user in the database.
         if let Some(e) = existing.clone() {
             self.google_anniversary_event_id = e.google_anniversary_event_id;
+            // Keep the validation of their home address, so we only validate it again
+            // when it changes.
+            self.address_validation = e.address_validation;
         }
 
         // See if we have a gsuite user for the user.
@@ -329,6 +366,11 @@ impl UserConfig {
             self.ramp_id = ramp_user.id.to_string();
         }
 
+        // Check if we have a Slack user for the user.
+        if let Some(slack_user) = slack_users.get(&self.email) {
+            self.slack_id = slack_user.id.to_string();
+        }
+
         // See if we have a zoom user for the user.
         if let Some(zoom_user) = zoom_users.get(&self.email) {
             self.zoom_id = zoom_user.id.to_string();
@@ -375,7 +417,7 @@ impl UserConfig {
                         self.update_from_gusto(gusto_user);
                     }
                 } else if let Ok((ref gusto, ref gusto_company_id)) = gusto_auth {
-                    self.populate_home_address().await?;
+                    self.populate_home_address(db, company).await?;
                     // Create the user in Gusto if necessary.
                     self.create_in_gusto_if_needed(gusto, gusto_company_id).await?;
                 }
@@ -385,8 +427,18 @@ impl UserConfig {
         // Expand the user.
         self.expand(db, company).await?;
 
+        // A user that is new, or that we never got a GSuite or Okta account for, has
+        // not been onboarded yet. Onboarding undoes everything it set up if a step
+        // fails, so leave setting up their accounts to it.
+        let needs_onboarding = existing.is_none() || (self.google_id.is_empty() && self.okta_id.is_empty());
+
         let mut new_user = self.upsert(db).await?;
 
+        if needs_onboarding {
+            info!("user `{}` needs to be onboarded", new_user.username);
+            return Ok(true);
+        }
+
         if let Some(ref okta) = okta_auth {
             // ONLY DO THIS IF WE USE OKTA FOR CONFIGURATION,
             // OTHERWISE THE GSUITE CODE WILL SEND ITS OWN EMAIL.
@@ -445,6 +497,22 @@ impl UserConfig {
             }
         }
 
+        if let Ok(ref slack) = slack_auth {
+            match slack.ensure_user(db, company, &new_user).await {
+                Ok(slack_id) => {
+                    // Set the Slack ID for the user, this is empty if they were just invited.
+                    if !slack_id.is_empty() {
+                        new_user.slack_id = slack_id.to_string();
+                        // Update the user in the database.
+                        new_user = new_user.update(db).await?;
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to ensure slack user `{}`: {}", new_user.email, e);
+                }
+            }
+        }
+
         // Get the Airtable information for the user.
         let airtable_id = airtable_auth.ensure_user(db, company, &new_user).await?;
         new_user.airtable_id = airtable_id.to_string();
@@ -454,7 +522,7 @@ impl UserConfig {
         // Update with any other changes we made to the user.
         new_user.update(db).await?;
 
-        Ok(())
+        Ok(false)
     }
 
     pub async fn create_in_gusto_if_needed(&mut self, gusto: &Gusto, gusto_company_id: &str) -> Result<()> {
@@ -577,13 +645,23 @@ impl UserConfig {
         Ok(())
     }
 
-    async fn populate_home_address(&mut self) -> Result<()> {
+    /// The user's home address.
+    pub fn home_postal_address(&self) -> PostalAddress {
+        PostalAddress {
+            street_1: self.home_address_street_1.to_string(),
+            street_2: self.home_address_street_2.to_string(),
+            city: self.home_address_city.to_string(),
+            state: self.home_address_state.to_string(),
+            zipcode: self.home_address_zipcode.to_string(),
+            country: self.home_address_country.to_string(),
+        }
+    }
+
+    fn populate_home_address_formatted(&mut self) {
         let mut street_address = self.home_address_street_1.to_string();
         if !self.home_address_street_2.is_empty() {
             street_address = format!("{}\n{}", self.home_address_street_1, self.home_address_street_2,);
         }
-        // Make sure the state is not an abreev.
-        self.home_address_state = crate::states::StatesMap::match_abreev_or_return_existing(&self.home_address_state);
 
         // Set the formatted address.
         self.home_address_formatted = format!(
@@ -598,6 +676,13 @@ impl UserConfig {
         .trim_matches(',')
         .trim()
         .to_string();
+    }
+
+    async fn populate_home_address(&mut self, db: &Database, company: &Company) -> Result<()> {
+        // Make sure the state is not an abreev.
+        self.home_address_state = crate::states::StatesMap::match_abreev_or_return_existing(&self.home_address_state);
+
+        self.populate_home_address_formatted();
 
         // Populate the country code.
         if self.home_address_country.is_empty() || self.home_address_country == "United States" {
@@ -606,19 +691,87 @@ impl UserConfig {
         }
 
         if !self.home_address_formatted.is_empty() {
-            // Create the geocode client.
-            let geocode = Geocode::new_from_env();
-            // Get the latitude and longitude.
-            if let Ok(result) = geocode.get(&self.home_address_formatted).await {
-                let location = result.geometry.location;
-                self.home_address_latitude = location.lat as f32;
-                self.home_address_longitude = location.lng as f32;
+            // Validate the address, this also gets the latitude and longitude.
+            match validate_address(&self.home_postal_address(), &self.address_validation).await {
+                Ok(validation) => {
+                    if validation.verdict == AddressVerdict::Valid {
+                        // Normalize the address. The validation stays keyed on the
+                        // address as it is in the config, so we don't geocode it
+                        // again every time we sync.
+                        self.home_address_street_1 = validation.suggestion.street_1.to_string();
+                        self.home_address_street_2 = validation.suggestion.street_2.to_string();
+                        self.home_address_city = validation.suggestion.city.to_string();
+                        self.home_address_state =
+                            crate::states::StatesMap::match_abreev_or_return_existing(&validation.suggestion.state);
+                        self.home_address_zipcode = validation.suggestion.zipcode.to_string();
+                        if !validation.suggestion.country.is_empty() {
+                            self.home_address_country_code = validation.suggestion.country.to_string();
+                        }
+                        self.populate_home_address_formatted();
+                    } else if !validation.confirmed && validation.to_cache() != self.address_validation {
+                        // Only tell someone the first time, not every time we sync.
+                        if let Err(e) = self
+                            .send_home_address_slack_notification(db, company, &validation)
+                            .await
+                        {
+                            warn!(
+                                "posting the home address of user `{}` to slack failed: {}",
+                                self.username, e
+                            );
+                        }
+                    }
+
+                    self.home_address_latitude = validation.latitude;
+                    self.home_address_longitude = validation.longitude;
+                    self.address_validation = validation.to_cache();
+                }
+                Err(e) => warn!("validating home address for user `{}` failed: {}", self.username, e),
             }
         }
 
         Ok(())
     }
 
+    /// Post what is wrong with the home address to Slack, so someone fixes it before
+    /// we ship anything there.
+    async fn send_home_address_slack_notification(
+        &self,
+        db: &Database,
+        company: &Company,
+        validation: &AddressValidation,
+    ) -> Result<()> {
+        let mut text = format!(
+            "The home address of `{}` is {}: {}.\n>{}",
+            self.username,
+            validation.verdict.to_string(),
+            validation.messages.join(", "),
+            self.home_postal_address().to_string()
+        );
+        if validation.verdict != AddressVerdict::Undeliverable {
+            text = format!("{}\nSuggested address:\n>{}", text, validation.suggestion.to_string());
+        }
+        text = format!("{}\nFix it in Gusto or the config file.", text);
+
+        let msg = FormattedMessage {
+            channel: company.slack_channel_finance.to_string(),
+            blocks: vec![MessageBlock {
+                block_type: MessageBlockType::Section,
+                text: Some(MessageBlockText {
+                    text_type: MessageType::Markdown,
+                    text,
+                }),
+                elements: Default::default(),
+                accessory: Default::default(),
+                block_id: Default::default(),
+                fields: Default::default(),
+            }],
+            attachments: Default::default(),
+        };
+        company.post_to_slack_channel(db, &msg).await?;
+
+        Ok(())
+    }
+
     async fn populate_work_address(&mut self, db: &Database) {
         // Populate the address based on the user's location.
         if !self.building.is_empty() {
@@ -713,6 +866,11 @@ impl UserConfig {
         self.typev == "full-time"
     }
 
+    /// Returns true if the user has an end date and it is on or before the given day.
+    pub fn has_ended(&self, today: NaiveDate) -> bool {
+        self.end_date != crate::utils::default_date() && self.end_date <= today
+    }
+
     pub fn ensure_all_aliases(&mut self) {
         if !self.github.is_empty() && !self.aliases.contains(&self.github) {
             self.aliases.push(self.github.to_string());
@@ -742,7 +900,9 @@ impl UserConfig {
         }
     }
 
-    pub async fn expand(&mut self, db: &Database, company: &Company) -> Result<()> {
+    /// Expand the fields that can be derived from the config file alone, without
+    /// talking to any other services.
+    pub fn expand_from_config(&mut self, company: &Company) {
         self.cio_company_id = company.id;
 
         self.email = format!("{}@{}", self.username, company.gsuite_domain);
@@ -753,13 +913,6 @@ impl UserConfig {
         self.ensure_all_aliases();
         self.ensure_all_groups();
 
-        self.populate_ssh_keys().await?;
-
-        self.populate_home_address().await?;
-        self.populate_work_address(db).await;
-
-        self.populate_start_date(db).await;
-
         // Create the link to the manager.
         if !self.manager.is_empty() {
             self.link_to_manager = vec![self.manager.to_string()];
@@ -767,6 +920,17 @@ impl UserConfig {
 
         // Title case the department.
         self.department = titlecase::titlecase(&self.department);
+    }
+
+    pub async fn expand(&mut self, db: &Database, company: &Company) -> Result<()> {
+        self.expand_from_config(company);
+
+        self.populate_ssh_keys().await?;
+
+        self.populate_home_address(db, company).await?;
+        self.populate_work_address(db).await;
+
+        self.populate_start_date(db).await;
 
         Ok(())
     }
@@ -806,7 +970,7 @@ impl User {
     /// - Create a record in outgoing shipments.
     /// - Generate the shippo label.
     /// - Print said shippo label.
-    pub async fn create_shipment_to_home_address(&self, db: &Database) -> Result<()> {
+    pub async fn create_shipment_to_home_address(&self, db: &Database) -> Result<Option<OutboundShipment>> {
         // First let's check if the user even has an address.
         // If not we can return early.
         if self.home_address_formatted.is_empty() {
@@ -814,19 +978,48 @@ impl User {
                 "cannot create shipping label for user {} since we don't know their home address",
                 self.username
             );
-            return Ok(());
+            return Ok(None);
         }
 
         // Let's create the shipment.
         let new_shipment = NewOutboundShipment::from(self.clone());
-        // Let's add it to our database.
-        let mut shipment = new_shipment.upsert_in_db(db).await?;
-        // Create the shipment in shippo.
-        shipment.create_or_get_shippo_shipment(db).await?;
+        // Let's add it to our database. It has no tracking number yet, so don't
+        // match it against the other shipments without a label.
+        let mut shipment = new_shipment.create_in_db(db).await?;
+        // Create the shipment label.
+        shipment.create_or_get_shipment(db).await?;
         // Update airtable and the database again.
-        shipment.update(db).await?;
+        let shipment = shipment.update(db).await?;
 
-        Ok(())
+        Ok(Some(shipment))
+    }
+
+    /// Create a prepaid return label from an employee's home address, so they can
+    /// send their equipment back. The label is emailed to them.
+    pub async fn create_return_shipment_from_home_address(&self, db: &Database) -> Result<Option<OutboundShipment>> {
+        // First let's check if the user even has an address.
+        // If not we can return early.
+        if self.home_address_formatted.is_empty() {
+            warn!(
+                "cannot create return label for user {} since we don't know their home address",
+                self.username
+            );
+            return Ok(None);
+        }
+
+        // Let's create the shipment.
+        let mut new_shipment = NewOutboundShipment::from(self.clone());
+        new_shipment.contents = "Company equipment".to_string();
+        new_shipment.is_return = true;
+        // Let's add it to our database. It has no tracking number yet, so don't
+        // match it against the other shipments without a label.
+        let mut shipment = new_shipment.create_in_db(db).await?;
+        // Create the shipment label.
+        shipment.create_or_get_shipment(db).await?;
+        // Update airtable and the database again.
+        let shipment = shipment.update(db).await?;
+
+        Ok(Some(shipment))
     }
 
     /// Send an email to the new consultant about their account.
@@ -1315,6 +1508,11 @@ pub struct GroupConfig {
     /// Specifies whether a collaborative inbox will remain turned on for the group.
     #[serde(default)]
     pub enable_collaborative_inbox: bool,
+
+    /// The Slack channels members of the group are added to by default. These
+    /// can be channel IDs or `#channel-name`s.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slack_channels: Vec<String>,
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
@@ -1383,6 +1581,24 @@ pub struct BuildingConfig {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub conference_rooms: Vec<String>,
 
+    /// The timezone of the building, for example `America/Los_Angeles`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub timezone: String,
+    /// An iCalendar feed of the days the building is closed, on top of the
+    /// holidays in the configs repo.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub holidays_ical_url: String,
+    /// The time of day carriers can start picking up packages, for example `09:00`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pickup_window_start: String,
+    /// The time of day carriers have to pick up packages by, for example `17:00`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pickup_window_end: String,
+    /// The last time of day packages can be handed to a carrier to ship that day,
+    /// for example `USPS 15:00`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub carrier_cutoffs: Vec<String>,
+
     /// This field is used by Airtable for mapping the location data.
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub geocode_cache: String,
@@ -1548,29 +1764,30 @@ impl HuddleConfig {
 }
 /// Get the configs from the GitHub repository and parse them.
 pub async fn get_configs_from_repo(github: &octorust::Client, company: &Company) -> Result<Config> {
+    // Leaving the ref blank gives us the default branch.
+    get_configs_from_repo_at_ref(github, company, "").await
+}
+
+/// Get the configs from the GitHub repository at a specific branch or commit and parse them.
+pub async fn get_configs_from_repo_at_ref(
+    github: &octorust::Client,
+    company: &Company,
+    git_ref: &str,
+) -> Result<Config> {
     let owner = &company.github_org;
     let repo = "configs";
 
     log::info!("Getting configs from GitHub");
     let files = github
         .repos()
-        .get_content_vec_entries(
-            owner,
-            repo,
-            "/configs/",
-            "", // leaving the branch blank gives us the default branch
-        )
+        .get_content_vec_entries(owner, repo, "/configs/", git_ref)
         .await?;
 
     let mut file_contents = String::new();
     for file in files {
         info!("decoding {}", file.name);
         // Get the contents of the file.
-        let (contents, _) = get_file_content_from_repo(
-            github, owner, repo, "", // leaving the branch blank gives us the default branch
-            &file.path,
-        )
-        .await?;
+        let (contents, _) = get_file_content_from_repo(github, owner, repo, git_ref, &file.path).await?;
 
         let decoded = from_utf8(&contents)?.trim().to_string();
 
@@ -1721,19 +1938,16 @@ pub async fn sync_github_outside_collaborators(
 }
 
 /// Sync our users with our database and then update Airtable from the database.
+/// Returns the usernames of the users that still need to be onboarded.
 pub async fn sync_users(
     db: &Database,
     github: &octorust::Client,
     users: BTreeMap<String, UserConfig>,
     company: &Company,
-) -> Result<()> {
+) -> Result<Vec<String>> {
     // Get everything we need to authenticate with GSuite.
     // Initialize the GSuite client.
     let gsuite = company.authenticate_google_admin(db).await?;
-    let gcal = company.authenticate_google_calendar(db).await?;
-
-    // We don't need a base id here since we are only using the enterprise api features.
-    let airtable_auth = company.authenticate_airtable("");
 
     // Initialize the Gusto client.
     let mut gusto_users: HashMap<String, gusto_api::types::Employee> = HashMap::new();
@@ -1772,6 +1986,21 @@ pub async fn sync_users(
         }
     }
 
+    // Initialize the Slack client.
+    let mut slack_users: HashMap<String, slack_chat_api::User> = HashMap::new();
+    if let Ok(ref slack) = company.authenticate_slack(db).await {
+        match slack.list_provider_users(company).await {
+            Ok(su) => {
+                for s in su {
+                    slack_users.insert(s.profile.email.to_string(), s);
+                }
+            }
+            Err(e) => {
+                warn!("getting slack users for company {} failed: {}", company.name, e);
+            }
+        }
+    }
+
     // Initialize the Zoom client.
     let mut zoom_users: HashMap<String, zoom_api::types::UsersResponse> = HashMap::new();
     let mut zoom_users_pending: HashMap<String, zoom_api::types::UsersResponse> = HashMap::new();
@@ -1825,24 +2054,6 @@ pub async fn sync_users(
         gsuite_groups.insert(g.name.to_string(), g);
     }
 
-    // Get the list of our calendars.
-    let calendars = gcal
-        .calendar_list()
-        .list_all(google_calendar::types::MinAccessRole::Noop, false, false)
-        .await?;
-
-    let mut anniversary_cal_id = "".to_string();
-
-    // Find the anniversary calendar.
-    // Iterate over the calendars.
-    for calendar in calendars {
-        if calendar.summary.contains("Anniversaries") {
-            // We are on the anniversaries calendar.
-            anniversary_cal_id = calendar.id;
-            break;
-        }
-    }
-
     // Get all the users.
     let db_users = Users::get_from_db(db, company.id).await?;
     // Create a BTreeMap
@@ -1851,9 +2062,15 @@ pub async fn sync_users(
         user_map.insert(u.username.to_string(), u);
     }
 
+    // Users whose end date has passed are offboarded below instead of synced.
+    let today = Utc::now().naive_utc().date();
+    let (ended_users, users): (BTreeMap<String, UserConfig>, BTreeMap<String, UserConfig>) =
+        users.into_iter().partition(|(_, u)| u.has_ended(today));
+
     // Sync users.
     // Iterate over the users and update.
     // We should do these concurrently, but limit it to maybe 3 at a time.
+    let mut needs_onboarding: Vec<String> = Default::default();
     let mut i = 0;
     let take = 3;
     let mut skip = 0;
@@ -1864,31 +2081,35 @@ pub async fn sync_users(
             .skip(skip)
             .take(take)
             .map(|(_, mut user)| {
-                tokio::spawn(crate::enclose! { (db, company, github, gsuite_users_map, okta_users, ramp_users, zoom_users, zoom_users_pending, gusto_users, gusto_users_by_id) async move {
-                user.sync(
+                tokio::spawn(crate::enclose! { (db, company, github, gsuite_users_map, okta_users, ramp_users, slack_users, zoom_users, zoom_users_pending, gusto_users, gusto_users_by_id) async move {
+                let result = user.sync(
                     &db,
                     &company,
                     &github,
                     &gsuite_users_map,
                     &okta_users,
                     &ramp_users,
+                    &slack_users,
                     &zoom_users,
                     &zoom_users_pending,
                     &gusto_users,
                     &gusto_users_by_id,
                 )
-                .await
+                .await;
+                (user.username.to_string(), result)
                 }})
             })
             .collect();
 
-        let mut results: Vec<Result<()>> = Default::default();
+        let mut results: Vec<(String, Result<bool>)> = Default::default();
         for task in tasks {
             results.push(task.await?);
         }
 
-        for result in results {
-            result?;
+        for (username, result) in results {
+            if result? {
+                needs_onboarding.push(username);
+            }
         }
 
         i += take;
@@ -1900,61 +2121,39 @@ pub async fn sync_users(
         user_map.remove(&user.username);
     }
 
+    // Offboard any users whose end date has passed. We keep them in the database
+    // since they are still in the config files.
+    for (username, _) in ended_users {
+        if let Some(user) = user_map.remove(&username) {
+            if !crate::offboarding::was_offboarded(db, company, &user).await? {
+                // Keep syncing everyone else, the steps that failed are retried on
+                // the next sync.
+                if let Err(e) = crate::offboarding::offboard_user(db, company, &user, OffboardingReason::EndDate).await
+                {
+                    warn!("offboarding user `{}` failed: {}", username, e);
+                }
+            }
+        }
+    }
+
     // Remove any users that should no longer be in the database.
     // This is found by the remaining users that are in the map since we removed
     // the existing repos from the map above.
     for (username, user) in user_map {
         info!("deleting user `{}` from the database and other services", username);
 
-        if !user.google_anniversary_event_id.is_empty() {
-            // First delete the recurring event for their anniversary.
-            gcal.events()
-                .delete(
-                    &anniversary_cal_id,
-                    &user.google_anniversary_event_id,
-                    true, // send_notifications
-                    google_calendar::types::SendUpdates::All,
-                )
-                .await?;
-            info!(
-                "deleted user {} event {} from google",
-                username, user.google_anniversary_event_id
-            );
-        }
-
-        // Supend the user from okta.
-        if let Some(ref okta) = okta_auth {
-            okta.delete_user(db, company, &user).await?;
-        }
-
-        if company.okta_domain.is_empty() {
-            // Delete the user from GSuite and other apps.
-            // ONLY DO THIS IF THE COMPANY DOES NOT USE OKTA.
-            // Suspend the user from GSuite so we can transfer their data.
-            gsuite.delete_user(db, company, &user).await?;
-        }
-
-        // Remove the user from the github org.
-        github.delete_user(db, company, &user).await?;
-
-        // TODO: Deactivate the user from Ramp.
-        // We only want to lock the cards from more purchases. Removing GSuite/Okta
-        // will disallow them from logging in. And we want their purchase history so
-        // we don't want to delete them.
-
-        // TODO: Delete the user from Slack.
-        // Removing SSO (GSuite/Okta) will disallow them from logging in.
-
-        // Delete the user from Zoom.
-        if let Ok(ref zoom) = zoom_auth {
-            zoom.delete_user(db, company, &user).await?;
+        // We might have already offboarded them when their end date passed.
+        if !crate::offboarding::was_offboarded(db, company, &user).await? {
+            if let Err(e) =
+                crate::offboarding::offboard_user(db, company, &user, OffboardingReason::RemovedFromConfig).await
+            {
+                // Keep the user around so the steps that failed are retried on the
+                // next sync, and keep syncing everyone else.
+                warn!("offboarding user `{}` failed: {}", username, e);
+                continue;
+            }
         }
 
-        // Delete the user from Airtable.
-        // Okta should take care of this if we are using Okta.
-        // But let's do it anyway.
-        airtable_auth.delete_user(db, company, &user).await?;
-
         // Delete the user from the database and Airtable.
         user.delete(db).await?;
     }
@@ -1964,7 +2163,25 @@ pub async fn sync_users(
     // Update users in airtable.
     Users::get_from_db(db, company.id).await?.update_airtable(db).await?;
 
-    Ok(())
+    Ok(needs_onboarding)
+}
+
+/// Get the id of the calendar we put everyone's work anniversaries on.
+pub async fn get_anniversary_calendar_id(gcal: &GoogleCalendar) -> Result<String> {
+    // Get the list of our calendars.
+    let calendars = gcal
+        .calendar_list()
+        .list_all(google_calendar::types::MinAccessRole::Noop, false, false)
+        .await?;
+
+    // Find the anniversary calendar.
+    for calendar in calendars {
+        if calendar.summary.contains("Anniversaries") {
+            return Ok(calendar.id);
+        }
+    }
+
+    bail!("Couldn't find calendar named 'Anniversaries'!")
 }
 
 /// Sync our buildings with our database and then update Airtable from the database.
@@ -2215,6 +2432,8 @@ pub async fn sync_groups(db: &Database, groups: BTreeMap<String, GroupConfig>, c
 
     let okta_auth = company.authenticate_okta();
 
+    let slack_auth = company.authenticate_slack(db).await;
+
     // Get all the groups.
     let db_groups = Groups::get_from_db(db, company.id).await?;
     // Create a BTreeMap
@@ -2249,6 +2468,10 @@ pub async fn sync_groups(db: &Database, groups: BTreeMap<String, GroupConfig>, c
         if let Some(ref okta) = okta_auth {
             okta.delete_group(company, &group).await?;
         }
+
+        if let Ok(ref slack) = slack_auth {
+            slack.delete_group(company, &group).await?;
+        }
     }
 
     info!("updated configs groups in the database");
@@ -2266,6 +2489,10 @@ pub async fn sync_groups(db: &Database, groups: BTreeMap<String, GroupConfig>, c
         if let Some(ref okta) = okta_auth {
             okta.ensure_group(db, company, &g).await?;
         }
+
+        if let Ok(ref slack) = slack_auth {
+            slack.ensure_group(db, company, &g).await?;
+        }
     }
 
     // Update groups in airtable.
@@ -2433,12 +2660,25 @@ pub async fn refresh_db_configs_and_airtable(db: &Database, company: &Company) -
     // Sync conference rooms.
     sync_conference_rooms(db, configs.resources, company).await?;
 
+    // Sync holidays.
+    // Syncing holidays must happen after we sync buildings, since buildings can have
+    // their own holiday feeds.
+    if let Err(e) = sync_holidays(db, configs.holidays, company).await {
+        warn!("error syncing holidays: {}", e);
+    }
+
     // Sync groups.
     // Syncing groups must happen before we sync the users.
     sync_groups(db, configs.groups, company).await?;
 
     // Sync users.
-    sync_users(db, &github, configs.users, company).await?;
+    // We can't start the onboarding saga from here, it runs in the server.
+    for username in sync_users(db, &github, configs.users, company).await? {
+        warn!(
+            "user `{}` has not been onboarded, they will be when `configs/users.toml` is next pushed or by `/users/onboard`",
+            username
+        );
+    }
 
     // Sync links.
     let (links, certs, ghout, ann) = tokio::join!(
@@ -2468,29 +2708,7 @@ pub async fn refresh_db_configs_and_airtable(db: &Database, company: &Company) -
 
 pub async fn refresh_anniversary_events(db: &Database, company: &Company) -> Result<()> {
     let gcal = company.authenticate_google_calendar(db).await?;
-
-    // Get the list of our calendars.
-    let calendars = gcal
-        .calendar_list()
-        .list_all(google_calendar::types::MinAccessRole::Noop, false, false)
-        .await?;
-
-    let mut anniversary_cal_id = "".to_string();
-
-    // Find the anniversary calendar.
-    // Iterate over the calendars.
-    for calendar in calendars {
-        if calendar.summary.contains("Anniversaries") {
-            // We are on the anniversaries calendar.
-            anniversary_cal_id = calendar.id;
-            break;
-        }
-    }
-
-    if anniversary_cal_id.is_empty() {
-        // Return early we couldn't find the calendar.
-        bail!("Couldn't find calendar named 'Anniversaries'!");
-    }
+    let anniversary_cal_id = get_anniversary_calendar_id(&gcal).await?;
 
     // Get our list of users from our database.
     let users = Users::get_from_db(db, company.id).await?;
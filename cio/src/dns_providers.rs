@@ -1,24 +1,116 @@
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
 use anyhow::{bail, Result};
 use async_trait::async_trait;
+pub use cloudflare::endpoints::dns::DnsContent;
 use cloudflare::{
     endpoints::{dns, zone},
     framework::async_api::{ApiClient, Client as CloudflareClient},
 };
 use log::info;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use tokio::net::{lookup_host, TcpStream};
+use trust_dns_client::{
+    client::{AsyncClient, ClientHandle},
+    op::{update_message, DnsResponse, Message, MessageType, OpCode, Query, ResponseCode},
+    proto::{
+        iocompat::AsyncIoTokioAsStd,
+        xfer::{DnsRequest, DnsRequestOptions},
+        DnsHandle,
+    },
+    rr::{
+        rdata::{MX, NULL, TXT},
+        DNSClass, Name, RData, Record, RecordType,
+    },
+    tcp::TcpClientStream,
+};
+
+/// The TTL we give records we create, this is the minimum Cloudflare allows.
+const DNS_TTL: u32 = 120;
+
+/// How long we wait for an address of a name server to answer before we try the
+/// next one.
+const NAMESERVER_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How far apart the name server's clock and ours can be for it to accept our
+/// signatures, this is what `nsupdate` uses.
+const TSIG_FUDGE: u16 = 300;
+
+/// The record type of a TSIG record, trust-dns doesn't know about it.
+const TSIG_RECORD_TYPE: u16 = 250;
 
 /// This trait defines how to implement a provider for a vendor that manages DNS records.
 #[async_trait]
 pub trait DNSProviderOps {
     /// Ensure the record exists and has the correct information.
-    async fn ensure_record(&self, domain: &str, content: cloudflare::endpoints::dns::DnsContent) -> Result<()>;
+    async fn ensure_record(&self, domain: &str, content: DnsContent) -> Result<()>;
 
-    /// Delete the record if it exists.
-    async fn delete_record(&self, domain: &str, content: cloudflare::endpoints::dns::DnsContent) -> Result<()>;
+    /// Delete the record if it exists. Returns true if the record was deleted.
+    async fn delete_record(&self, domain: &str, content: DnsContent) -> Result<bool>;
+
+    /// List all the records in the zone the domain belongs to.
+    async fn list_records(&self, domain: &str) -> Result<Vec<DnsRecord>>;
+
+    /// Get the authoritative name servers for the zone the domain belongs to.
+    async fn nameservers(&self, domain: &str) -> Result<Vec<NameServer>>;
+}
+
+/// An authoritative name server for a zone.
+#[derive(Debug, Clone)]
+pub struct NameServer {
+    /// The host name of the name server.
+    pub name: String,
+    /// The addresses the host name resolves to, IPv4 first. Any one of them
+    /// answering is enough, since we can't always reach all of them, like IPv6
+    /// addresses from a host without IPv6.
+    pub addrs: Vec<SocketAddr>,
+}
+
+/// A DNS record returned by a provider.
+#[derive(Debug, Clone)]
+pub struct DnsRecord {
+    /// The fully qualified name of the record, without the trailing dot.
+    pub name: String,
+    pub content: DnsContent,
+}
+
+/// The DNS providers a company can use, this is set in `Company::dns_provider`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DNSProvider {
+    Cloudflare,
+    /// Any authoritative name server that supports RFC 2136 dynamic updates, like BIND.
+    DynamicUpdate,
+}
+
+impl ToString for DNSProvider {
+    fn to_string(&self) -> String {
+        match self {
+            DNSProvider::Cloudflare => "cloudflare".to_string(),
+            DNSProvider::DynamicUpdate => "rfc2136".to_string(),
+        }
+    }
+}
+
+impl FromStr for DNSProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            // Companies that were set up before we supported other providers use Cloudflare.
+            "" | "cloudflare" => Ok(DNSProvider::Cloudflare),
+            "rfc2136" => Ok(DNSProvider::DynamicUpdate),
+            _ => bail!("unknown dns provider `{}`", s),
+        }
+    }
 }
 
 #[async_trait]
 impl DNSProviderOps for CloudflareClient {
-    async fn ensure_record(&self, domain: &str, content: cloudflare::endpoints::dns::DnsContent) -> Result<()> {
+    async fn ensure_record(&self, domain: &str, content: DnsContent) -> Result<()> {
         let domain = &domain.to_lowercase();
         let zone_identifier = &get_zone_identifier(self, domain).await?;
 
@@ -43,8 +135,7 @@ impl DNSProviderOps for CloudflareClient {
                     params: dns::CreateDnsRecordParams {
                         name: domain,
                         content: content.clone(),
-                        // This is the min.
-                        ttl: Some(120),
+                        ttl: Some(DNS_TTL),
                         proxied: None,
                         priority: None,
                     },
@@ -65,11 +156,11 @@ impl DNSProviderOps for CloudflareClient {
             }
         }
 
-        let is_a_record = matches!(content, cloudflare::endpoints::dns::DnsContent::A { content: _ });
+        let is_a_record = matches!(content, DnsContent::A { content: _ });
 
-        let is_aaaa_record = matches!(content, cloudflare::endpoints::dns::DnsContent::AAAA { content: _ });
+        let is_aaaa_record = matches!(content, DnsContent::AAAA { content: _ });
 
-        let is_cname_record = matches!(content, cloudflare::endpoints::dns::DnsContent::CNAME { content: _ });
+        let is_cname_record = matches!(content, DnsContent::CNAME { content: _ });
 
         if domain.starts_with("_acme-challenge.") || is_a_record || is_aaaa_record || is_cname_record {
             if dns_records.len() > 1 {
@@ -90,8 +181,7 @@ impl DNSProviderOps for CloudflareClient {
                     params: dns::UpdateDnsRecordParams {
                         name: domain,
                         content: content.clone(),
-                        // This is the min.
-                        ttl: Some(120),
+                        ttl: Some(DNS_TTL),
                         proxied: None,
                     },
                 })
@@ -108,8 +198,7 @@ impl DNSProviderOps for CloudflareClient {
                     params: dns::CreateDnsRecordParams {
                         name: domain,
                         content: content.clone(),
-                        // This is the min.
-                        ttl: Some(120),
+                        ttl: Some(DNS_TTL),
                         proxied: None,
                         priority: None,
                     },
@@ -123,7 +212,7 @@ impl DNSProviderOps for CloudflareClient {
         Ok(())
     }
 
-    async fn delete_record(&self, domain: &str, content: cloudflare::endpoints::dns::DnsContent) -> Result<()> {
+    async fn delete_record(&self, domain: &str, content: DnsContent) -> Result<bool> {
         let domain = &domain.to_lowercase();
         let zone_identifier = &get_zone_identifier(self, domain).await?;
 
@@ -142,65 +231,559 @@ impl DNSProviderOps for CloudflareClient {
         if dns_records.is_empty() {
             info!("dns record for domain `{}` does not exist", domain);
 
-            return Ok(());
+            return Ok(false);
         }
 
         for record in dns_records {
             if record.name == *domain && content_equals(record.content.clone(), content.clone()) {
                 // TODO: Delete the record.
-                info!("deleted dns record for domain `{}`", domain);
+                info!(
+                    "not deleting dns record for domain `{}`, this is not supported yet",
+                    domain
+                );
 
-                return Ok(());
+                return Ok(false);
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn list_records(&self, domain: &str) -> Result<Vec<DnsRecord>> {
+        let zone_identifier = &get_zone_identifier(self, &domain.to_lowercase()).await?;
+
+        let dns_records = self
+            .request(&dns::ListDnsRecords {
+                zone_identifier,
+                params: dns::ListDnsRecordsParams {
+                    // From: https://api.cloudflare.com/#dns-records-for-a-zone-list-dns-records
+                    per_page: Some(5000),
+                    ..Default::default()
+                },
+            })
+            .await?
+            .result;
+
+        Ok(dns_records
+            .into_iter()
+            .map(|record| DnsRecord {
+                name: record.name,
+                content: record.content,
+            })
+            .collect())
+    }
+
+    async fn nameservers(&self, domain: &str) -> Result<Vec<NameServer>> {
+        let zones = self
+            .request(&zone::ListZones {
+                params: zone::ListZonesParams {
+                    name: Some(root_domain(&domain.to_lowercase())),
+                    ..Default::default()
+                },
+            })
+            .await?
+            .result;
+
+        if zones.is_empty() {
+            bail!("no cloudflare zone found for domain `{}`", domain);
+        }
+
+        let mut nameservers: Vec<NameServer> = Default::default();
+        for ns in &zones[0].name_servers {
+            let mut addrs: Vec<SocketAddr> = lookup_host((ns.as_str(), 53)).await?.collect();
+            addrs.sort_by_key(|a| a.is_ipv6());
+            nameservers.push(NameServer {
+                name: ns.to_string(),
+                addrs,
+            });
+        }
+
+        Ok(nameservers)
+    }
+}
+
+/// A TSIG key (RFC 8945) shared with a name server, we sign our updates and zone
+/// transfers with it.
+#[derive(Clone)]
+pub struct TsigKey {
+    name: Name,
+    algorithm: Name,
+    digest: MessageDigest,
+    secret: Vec<u8>,
+}
+
+impl FromStr for TsigKey {
+    type Err = anyhow::Error;
+
+    /// Parse a key in the `[algorithm:]name:secret` format `nsupdate -y` uses, the
+    /// secret is base64 and the algorithm defaults to `hmac-sha256`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        let (algorithm, name, secret) = match parts[..] {
+            [name, secret] => ("hmac-sha256", name, secret),
+            [algorithm, name, secret] => (algorithm, name, secret),
+            _ => bail!("tsig keys look like `[algorithm:]name:secret`"),
+        };
+
+        let algorithm = algorithm.to_lowercase();
+        let digest = match algorithm.as_str() {
+            "hmac-sha256" => MessageDigest::sha256(),
+            "hmac-sha512" => MessageDigest::sha512(),
+            _ => bail!("unsupported tsig algorithm `{}`", algorithm),
+        };
+
+        Ok(TsigKey {
+            name: Name::from_str(&format!("{}.", name.trim_end_matches('.')))?,
+            algorithm: Name::from_str(&format!("{}.", algorithm))?,
+            digest,
+            secret: base64::decode(secret)?,
+        })
+    }
+}
+
+impl TsigKey {
+    /// Sign a message, the signature is added as the last record of the message.
+    fn sign(&self, message: &Message, time_signed: u64) -> Result<Message> {
+        // The TSIG record has to be the last record in the message, but trust-dns
+        // always writes the EDNS record after the others. Our messages are small,
+        // so we go without EDNS.
+        let mut message = without_edns(message);
+        let wire = message.to_vec()?;
+
+        let algorithm = name_to_wire(&self.algorithm);
+        // The time is a 48 bit number.
+        let time = &time_signed.to_be_bytes()[2..];
+
+        // FROM: https://datatracker.ietf.org/doc/html/rfc8945#section-4.3.3
+        let mut variables = name_to_wire(&self.name);
+        variables.extend_from_slice(&u16::from(DNSClass::ANY).to_be_bytes());
+        // The TTL.
+        variables.extend_from_slice(&0u32.to_be_bytes());
+        variables.extend_from_slice(&algorithm);
+        variables.extend_from_slice(time);
+        variables.extend_from_slice(&TSIG_FUDGE.to_be_bytes());
+        // The error and the length of the other data, both are zero.
+        variables.extend_from_slice(&[0, 0, 0, 0]);
+
+        let key = PKey::hmac(&self.secret)?;
+        let mut signer = Signer::new(self.digest, &key)?;
+        signer.update(&wire)?;
+        signer.update(&variables)?;
+        let mac = signer.sign_to_vec()?;
+
+        // FROM: https://datatracker.ietf.org/doc/html/rfc8945#section-4.2
+        let mut rdata = algorithm;
+        rdata.extend_from_slice(time);
+        rdata.extend_from_slice(&TSIG_FUDGE.to_be_bytes());
+        rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+        rdata.extend_from_slice(&mac);
+        // The server checks the signature against this ID, so it doesn't matter that
+        // the client gives the message a new one when it sends it.
+        rdata.extend_from_slice(&message.id().to_be_bytes());
+        rdata.extend_from_slice(&[0, 0, 0, 0]);
+
+        let mut record = Record::from_rdata(
+            self.name.clone(),
+            0,
+            RData::Unknown {
+                code: TSIG_RECORD_TYPE,
+                rdata: NULL::with(rdata),
+            },
+        );
+        record.set_dns_class(DNSClass::ANY);
+        message.add_additional(record);
+
+        Ok(message)
+    }
+}
+
+/// A client for an authoritative name server that supports RFC 2136 dynamic updates,
+/// like BIND. The server must allow updates and zone transfers from us, either with
+/// our TSIG key or by our address.
+pub struct DynamicUpdateClient {
+    server: SocketAddr,
+    key: Option<TsigKey>,
+}
+
+impl DynamicUpdateClient {
+    /// Create a client for the name server at `server`, this is a `host:port`, the
+    /// port defaults to 53.
+    pub fn new(server: &str, key: Option<TsigKey>) -> Result<Self> {
+        let addr = if server.contains(':') {
+            server.to_string()
+        } else {
+            format!("{}:53", server)
+        };
+
+        match addr.to_socket_addrs()?.next() {
+            Some(server) => Ok(DynamicUpdateClient { server, key }),
+            None => bail!("could not resolve dns server `{}`", server),
+        }
+    }
+
+    /// Send an update or a zone transfer, signed if we have a key.
+    async fn send(&self, client: &mut AsyncClient, message: Message) -> Result<DnsResponse> {
+        let message = match &self.key {
+            Some(key) => key.sign(&message, SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())?,
+            None => message,
+        };
+
+        Ok(client
+            .send(DnsRequest::new(message, DnsRequestOptions::default()))
+            .await?)
+    }
+
+    /// Get the existing records for the name with the same type as the content.
+    async fn get_records(&self, client: &mut AsyncClient, name: &Name, content: &DnsContent) -> Result<Vec<Record>> {
+        let response = client.query(name.clone(), DNSClass::IN, record_type(content)).await?;
+
+        Ok(response.answers().to_vec())
+    }
+}
+
+#[async_trait]
+impl DNSProviderOps for DynamicUpdateClient {
+    async fn ensure_record(&self, domain: &str, content: DnsContent) -> Result<()> {
+        let domain = &domain.to_lowercase();
+        let name = Name::from_str(&format!("{}.", domain))?;
+        let zone_origin = Name::from_str(&format!("{}.", root_domain(domain)))?;
+        let record = to_record(&name, &content)?;
+
+        let mut client = connect(self.server).await?;
+        let existing = self.get_records(&mut client, &name, &content).await?;
+
+        for r in &existing {
+            if let Some(c) = from_record(r) {
+                if content_equals(c, content.clone()) {
+                    info!("dns record for domain `{}` already exists: {:?}", domain, content);
+
+                    return Ok(());
+                }
             }
         }
 
+        let is_single_record = matches!(
+            content,
+            DnsContent::A { content: _ } | DnsContent::AAAA { content: _ } | DnsContent::CNAME { content: _ }
+        );
+
+        let response = if !existing.is_empty() && (domain.starts_with("_acme-challenge.") || is_single_record) {
+            if existing.len() > 1 {
+                bail!(
+                    "we don't know which DNS record to update for domain `{}`: {:?}\nexisting records: {:?}",
+                    domain,
+                    content,
+                    existing
+                );
+            }
+
+            // Replace the record in a single update, so there is never a moment
+            // where the domain has no record.
+            self.send(
+                &mut client,
+                update_message::compare_and_swap(existing[0].clone().into(), record.into(), zone_origin),
+            )
+            .await?
+        } else {
+            self.send(&mut client, update_message::append(record.into(), zone_origin, false))
+                .await?
+        };
+        check_response(&response, domain)?;
+
+        info!("ensured dns record for domain `{}`: {:?}", domain, content);
+
         Ok(())
     }
+
+    async fn delete_record(&self, domain: &str, content: DnsContent) -> Result<bool> {
+        let domain = &domain.to_lowercase();
+        let name = Name::from_str(&format!("{}.", domain))?;
+        let zone_origin = Name::from_str(&format!("{}.", root_domain(domain)))?;
+
+        let mut client = connect(self.server).await?;
+        for r in self.get_records(&mut client, &name, &content).await? {
+            if let Some(c) = from_record(&r) {
+                if content_equals(c, content.clone()) {
+                    let response = self
+                        .send(&mut client, update_message::delete_by_rdata(r.into(), zone_origin))
+                        .await?;
+                    check_response(&response, domain)?;
+
+                    info!("deleted dns record for domain `{}`", domain);
+
+                    return Ok(true);
+                }
+            }
+        }
+
+        info!("dns record for domain `{}` does not exist", domain);
+
+        Ok(false)
+    }
+
+    async fn list_records(&self, domain: &str) -> Result<Vec<DnsRecord>> {
+        let zone = root_domain(&domain.to_lowercase());
+        let zone_origin = Name::from_str(&format!("{}.", zone))?;
+
+        // Do a zone transfer.
+        let mut message = Message::new();
+        message
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .add_query(Query::query(zone_origin, RecordType::AXFR));
+
+        let mut client = connect(self.server).await?;
+        let response = self.send(&mut client, message).await?;
+        check_response(&response, &zone)?;
+
+        // A zone transfer starts and ends with the SOA record, our client only reads
+        // the first message of the response so make sure we got everything.
+        let answers = response.answers();
+        if answers.len() < 2 || answers[answers.len() - 1].record_type() != RecordType::SOA {
+            bail!(
+                "the zone transfer for `{}` did not fit in a single message, this is not supported",
+                zone
+            );
+        }
+
+        Ok(answers
+            .iter()
+            .filter_map(|r| {
+                from_record(r).map(|content| DnsRecord {
+                    name: r.name().to_string().trim_end_matches('.').to_string(),
+                    content,
+                })
+            })
+            .collect())
+    }
+
+    async fn nameservers(&self, _domain: &str) -> Result<Vec<NameServer>> {
+        // The server we send updates to is the primary for the zone, any secondaries
+        // get the records from it.
+        Ok(vec![NameServer {
+            name: self.server.to_string(),
+            addrs: vec![self.server],
+        }])
+    }
+}
+
+/// Wait until every one of the name servers answers with the record for the domain
+/// on at least one of its addresses, or bail if that takes longer than `timeout`.
+pub async fn wait_for_record(
+    nameservers: &[NameServer],
+    domain: &str,
+    content: &DnsContent,
+    timeout: Duration,
+) -> Result<()> {
+    if nameservers.is_empty() {
+        bail!("no name servers to check for domain `{}`", domain);
+    }
+
+    let name = Name::from_str(&format!("{}.", domain.to_lowercase()))?;
+    let start = Instant::now();
+
+    loop {
+        let mut pending: Vec<&str> = Default::default();
+        for ns in nameservers {
+            let mut found = false;
+            for addr in &ns.addrs {
+                // Don't get stuck on an address we can't reach.
+                if let Ok(Ok(true)) =
+                    tokio::time::timeout(NAMESERVER_QUERY_TIMEOUT, has_record(*addr, &name, content)).await
+                {
+                    found = true;
+                    break;
+                }
+            }
+
+            if !found {
+                pending.push(&ns.name);
+            }
+        }
+
+        if pending.is_empty() {
+            info!(
+                "dns record for domain `{}` is on all name servers: {:?}",
+                domain, content
+            );
+
+            return Ok(());
+        }
+
+        if start.elapsed() > timeout {
+            bail!(
+                "dns record for domain `{}` was not on name servers `{:?}` after {:?}: {:?}",
+                domain,
+                pending,
+                timeout,
+                content
+            );
+        }
+
+        info!(
+            "waiting for dns record for domain `{}` on name servers `{:?}`",
+            domain, pending
+        );
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+/// Check if the name server answers with the record for the name.
+async fn has_record(server: SocketAddr, name: &Name, content: &DnsContent) -> Result<bool> {
+    let mut client = connect(server).await?;
+    let response = client.query(name.clone(), DNSClass::IN, record_type(content)).await?;
+
+    Ok(response
+        .answers()
+        .iter()
+        .filter_map(from_record)
+        .any(|c| content_equals(c, content.clone())))
+}
+
+async fn connect(server: SocketAddr) -> Result<AsyncClient> {
+    // We use TCP so zone transfers work.
+    let (stream, sender) = TcpClientStream::<AsyncIoTokioAsStd<TcpStream>>::new(server);
+    let (client, background) = AsyncClient::new(stream, sender, None).await?;
+    tokio::spawn(background);
+
+    Ok(client)
+}
+
+/// Copy a message without its EDNS record.
+fn without_edns(message: &Message) -> Message {
+    let mut m = Message::new();
+    m.set_id(message.id())
+        .set_message_type(message.message_type())
+        .set_op_code(message.op_code())
+        .set_recursion_desired(message.recursion_desired());
+    for query in message.queries() {
+        m.add_query(query.clone());
+    }
+    m.insert_answers(message.answers().to_vec());
+    m.insert_name_servers(message.name_servers().to_vec());
+    m.insert_additionals(message.additionals().to_vec());
+
+    m
+}
+
+/// Write a name in its canonical wire format, lowercase and uncompressed.
+fn name_to_wire(name: &Name) -> Vec<u8> {
+    let mut wire: Vec<u8> = Default::default();
+    for label in name.to_lowercase().iter() {
+        wire.push(label.len() as u8);
+        wire.extend_from_slice(label);
+    }
+    wire.push(0);
+
+    wire
+}
+
+fn check_response(response: &DnsResponse, domain: &str) -> Result<()> {
+    if response.response_code() != ResponseCode::NoError {
+        bail!(
+            "dns server returned `{}` for domain `{}`",
+            response.response_code(),
+            domain
+        );
+    }
+
+    Ok(())
+}
+
+fn record_type(content: &DnsContent) -> RecordType {
+    match content {
+        DnsContent::A { content: _ } => RecordType::A,
+        DnsContent::AAAA { content: _ } => RecordType::AAAA,
+        DnsContent::CNAME { content: _ } => RecordType::CNAME,
+        DnsContent::NS { content: _ } => RecordType::NS,
+        DnsContent::MX {
+            content: _,
+            priority: _,
+        } => RecordType::MX,
+        DnsContent::TXT { content: _ } => RecordType::TXT,
+        DnsContent::SRV { content: _ } => RecordType::SRV,
+    }
+}
+
+fn to_record(name: &Name, content: &DnsContent) -> Result<Record> {
+    let rdata = match content {
+        DnsContent::A { content } => RData::A(*content),
+        DnsContent::AAAA { content } => RData::AAAA(*content),
+        DnsContent::CNAME { content } => RData::CNAME(Name::from_str(&format!("{}.", content))?),
+        DnsContent::NS { content } => RData::NS(Name::from_str(&format!("{}.", content))?),
+        DnsContent::MX { content, priority } => {
+            RData::MX(MX::new(*priority, Name::from_str(&format!("{}.", content))?))
+        }
+        // TXT strings can be at most 255 bytes, so split up longer content.
+        DnsContent::TXT { content } => RData::TXT(TXT::from_bytes(content.as_bytes().chunks(255).collect())),
+        DnsContent::SRV { content: _ } => bail!("SRV records are not supported for dynamic updates"),
+    };
+
+    Ok(Record::from_rdata(name.clone(), DNS_TTL, rdata))
+}
+
+fn from_record(record: &Record) -> Option<DnsContent> {
+    let name = |n: &Name| n.to_string().trim_end_matches('.').to_string();
+
+    match record.rdata() {
+        RData::A(ip) => Some(DnsContent::A { content: *ip }),
+        RData::AAAA(ip) => Some(DnsContent::AAAA { content: *ip }),
+        RData::CNAME(n) => Some(DnsContent::CNAME { content: name(n) }),
+        RData::NS(n) => Some(DnsContent::NS { content: name(n) }),
+        RData::MX(mx) => Some(DnsContent::MX {
+            content: name(mx.exchange()),
+            priority: mx.preference(),
+        }),
+        RData::TXT(txt) => Some(DnsContent::TXT {
+            content: txt.txt_data().iter().map(|t| String::from_utf8_lossy(t)).collect(),
+        }),
+        _ => None,
+    }
 }
 
 /// TODO: remove this stupid function when cloudflare has PartialEq on their types...
-fn content_equals(a: cloudflare::endpoints::dns::DnsContent, b: cloudflare::endpoints::dns::DnsContent) -> bool {
+fn content_equals(a: DnsContent, b: DnsContent) -> bool {
     match a {
-        cloudflare::endpoints::dns::DnsContent::A { content } => {
+        DnsContent::A { content } => {
             let a_content = content;
-            if let cloudflare::endpoints::dns::DnsContent::A { content } = b {
+            if let DnsContent::A { content } = b {
                 return a_content == content;
             }
         }
-        cloudflare::endpoints::dns::DnsContent::AAAA { content } => {
+        DnsContent::AAAA { content } => {
             let a_content = content;
-            if let cloudflare::endpoints::dns::DnsContent::AAAA { content } = b {
+            if let DnsContent::AAAA { content } = b {
                 return a_content == content;
             }
         }
-        cloudflare::endpoints::dns::DnsContent::CNAME { content } => {
+        DnsContent::CNAME { content } => {
             let a_content = content;
-            if let cloudflare::endpoints::dns::DnsContent::CNAME { content } = b {
+            if let DnsContent::CNAME { content } = b {
                 return a_content == content;
             }
         }
-        cloudflare::endpoints::dns::DnsContent::NS { content } => {
+        DnsContent::NS { content } => {
             let a_content = content;
-            if let cloudflare::endpoints::dns::DnsContent::NS { content } = b {
+            if let DnsContent::NS { content } = b {
                 return a_content == content;
             }
         }
-        cloudflare::endpoints::dns::DnsContent::MX { content, priority } => {
+        DnsContent::MX { content, priority } => {
             let a_content = content;
             let a_priority = priority;
-            if let cloudflare::endpoints::dns::DnsContent::MX { content, priority } = b {
+            if let DnsContent::MX { content, priority } = b {
                 return a_content == content && a_priority == priority;
             }
         }
-        cloudflare::endpoints::dns::DnsContent::TXT { content } => {
+        DnsContent::TXT { content } => {
             let a_content = content;
-            if let cloudflare::endpoints::dns::DnsContent::TXT { content } = b {
+            if let DnsContent::TXT { content } = b {
                 return a_content == content;
             }
         }
-        cloudflare::endpoints::dns::DnsContent::SRV { content } => {
+        DnsContent::SRV { content } => {
             let a_content = content;
-            if let cloudflare::endpoints::dns::DnsContent::SRV { content } = b {
+            if let DnsContent::SRV { content } = b {
                 return a_content == content;
             }
         }
@@ -209,19 +792,42 @@ fn content_equals(a: cloudflare::endpoints::dns::DnsContent, b: cloudflare::endp
     false
 }
 
-pub async fn get_zone_identifier(client: &CloudflareClient, domain: &str) -> Result<String> {
-    // We need the root of the domain not a subdomain.
+/// Public suffixes that span two labels. A domain under one of these has its
+/// root one label further left, `foo.example.co.uk` becomes `example.co.uk`.
+const MULTI_LABEL_SUFFIXES: &[&str] = &[
+    "ac.jp", "ac.nz", "ac.uk", "co.in", "co.jp", "co.kr", "co.nz", "co.uk", "co.za", "com.ar", "com.au", "com.br",
+    "com.cn", "com.mx", "com.sg", "com.tr", "com.tw", "gov.au", "gov.uk", "ltd.uk", "me.uk", "ne.jp", "net.au",
+    "net.br", "net.nz", "or.jp", "org.au", "org.br", "org.nz", "org.uk", "org.za", "plc.uk",
+];
+
+/// Get the root of a domain, `foo.bar.example.com` becomes `example.com`.
+pub fn root_domain(domain: &str) -> String {
+    let domain = domain.trim_end_matches('.');
     let domain_parts: Vec<&str> = domain.split('.').collect();
-    let root_domain = if domain_parts.len() > 2 {
+
+    // The root is one label more than the public suffix.
+    let suffix = format!(
+        "{}.{}",
+        domain_parts[domain_parts.len().saturating_sub(2)],
+        domain_parts[domain_parts.len() - 1]
+    );
+    let labels = if MULTI_LABEL_SUFFIXES.contains(&suffix.to_lowercase().as_str()) {
+        3
+    } else {
+        2
+    };
+
+    if domain_parts.len() > labels {
         // We have a subdomain, get the root part of the domain.
-        format!(
-            "{}.{}",
-            domain_parts[domain_parts.len() - 2],
-            domain_parts[domain_parts.len() - 1]
-        )
+        domain_parts[domain_parts.len() - labels..].join(".")
     } else {
         domain.to_string()
-    };
+    }
+}
+
+pub async fn get_zone_identifier(client: &CloudflareClient, domain: &str) -> Result<String> {
+    // We need the root of the domain not a subdomain.
+    let root_domain = root_domain(domain);
 
     // Get the zone ID for the domain.
     let zones = client
@@ -237,3 +843,78 @@ pub async fn get_zone_identifier(client: &CloudflareClient, domain: &str) -> Res
     // Our zone identifier should be the first record's ID.
     Ok(zones[0].id.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_domain() {
+        assert_eq!(root_domain("oxide.computer"), "oxide.computer");
+        assert_eq!(root_domain("rfd.shared.oxide.computer"), "oxide.computer");
+        assert_eq!(root_domain("_acme-challenge.internal.oxide.computer"), "oxide.computer");
+        assert_eq!(root_domain("example.co.uk"), "example.co.uk");
+        assert_eq!(root_domain("foo.bar.example.co.uk"), "example.co.uk");
+        assert_eq!(root_domain("_acme-challenge.example.com.au"), "example.com.au");
+        assert_eq!(root_domain("example.uk"), "example.uk");
+    }
+
+    #[test]
+    fn test_tsig_key_sign() {
+        assert!(TsigKey::from_str("hmac-md5:update-key:c2VjcmV0").is_err());
+        assert!(TsigKey::from_str("c2VjcmV0").is_err());
+
+        let key = TsigKey::from_str("update-key:c2VjcmV0").unwrap();
+        let record = to_record(
+            &Name::from_str("_acme-challenge.example.test.").unwrap(),
+            &DnsContent::TXT {
+                content: "proof".to_string(),
+            },
+        )
+        .unwrap();
+        let mut message = update_message::append(record.into(), Name::from_str("example.test.").unwrap(), false);
+        message.set_id(4660);
+
+        let signed = Message::from_vec(&key.sign(&message, 1650000000).unwrap().to_vec().unwrap()).unwrap();
+        assert!(signed.edns().is_none());
+
+        let tsig = signed.additionals().last().unwrap();
+        assert_eq!(tsig.name().to_string(), "update-key.");
+        assert_eq!(tsig.dns_class(), DNSClass::ANY);
+        assert_eq!(tsig.record_type(), RecordType::Unknown(TSIG_RECORD_TYPE));
+
+        // The MAC was checked against another implementation of RFC 8945.
+        let rdata = match tsig.rdata() {
+            RData::Unknown { rdata, .. } => rdata.anything().unwrap().to_vec(),
+            _ => panic!("the tsig record should be unknown to trust-dns"),
+        };
+        let mac: String = rdata[23..55].iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(mac, "75202f307669e7b7a244f6a3bd6c59eaee5f6545c4c1939a7056b77d5abc045f");
+        assert_eq!(&rdata[55..57], &4660u16.to_be_bytes());
+    }
+
+    #[test]
+    fn test_dynamic_update_record_round_trip() {
+        let name = Name::from_str("_acme-challenge.example.test.").unwrap();
+        let content = DnsContent::TXT {
+            content: "a".repeat(300),
+        };
+
+        let record = to_record(&name, &content).unwrap();
+        assert_eq!(record.record_type(), RecordType::TXT);
+        assert!(content_equals(from_record(&record).unwrap(), content));
+
+        let content = DnsContent::CNAME {
+            content: "rfd.example.test".to_string(),
+        };
+        let record = to_record(&name, &content).unwrap();
+        assert!(content_equals(from_record(&record).unwrap(), content));
+    }
+
+    #[test]
+    fn test_dns_provider_from_str() {
+        assert_eq!(DNSProvider::from_str("").unwrap(), DNSProvider::Cloudflare);
+        assert_eq!(DNSProvider::from_str("RFC2136").unwrap(), DNSProvider::DynamicUpdate);
+        assert!(DNSProvider::from_str("route53").is_err());
+    }
+}
@@ -1,10 +1,16 @@
-use std::{fmt, ops::Deref};
+use std::{collections::BTreeMap, env, fmt, ops::Deref};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_bb8_diesel::AsyncRunQueryDsl;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use chrono_humanize::HumanTime;
+use diesel::{
+    pg::Pg,
+    query_builder::{BoxedSqlQuery, SqlQuery},
+    sql_types::{BigInt, Nullable, Timestamptz, Varchar},
+};
+use lazy_static::lazy_static;
 use macros::db;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -18,6 +24,18 @@ use crate::{
     schema::functions, utils::truncate,
 };
 
+/// How often the process running a function updates its heartbeat.
+pub const FUNCTION_HEARTBEAT_INTERVAL_SECS: i64 = 30;
+
+lazy_static! {
+    /// Who we are when we own a function, this is unique to this process.
+    pub static ref FUNCTION_OWNER: String = format!(
+        "{}/{}",
+        env::var("HOSTNAME").unwrap_or_else(|_| "webhooky".to_string()),
+        uuid::Uuid::new_v4()
+    );
+}
+
 #[db {
     new_struct_name = "Function",
     airtable_base = "cio",
@@ -42,6 +60,12 @@ pub struct NewFunction {
     pub logs: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub saga_id: String,
+    /// The process running the function.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub owner: String,
+    /// The last time the process running the function said it was still running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_at: Option<DateTime<Utc>>,
 
     /// The CIO company ID.
     #[serde(default)]
@@ -277,6 +301,65 @@ impl Function {
         Ok(())
     }
 
+    /// Mark a saga as completed with the given conclusion. This is used when a job was
+    /// stopped before it could finish, for example if it was cancelled or timed out.
+    pub async fn complete_with_conclusion(
+        db: &Database,
+        saga_id: &uuid::Uuid,
+        logs: &str,
+        conclusion: &octorust::types::Conclusion,
+    ) -> Result<Self> {
+        // Get the saga from it's id.
+        let mut nf = match Function::get_from_db(db, saga_id.to_string()).await {
+            Some(f) => f,
+            None => bail!("no function was found with saga id `{}`", saga_id),
+        };
+
+        nf.logs = logs.trim().to_string();
+        nf.status = octorust::types::JobStatus::Completed.to_string();
+        nf.conclusion = conclusion.to_string();
+        nf.completed_at = Some(Utc::now());
+        let new = nf.update(db).await?;
+
+        let company = new.company(db).await?;
+        new.send_slack_notification(db, &company).await?;
+
+        Ok(new)
+    }
+
+    /// Record that the process `owner` is still running the function. This returns
+    /// the function, so the owner can see if it was cancelled by another process.
+    pub async fn heartbeat(db: &Database, saga_id: &uuid::Uuid, owner: &str) -> Result<Self> {
+        Ok(diesel::update(functions::dsl::functions)
+            .filter(functions::dsl::saga_id.eq(saga_id.to_string()))
+            .set((
+                functions::dsl::owner.eq(owner.to_string()),
+                functions::dsl::heartbeat_at.eq(Some(Utc::now())),
+            ))
+            .get_result_async::<Function>(db.pool())
+            .await?)
+    }
+
+    /// Returns true if a process is still running the function, which is when its
+    /// owner updated the heartbeat recently. This works across every process, unlike
+    /// the jobs each one tracks itself.
+    pub fn is_alive(&self, now: DateTime<Utc>) -> bool {
+        if self.status == octorust::types::JobStatus::Completed.to_string() || self.owner.is_empty() {
+            return false;
+        }
+
+        self.heartbeat_at.map_or(false, |h| {
+            now - h < chrono::Duration::seconds(3 * FUNCTION_HEARTBEAT_INTERVAL_SECS)
+        })
+    }
+
+    /// Returns true if the job was stopped before it could finish, in which case
+    /// its conclusion should not be overwritten by the failure of the saga.
+    pub fn was_stopped(&self) -> bool {
+        self.conclusion == octorust::types::Conclusion::Cancelled.to_string()
+            || self.conclusion == octorust::types::Conclusion::TimedOut.to_string()
+    }
+
     /// Update a job from SagaCreateParams.
     pub async fn from_saga_create_params(db: &Database, saga: &steno::SagaCreateParams) -> Result<Self> {
         let status = match saga.state {
@@ -293,6 +376,10 @@ impl Function {
             completed_at: None,
             logs: "".to_string(),
             saga_id: saga.id.to_string(),
+            // The saga is created by the process that runs it, so claim it now,
+            // before anyone can mistake it for a stale one.
+            owner: FUNCTION_OWNER.to_string(),
+            heartbeat_at: Some(Utc::now()),
             cio_company_id: 1, // This is always 1 because these are meta and tied to Oxide.
         };
 
@@ -365,7 +452,10 @@ impl Function {
             steno::SagaNodeEventType::Failed(err) => {
                 // Save the error to the logs.
                 nf.logs = format!("{}\n\n{:?}", nf.logs, err).trim().to_string();
-                nf.conclusion = octorust::types::Conclusion::Failure.to_string();
+                // Keep the conclusion if the job was cancelled or timed out.
+                if !nf.was_stopped() {
+                    nf.conclusion = octorust::types::Conclusion::Failure.to_string();
+                }
                 nf.completed_at = Some(Utc::now());
             }
             steno::SagaNodeEventType::UndoStarted => (),
@@ -382,6 +472,254 @@ impl Function {
     }
 }
 
+/// Filters for listing function runs.
+#[derive(Debug, Default, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct FunctionFilter {
+    /// The name of the function, for example `sync-finance`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conclusion: Option<String>,
+    /// Only return runs created at or after this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only return runs created before this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<DateTime<Utc>>,
+    /// The maximum number of runs to return, newest first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+}
+
+/// A function run without its logs, since those can be huge.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize, QueryableByName)]
+#[diesel(table_name = functions)]
+pub struct FunctionSummary {
+    pub id: i32,
+    pub name: String,
+    pub status: String,
+    pub conclusion: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    pub saga_id: String,
+}
+
+impl FunctionSummary {
+    /// Return how long the run took, if it has completed.
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        self.completed_at.map(|c| c - self.created_at)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.conclusion == octorust::types::Conclusion::Success.to_string()
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.status == octorust::types::JobStatus::Completed.to_string()
+    }
+}
+
+/// Success rate and duration statistics for all the runs of a function.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct FunctionStats {
+    pub name: String,
+    pub runs: usize,
+    pub in_progress: usize,
+    pub successes: usize,
+    /// Completed runs that did not succeed, this includes timeouts.
+    pub failures: usize,
+    /// The fraction of completed runs that succeeded, from 0 to 1.
+    pub success_rate: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_duration_secs: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_duration_secs: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<FunctionSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<FunctionSummary>,
+}
+
+impl Function {
+    /// List the runs matching the filter, newest first.
+    pub async fn list(db: &Database, filter: &FunctionFilter) -> Result<Vec<FunctionSummary>> {
+        let mut query = functions::dsl::functions
+            .select((
+                functions::dsl::id,
+                functions::dsl::name,
+                functions::dsl::status,
+                functions::dsl::conclusion,
+                functions::dsl::created_at,
+                functions::dsl::completed_at,
+                functions::dsl::saga_id,
+            ))
+            .order_by(functions::dsl::created_at.desc())
+            .into_boxed();
+
+        if let Some(name) = &filter.name {
+            query = query.filter(functions::dsl::name.eq(name.to_string()));
+        }
+        if let Some(status) = &filter.status {
+            query = query.filter(functions::dsl::status.eq(status.to_string()));
+        }
+        if let Some(conclusion) = &filter.conclusion {
+            query = query.filter(functions::dsl::conclusion.eq(conclusion.to_string()));
+        }
+        if let Some(after) = filter.created_after {
+            query = query.filter(functions::dsl::created_at.ge(after));
+        }
+        if let Some(before) = filter.created_before {
+            query = query.filter(functions::dsl::created_at.lt(before));
+        }
+        if let Some(limit) = filter.limit {
+            query = query.limit(limit);
+        }
+
+        let rows = query
+            .load_async::<(
+                i32,
+                String,
+                String,
+                String,
+                DateTime<Utc>,
+                Option<DateTime<Utc>>,
+                String,
+            )>(db.pool())
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(
+                |(id, name, status, conclusion, created_at, completed_at, saga_id)| FunctionSummary {
+                    id,
+                    name,
+                    status,
+                    conclusion,
+                    created_at,
+                    completed_at,
+                    saga_id,
+                },
+            )
+            .collect())
+    }
+
+    /// Get the statistics for every function with runs matching the filter. The
+    /// database does the counting, so we never load every run.
+    pub async fn stats(db: &Database, filter: &FunctionFilter) -> Result<Vec<FunctionStats>> {
+        let completed = octorust::types::JobStatus::Completed.to_string();
+        let success = octorust::types::Conclusion::Success.to_string();
+        let duration = "extract(epoch FROM completed_at - created_at)";
+
+        let aggregates = filter
+            .bind(&format!(
+                "SELECT name, count(*) AS runs, count(*) FILTER (WHERE status = '{completed}') AS completed, \
+                 count(*) FILTER (WHERE status = '{completed}' AND conclusion = '{success}') AS successes, \
+                 (avg({duration}) FILTER (WHERE status = '{completed}'))::int8 AS avg_duration_secs, \
+                 (max({duration}) FILTER (WHERE status = '{completed}'))::int8 AS max_duration_secs \
+                 FROM functions WHERE {filter} GROUP BY name ORDER BY name",
+                completed = completed,
+                success = success,
+                duration = duration,
+                filter = FUNCTION_FILTER_SQL,
+            ))
+            .load_async::<FunctionAggregates>(db.pool())
+            .await?;
+
+        let last_runs = filter
+            .bind(&format!(
+                "SELECT DISTINCT ON (name) {} FROM functions WHERE {} ORDER BY name, created_at DESC",
+                FUNCTION_SUMMARY_COLUMNS, FUNCTION_FILTER_SQL
+            ))
+            .load_async::<FunctionSummary>(db.pool())
+            .await?;
+
+        let last_successes = filter
+            .bind(&format!(
+                "SELECT DISTINCT ON (name) {} FROM functions WHERE {} AND conclusion = '{}' ORDER BY name, \
+                 created_at DESC",
+                FUNCTION_SUMMARY_COLUMNS, FUNCTION_FILTER_SQL, success
+            ))
+            .load_async::<FunctionSummary>(db.pool())
+            .await?;
+
+        Ok(function_stats(aggregates, last_runs, last_successes))
+    }
+}
+
+/// The `WHERE` clause for a `FunctionFilter`, the filter binds its values with
+/// `FunctionFilter::bind`. Filters that are not set match every run.
+const FUNCTION_FILTER_SQL: &str = "($1::varchar IS NULL OR name = $1) AND ($2::varchar IS NULL OR status = $2) AND \
+                                   ($3::varchar IS NULL OR conclusion = $3) AND ($4::timestamptz IS NULL OR \
+                                   created_at >= $4) AND ($5::timestamptz IS NULL OR created_at < $5)";
+
+/// The columns of a `FunctionSummary`.
+const FUNCTION_SUMMARY_COLUMNS: &str = "id, name, status, conclusion, created_at, completed_at, saga_id";
+
+impl FunctionFilter {
+    /// Bind the filter to a query that uses `FUNCTION_FILTER_SQL`.
+    fn bind(&self, sql: &str) -> BoxedSqlQuery<'static, Pg, SqlQuery> {
+        diesel::sql_query(sql)
+            .into_boxed()
+            .bind::<Nullable<Varchar>, _>(self.name.clone())
+            .bind::<Nullable<Varchar>, _>(self.status.clone())
+            .bind::<Nullable<Varchar>, _>(self.conclusion.clone())
+            .bind::<Nullable<Timestamptz>, _>(self.created_after)
+            .bind::<Nullable<Timestamptz>, _>(self.created_before)
+    }
+}
+
+/// The counts and durations for the runs of a function.
+#[derive(Debug, Clone, QueryableByName)]
+struct FunctionAggregates {
+    #[diesel(sql_type = Varchar)]
+    name: String,
+    #[diesel(sql_type = BigInt)]
+    runs: i64,
+    #[diesel(sql_type = BigInt)]
+    completed: i64,
+    #[diesel(sql_type = BigInt)]
+    successes: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    avg_duration_secs: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    max_duration_secs: Option<i64>,
+}
+
+/// Combine the counts for each function with its most recent run and success.
+fn function_stats(
+    aggregates: Vec<FunctionAggregates>,
+    last_runs: Vec<FunctionSummary>,
+    last_successes: Vec<FunctionSummary>,
+) -> Vec<FunctionStats> {
+    let mut last_runs: BTreeMap<String, FunctionSummary> =
+        last_runs.into_iter().map(|r| (r.name.to_string(), r)).collect();
+    let mut last_successes: BTreeMap<String, FunctionSummary> =
+        last_successes.into_iter().map(|r| (r.name.to_string(), r)).collect();
+
+    aggregates
+        .into_iter()
+        .map(|a| FunctionStats {
+            runs: a.runs as usize,
+            in_progress: (a.runs - a.completed) as usize,
+            successes: a.successes as usize,
+            failures: (a.completed - a.successes) as usize,
+            success_rate: if a.completed == 0 {
+                0.0
+            } else {
+                a.successes as f64 / a.completed as f64
+            },
+            avg_duration_secs: a.avg_duration_secs,
+            max_duration_secs: a.max_duration_secs,
+            last_run: last_runs.remove(&a.name),
+            last_success: last_successes.remove(&a.name),
+            name: a.name,
+        })
+        .collect()
+}
+
 pub async fn refresh_functions() -> Result<()> {
     let db = Database::new().await;
     let company = Company::get_by_id(&db, 1).await?;
@@ -425,3 +763,146 @@ pub async fn refresh_functions() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn run(name: &str, conclusion: &str, start: i64, secs: Option<i64>) -> FunctionSummary {
+        let created_at = Utc.timestamp(start, 0);
+        FunctionSummary {
+            id: start as i32,
+            name: name.to_string(),
+            status: if secs.is_some() {
+                octorust::types::JobStatus::Completed.to_string()
+            } else {
+                octorust::types::JobStatus::InProgress.to_string()
+            },
+            conclusion: conclusion.to_string(),
+            created_at,
+            completed_at: secs.map(|s| created_at + chrono::Duration::seconds(s)),
+            saga_id: start.to_string(),
+        }
+    }
+
+    fn function(status: &str, owner: &str, heartbeat_at: Option<i64>) -> Function {
+        serde_json::from_value(json!({
+            "name": "sync-finance",
+            "status": status,
+            "created_at": Utc.timestamp(0, 0),
+            "saga_id": "saga",
+            "owner": owner,
+            "heartbeat_at": heartbeat_at.map(|h| Utc.timestamp(h, 0)),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_is_alive() {
+        let in_progress = octorust::types::JobStatus::InProgress.to_string();
+        let now = Utc.timestamp(1000, 0);
+
+        assert!(function(
+            &in_progress,
+            "webhooky/1",
+            Some(1000 - FUNCTION_HEARTBEAT_INTERVAL_SECS)
+        )
+        .is_alive(now));
+        // The process stopped updating the heartbeat.
+        assert!(!function(
+            &in_progress,
+            "webhooky/1",
+            Some(1000 - 3 * FUNCTION_HEARTBEAT_INTERVAL_SECS)
+        )
+        .is_alive(now));
+        // No process ever claimed it.
+        assert!(!function(&in_progress, "", Some(1000)).is_alive(now));
+        assert!(!function(&in_progress, "webhooky/1", None).is_alive(now));
+
+        let completed = octorust::types::JobStatus::Completed.to_string();
+        assert!(!function(&completed, "webhooky/1", Some(1000)).is_alive(now));
+    }
+
+    #[test]
+    fn test_was_stopped() {
+        let mut f = function(&octorust::types::JobStatus::Completed.to_string(), "", None);
+        for (conclusion, stopped) in [
+            (octorust::types::Conclusion::Cancelled, true),
+            (octorust::types::Conclusion::TimedOut, true),
+            (octorust::types::Conclusion::Failure, false),
+            (octorust::types::Conclusion::Success, false),
+        ] {
+            f.conclusion = conclusion.to_string();
+            assert_eq!(f.was_stopped(), stopped);
+        }
+    }
+
+    #[test]
+    fn test_function_stats() {
+        let success = octorust::types::Conclusion::Success.to_string();
+        let failure = octorust::types::Conclusion::Failure.to_string();
+
+        let aggregates = vec![
+            FunctionAggregates {
+                name: "sync-finance".to_string(),
+                runs: 3,
+                completed: 2,
+                successes: 1,
+                avg_duration_secs: Some(20),
+                max_duration_secs: Some(30),
+            },
+            FunctionAggregates {
+                name: "sync-rfds".to_string(),
+                runs: 1,
+                completed: 1,
+                successes: 0,
+                avg_duration_secs: Some(5),
+                max_duration_secs: Some(5),
+            },
+        ];
+        let last_runs = vec![
+            run("sync-finance", "", 300, None),
+            run("sync-rfds", &failure, 150, Some(5)),
+        ];
+        let last_successes = vec![run("sync-finance", &success, 100, Some(10))];
+
+        let stats = function_stats(aggregates, last_runs, last_successes);
+        assert_eq!(stats.len(), 2);
+
+        let finance = &stats[0];
+        assert_eq!(finance.name, "sync-finance");
+        assert_eq!(finance.runs, 3);
+        assert_eq!(finance.in_progress, 1);
+        assert_eq!(finance.successes, 1);
+        assert_eq!(finance.failures, 1);
+        assert_eq!(finance.success_rate, 0.5);
+        assert_eq!(finance.avg_duration_secs, Some(20));
+        assert_eq!(finance.max_duration_secs, Some(30));
+        assert_eq!(finance.last_run.as_ref().unwrap().id, 300);
+        assert_eq!(finance.last_success.as_ref().unwrap().id, 100);
+
+        let rfds = &stats[1];
+        assert_eq!(rfds.success_rate, 0.0);
+        assert_eq!(rfds.last_run.as_ref().unwrap().id, 150);
+        assert!(rfds.last_success.is_none());
+    }
+
+    #[ignore]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_function_stats_query() {
+        crate::utils::setup_logger();
+
+        let db = Database::new().await;
+
+        let filter = FunctionFilter {
+            name: Some("sync-finance".to_string()),
+            ..Default::default()
+        };
+        for stats in Function::stats(&db, &filter).await.unwrap() {
+            assert_eq!(stats.name, "sync-finance");
+            assert_eq!(stats.runs, stats.in_progress + stats.successes + stats.failures);
+        }
+    }
+}
@@ -13,6 +13,7 @@ use log::info;
 use serde_json::Value;
 
 use crate::{
+    api_tokens::APIToken,
     companies::Company,
     configs::{Building, ConferenceRoom, Group, User},
     db::Database,
@@ -20,6 +21,9 @@ use crate::{
     utils::generate_password,
 };
 
+/// The id of the Drive and Docs application in the Google Data Transfer API.
+const DRIVE_DATA_TRANSFER_APPLICATION_ID: &str = "55656082996";
+
 /// Update a GSuite user.
 pub async fn update_gsuite_user(gu: &GSuiteUser, user: &User, change_password: bool, company: &Company) -> GSuiteUser {
     let mut gsuite_user = gu.clone();
@@ -424,3 +428,58 @@ pub fn update_gsuite_calendar_resource(
 
     gsuite_conference_room
 }
+
+/// Transfer ownership of all of a user's Drive files to another user, returning the id
+/// of the transfer. The gsuite-api crate does not cover the Data Transfer API, so we
+/// call it directly with the same token.
+pub async fn transfer_drive_ownership(
+    db: &Database,
+    company: &Company,
+    from: &GSuiteUser,
+    to: &GSuiteUser,
+) -> Result<String> {
+    // Authenticating refreshes the token if it is expired.
+    company.authenticate_google_admin(db).await?;
+    let token = match APIToken::get_from_db(db, company.id, "google".to_string()).await {
+        Some(t) => t,
+        None => bail!("no token"),
+    };
+
+    let resp = reqwest::Client::new()
+        .post("https://admin.googleapis.com/admin/datatransfer/v1/transfers")
+        .bearer_auth(&token.access_token)
+        .json(&serde_json::json!({
+            "oldOwnerUserId": from.id,
+            "newOwnerUserId": to.id,
+            "applicationDataTransfers": [{
+                "applicationId": DRIVE_DATA_TRANSFER_APPLICATION_ID,
+                "applicationTransferParams": [{
+                    "key": "PRIVACY_LEVEL",
+                    "value": ["PRIVATE", "SHARED"],
+                }],
+            }],
+        }))
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        bail!(
+            "transferring drive ownership from `{}` to `{}` failed with status `{}`: {}",
+            from.primary_email,
+            to.primary_email,
+            status,
+            resp.text().await?
+        );
+    }
+
+    let transfer: Value = resp.json().await?;
+    let id = transfer["id"].as_str().unwrap_or_default().to_string();
+
+    info!(
+        "started transfer `{}` of drive files from `{}` to `{}`",
+        id, from.primary_email, to.primary_email
+    );
+
+    Ok(id)
+}
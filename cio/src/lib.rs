@@ -2,6 +2,7 @@
 #![allow(clippy::field_reassign_with_default)]
 #![allow(clippy::nonstandard_macro_braces)]
 
+pub mod address_validation;
 pub mod airtable;
 pub mod analytics;
 pub mod api_tokens;
@@ -9,8 +10,10 @@ pub mod applicant_reviews;
 pub mod applicant_status;
 pub mod applicants;
 pub mod application_form;
+pub mod asciidoc;
 pub mod asset_inventory;
 pub mod auth_logins;
+pub mod business_days;
 pub mod certs;
 pub mod colors;
 pub mod companies;
@@ -30,16 +33,27 @@ pub mod huddles;
 pub mod interviews;
 pub mod journal_clubs;
 pub mod mailing_list;
+pub mod offboarding;
+pub mod plan;
 pub mod providers;
 pub mod rack_line;
 pub mod recorded_meetings;
 pub mod repos;
+pub mod rfd_changelogs;
+pub mod rfd_diffs;
+pub mod rfd_feeds;
+pub mod rfd_references;
+pub mod rfd_reservations;
+pub mod rfd_search;
 pub mod rfds;
 pub mod schema;
 pub mod shipment_status;
+pub mod shipment_tracking;
 pub mod shipments;
+pub mod shipping;
 pub mod shorturls;
 pub mod states;
+pub mod swag_forecast;
 pub mod swag_inventory;
 pub mod swag_store;
 pub mod tailscale;
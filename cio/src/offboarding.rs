@@ -0,0 +1,643 @@
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use async_trait::async_trait;
+use chrono::{naive::NaiveDate, DateTime, Utc};
+use log::{info, warn};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
+
+use crate::{
+    airtable::AIRTABLE_OFFBOARDING_EVENTS_TABLE,
+    companies::Company,
+    configs::{get_anniversary_calendar_id, User},
+    core::UpdateAirtableRecord,
+    db::Database,
+    gsuite::transfer_drive_ownership,
+    providers::ProviderOps,
+    schema::offboarding_events,
+    shipments::NewOutboundShipment,
+};
+
+/// A record of a single step of offboarding a user. Together the steps of an
+/// offboarding make up its audit trail.
+#[db {
+    new_struct_name = "OffboardingEvent",
+    airtable_base = "directory",
+    airtable_table = "AIRTABLE_OFFBOARDING_EVENTS_TABLE",
+    match_on = {
+        "offboarding_id" = "String",
+        "step" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[diesel(table_name = offboarding_events)]
+pub struct NewOffboardingEvent {
+    /// The id shared by all the steps of a single offboarding.
+    pub offboarding_id: String,
+    pub username: String,
+    pub email: String,
+    pub reason: String,
+    pub step: String,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub message: String,
+    pub created_at: DateTime<Utc>,
+    /// The start date of the user, so we can tell a rehire apart from the
+    /// earlier employment of the same username.
+    pub start_date: NaiveDate,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// Implement updating the Airtable record for an OffboardingEvent.
+#[async_trait]
+impl UpdateAirtableRecord<OffboardingEvent> for OffboardingEvent {
+    async fn update_airtable_record(&mut self, _record: OffboardingEvent) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Why a user is being offboarded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OffboardingReason {
+    RemovedFromConfig,
+    EndDate,
+    Manual,
+}
+
+impl ToString for OffboardingReason {
+    fn to_string(&self) -> String {
+        match self {
+            OffboardingReason::RemovedFromConfig => "removed from config".to_string(),
+            OffboardingReason::EndDate => "end date".to_string(),
+            OffboardingReason::Manual => "manual".to_string(),
+        }
+    }
+}
+
+/// The steps of offboarding a user, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OffboardingStep {
+    SuspendGSuite,
+    TransferDrive,
+    RemoveOkta,
+    RemoveGitHub,
+    RemoveZoom,
+    RemoveRamp,
+    RemoveAirtable,
+    RemoveSlack,
+    RevokeTailscale,
+    DeleteAnniversaryEvent,
+    ReturnAssets,
+    /// This is recorded once all the other steps have run.
+    Done,
+}
+
+impl OffboardingStep {
+    pub fn all() -> Vec<OffboardingStep> {
+        vec![
+            OffboardingStep::SuspendGSuite,
+            OffboardingStep::TransferDrive,
+            OffboardingStep::RemoveOkta,
+            OffboardingStep::RemoveGitHub,
+            OffboardingStep::RemoveZoom,
+            OffboardingStep::RemoveRamp,
+            OffboardingStep::RemoveAirtable,
+            OffboardingStep::RemoveSlack,
+            OffboardingStep::RevokeTailscale,
+            OffboardingStep::DeleteAnniversaryEvent,
+            OffboardingStep::ReturnAssets,
+        ]
+    }
+}
+
+impl ToString for OffboardingStep {
+    fn to_string(&self) -> String {
+        match self {
+            OffboardingStep::SuspendGSuite => "suspend-gsuite".to_string(),
+            OffboardingStep::TransferDrive => "transfer-drive".to_string(),
+            OffboardingStep::RemoveOkta => "remove-okta".to_string(),
+            OffboardingStep::RemoveGitHub => "remove-github".to_string(),
+            OffboardingStep::RemoveZoom => "remove-zoom".to_string(),
+            OffboardingStep::RemoveRamp => "remove-ramp".to_string(),
+            OffboardingStep::RemoveAirtable => "remove-airtable".to_string(),
+            OffboardingStep::RemoveSlack => "remove-slack".to_string(),
+            OffboardingStep::RevokeTailscale => "revoke-tailscale".to_string(),
+            OffboardingStep::DeleteAnniversaryEvent => "delete-anniversary-event".to_string(),
+            OffboardingStep::ReturnAssets => "return-assets".to_string(),
+            OffboardingStep::Done => "done".to_string(),
+        }
+    }
+}
+
+/// The outcome of a single offboarding step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepStatus {
+    Success,
+    Skipped,
+    /// Someone has to do the step by hand.
+    ManualActionRequired,
+    Failed,
+}
+
+impl ToString for StepStatus {
+    fn to_string(&self) -> String {
+        match self {
+            StepStatus::Success => "success".to_string(),
+            StepStatus::Skipped => "skipped".to_string(),
+            StepStatus::ManualActionRequired => "manual-action-required".to_string(),
+            StepStatus::Failed => "failed".to_string(),
+        }
+    }
+}
+
+/// Returns true if the user has already been fully offboarded. Only offboardings
+/// since the user's current start date count, so a rehire is offboarded again.
+pub async fn was_offboarded(db: &Database, company: &Company, user: &User) -> Result<bool> {
+    let events = offboarding_events::dsl::offboarding_events
+        .filter(offboarding_events::dsl::cio_company_id.eq(company.id))
+        .filter(offboarding_events::dsl::username.eq(user.username.to_string()))
+        .filter(offboarding_events::dsl::start_date.eq(user.start_date))
+        .filter(offboarding_events::dsl::step.eq(OffboardingStep::Done.to_string()))
+        .filter(offboarding_events::dsl::status.eq(StepStatus::Success.to_string()))
+        .load_async::<OffboardingEvent>(db.pool())
+        .await?;
+
+    Ok(!events.is_empty())
+}
+
+/// Returns the steps that already succeeded for the user, or that we already asked
+/// someone to do by hand, in any earlier offboarding since the user's current start
+/// date.
+pub async fn succeeded_steps(db: &Database, company: &Company, user: &User) -> Result<BTreeSet<String>> {
+    let events = offboarding_events::dsl::offboarding_events
+        .filter(offboarding_events::dsl::cio_company_id.eq(company.id))
+        .filter(offboarding_events::dsl::username.eq(user.username.to_string()))
+        .filter(offboarding_events::dsl::start_date.eq(user.start_date))
+        .filter(offboarding_events::dsl::status.eq_any(vec![
+            StepStatus::Success.to_string(),
+            StepStatus::ManualActionRequired.to_string(),
+        ]))
+        .load_async::<OffboardingEvent>(db.pool())
+        .await?;
+
+    Ok(events.into_iter().map(|e| e.step).collect())
+}
+
+/// Offboard a user from every provider. Every step runs even if an earlier one
+/// failed, so we revoke as much access as we can, and each step is recorded in
+/// the audit trail. Steps that succeeded in an earlier offboarding of the user are
+/// not run again, so retrying a failed offboarding only retries what failed. Steps
+/// we can't do ourselves are posted to Slack for someone to do by hand. This
+/// returns an error if any step failed.
+pub async fn offboard_user(db: &Database, company: &Company, user: &User, reason: OffboardingReason) -> Result<()> {
+    let offboarding_id = uuid::Uuid::new_v4().to_string();
+    info!(
+        "offboarding user `{}` ({}) as `{}`",
+        user.username,
+        reason.to_string(),
+        offboarding_id
+    );
+
+    let succeeded = succeeded_steps(db, company, user).await?;
+
+    let mut failed: Vec<String> = Default::default();
+    let mut manual: Vec<String> = Default::default();
+    for step in OffboardingStep::all() {
+        if succeeded.contains(&step.to_string()) {
+            // Don't do things like buying another asset return label.
+            info!(
+                "offboarding step `{}` for `{}` already succeeded, skipping",
+                step.to_string(),
+                user.username
+            );
+            record_step(
+                db,
+                company,
+                user,
+                &offboarding_id,
+                reason,
+                step,
+                StepStatus::Skipped,
+                "already done in an earlier offboarding",
+            )
+            .await?;
+            continue;
+        }
+
+        let (status, message) = match run_step(db, company, user, step).await {
+            Ok(StepOutcome::Completed(message)) => (StepStatus::Success, message),
+            Ok(StepOutcome::NotApplicable) => (StepStatus::Skipped, "".to_string()),
+            Ok(StepOutcome::ManualActionRequired(message)) => {
+                manual.push(message.to_string());
+                (StepStatus::ManualActionRequired, message)
+            }
+            Err(e) => {
+                warn!(
+                    "offboarding step `{}` for `{}` failed: {}",
+                    step.to_string(),
+                    user.username,
+                    e
+                );
+                failed.push(step.to_string());
+                (StepStatus::Failed, format!("{:?}", e))
+            }
+        };
+
+        record_step(db, company, user, &offboarding_id, reason, step, status, &message).await?;
+    }
+
+    let status = if failed.is_empty() {
+        StepStatus::Success
+    } else {
+        StepStatus::Failed
+    };
+    record_step(
+        db,
+        company,
+        user,
+        &offboarding_id,
+        reason,
+        OffboardingStep::Done,
+        status,
+        "",
+    )
+    .await?;
+
+    if !manual.is_empty() {
+        // Let someone know what they have to do by hand, this should not fail the
+        // offboarding.
+        if let Err(e) = send_manual_actions_slack_notification(db, company, user, &manual).await {
+            warn!(
+                "posting the manual offboarding steps for `{}` to slack failed: {}",
+                user.username, e
+            );
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!(
+            "offboarding user `{}` failed for steps: {}",
+            user.username,
+            failed.join(", ")
+        );
+    }
+
+    info!("offboarded user `{}`", user.username);
+
+    Ok(())
+}
+
+/// Post the offboarding steps someone has to do by hand to the finance channel.
+async fn send_manual_actions_slack_notification(
+    db: &Database,
+    company: &Company,
+    user: &User,
+    actions: &[String],
+) -> Result<()> {
+    if company.slack_channel_finance.is_empty() {
+        // Return early.
+        return Ok(());
+    }
+
+    let text = format!(
+        "*Offboarding `{}`* needs someone to do this by hand:\n{}",
+        user.username,
+        actions
+            .iter()
+            .map(|a| format!("• {}", a))
+            .collect::<Vec<String>>()
+            .join("\n")
+    );
+    let msg = FormattedMessage {
+        channel: company.slack_channel_finance.to_string(),
+        blocks: vec![MessageBlock {
+            block_type: MessageBlockType::Section,
+            text: Some(MessageBlockText {
+                text_type: MessageType::Markdown,
+                text,
+            }),
+            elements: Default::default(),
+            accessory: Default::default(),
+            block_id: Default::default(),
+            fields: Default::default(),
+        }],
+        attachments: Default::default(),
+    };
+    company.post_to_slack_channel(db, &msg).await?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn record_step(
+    db: &Database,
+    company: &Company,
+    user: &User,
+    offboarding_id: &str,
+    reason: OffboardingReason,
+    step: OffboardingStep,
+    status: StepStatus,
+    message: &str,
+) -> Result<OffboardingEvent> {
+    NewOffboardingEvent {
+        offboarding_id: offboarding_id.to_string(),
+        username: user.username.to_string(),
+        email: user.email.to_string(),
+        reason: reason.to_string(),
+        step: step.to_string(),
+        status: status.to_string(),
+        message: message.to_string(),
+        created_at: Utc::now(),
+        start_date: user.start_date,
+        cio_company_id: company.id,
+    }
+    .upsert_in_db(db)
+    .await
+}
+
+/// What came of running a single step.
+enum StepOutcome {
+    /// The step was done, with a message describing what was done.
+    Completed(String),
+    /// The step does not apply to this user or company.
+    NotApplicable,
+    /// We can't do the step ourselves, someone has to do what the message says.
+    ManualActionRequired(String),
+}
+
+/// Run a single step.
+async fn run_step(db: &Database, company: &Company, user: &User, step: OffboardingStep) -> Result<StepOutcome> {
+    match step {
+        OffboardingStep::SuspendGSuite => {
+            // If the company uses Okta, removing them from Okta takes care of GSuite.
+            if !company.okta_domain.is_empty() {
+                return Ok(StepOutcome::NotApplicable);
+            }
+
+            // Suspend the user rather than deleting them so we can transfer their data.
+            let gsuite = company.authenticate_google_admin(db).await?;
+            gsuite.delete_user(db, company, user).await?;
+
+            Ok(StepOutcome::Completed(format!("suspended `{}`", user.email)))
+        }
+        OffboardingStep::TransferDrive => {
+            let manager = user.manager(db).await;
+            if manager.username == user.username {
+                return Ok(StepOutcome::NotApplicable);
+            }
+
+            let gsuite = company.authenticate_google_admin(db).await?;
+            let from = gsuite
+                .users()
+                .get(
+                    &user.email,
+                    gsuite_api::types::DirectoryUsersListProjection::Full,
+                    gsuite_api::types::ViewType::AdminView,
+                )
+                .await?;
+            let to = gsuite
+                .users()
+                .get(
+                    &manager.email,
+                    gsuite_api::types::DirectoryUsersListProjection::Full,
+                    gsuite_api::types::ViewType::AdminView,
+                )
+                .await?;
+
+            let transfer_id = transfer_drive_ownership(db, company, &from, &to).await?;
+
+            Ok(StepOutcome::Completed(format!(
+                "started transfer `{}` of drive files to `{}`",
+                transfer_id, manager.email
+            )))
+        }
+        OffboardingStep::RemoveOkta => match company.authenticate_okta() {
+            Some(okta) => {
+                okta.delete_user(db, company, user).await?;
+                Ok(StepOutcome::Completed(format!("suspended `{}`", user.email)))
+            }
+            None => Ok(StepOutcome::NotApplicable),
+        },
+        OffboardingStep::RemoveGitHub => {
+            if user.github.is_empty() {
+                return Ok(StepOutcome::NotApplicable);
+            }
+
+            let github = company.authenticate_github()?;
+            github.delete_user(db, company, user).await?;
+
+            Ok(StepOutcome::Completed(format!(
+                "removed `{}` from `{}`",
+                user.github, company.github_org
+            )))
+        }
+        OffboardingStep::RemoveZoom => match company.authenticate_zoom(db).await {
+            Ok(zoom) => {
+                zoom.delete_user(db, company, user).await?;
+                Ok(StepOutcome::Completed(format!("deleted `{}`", user.email)))
+            }
+            Err(_) => Ok(StepOutcome::NotApplicable),
+        },
+        OffboardingStep::RemoveRamp => match company.authenticate_ramp(db).await {
+            // The Ramp API can't deactivate users, so someone has to do it by hand.
+            // We keep the Ramp user so we keep their purchase history.
+            Ok(_) => Ok(StepOutcome::ManualActionRequired(format!(
+                "deactivate `{}` in Ramp, we can't do it through the API",
+                user.email
+            ))),
+            Err(_) => Ok(StepOutcome::NotApplicable),
+        },
+        OffboardingStep::RemoveAirtable => {
+            // We don't need a base id here since we are only using the enterprise api features.
+            let airtable = company.authenticate_airtable("");
+            airtable.delete_user(db, company, user).await?;
+
+            Ok(StepOutcome::Completed(format!("removed `{}`", user.email)))
+        }
+        OffboardingStep::RemoveSlack => match company.authenticate_slack(db).await {
+            Ok(slack) => {
+                slack.delete_user(db, company, user).await?;
+                Ok(StepOutcome::Completed(format!("removed `{}`", user.email)))
+            }
+            Err(_) => Ok(StepOutcome::NotApplicable),
+        },
+        OffboardingStep::RevokeTailscale => {
+            if company.tailscale_api_key.is_empty() {
+                return Ok(StepOutcome::NotApplicable);
+            }
+
+            let tailscale = company.authenticate_tailscale();
+            let mut revoked: Vec<String> = Default::default();
+            for device in tailscale.list_devices().await? {
+                if device.user != user.email {
+                    continue;
+                }
+
+                tailscale.delete_device(&device.id).await?;
+                revoked.push(device.name);
+            }
+
+            if revoked.is_empty() {
+                return Ok(StepOutcome::NotApplicable);
+            }
+
+            Ok(StepOutcome::Completed(format!(
+                "revoked devices: {}",
+                revoked.join(", ")
+            )))
+        }
+        OffboardingStep::DeleteAnniversaryEvent => {
+            if user.google_anniversary_event_id.is_empty() {
+                return Ok(StepOutcome::NotApplicable);
+            }
+
+            let gcal = company.authenticate_google_calendar(db).await?;
+            let anniversary_cal_id = get_anniversary_calendar_id(&gcal).await?;
+            gcal.events()
+                .delete(
+                    &anniversary_cal_id,
+                    &user.google_anniversary_event_id,
+                    true, // send_notifications
+                    google_calendar::types::SendUpdates::All,
+                )
+                .await?;
+
+            Ok(StepOutcome::Completed(format!(
+                "deleted event `{}`",
+                user.google_anniversary_event_id
+            )))
+        }
+        OffboardingStep::ReturnAssets => {
+            if user.home_address_formatted.is_empty() {
+                return Ok(StepOutcome::NotApplicable);
+            }
+
+            // Send them a box and a return label for their equipment. Their work
+            // email is suspended, so use their personal one.
+            let mut new_shipment = NewOutboundShipment::from(user.clone());
+            new_shipment.email = user.recovery_email.to_string();
+            new_shipment.contents = "Asset return kit: box and return label for company equipment".to_string();
+
+            // Always a new shipment, matching on their address would update whatever
+            // we last sent them.
+            let mut shipment = new_shipment.create_in_db(db).await?;
+            if let Err(e) = shipment.create_or_get_shipment(db).await {
+                if shipment.provider_id.is_empty() {
+                    // Retrying the step creates a new shipment, so don't leave this
+                    // one around for the next shipments refresh to buy a label for.
+                    shipment.messages = format!("Buying the label failed: {}", e);
+                    if let Err(err) = shipment.cancel(db).await {
+                        warn!("cancelling asset return shipment {} failed: {}", shipment.id, err);
+                    }
+                }
+
+                return Err(e);
+            }
+            shipment.update(db).await?;
+
+            // Email them the prepaid label to send the box back with.
+            let return_shipment = shipment.create_return_shipment(db).await?;
+
+            Ok(StepOutcome::Completed(format!(
+                "created asset return shipment to `{}`, return label `{}`",
+                shipment.address_formatted, return_shipment.tracking_number
+            )))
+        }
+        OffboardingStep::Done => Ok(StepOutcome::NotApplicable),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde_json::json;
+
+    use crate::{
+        companies::Company,
+        configs::User,
+        db::Database,
+        offboarding::{record_step, succeeded_steps, was_offboarded, OffboardingReason, OffboardingStep, StepStatus},
+    };
+
+    #[test]
+    fn test_offboarding_steps() {
+        let steps = OffboardingStep::all();
+        assert!(!steps.contains(&OffboardingStep::Done));
+
+        // We match on the names of earlier steps, so they have to be unique.
+        let names: BTreeSet<String> = steps.iter().map(|s| s.to_string()).collect();
+        assert_eq!(names.len(), steps.len());
+        assert!(!names.contains(&OffboardingStep::Done.to_string()));
+    }
+
+    #[ignore]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_offboarding_rehire() {
+        crate::utils::setup_logger();
+
+        let db = Database::new().await;
+
+        // Get the company id for Oxide.
+        let oxide = Company::get_from_db(&db, "Oxide".to_string()).await.unwrap();
+
+        let mut user: User = serde_json::from_value(json!({
+            "first_name": "Offboarding",
+            "last_name": "Test",
+            "username": "offboarding-test",
+            "email": "offboarding-test@example.com",
+            "start_date": "2020-01-06",
+            "cio_company_id": oxide.id,
+        }))
+        .unwrap();
+
+        let mut events = vec![record_step(
+            &db,
+            &oxide,
+            &user,
+            "offboarding-test",
+            OffboardingReason::Manual,
+            OffboardingStep::SuspendGSuite,
+            StepStatus::Success,
+            "",
+        )
+        .await
+        .unwrap()];
+        assert!(!was_offboarded(&db, &oxide, &user).await.unwrap());
+
+        events.push(
+            record_step(
+                &db,
+                &oxide,
+                &user,
+                "offboarding-test",
+                OffboardingReason::Manual,
+                OffboardingStep::Done,
+                StepStatus::Success,
+                "",
+            )
+            .await
+            .unwrap(),
+        );
+        assert!(was_offboarded(&db, &oxide, &user).await.unwrap());
+        assert!(succeeded_steps(&db, &oxide, &user)
+            .await
+            .unwrap()
+            .contains(&OffboardingStep::SuspendGSuite.to_string()));
+
+        // A rehire with the same username is offboarded again, from the start.
+        user.start_date = chrono::NaiveDate::from_ymd(2022, 3, 7);
+        assert!(!was_offboarded(&db, &oxide, &user).await.unwrap());
+        assert!(succeeded_steps(&db, &oxide, &user).await.unwrap().is_empty());
+
+        for event in events {
+            event.delete_from_db(&db).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,1094 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    companies::Company,
+    configs::{
+        BuildingConfig, Buildings, ConferenceRooms, Config, GroupConfig, Groups, ResourceConfig, UserConfig, Users,
+    },
+    db::Database,
+    offboarding::was_offboarded,
+    providers::ProviderOps,
+};
+
+/// The fields on a user that are populated by other services rather than the
+/// config files, so we don't count them as changes in a plan.
+const USER_IGNORED_FIELDS: &[&str] = &[
+    "id",
+    "airtable_record_id",
+    "cio_company_id",
+    "home_address_street_1",
+    "home_address_street_2",
+    "home_address_city",
+    "home_address_state",
+    "home_address_zipcode",
+    "home_address_country",
+    "home_address_country_code",
+    "home_address_formatted",
+    "home_address_latitude",
+    "home_address_longitude",
+    "work_address_street_1",
+    "work_address_street_2",
+    "work_address_city",
+    "work_address_state",
+    "work_address_zipcode",
+    "work_address_country",
+    "work_address_country_code",
+    "work_address_formatted",
+    "link_to_building",
+    "start_date",
+    "birthday",
+    "public_ssh_keys",
+    "google_anniversary_event_id",
+    "gusto_id",
+    "okta_id",
+    "google_id",
+    "airtable_id",
+    "ramp_id",
+    "zoom_id",
+    "slack_id",
+    "geocode_cache",
+    "working_on",
+    "address_validation",
+];
+
+/// The fields on a group that are populated by other services rather than the
+/// config files.
+const GROUP_IGNORED_FIELDS: &[&str] = &["id", "airtable_record_id", "cio_company_id", "members"];
+
+/// The fields on a building that are populated by other services rather than the
+/// config files.
+const BUILDING_IGNORED_FIELDS: &[&str] = &[
+    "id",
+    "airtable_record_id",
+    "cio_company_id",
+    "employees",
+    "conference_rooms",
+    "geocode_cache",
+];
+
+/// The fields on a conference room that are populated by other services rather than the
+/// config files.
+const CONFERENCE_ROOM_IGNORED_FIELDS: &[&str] = &["id", "airtable_record_id", "cio_company_id", "link_to_building"];
+
+/// A service that a config sync writes to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, JsonSchema, Deserialize, Serialize)]
+pub enum Provider {
+    Database,
+    GSuite,
+    Okta,
+    GitHub,
+    Zoom,
+    Ramp,
+    Gusto,
+    Slack,
+    Airtable,
+}
+
+impl ToString for Provider {
+    fn to_string(&self) -> String {
+        match self {
+            Provider::Database => "Database".to_string(),
+            Provider::GSuite => "GSuite".to_string(),
+            Provider::Okta => "Okta".to_string(),
+            Provider::GitHub => "GitHub".to_string(),
+            Provider::Zoom => "Zoom".to_string(),
+            Provider::Ramp => "Ramp".to_string(),
+            Provider::Gusto => "Gusto".to_string(),
+            Provider::Slack => "Slack".to_string(),
+            Provider::Airtable => "Airtable".to_string(),
+        }
+    }
+}
+
+/// The kind of change a sync would make to a resource.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, JsonSchema, Deserialize, Serialize)]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+}
+
+impl Operation {
+    /// The prefix for the operation in a diff.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operation::Create => "+",
+            Operation::Update => "!",
+            Operation::Delete => "-",
+        }
+    }
+}
+
+impl ToString for Operation {
+    fn to_string(&self) -> String {
+        match self {
+            Operation::Create => "create".to_string(),
+            Operation::Update => "update".to_string(),
+            Operation::Delete => "delete".to_string(),
+        }
+    }
+}
+
+/// A single field that would change on a resource.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+/// A single change a sync would make against a provider.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct PlannedChange {
+    pub provider: Provider,
+    pub operation: Operation,
+    /// The kind of resource, for example `user` or `group membership`.
+    pub kind: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldChange>,
+}
+
+/// The full set of changes a config sync would make, without making any of them.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct Plan {
+    pub company: String,
+    /// The branch or commit of the configs repo the plan was computed from.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub git_ref: String,
+    #[serde(default)]
+    pub changes: Vec<PlannedChange>,
+}
+
+impl Plan {
+    pub fn new(company: &Company, git_ref: &str) -> Self {
+        Plan {
+            company: company.name.to_string(),
+            git_ref: git_ref.to_string(),
+            changes: Default::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn push(&mut self, provider: Provider, operation: Operation, kind: &str, name: &str, fields: Vec<FieldChange>) {
+        self.changes.push(PlannedChange {
+            provider,
+            operation,
+            kind: kind.to_string(),
+            name: name.to_string(),
+            fields,
+        });
+    }
+
+    /// Return the number of changes for an operation.
+    pub fn count(&self, operation: Operation) -> usize {
+        self.changes.iter().filter(|c| c.operation == operation).count()
+    }
+
+    /// Return a one line summary of the plan.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} to create, {} to update, {} to delete",
+            self.count(Operation::Create),
+            self.count(Operation::Update),
+            self.count(Operation::Delete)
+        )
+    }
+
+    /// Render the plan as a human-readable diff, grouped by provider.
+    pub fn as_diff(&self) -> String {
+        let mut by_provider: BTreeMap<Provider, Vec<&PlannedChange>> = BTreeMap::new();
+        for change in &self.changes {
+            by_provider.entry(change.provider).or_default().push(change);
+        }
+
+        let mut out = format!("Plan for `{}`", self.company);
+        if !self.git_ref.is_empty() {
+            out.push_str(&format!(" at `{}`", self.git_ref));
+        }
+        out.push_str(&format!(": {}\n", self.summary()));
+
+        for (provider, changes) in by_provider {
+            out.push_str(&format!("\n{}:\n", provider.to_string()));
+            for change in changes {
+                out.push_str(&format!(
+                    "{} {} `{}`\n",
+                    change.operation.symbol(),
+                    change.kind,
+                    change.name
+                ));
+                for field in &change.fields {
+                    out.push_str(&format!(
+                        "{}     {}: {} -> {}\n",
+                        change.operation.symbol(),
+                        field.field,
+                        field.old,
+                        field.new
+                    ));
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Render the plan as markdown, suitable for a GitHub check run or comment.
+    pub fn as_markdown(&self) -> String {
+        if self.is_empty() {
+            return format!("No changes for `{}`.", self.company);
+        }
+
+        format!("**{}**\n\n```diff\n{}```", self.summary(), self.as_diff())
+    }
+}
+
+/// Compare two serialized resources and return the fields that differ.
+/// Fields that are missing on one side are treated as `null`.
+pub fn diff_fields(old: &serde_json::Value, new: &serde_json::Value, ignore: &[&str]) -> Vec<FieldChange> {
+    let empty = serde_json::Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    let mut changes: Vec<FieldChange> = Default::default();
+    for key in keys {
+        if ignore.contains(&key.as_str()) {
+            continue;
+        }
+
+        let o = old.get(key).cloned().unwrap_or(serde_json::Value::Null);
+        let n = new.get(key).cloned().unwrap_or(serde_json::Value::Null);
+        if is_empty_value(&o) && is_empty_value(&n) {
+            continue;
+        }
+
+        if o != n {
+            changes.push(FieldChange {
+                field: key.to_string(),
+                old: o,
+                new: n,
+            });
+        }
+    }
+
+    changes
+}
+
+fn is_empty_value(v: &serde_json::Value) -> bool {
+    match v {
+        serde_json::Value::Null => true,
+        serde_json::Value::String(s) => s.is_empty(),
+        serde_json::Value::Array(a) => a.is_empty(),
+        _ => false,
+    }
+}
+
+/// Return the members that were added and removed between two lists.
+fn diff_members(old: &[String], new: &[String]) -> (Vec<String>, Vec<String>) {
+    let added = new.iter().filter(|g| !old.contains(g)).cloned().collect();
+    let removed = old.iter().filter(|g| !new.contains(g)).cloned().collect();
+    (added, removed)
+}
+
+/// Plan the changes that `sync_users` would make.
+pub async fn plan_users(
+    db: &Database,
+    github: &octorust::Client,
+    users: &BTreeMap<String, UserConfig>,
+    company: &Company,
+    plan: &mut Plan,
+) -> Result<()> {
+    let gsuite = company.authenticate_google_admin(db).await?;
+    let okta_auth = company.authenticate_okta();
+    let ramp_auth = company.authenticate_ramp(db).await;
+    let zoom_auth = company.authenticate_zoom(db).await;
+    let gusto_auth = company.authenticate_gusto(db).await;
+    let slack_auth = company.authenticate_slack(db).await;
+
+    let gsuite_users: BTreeSet<String> = gsuite
+        .list_provider_users(company)
+        .await?
+        .into_iter()
+        .map(|u| u.primary_email)
+        .collect();
+
+    let mut okta_users: BTreeSet<String> = Default::default();
+    if let Some(ref okta) = okta_auth {
+        for u in okta.list_provider_users(company).await? {
+            if let Some(profile) = u.profile {
+                okta_users.insert(profile.email);
+            }
+        }
+    }
+
+    let github_members: BTreeSet<String> = github
+        .list_provider_users(company)
+        .await?
+        .into_iter()
+        .map(|u| u.login.to_lowercase())
+        .collect();
+
+    let mut ramp_users: BTreeSet<String> = Default::default();
+    if let Ok(ref ramp) = ramp_auth {
+        for u in ramp.list_provider_users(company).await? {
+            ramp_users.insert(u.email);
+        }
+    }
+
+    let mut zoom_users: BTreeSet<String> = Default::default();
+    if let Ok(ref zoom) = zoom_auth {
+        for u in zoom.list_provider_users(company).await? {
+            zoom_users.insert(u.email);
+        }
+        for u in zoom
+            .users()
+            .get_all(
+                zoom_api::types::UsersStatus::Pending,
+                "", // role id
+                zoom_api::types::UsersIncludeFields::Noop,
+            )
+            .await?
+        {
+            zoom_users.insert(u.email);
+        }
+    }
+
+    let mut gusto_users: BTreeSet<String> = Default::default();
+    if let Ok((ref gusto, ref gusto_company_id)) = gusto_auth {
+        for e in gusto.employees().get_all_company(gusto_company_id, false, &[]).await? {
+            gusto_users.insert(e.email);
+        }
+    }
+
+    let mut slack_users: BTreeSet<String> = Default::default();
+    if let Ok(ref slack) = slack_auth {
+        for u in slack.list_provider_users(company).await? {
+            slack_users.insert(u.profile.email);
+        }
+    }
+
+    let mut db_users: BTreeMap<String, crate::configs::User> = Default::default();
+    for u in Users::get_from_db(db, company.id).await? {
+        db_users.insert(u.username.to_string(), u);
+    }
+
+    // The identity provider is Okta if the company uses it, otherwise GSuite.
+    let (identity_provider, identity_users) = if okta_auth.is_some() {
+        (Provider::Okta, &okta_users)
+    } else {
+        (Provider::GSuite, &gsuite_users)
+    };
+
+    // Users whose end date has passed are offboarded instead of synced.
+    let today = Utc::now().naive_utc().date();
+    let mut ended_users: Vec<crate::configs::User> = Default::default();
+
+    for config in users.values() {
+        let mut user = config.clone();
+        user.expand_from_config(company);
+
+        let existing = db_users.remove(&user.username);
+
+        if user.has_ended(today) {
+            // We keep them in the database, since they are still in the config files.
+            if let Some(e) = existing {
+                if !was_offboarded(db, company, &e).await? {
+                    ended_users.push(e);
+                }
+            }
+            continue;
+        }
+
+        let old = match &existing {
+            Some(e) => serde_json::to_value(e)?,
+            None => serde_json::json!({}),
+        };
+        let fields = diff_fields(&old, &serde_json::to_value(&user)?, USER_IGNORED_FIELDS);
+
+        if existing.is_none() {
+            plan.push(
+                Provider::Database,
+                Operation::Create,
+                "user",
+                &user.username,
+                fields.clone(),
+            );
+            plan.push(Provider::Airtable, Operation::Create, "user", &user.username, vec![]);
+        } else if !fields.is_empty() {
+            plan.push(
+                Provider::Database,
+                Operation::Update,
+                "user",
+                &user.username,
+                fields.clone(),
+            );
+            plan.push(Provider::Airtable, Operation::Update, "user", &user.username, vec![]);
+        }
+
+        if !identity_users.contains(&user.email) {
+            plan.push(identity_provider, Operation::Create, "user", &user.email, vec![]);
+        } else if !fields.is_empty() {
+            plan.push(
+                identity_provider,
+                Operation::Update,
+                "user",
+                &user.email,
+                fields.clone(),
+            );
+        }
+
+        // Zoom is only managed by us if the company does not use Okta.
+        if okta_auth.is_none() && zoom_auth.is_ok() && user.is_full_time() && !zoom_users.contains(&user.email) {
+            plan.push(Provider::Zoom, Operation::Create, "user", &user.email, vec![]);
+        }
+
+        // Figure out which groups the user was added to or removed from.
+        let old_groups = existing.as_ref().map(|e| e.groups.clone()).unwrap_or_default();
+        let (added, removed) = diff_members(&old_groups, &user.groups);
+        for (operation, groups) in [(Operation::Create, added), (Operation::Delete, removed)] {
+            for group in groups {
+                let name = format!("{} in {}", user.username, group);
+                plan.push(identity_provider, operation, "group membership", &name, vec![]);
+                if !user.github.is_empty() {
+                    plan.push(Provider::GitHub, operation, "team membership", &name, vec![]);
+                }
+                if slack_auth.is_ok() {
+                    plan.push(Provider::Slack, operation, "group membership", &name, vec![]);
+                }
+            }
+        }
+
+        if !user.github.is_empty() && !github_members.contains(&user.github.to_lowercase()) {
+            plan.push(
+                Provider::GitHub,
+                Operation::Create,
+                "org membership",
+                &user.github,
+                vec![],
+            );
+        }
+
+        if ramp_auth.is_ok() && user.is_full_time() && !user.recovery_phone.is_empty() {
+            if !ramp_users.contains(&user.email) {
+                plan.push(Provider::Ramp, Operation::Create, "user", &user.email, vec![]);
+            } else {
+                let ramp_fields: Vec<FieldChange> = fields
+                    .iter()
+                    .filter(|f| f.field == "department" || f.field == "manager")
+                    .cloned()
+                    .collect();
+                if !ramp_fields.is_empty() {
+                    plan.push(Provider::Ramp, Operation::Update, "user", &user.email, ramp_fields);
+                }
+            }
+        }
+
+        if slack_auth.is_ok() {
+            if !slack_users.contains(&user.email) {
+                plan.push(Provider::Slack, Operation::Create, "user invite", &user.email, vec![]);
+            } else {
+                let profile_fields: Vec<FieldChange> = fields
+                    .iter()
+                    .filter(|f| f.field == "title" || f.field == "pronouns" || f.field == "manager")
+                    .cloned()
+                    .collect();
+                if !profile_fields.is_empty() {
+                    plan.push(
+                        Provider::Slack,
+                        Operation::Update,
+                        "profile",
+                        &user.email,
+                        profile_fields,
+                    );
+                }
+            }
+        }
+
+        // We only create users in Gusto once we know their start date and US home
+        // address, which we only ever have for users that are already in the database.
+        if let Some(e) = &existing {
+            if gusto_auth.is_ok()
+                && user.is_full_time()
+                && e.gusto_id.is_empty()
+                && !gusto_users.contains(&user.email)
+                && !gusto_users.contains(&user.recovery_email)
+                && e.start_date != crate::utils::default_date()
+                && !e.home_address_street_1.is_empty()
+                && (e.home_address_country == "US"
+                    || e.home_address_country == "United States"
+                    || e.home_address_country == "USA")
+            {
+                plan.push(
+                    Provider::Gusto,
+                    Operation::Create,
+                    "employee",
+                    &user.recovery_email,
+                    vec![],
+                );
+            }
+        }
+    }
+
+    for user in &ended_users {
+        plan_offboarding(
+            plan,
+            user,
+            okta_auth.is_some(),
+            &gsuite_users,
+            &github_members,
+            &zoom_users,
+            &slack_users,
+        );
+    }
+
+    // Any users left over in the database would be offboarded, unless they already
+    // were when their end date passed, and removed.
+    for (username, user) in db_users {
+        plan.push(Provider::Database, Operation::Delete, "user", &username, vec![]);
+
+        if !was_offboarded(db, company, &user).await? {
+            plan_offboarding(
+                plan,
+                &user,
+                okta_auth.is_some(),
+                &gsuite_users,
+                &github_members,
+                &zoom_users,
+                &slack_users,
+            );
+        }
+
+        plan.push(Provider::Airtable, Operation::Delete, "user", &username, vec![]);
+    }
+
+    Ok(())
+}
+
+/// Plan the changes that offboarding a user would make. Their GSuite, Okta and
+/// Slack accounts are deactivated rather than deleted, so we keep their data.
+fn plan_offboarding(
+    plan: &mut Plan,
+    user: &crate::configs::User,
+    uses_okta: bool,
+    gsuite_users: &BTreeSet<String>,
+    github_members: &BTreeSet<String>,
+    zoom_users: &BTreeSet<String>,
+    slack_users: &BTreeSet<String>,
+) {
+    let deactivated = vec![FieldChange {
+        field: "active".to_string(),
+        old: serde_json::json!(true),
+        new: serde_json::json!(false),
+    }];
+
+    // If the company uses Okta, deactivating them in Okta takes care of GSuite.
+    if uses_okta {
+        plan.push(
+            Provider::Okta,
+            Operation::Update,
+            "user",
+            &user.email,
+            deactivated.clone(),
+        );
+    } else if gsuite_users.contains(&user.email) {
+        plan.push(
+            Provider::GSuite,
+            Operation::Update,
+            "user",
+            &user.email,
+            deactivated.clone(),
+        );
+    }
+
+    if !user.github.is_empty() && github_members.contains(&user.github.to_lowercase()) {
+        plan.push(
+            Provider::GitHub,
+            Operation::Delete,
+            "org membership",
+            &user.github,
+            vec![],
+        );
+    }
+
+    if zoom_users.contains(&user.email) {
+        plan.push(Provider::Zoom, Operation::Delete, "user", &user.email, vec![]);
+    }
+
+    if slack_users.contains(&user.email) {
+        plan.push(Provider::Slack, Operation::Update, "user", &user.email, deactivated);
+    }
+}
+
+/// Plan the changes that `sync_groups` would make.
+pub async fn plan_groups(
+    db: &Database,
+    groups: &BTreeMap<String, GroupConfig>,
+    company: &Company,
+    plan: &mut Plan,
+) -> Result<()> {
+    let gsuite = company.authenticate_google_admin(db).await?;
+    let github = company.authenticate_github()?;
+    let okta_auth = company.authenticate_okta();
+    let slack_auth = company.authenticate_slack(db).await;
+
+    let gsuite_groups: BTreeSet<String> = gsuite
+        .list_provider_groups(company)
+        .await?
+        .into_iter()
+        .map(|g| g.email)
+        .collect();
+
+    let github_teams: BTreeSet<String> = github
+        .list_provider_groups(company)
+        .await?
+        .into_iter()
+        .map(|t| t.slug)
+        .collect();
+
+    let mut okta_groups: BTreeSet<String> = Default::default();
+    if let Some(ref okta) = okta_auth {
+        for g in okta.list_provider_groups(company).await? {
+            if let Some(profile) = g.profile {
+                okta_groups.insert(profile.name);
+            }
+        }
+    }
+
+    // Slack does not let us delete user groups, so the ones we removed are disabled.
+    let mut slack_groups: BTreeSet<String> = Default::default();
+    if let Ok(ref slack) = slack_auth {
+        for g in slack.list_provider_groups(company).await? {
+            if !g.is_disabled() {
+                slack_groups.insert(g.handle);
+            }
+        }
+    }
+
+    let mut db_groups: BTreeMap<String, crate::configs::Group> = Default::default();
+    for g in Groups::get_from_db(db, company.id).await? {
+        db_groups.insert(g.name.to_string(), g);
+    }
+
+    for config in groups.values() {
+        let mut group = config.clone();
+        group.expand(company);
+
+        let existing = db_groups.remove(&group.name);
+        let old = match &existing {
+            Some(e) => serde_json::to_value(e)?,
+            None => serde_json::json!({}),
+        };
+        let fields = diff_fields(&old, &serde_json::to_value(&group)?, GROUP_IGNORED_FIELDS);
+
+        if existing.is_none() {
+            plan.push(
+                Provider::Database,
+                Operation::Create,
+                "group",
+                &group.name,
+                fields.clone(),
+            );
+            plan.push(Provider::Airtable, Operation::Create, "group", &group.name, vec![]);
+        } else if !fields.is_empty() {
+            plan.push(
+                Provider::Database,
+                Operation::Update,
+                "group",
+                &group.name,
+                fields.clone(),
+            );
+            plan.push(Provider::Airtable, Operation::Update, "group", &group.name, vec![]);
+        }
+
+        let email = format!("{}@{}", group.name, company.gsuite_domain);
+        let mut providers = vec![
+            (Provider::GSuite, gsuite_groups.contains(&email)),
+            (Provider::GitHub, github_teams.contains(&group.name)),
+        ];
+        if okta_auth.is_some() {
+            providers.push((Provider::Okta, okta_groups.contains(&group.name)));
+        }
+        if slack_auth.is_ok() {
+            providers.push((Provider::Slack, slack_groups.contains(&group.name)));
+        }
+
+        for (provider, exists) in providers {
+            if !exists {
+                plan.push(provider, Operation::Create, "group", &group.name, vec![]);
+            } else if !fields.is_empty() {
+                plan.push(provider, Operation::Update, "group", &group.name, fields.clone());
+            }
+        }
+    }
+
+    // Any groups left over in the database would be removed everywhere.
+    for name in db_groups.keys() {
+        plan.push(Provider::Database, Operation::Delete, "group", name, vec![]);
+        plan.push(Provider::Airtable, Operation::Delete, "group", name, vec![]);
+
+        if gsuite_groups.contains(&format!("{}@{}", name, company.gsuite_domain)) {
+            plan.push(Provider::GSuite, Operation::Delete, "group", name, vec![]);
+        }
+
+        if github_teams.contains(name) {
+            plan.push(Provider::GitHub, Operation::Delete, "group", name, vec![]);
+        }
+
+        if okta_groups.contains(name) {
+            plan.push(Provider::Okta, Operation::Delete, "group", name, vec![]);
+        }
+
+        if slack_groups.contains(name) {
+            plan.push(Provider::Slack, Operation::Delete, "group", name, vec![]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Plan the changes that `sync_buildings` would make.
+pub async fn plan_buildings(
+    db: &Database,
+    buildings: &BTreeMap<String, BuildingConfig>,
+    company: &Company,
+    plan: &mut Plan,
+) -> Result<()> {
+    let gsuite = company.authenticate_google_admin(db).await?;
+
+    let mut gsuite_buildings: BTreeSet<String> = gsuite
+        .resources()
+        .buildings_list_all(&company.gsuite_account_id)
+        .await?
+        .into_iter()
+        .map(|b| b.building_id)
+        .collect();
+
+    let mut db_buildings: BTreeMap<String, crate::configs::Building> = Default::default();
+    for b in Buildings::get_from_db(db, company.id).await? {
+        db_buildings.insert(b.name.to_string(), b);
+    }
+
+    for config in buildings.values() {
+        let mut building = config.clone();
+        building.expand(company);
+
+        let existing = db_buildings.remove(&building.name);
+        let old = match &existing {
+            Some(e) => serde_json::to_value(e)?,
+            None => serde_json::json!({}),
+        };
+        let fields = diff_fields(&old, &serde_json::to_value(&building)?, BUILDING_IGNORED_FIELDS);
+
+        if existing.is_none() {
+            plan.push(
+                Provider::Database,
+                Operation::Create,
+                "building",
+                &building.name,
+                fields.clone(),
+            );
+            plan.push(
+                Provider::Airtable,
+                Operation::Create,
+                "building",
+                &building.name,
+                vec![],
+            );
+        } else if !fields.is_empty() {
+            plan.push(
+                Provider::Database,
+                Operation::Update,
+                "building",
+                &building.name,
+                fields.clone(),
+            );
+            plan.push(
+                Provider::Airtable,
+                Operation::Update,
+                "building",
+                &building.name,
+                vec![],
+            );
+        }
+
+        if !gsuite_buildings.remove(&building.name) {
+            plan.push(Provider::GSuite, Operation::Create, "building", &building.name, vec![]);
+        } else if !fields.is_empty() {
+            plan.push(Provider::GSuite, Operation::Update, "building", &building.name, fields);
+        }
+    }
+
+    for name in db_buildings.keys() {
+        plan.push(Provider::Database, Operation::Delete, "building", name, vec![]);
+        plan.push(Provider::Airtable, Operation::Delete, "building", name, vec![]);
+    }
+
+    // Any buildings in GSuite that are not in the config would be removed.
+    for name in gsuite_buildings {
+        plan.push(Provider::GSuite, Operation::Delete, "building", &name, vec![]);
+    }
+
+    Ok(())
+}
+
+/// Plan the changes that `sync_conference_rooms` would make.
+pub async fn plan_conference_rooms(
+    db: &Database,
+    conference_rooms: &BTreeMap<String, ResourceConfig>,
+    company: &Company,
+    plan: &mut Plan,
+) -> Result<()> {
+    let gsuite = company.authenticate_google_admin(db).await?;
+
+    let mut gsuite_resources: BTreeSet<String> = gsuite
+        .resources()
+        .calendars_list_all(
+            &company.gsuite_account_id,
+            "", // order by
+            "", // query
+        )
+        .await?
+        .into_iter()
+        .map(|r| r.resource_name)
+        .collect();
+
+    let mut db_rooms: BTreeMap<String, crate::configs::ConferenceRoom> = Default::default();
+    for r in ConferenceRooms::get_from_db(db, company.id).await? {
+        db_rooms.insert(r.name.to_string(), r);
+    }
+
+    for config in conference_rooms.values() {
+        let mut room = config.clone();
+        room.cio_company_id = company.id;
+
+        let existing = db_rooms.remove(&room.name);
+        let old = match &existing {
+            Some(e) => serde_json::to_value(e)?,
+            None => serde_json::json!({}),
+        };
+        let fields = diff_fields(&old, &serde_json::to_value(&room)?, CONFERENCE_ROOM_IGNORED_FIELDS);
+
+        if existing.is_none() {
+            plan.push(
+                Provider::Database,
+                Operation::Create,
+                "conference room",
+                &room.name,
+                fields.clone(),
+            );
+            plan.push(
+                Provider::Airtable,
+                Operation::Create,
+                "conference room",
+                &room.name,
+                vec![],
+            );
+        } else if !fields.is_empty() {
+            plan.push(
+                Provider::Database,
+                Operation::Update,
+                "conference room",
+                &room.name,
+                fields.clone(),
+            );
+            plan.push(
+                Provider::Airtable,
+                Operation::Update,
+                "conference room",
+                &room.name,
+                vec![],
+            );
+        }
+
+        if !gsuite_resources.remove(&room.name) {
+            plan.push(
+                Provider::GSuite,
+                Operation::Create,
+                "conference room",
+                &room.name,
+                vec![],
+            );
+        } else if !fields.is_empty() {
+            plan.push(
+                Provider::GSuite,
+                Operation::Update,
+                "conference room",
+                &room.name,
+                fields,
+            );
+        }
+    }
+
+    for name in db_rooms.keys() {
+        plan.push(Provider::Database, Operation::Delete, "conference room", name, vec![]);
+        plan.push(Provider::Airtable, Operation::Delete, "conference room", name, vec![]);
+    }
+
+    // Any resources in GSuite that are not in the config would be removed.
+    for name in gsuite_resources {
+        plan.push(Provider::GSuite, Operation::Delete, "conference room", &name, vec![]);
+    }
+
+    Ok(())
+}
+
+/// Compute the full plan for syncing the given configs, in the same order that
+/// `refresh_db_configs_and_airtable` syncs them. Nothing is mutated.
+pub async fn plan_configs(
+    db: &Database,
+    github: &octorust::Client,
+    configs: &Config,
+    company: &Company,
+    git_ref: &str,
+) -> Result<Plan> {
+    let mut plan = Plan::new(company, git_ref);
+
+    plan_buildings(db, &configs.buildings, company, &mut plan).await?;
+    plan_conference_rooms(db, &configs.resources, company, &mut plan).await?;
+    plan_groups(db, &configs.groups, company, &mut plan).await?;
+    plan_users(db, github, &configs.users, company, &mut plan).await?;
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_fields() {
+        let old = serde_json::json!({
+            "name": "jess",
+            "department": "Eng",
+            "aliases": [],
+            "id": 1,
+        });
+        let new = serde_json::json!({
+            "name": "jess",
+            "department": "Engineering",
+            "github": "jessfraz",
+        });
+
+        let changes = diff_fields(&old, &new, &["id"]);
+
+        assert_eq!(
+            changes,
+            vec![
+                FieldChange {
+                    field: "department".to_string(),
+                    old: serde_json::json!("Eng"),
+                    new: serde_json::json!("Engineering"),
+                },
+                FieldChange {
+                    field: "github".to_string(),
+                    old: serde_json::Value::Null,
+                    new: serde_json::json!("jessfraz"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_as_diff() {
+        let mut plan = Plan {
+            company: "Oxide".to_string(),
+            git_ref: "abc123".to_string(),
+            changes: vec![],
+        };
+        plan.push(Provider::GSuite, Operation::Delete, "group", "old", vec![]);
+        plan.push(
+            Provider::Database,
+            Operation::Update,
+            "user",
+            "jess",
+            vec![FieldChange {
+                field: "department".to_string(),
+                old: serde_json::json!("Eng"),
+                new: serde_json::json!("Engineering"),
+            }],
+        );
+
+        assert_eq!(
+            plan.as_diff(),
+            r#"Plan for `Oxide` at `abc123`: 0 to create, 1 to update, 1 to delete
+
+Database:
+! user `jess`
+!     department: "Eng" -> "Engineering"
+
+GSuite:
+- group `old`
+"#
+        );
+    }
+
+    #[test]
+    fn test_plan_offboarding() {
+        let user: crate::configs::User = serde_json::from_value(serde_json::json!({
+            "first_name": "Jess",
+            "last_name": "Frazelle",
+            "username": "jess",
+            "email": "jess@example.com",
+            "github": "JessFraz",
+        }))
+        .unwrap();
+        let users: BTreeSet<String> = vec!["jess@example.com".to_string()].into_iter().collect();
+        let github_members: BTreeSet<String> = vec!["jessfraz".to_string()].into_iter().collect();
+
+        let mut plan = Plan {
+            company: "Oxide".to_string(),
+            git_ref: Default::default(),
+            changes: vec![],
+        };
+        plan_offboarding(
+            &mut plan,
+            &user,
+            false,
+            &users,
+            &github_members,
+            &Default::default(),
+            &users,
+        );
+
+        assert_eq!(
+            plan.as_diff(),
+            r#"Plan for `Oxide`: 0 to create, 2 to update, 1 to delete
+
+GSuite:
+! user `jess@example.com`
+!     active: true -> false
+
+GitHub:
+- org membership `JessFraz`
+
+Slack:
+! user `jess@example.com`
+!     active: true -> false
+"#
+        );
+
+        // With Okta, only Okta is changed, it takes care of GSuite.
+        let mut plan = Plan::default();
+        plan_offboarding(
+            &mut plan,
+            &user,
+            true,
+            &users,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+        );
+        assert_eq!(plan.changes.len(), 1);
+        assert_eq!(plan.changes[0].provider, Provider::Okta);
+        assert_eq!(plan.changes[0].operation, Operation::Update);
+    }
+}
@@ -1,10 +1,12 @@
+use std::collections::{BTreeSet, HashMap};
+
 use anyhow::{bail, Result};
 use async_trait::async_trait;
 use log::{info, warn};
 
 use crate::{
     companies::Company,
-    configs::{Group, User},
+    configs::{Group, Groups, User},
     db::Database,
 };
 
@@ -15,6 +17,10 @@ pub trait ProviderOps<U, G> {
     /// Ensure the user exists and has the correct information.
     async fn ensure_user(&self, db: &Database, company: &Company, user: &User) -> Result<String>;
 
+    /// Returns true if the user already has an account with the provider. This only
+    /// looks up the one user, unlike `list_provider_users`.
+    async fn check_user_exists(&self, company: &Company, user: &User) -> Result<bool>;
+
     /// Ensure the group exists and has the correct information.
     async fn ensure_group(&self, db: &Database, company: &Company, group: &Group) -> Result<()>;
 
@@ -164,6 +170,15 @@ impl ProviderOps<ramp_api::types::User, ()> for ramp_api::Client {
         Ok(())
     }
 
+    async fn check_user_exists(&self, _company: &Company, user: &User) -> Result<bool> {
+        // Ramp can only look up users by their id.
+        if user.ramp_id.is_empty() {
+            return Ok(false);
+        }
+
+        user_found(self.users().get(&user.ramp_id).await)
+    }
+
     async fn list_provider_users(&self, _company: &Company) -> Result<Vec<ramp_api::types::User>> {
         self.users()
             .get_all(
@@ -439,6 +454,19 @@ impl ProviderOps<octorust::types::SimpleUser, octorust::types::Team> for octorus
         Ok(())
     }
 
+    async fn check_user_exists(&self, company: &Company, user: &User) -> Result<bool> {
+        if user.github.is_empty() {
+            return Ok(false);
+        }
+
+        // This includes users we have invited to the org but who have not accepted yet.
+        user_found(
+            self.orgs()
+                .get_membership_for_user(&company.github_org, &user.github)
+                .await,
+        )
+    }
+
     async fn list_provider_users(&self, company: &Company) -> Result<Vec<octorust::types::SimpleUser>> {
         // List all the users in the GitHub organization.
         self.orgs()
@@ -732,6 +760,18 @@ impl ProviderOps<gsuite_api::types::User, gsuite_api::types::Group> for gsuite_a
         Ok(())
     }
 
+    async fn check_user_exists(&self, _company: &Company, user: &User) -> Result<bool> {
+        user_found(
+            self.users()
+                .get(
+                    &user.email,
+                    gsuite_api::types::DirectoryUsersListProjection::Full,
+                    gsuite_api::types::ViewType::AdminView,
+                )
+                .await,
+        )
+    }
+
     async fn list_provider_users(&self, company: &Company) -> Result<Vec<gsuite_api::types::User>> {
         self.users()
             .list_all(
@@ -1104,6 +1144,10 @@ impl ProviderOps<okta::types::User, okta::types::Group> for okta::Client {
         Ok(())
     }
 
+    async fn check_user_exists(&self, _company: &Company, user: &User) -> Result<bool> {
+        user_found(self.users().get(&user.email.replace('@', "%40")).await)
+    }
+
     async fn list_provider_users(&self, _company: &Company) -> Result<Vec<okta::types::User>> {
         self.users()
             .list_all(
@@ -1318,6 +1362,15 @@ impl ProviderOps<zoom_api::types::UsersResponse, ()> for zoom_api::Client {
         Ok(())
     }
 
+    async fn check_user_exists(&self, _company: &Company, user: &User) -> Result<bool> {
+        // Zoom lets us get a user by their email, this includes pending users.
+        user_found(
+            self.users()
+                .user(&user.email, zoom_api::types::LoginType::Noop, false)
+                .await,
+        )
+    }
+
     async fn list_provider_users(&self, _company: &Company) -> Result<Vec<zoom_api::types::UsersResponse>> {
         self.users()
             .get_all(
@@ -1443,6 +1496,10 @@ impl ProviderOps<(), ()> for airtable_api::Airtable {
         Ok(())
     }
 
+    async fn check_user_exists(&self, _company: &Company, user: &User) -> Result<bool> {
+        Ok(!user.airtable_id.is_empty())
+    }
+
     async fn list_provider_users(&self, _company: &Company) -> Result<Vec<()>> {
         Ok(vec![])
     }
@@ -1471,6 +1528,366 @@ impl ProviderOps<(), ()> for airtable_api::Airtable {
         Ok(())
     }
 }
+#[async_trait]
+impl ProviderOps<slack_chat_api::User, slack_chat_api::UserGroup> for slack_chat_api::Slack {
+    async fn ensure_user(&self, db: &Database, company: &Company, user: &User) -> Result<String> {
+        // The sync already matched the user against everyone in the workspace, so we
+        // only look them up when we don't know their Slack ID yet.
+        let slack_id = if !user.slack_id.is_empty() {
+            user.slack_id.to_string()
+        } else if let Some(slack_user) = self.lookup_user_by_email(&user.email).await? {
+            slack_user.id
+        } else {
+            // Invite the user, they will be added to the default channels of
+            // their groups. We can't set their profile or user groups until they
+            // accept the invite, the next sync will do that.
+            let mut channel_ids: Vec<String> = Default::default();
+            for name in &user.groups {
+                if let Some(group) = Group::get_from_db(db, company.id, name.to_string()).await {
+                    channel_ids.append(&mut resolve_slack_channels(self, &group.slack_channels).await?);
+                }
+            }
+            if channel_ids.is_empty() {
+                // Slack requires at least one channel for an invite.
+                if let Some(general) = self.list_channels().await?.into_iter().find(|c| c.is_general) {
+                    channel_ids.push(general.id);
+                }
+            }
+            channel_ids.sort();
+            channel_ids.dedup();
+
+            self.invite_user(slack_chat_api::UserInvite {
+                channel_ids,
+                email: user.email.to_string(),
+                team_id: self.workspace_id().to_string(),
+                real_name: user.full_name(),
+                resend: true,
+                ..Default::default()
+            })
+            .await?;
+
+            info!("invited user `{}` to slack", user.email);
+
+            return Ok(String::new());
+        };
+
+        let mut user = user.clone();
+        user.slack_id = slack_id;
+
+        // Update their profile.
+        let mut profile = slack_chat_api::UserProfile {
+            title: user.title.to_string(),
+            pronouns: user.pronouns.to_string(),
+            ..Default::default()
+        };
+
+        let manager = user.manager(db).await;
+        if manager.id != user.id && !manager.slack_id.is_empty() {
+            // The manager is a custom profile field, only set it if the workspace has one.
+            let fields = self.get_team_profile_fields().await?;
+            if let Some(field) = fields.iter().find(|f| f.label.to_lowercase() == "manager") {
+                let mut f: HashMap<String, slack_chat_api::UserProfileFields> = HashMap::new();
+                f.insert(
+                    field.id.to_string(),
+                    slack_chat_api::UserProfileFields {
+                        alt: String::new(),
+                        label: String::new(),
+                        value: manager.slack_id.to_string(),
+                    },
+                );
+                profile.fields = Some(f);
+            }
+        }
+
+        self.update_user_profile(&user.slack_id, profile).await?;
+
+        info!("updated slack profile for user `{}`", user.email);
+
+        // Now we need to ensure our user is a member of all the correct user groups,
+        // and not a member of any others. We only touch the user groups that come
+        // from the configs repo, people make their own in Slack and those are left
+        // alone.
+        let managed: BTreeSet<String> = Groups::get_from_db(db, company.id)
+            .await?
+            .into_iter()
+            .map(|g| g.name)
+            .collect();
+        let groups: Vec<slack_chat_api::UserGroup> = self
+            .list_provider_groups(company)
+            .await?
+            .into_iter()
+            .filter(|g| managed.contains(&g.handle))
+            .collect();
+
+        // We only need to know who we are if one of the groups is disabled.
+        let owner_id = if groups.iter().any(|g| g.is_disabled()) {
+            self.user_token_owner_id().await?
+        } else {
+            String::new()
+        };
+
+        for group in groups {
+            if slack_group_disabled_by_someone_else(&group, &owner_id) {
+                // Someone turned this group off in Slack, leave it alone.
+                continue;
+            }
+
+            // A group we disabled has no members as far as we are concerned.
+            let is_member = !group.is_disabled() && group.users.contains(&user.slack_id);
+            let should_be_member = user.groups.contains(&group.handle);
+
+            if should_be_member && !is_member {
+                add_slack_group_member(self, &group, &user).await?;
+            } else if !should_be_member && is_member {
+                remove_slack_group_member(self, &group, &user).await?;
+            }
+        }
+
+        Ok(user.slack_id.to_string())
+    }
+
+    async fn ensure_group(&self, _db: &Database, company: &Company, group: &Group) -> Result<()> {
+        let channels = resolve_slack_channels(self, &group.slack_channels).await?;
+
+        let mut request = slack_chat_api::UserGroupRequest {
+            usergroup: String::new(),
+            name: group.name.to_string(),
+            handle: group.name.to_string(),
+            description: group.description.to_string(),
+            channels: channels.join(","),
+        };
+
+        // Check if the user group exists.
+        let existing = self
+            .list_provider_groups(company)
+            .await?
+            .into_iter()
+            .find(|g| g.handle == group.name);
+
+        if let Some(existing) = existing {
+            if existing.is_disabled()
+                && slack_group_disabled_by_someone_else(&existing, &self.user_token_owner_id().await?)
+            {
+                info!("group `{}` was disabled in slack, leaving it alone", group.name);
+
+                // Return early here.
+                return Ok(());
+            }
+
+            // Groups we disabled ourselves, because they were removed from the configs or
+            // had no members left, are enabled again when someone is added to them.
+            request.usergroup = existing.id.to_string();
+            self.update_user_group(&request).await?;
+
+            info!("updated group `{}` in slack", group.name);
+
+            // Return early here.
+            return Ok(());
+        }
+
+        self.create_user_group(&request).await?;
+
+        info!("created group `{}` in slack", group.name);
+
+        Ok(())
+    }
+
+    async fn check_user_is_member_of_group(&self, company: &Company, user: &User, group: &str) -> Result<bool> {
+        if user.slack_id.is_empty() {
+            // Return early.
+            return Ok(false);
+        }
+
+        match get_slack_user_group(self, company, group).await? {
+            Some(g) => Ok(g.users.contains(&user.slack_id)),
+            None => Ok(false),
+        }
+    }
+
+    async fn add_user_to_group(&self, company: &Company, user: &User, group: &str) -> Result<()> {
+        if user.slack_id.is_empty() {
+            // Return early.
+            return Ok(());
+        }
+
+        let g = match get_slack_user_group(self, company, group).await? {
+            Some(g) => g,
+            None => bail!("slack user group `{}` does not exist", group),
+        };
+
+        if g.is_disabled() && slack_group_disabled_by_someone_else(&g, &self.user_token_owner_id().await?) {
+            bail!("slack user group `{}` was disabled in slack", group);
+        }
+
+        add_slack_group_member(self, &g, user).await
+    }
+
+    async fn remove_user_from_group(&self, company: &Company, user: &User, group: &str) -> Result<()> {
+        if user.slack_id.is_empty() {
+            // Return early.
+            return Ok(());
+        }
+
+        match get_slack_user_group(self, company, group).await? {
+            Some(g) => remove_slack_group_member(self, &g, user).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn check_user_exists(&self, _company: &Company, user: &User) -> Result<bool> {
+        if !user.slack_id.is_empty() {
+            return Ok(true);
+        }
+
+        Ok(self.lookup_user_by_email(&user.email).await?.is_some())
+    }
+
+    async fn list_provider_users(&self, _company: &Company) -> Result<Vec<slack_chat_api::User>> {
+        let users = self.list_users().await?;
+
+        // Skip bots and users that have already been deactivated.
+        Ok(users.into_iter().filter(|u| !u.is_bot && !u.deleted).collect())
+    }
+
+    async fn list_provider_groups(&self, _company: &Company) -> Result<Vec<slack_chat_api::UserGroup>> {
+        self.list_user_groups().await
+    }
+
+    async fn delete_user(&self, _db: &Database, _company: &Company, user: &User) -> Result<()> {
+        let slack_id = if user.slack_id.is_empty() {
+            match self.lookup_user_by_email(&user.email).await? {
+                Some(slack_user) => slack_user.id,
+                None => {
+                    // Return early, the user is not in Slack.
+                    return Ok(());
+                }
+            }
+        } else {
+            user.slack_id.to_string()
+        };
+
+        self.remove_user(&slack_id).await?;
+
+        info!("removed user `{}` from slack", user.email);
+
+        Ok(())
+    }
+
+    async fn delete_group(&self, company: &Company, group: &Group) -> Result<()> {
+        if let Some(g) = get_slack_user_group(self, company, &group.name).await? {
+            if !g.is_disabled() {
+                // Slack does not let you delete user groups, so we disable it.
+                self.disable_user_group(&g.id).await?;
+
+                info!("disabled group `{}` in slack", group.name);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get a Slack user group by its handle.
+async fn get_slack_user_group(
+    slack: &slack_chat_api::Slack,
+    company: &Company,
+    handle: &str,
+) -> Result<Option<slack_chat_api::UserGroup>> {
+    Ok(slack
+        .list_provider_groups(company)
+        .await?
+        .into_iter()
+        .find(|g| g.handle == handle))
+}
+
+/// Returns true if a Slack user group was disabled by someone other than the owner of
+/// our user token, we don't turn those back on.
+fn slack_group_disabled_by_someone_else(group: &slack_chat_api::UserGroup, owner_id: &str) -> bool {
+    group.is_disabled() && group.deleted_by != owner_id
+}
+
+/// Add a user to a Slack user group, enabling the group if we disabled it.
+async fn add_slack_group_member(
+    slack: &slack_chat_api::Slack,
+    group: &slack_chat_api::UserGroup,
+    user: &User,
+) -> Result<()> {
+    let mut users = if group.is_disabled() {
+        // The members of a disabled group are whoever was in it when it was
+        // disabled, start again from nobody.
+        slack.enable_user_group(&group.id).await?;
+        vec![]
+    } else {
+        group.users.clone()
+    };
+
+    if users.contains(&user.slack_id) {
+        // Return early, they are already a member.
+        return Ok(());
+    }
+
+    users.push(user.slack_id.to_string());
+    slack.update_user_group_users(&group.id, &users).await?;
+
+    info!("added user `{}` to slack group `{}`", user.email, group.handle);
+
+    Ok(())
+}
+
+/// Remove a user from a Slack user group.
+async fn remove_slack_group_member(
+    slack: &slack_chat_api::Slack,
+    group: &slack_chat_api::UserGroup,
+    user: &User,
+) -> Result<()> {
+    if group.is_disabled() {
+        // Return early, a disabled group has no members to remove.
+        return Ok(());
+    }
+
+    let users: Vec<String> = group.users.iter().filter(|u| **u != user.slack_id).cloned().collect();
+    if users.len() == group.users.len() {
+        // Return early, they are not a member.
+        return Ok(());
+    }
+
+    if users.is_empty() {
+        // Slack does not allow a user group without members, so disable it instead.
+        // It will be enabled again when someone is added to the group.
+        slack.disable_user_group(&group.id).await?;
+    } else {
+        slack.update_user_group_users(&group.id, &users).await?;
+    }
+
+    info!("removed user `{}` from slack group `{}`", user.email, group.handle);
+
+    Ok(())
+}
+
+/// Turn the result of getting a user from a provider into whether the user exists,
+/// the providers return a 404 if they don't.
+fn user_found<T, E: std::fmt::Display>(result: std::result::Result<T, E>) -> Result<bool> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(e) if e.to_string().contains("404") => Ok(false),
+        Err(e) => bail!("getting user failed: {}", e),
+    }
+}
+
+/// Resolve a list of Slack channels, which can be IDs or `#channel-name`s, into IDs.
+async fn resolve_slack_channels(slack: &slack_chat_api::Slack, channels: &[String]) -> Result<Vec<String>> {
+    let mut ids: Vec<String> = Default::default();
+    for channel in channels {
+        if channel.starts_with('#') {
+            ids.push(slack.channel_id(channel).await?);
+        } else {
+            ids.push(channel.to_string());
+        }
+    }
+
+    Ok(ids)
+}
+
 /*
  *
  * Keep as empty boiler plate for now.
@@ -1497,6 +1914,10 @@ impl ProviderOps<ramp_api::types::User, ()> for ramp_api::Client {
         Ok(())
     }
 
+    async fn check_user_exists(&self, _company: &Company, _user: &User) -> Result<bool> {
+        Ok(false)
+    }
+
     async fn list_provider_users(&self, _company: &Company) -> Result<Vec<ramp_api::types::User>> {
         Ok(vec![])
     }
@@ -1515,3 +1936,30 @@ impl ProviderOps<ramp_api::types::User, ()> for ramp_api::Client {
 }
 
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slack_group_disabled_by_someone_else() {
+        let mut group = slack_chat_api::UserGroup {
+            id: "S1".to_string(),
+            handle: "eng".to_string(),
+            ..Default::default()
+        };
+        assert!(!slack_group_disabled_by_someone_else(&group, "U1"));
+
+        group.date_delete = 1650000000;
+        group.deleted_by = "U1".to_string();
+        assert!(!slack_group_disabled_by_someone_else(&group, "U1"));
+        assert!(slack_group_disabled_by_someone_else(&group, "U2"));
+    }
+
+    #[test]
+    fn test_user_found() {
+        assert!(user_found::<_, String>(Ok(())).unwrap());
+        assert!(!user_found::<(), _>(Err("code 404 Not Found: Resource Not Found: userKey")).unwrap());
+        assert!(user_found::<(), _>(Err("code 500 Internal Server Error")).is_err());
+    }
+}
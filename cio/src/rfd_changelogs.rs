@@ -0,0 +1,950 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::{bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use log::{info, warn};
+use macros::db;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use schemars::JsonSchema;
+use sendgrid_api::Client as SendGrid;
+use serde::{Deserialize, Serialize};
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
+
+use crate::{
+    airtable::AIRTABLE_RFD_CHANGELOG_SUBSCRIPTIONS_TABLE,
+    asciidoc::escape,
+    companies::Company,
+    core::UpdateAirtableRecord,
+    db::Database,
+    rfds::{NewRfdStateChange, RFDs, RfdState, RfdStateChange, RfdStateChanges, RFD},
+    schema::rfd_changelog_subscriptions,
+};
+
+/// Slack allows at most 50 blocks in a message.
+const MAX_SLACK_BLOCKS: usize = 50;
+
+/// Where subscribers confirm their subscription and unsubscribe.
+const CHANGELOG_URL: &str = "https://webhooks.corp.oxide.computer/rfd/changelog";
+
+/// How often a subscriber gets a changelog digest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangelogCadence {
+    Daily,
+    Weekly,
+}
+
+impl ToString for ChangelogCadence {
+    fn to_string(&self) -> String {
+        match self {
+            ChangelogCadence::Daily => "daily".to_string(),
+            ChangelogCadence::Weekly => "weekly".to_string(),
+        }
+    }
+}
+
+impl FromStr for ChangelogCadence {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "daily" => Ok(ChangelogCadence::Daily),
+            "" | "weekly" => Ok(ChangelogCadence::Weekly),
+            _ => bail!("unknown changelog cadence `{}`", s),
+        }
+    }
+}
+
+impl ChangelogCadence {
+    /// The period a digest covers.
+    pub fn period(&self) -> Duration {
+        match self {
+            ChangelogCadence::Daily => Duration::days(1),
+            ChangelogCadence::Weekly => Duration::days(7),
+        }
+    }
+}
+
+/// Where a subscriber gets their changelog digest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangelogChannel {
+    Email,
+    Slack,
+}
+
+impl ToString for ChangelogChannel {
+    fn to_string(&self) -> String {
+        match self {
+            ChangelogChannel::Email => "email".to_string(),
+            ChangelogChannel::Slack => "slack".to_string(),
+        }
+    }
+}
+
+impl FromStr for ChangelogChannel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "email" => Ok(ChangelogChannel::Email),
+            "slack" => Ok(ChangelogChannel::Slack),
+            _ => bail!("unknown changelog channel `{}`", s),
+        }
+    }
+}
+
+/// A subscription to a digest of the changes to the RFDs.
+#[db {
+    new_struct_name = "RfdChangelogSubscription",
+    airtable_base = "roadmap",
+    airtable_table = "AIRTABLE_RFD_CHANGELOG_SUBSCRIPTIONS_TABLE",
+    match_on = {
+        "cio_company_id" = "i32",
+        "email" = "String",
+        "channel" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[diesel(table_name = rfd_changelog_subscriptions)]
+pub struct NewRfdChangelogSubscription {
+    /// The email of the subscriber. For Slack, this is used to find them in Slack.
+    pub email: String,
+    /// `daily` or `weekly`, defaults to `weekly`.
+    #[serde(default)]
+    pub cadence: String,
+    /// `email` or `slack`, defaults to `email`.
+    #[serde(default)]
+    pub channel: String,
+    /// The numbers of the RFDs to include.
+    #[serde(default)]
+    pub rfd_numbers: Vec<i32>,
+    /// The states of the RFDs to include.
+    #[serde(default)]
+    pub states: Vec<String>,
+    /// The relevant components of the RFDs to include.
+    #[serde(default)]
+    pub relevant_components: Vec<String>,
+    /// When the last digest was sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sent_at: Option<DateTime<Utc>>,
+    /// The secret in the links to confirm the subscription and unsubscribe, so only
+    /// the subscriber can. The subscription to everyone has none.
+    #[serde(default, skip_serializing)]
+    pub token: String,
+    /// If the subscriber confirmed the subscription, we only send digests once they
+    /// have.
+    #[serde(default)]
+    pub confirmed: bool,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// Implement updating the Airtable record for an RfdChangelogSubscription.
+#[async_trait]
+impl UpdateAirtableRecord<RfdChangelogSubscription> for RfdChangelogSubscription {
+    async fn update_airtable_record(&mut self, _record: RfdChangelogSubscription) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl NewRfdChangelogSubscription {
+    /// The weekly email to everyone at the company with all the changes.
+    pub fn everyone(company: &Company) -> Self {
+        NewRfdChangelogSubscription {
+            email: format!("all@{}", company.gsuite_domain),
+            cadence: ChangelogCadence::Weekly.to_string(),
+            channel: ChangelogChannel::Email.to_string(),
+            rfd_numbers: Default::default(),
+            states: Default::default(),
+            relevant_components: Default::default(),
+            last_sent_at: None,
+            token: Default::default(),
+            confirmed: true,
+            cio_company_id: company.id,
+        }
+    }
+
+    /// The link to unsubscribe, for the subscriptions that have one.
+    pub fn unsubscribe_link(&self) -> Option<String> {
+        if self.token.is_empty() {
+            return None;
+        }
+
+        Some(format!("{}/unsubscribe?token={}", CHANGELOG_URL, self.token))
+    }
+
+    /// Returns if the subscription wants the changes to an RFD. A subscription
+    /// without any filters wants all of them, otherwise the RFD has to match one of
+    /// its numbers, states or relevant components.
+    pub fn matches(&self, number: i32, state: &str, relevant_components: &[String]) -> bool {
+        if self.rfd_numbers.is_empty() && self.states.is_empty() && self.relevant_components.is_empty() {
+            return true;
+        }
+
+        self.rfd_numbers.contains(&number)
+            || self.states.iter().any(|s| s.trim().eq_ignore_ascii_case(state.trim()))
+            || self.relevant_components.iter().any(|c| {
+                relevant_components
+                    .iter()
+                    .any(|r| r.trim().eq_ignore_ascii_case(c.trim()))
+            })
+    }
+
+    /// Returns if a digest should be sent. Daily digests go out once a day and
+    /// weekly digests once a week, on Mondays.
+    pub fn is_due(&self, now: DateTime<Utc>) -> Result<bool> {
+        let cadence: ChangelogCadence = self.cadence.parse()?;
+        if cadence == ChangelogCadence::Weekly && now.weekday() != Weekday::Mon {
+            return Ok(false);
+        }
+
+        Ok(match self.last_sent_at {
+            // Leave some slack for when the job runs a bit earlier than last time.
+            Some(last_sent_at) => now - last_sent_at >= cadence.period() - Duration::hours(1),
+            None => true,
+        })
+    }
+
+    /// The start of the period the next digest covers.
+    pub fn since(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        let cadence: ChangelogCadence = self.cadence.parse()?;
+
+        Ok(self.last_sent_at.unwrap_or_else(|| now - cadence.period()))
+    }
+}
+
+/// A commit to an RFD.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RfdChangelogCommit {
+    /// The first line of the commit message.
+    pub message: String,
+    /// The GitHub login of the author, if we know it.
+    pub author: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub committed_at: Option<DateTime<Utc>>,
+    pub sha: String,
+    pub html_url: String,
+}
+
+/// The changes to an RFD in a digest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RfdDigestEntry {
+    pub name: String,
+    pub state: String,
+    pub link: String,
+    pub discussion: String,
+    pub state_changes: Vec<NewRfdStateChange>,
+    pub commits: Vec<RfdChangelogCommit>,
+}
+
+impl RfdDigestEntry {
+    /// Returns if the RFD was opened for discussion during the digest.
+    pub fn is_new_discussion(&self) -> bool {
+        let discussion = RfdState::Discussion.to_string();
+        self.state_changes.iter().any(|c| c.to_state == discussion)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.state_changes.is_empty() && self.commits.is_empty()
+    }
+}
+
+/// Render a digest as HTML for email.
+pub fn render_digest_html(title: &str, entries: &[RfdDigestEntry], unsubscribe_link: Option<&str>) -> String {
+    let mut html = format!("<h1>{}</h1>\n", escape(title));
+
+    let new_discussions: Vec<&RfdDigestEntry> = entries.iter().filter(|e| e.is_new_discussion()).collect();
+    if !new_discussions.is_empty() {
+        html += "<h2>New discussions</h2>\n<ul>\n";
+        for entry in new_discussions {
+            let mut item = format!("<a href=\"{}\">{}</a>", escape(&entry.link), escape(&entry.name));
+            if !entry.discussion.is_empty() {
+                item += &format!(" (<a href=\"{}\">discussion</a>)", escape(&entry.discussion));
+            }
+            html += &format!("<li>{}</li>\n", item);
+        }
+        html += "</ul>\n";
+    }
+
+    html += "<h2>Changes</h2>\n";
+    for entry in entries {
+        html += &format!(
+            "<h3><a href=\"{}\">{}</a> <small>{}</small></h3>\n<ul>\n",
+            escape(&entry.link),
+            escape(&entry.name),
+            escape(&entry.state)
+        );
+        for change in &entry.state_changes {
+            let mut item = if change.from_state.is_empty() {
+                format!("State set to <strong>{}</strong>", escape(&change.to_state))
+            } else {
+                format!(
+                    "State changed from <strong>{}</strong> to <strong>{}</strong>",
+                    escape(&change.from_state),
+                    escape(&change.to_state)
+                )
+            };
+            if !change.actor.is_empty() {
+                item += &format!(" by @{}", escape(&change.actor));
+            }
+            html += &format!("<li>{}</li>\n", item);
+        }
+        for commit in &entry.commits {
+            let mut item = format!(
+                "<a href=\"{}\">{}</a>",
+                escape(&commit.html_url),
+                escape(&commit.message)
+            );
+            if !commit.author.is_empty() {
+                item += &format!(" by @{}", escape(&commit.author));
+            }
+            html += &format!("<li>{}</li>\n", item);
+        }
+        html += "</ul>\n";
+    }
+
+    if let Some(link) = unsubscribe_link {
+        html += &format!("<p><small><a href=\"{}\">Unsubscribe</a></small></p>\n", escape(link));
+    }
+
+    html
+}
+
+/// Render a digest as a Slack message.
+pub fn render_digest_slack(
+    title: &str,
+    entries: &[RfdDigestEntry],
+    unsubscribe_link: Option<&str>,
+) -> FormattedMessage {
+    let mut blocks = vec![MessageBlock {
+        block_type: MessageBlockType::Section,
+        text: Some(MessageBlockText {
+            text_type: MessageType::Markdown,
+            text: format!("*{}*", title),
+        }),
+        elements: Default::default(),
+        accessory: Default::default(),
+        block_id: Default::default(),
+        fields: Default::default(),
+    }];
+
+    // Leave room for the title, the RFDs that don't fit and the unsubscribe link.
+    for entry in entries.iter().take(MAX_SLACK_BLOCKS - 3) {
+        let mut text = format!("*<{}|{}>* _{}_", entry.link, entry.name, entry.state);
+        if entry.is_new_discussion() && !entry.discussion.is_empty() {
+            text += &format!(" | <{}|new discussion>", entry.discussion);
+        }
+        for change in &entry.state_changes {
+            if change.from_state.is_empty() {
+                text += &format!("\n• state set to *{}*", change.to_state);
+            } else {
+                text += &format!(
+                    "\n• state changed from *{}* to *{}*",
+                    change.from_state, change.to_state
+                );
+            }
+        }
+        for commit in &entry.commits {
+            text += &format!("\n• <{}|{}>", commit.html_url, commit.message);
+            if !commit.author.is_empty() {
+                text += &format!(" by @{}", commit.author);
+            }
+        }
+
+        blocks.push(MessageBlock {
+            block_type: MessageBlockType::Section,
+            text: Some(MessageBlockText {
+                text_type: MessageType::Markdown,
+                text,
+            }),
+            elements: Default::default(),
+            accessory: Default::default(),
+            block_id: Default::default(),
+            fields: Default::default(),
+        });
+    }
+
+    let context = |text: String| MessageBlock {
+        block_type: MessageBlockType::Context,
+        elements: vec![slack_chat_api::BlockOption::MessageBlockText(MessageBlockText {
+            text_type: MessageType::Markdown,
+            text,
+        })],
+        text: Default::default(),
+        accessory: Default::default(),
+        block_id: Default::default(),
+        fields: Default::default(),
+    };
+    if entries.len() > MAX_SLACK_BLOCKS - 3 {
+        blocks.push(context(format!(
+            "and {} more RFDs",
+            entries.len() - (MAX_SLACK_BLOCKS - 3)
+        )));
+    }
+    if let Some(link) = unsubscribe_link {
+        blocks.push(context(format!("<{}|Unsubscribe>", link)));
+    }
+
+    FormattedMessage {
+        channel: Default::default(),
+        attachments: Default::default(),
+        blocks,
+    }
+}
+
+/// Send an HTML email from SendGrid.
+async fn send_html_email(subject: &str, html: &str, to: &str, from: &str) -> Result<()> {
+    let sendgrid_client = SendGrid::new_from_env();
+
+    sendgrid_client
+        .mail_send()
+        .post(&sendgrid_api::types::PostMailSendRequest {
+            personalizations: vec![sendgrid_api::types::Personalizations {
+                to: vec![sendgrid_api::types::ReplyTo {
+                    email: to.to_string(),
+                    name: Default::default(),
+                }],
+                subject: subject.to_string(),
+                ..Default::default()
+            }],
+            from: sendgrid_api::types::FromEmailObject {
+                email: from.to_string(),
+                name: Default::default(),
+            },
+            subject: subject.to_string(),
+            content: vec![sendgrid_api::types::Content {
+                type_: "text/html".to_string(),
+                value: html.to_string(),
+            }],
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Build the digest for a subscription, from the commits to each RFD keyed by its
+/// number. The commits can go back further than `since`, the digest only has the
+/// ones after it.
+pub fn get_digest(
+    subscription: &NewRfdChangelogSubscription,
+    rfds: &[RFD],
+    state_changes: &[RfdStateChange],
+    commits: &BTreeMap<i32, Vec<RfdChangelogCommit>>,
+    since: DateTime<Utc>,
+) -> Vec<RfdDigestEntry> {
+    let mut entries: Vec<RfdDigestEntry> = Default::default();
+    for rfd in rfds
+        .iter()
+        .filter(|rfd| subscription.matches(rfd.number, &rfd.state, &rfd.relevant_components))
+    {
+        let entry = RfdDigestEntry {
+            name: rfd.name.to_string(),
+            state: rfd.state.to_string(),
+            link: rfd.rendered_link.to_string(),
+            discussion: rfd.discussion.to_string(),
+            state_changes: state_changes
+                .iter()
+                .filter(|c| c.rfd_number == rfd.number && c.changed_at >= since)
+                .map(|c| c.into())
+                .collect(),
+            commits: commits
+                .get(&rfd.number)
+                .map(|c| {
+                    c.iter()
+                        .filter(|c| c.committed_at.map_or(true, |t| t >= since))
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+
+        if !entry.is_empty() {
+            entries.push(entry);
+        }
+    }
+
+    entries
+}
+
+/// Send a digest to a subscriber.
+async fn send_digest(
+    db: &Database,
+    company: &Company,
+    subscription: &NewRfdChangelogSubscription,
+    title: &str,
+    entries: &[RfdDigestEntry],
+) -> Result<()> {
+    match subscription.channel.parse::<ChangelogChannel>()? {
+        ChangelogChannel::Email => {
+            send_html_email(
+                title,
+                &render_digest_html(title, entries, subscription.unsubscribe_link().as_deref()),
+                &subscription.email,
+                &format!("rfds@{}", company.gsuite_domain),
+            )
+            .await
+        }
+        ChangelogChannel::Slack => {
+            let slack = company.authenticate_slack(db).await?;
+            let user = match slack.lookup_user_by_email(&subscription.email).await? {
+                Some(user) => user,
+                None => bail!("could not find slack user with email `{}`", subscription.email),
+            };
+
+            let mut message = render_digest_slack(title, entries, subscription.unsubscribe_link().as_deref());
+            // Posting to the id of a user sends them a direct message.
+            message.channel = user.id.to_string();
+            company.post_to_slack_channel(db, &message).await
+        }
+    }
+}
+
+/// The query parameters of the links to confirm a subscription and unsubscribe.
+#[derive(Debug, Default, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct RfdChangelogTokenParams {
+    /// The token of the subscription, from the email or digest.
+    pub token: String,
+}
+
+/// Returns if someone with an email can subscribe. The digests have the RFDs that are
+/// not public yet, so only people at the company can.
+pub fn can_subscribe(email: &str, domain: &str) -> bool {
+    email
+        .trim()
+        .to_lowercase()
+        .ends_with(&format!("@{}", domain.trim().to_lowercase()))
+}
+
+/// Subscribe someone to the changelog. The subscription only starts once they
+/// confirm it from the email we send them, so no one can sign up someone else or
+/// change what they get.
+pub async fn subscribe(
+    db: &Database,
+    company: &Company,
+    mut subscription: NewRfdChangelogSubscription,
+) -> Result<RfdChangelogSubscription> {
+    subscription.email = subscription.email.trim().to_lowercase();
+    if subscription.email.is_empty() {
+        bail!("subscription email is empty");
+    }
+    if !can_subscribe(&subscription.email, &company.gsuite_domain) {
+        bail!(
+            "only emails at `{}` can subscribe to the rfd changelog",
+            company.gsuite_domain
+        );
+    }
+    // Make sure we can send the digest.
+    subscription.cadence.parse::<ChangelogCadence>()?;
+    subscription.channel.parse::<ChangelogChannel>()?;
+    if subscription.email == NewRfdChangelogSubscription::everyone(company).email {
+        bail!("the subscription for everyone can't be changed");
+    }
+
+    subscription.cio_company_id = company.id;
+    subscription.token = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    subscription.confirmed = false;
+    subscription.last_sent_at = None;
+
+    // Keep track of when the last digest went out for an existing subscription.
+    if let Some(existing) = RfdChangelogSubscription::get_from_db(
+        db,
+        company.id,
+        subscription.email.to_string(),
+        subscription.channel.to_string(),
+    )
+    .await
+    {
+        subscription.last_sent_at = existing.last_sent_at;
+    }
+
+    let subscription = subscription.upsert_in_db(db).await?;
+
+    let html = format!(
+        "<p>Someone subscribed {} to the {} RFD changelog over {}. If it was you, <a \
+         href=\"{}/confirm?token={}\">confirm the subscription</a>. Otherwise you can ignore this \
+         email.</p>",
+        escape(&subscription.email),
+        escape(&subscription.cadence),
+        escape(&subscription.channel),
+        CHANGELOG_URL,
+        subscription.token
+    );
+    send_html_email(
+        "Confirm your RFD changelog subscription",
+        &html,
+        &subscription.email,
+        &format!("rfds@{}", company.gsuite_domain),
+    )
+    .await?;
+
+    info!(
+        "sent the rfd changelog subscription confirmation to `{}` over {}",
+        subscription.email, subscription.channel
+    );
+
+    Ok(subscription)
+}
+
+/// Get the subscription with the token from a confirm or unsubscribe link.
+async fn get_subscription_by_token(db: &Database, company: &Company, token: &str) -> Result<RfdChangelogSubscription> {
+    if token.trim().is_empty() {
+        bail!("the rfd changelog subscription token is empty");
+    }
+
+    let subscription = rfd_changelog_subscriptions::dsl::rfd_changelog_subscriptions
+        .filter(rfd_changelog_subscriptions::dsl::cio_company_id.eq(company.id))
+        .filter(rfd_changelog_subscriptions::dsl::token.eq(token.trim().to_string()))
+        .first_async::<RfdChangelogSubscription>(db.pool())
+        .await?;
+
+    Ok(subscription)
+}
+
+/// Confirm a subscription from the link in the email we sent.
+pub async fn confirm_subscription(db: &Database, company: &Company, token: &str) -> Result<RfdChangelogSubscription> {
+    let mut subscription = get_subscription_by_token(db, company, token).await?;
+    subscription.confirmed = true;
+    let subscription = subscription.update_in_db(db).await?;
+
+    info!(
+        "subscribed `{}` to the rfd changelog over {}",
+        subscription.email, subscription.channel
+    );
+
+    Ok(subscription)
+}
+
+/// Unsubscribe from the link in a digest.
+pub async fn unsubscribe(db: &Database, company: &Company, token: &str) -> Result<()> {
+    let subscription = get_subscription_by_token(db, company, token).await?;
+    subscription.delete_from_db(db).await?;
+
+    info!(
+        "unsubscribed `{}` from the rfd changelog over {}",
+        subscription.email, subscription.channel
+    );
+
+    Ok(())
+}
+
+/// Send the RFD changelog digests that are due.
+pub async fn send_rfd_changelog(db: &Database, company: &Company) -> Result<()> {
+    let rfds: Vec<RFD> = RFDs::get_from_db(db, company.id).await?.into();
+
+    if rfds.is_empty() {
+        // Return early.
+        return Ok(());
+    }
+
+    // Make sure the weekly email to everyone exists.
+    let everyone = NewRfdChangelogSubscription::everyone(company);
+    if RfdChangelogSubscription::get_from_db(db, company.id, everyone.email.to_string(), everyone.channel.to_string())
+        .await
+        .is_none()
+    {
+        everyone.upsert_in_db(db).await?;
+    }
+
+    let github = company.authenticate_github()?;
+    let state_changes: Vec<RfdStateChange> = RfdStateChanges::get_from_db(db, company.id).await?.into();
+    let now = Utc::now();
+
+    // Figure out who is due a digest, and from when.
+    let mut due: Vec<(RfdChangelogSubscription, DateTime<Utc>)> = Default::default();
+    let subscriptions: Vec<RfdChangelogSubscription> =
+        RfdChangelogSubscriptions::get_from_db(db, company.id).await?.into();
+    for subscription in subscriptions {
+        let s: NewRfdChangelogSubscription = (&subscription).into();
+        if !s.confirmed {
+            continue;
+        }
+
+        match (s.is_due(now), s.since(now)) {
+            (Ok(true), Ok(since)) => due.push((subscription, since)),
+            (Ok(false), _) => (),
+            (Err(e), _) | (Ok(true), Err(e)) => {
+                warn!("skipping rfd changelog subscription for `{}`: {}", s.email, e)
+            }
+        }
+    }
+
+    let earliest = match due.iter().map(|(_, since)| *since).min() {
+        Some(earliest) => earliest,
+        // Return early, no one is due a digest.
+        None => return Ok(()),
+    };
+
+    // Get the commits to every RFD someone wants once, from the earliest digest, and
+    // filter them for each digest.
+    let mut commits: BTreeMap<i32, Vec<RfdChangelogCommit>> = Default::default();
+    for rfd in rfds.iter().filter(|rfd| {
+        due.iter().any(|(subscription, _)| {
+            let s: NewRfdChangelogSubscription = subscription.into();
+            s.matches(rfd.number, &rfd.state, &rfd.relevant_components)
+        })
+    }) {
+        match rfd.get_changelog_commits(&github, earliest, company).await {
+            Ok(c) => {
+                commits.insert(rfd.number, c);
+            }
+            Err(e) => warn!("getting the commits to rfd {} failed: {}", rfd.number, e),
+        }
+    }
+
+    for (mut subscription, since) in due {
+        let s: NewRfdChangelogSubscription = (&subscription).into();
+
+        let title = if s.cadence.parse::<ChangelogCadence>()? == ChangelogCadence::Weekly {
+            format!(
+                "RFD changelog for the week from {} to {}",
+                since.format("%m-%d-%Y"),
+                now.format("%m-%d-%Y")
+            )
+        } else {
+            format!("RFD changelog for {}", now.format("%m-%d-%Y"))
+        };
+
+        let entries = get_digest(&s, &rfds, &state_changes, &commits, since);
+        // Don't bother people with an empty digest.
+        if !entries.is_empty() {
+            if let Err(e) = send_digest(db, company, &s, &title, &entries).await {
+                warn!(
+                    "sending rfd changelog to `{}` over {} failed: {}",
+                    s.email, s.channel, e
+                );
+                continue;
+            }
+            info!("sent rfd changelog to `{}` over {}", s.email, s.channel);
+        }
+
+        subscription.last_sent_at = Some(now);
+        if let Err(e) = subscription.update_in_db(db).await {
+            warn!(
+                "updating rfd changelog subscription for `{}` over {} failed: {}",
+                s.email, s.channel, e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_rfd_changelog_subscription_due_and_matches() {
+        let mut subscription = NewRfdChangelogSubscription {
+            email: "all@example.com".to_string(),
+            cadence: "".to_string(),
+            channel: "".to_string(),
+            rfd_numbers: Default::default(),
+            states: Default::default(),
+            relevant_components: Default::default(),
+            last_sent_at: None,
+            token: Default::default(),
+            confirmed: true,
+            cio_company_id: 1,
+        };
+
+        // 2022-04-18 was a Monday.
+        let monday = Utc.ymd(2022, 4, 18).and_hms(15, 0, 0);
+        let tuesday = monday + Duration::days(1);
+
+        // Weekly is the default and only goes out on Mondays.
+        assert!(subscription.is_due(monday).unwrap());
+        assert!(!subscription.is_due(tuesday).unwrap());
+        assert_eq!(subscription.since(monday).unwrap(), monday - Duration::days(7));
+
+        // Even if the job ran a bit later last week.
+        subscription.last_sent_at = Some(monday - Duration::days(7) + Duration::minutes(10));
+        assert!(subscription.is_due(monday).unwrap());
+
+        subscription.cadence = "daily".to_string();
+        subscription.last_sent_at = Some(monday);
+        assert!(!subscription.is_due(monday + Duration::hours(2)).unwrap());
+        assert!(subscription.is_due(tuesday).unwrap());
+        assert_eq!(subscription.since(tuesday).unwrap(), monday);
+
+        subscription.cadence = "hourly".to_string();
+        assert!(subscription.is_due(tuesday).is_err());
+
+        let components = vec!["Control Plane".to_string()];
+
+        // No filters matches everything.
+        assert!(subscription.matches(63, "discussion", &components));
+
+        subscription.rfd_numbers = vec![1, 2];
+        assert!(!subscription.matches(63, "discussion", &components));
+        subscription.states = vec!["Discussion".to_string()];
+        assert!(subscription.matches(63, "discussion", &components));
+        subscription.states = vec!["published".to_string()];
+        subscription.relevant_components = vec!["control plane".to_string()];
+        assert!(subscription.matches(63, "discussion", &components));
+    }
+
+    fn rfd(number: i32, state: &str, relevant_components: &[&str]) -> RFD {
+        serde_json::from_value(serde_json::json!({
+            "id": number,
+            "number": number,
+            "title": "Things",
+            "name": format!("RFD {} Things", number),
+            "state": state,
+            "link": "",
+            "rendered_link": format!("https://rfd.shared.oxide.computer/rfd/{:04}", number),
+            "discussion": format!("https://github.com/oxidecomputer/rfd/pull/{}", number),
+            "relevant_components": relevant_components,
+        }))
+        .unwrap()
+    }
+
+    fn state_change(number: i32, from_state: &str, to_state: &str, changed_at: DateTime<Utc>) -> RfdStateChange {
+        serde_json::from_value(serde_json::json!({
+            "id": number,
+            "rfd_number": number,
+            "from_state": from_state,
+            "to_state": to_state,
+            "actor": "jane",
+            "changed_at": changed_at,
+        }))
+        .unwrap()
+    }
+
+    fn commit(message: &str, committed_at: DateTime<Utc>) -> RfdChangelogCommit {
+        RfdChangelogCommit {
+            message: message.to_string(),
+            author: "jane".to_string(),
+            committed_at: Some(committed_at),
+            sha: "abc".to_string(),
+            html_url: "https://github.com/oxidecomputer/rfd/commit/abc".to_string(),
+        }
+    }
+
+    fn digest() -> Vec<RfdDigestEntry> {
+        let since = Utc.ymd(2022, 4, 11).and_hms(15, 0, 0);
+        let rfds = vec![
+            rfd(1, "published", &[]),
+            rfd(2, "discussion", &["Control Plane"]),
+            rfd(3, "ideation", &["Control Plane"]),
+        ];
+        let state_changes = vec![
+            state_change(2, "ideation", "discussion", since + Duration::days(1)),
+            state_change(3, "", "ideation", since - Duration::days(1)),
+        ];
+        let mut commits: BTreeMap<i32, Vec<RfdChangelogCommit>> = Default::default();
+        commits.insert(
+            2,
+            vec![
+                commit("Open <it> for discussion", since + Duration::days(1)),
+                commit("Before the digest", since - Duration::days(1)),
+            ],
+        );
+        commits.insert(3, vec![commit("Also before the digest", since - Duration::hours(1))]);
+
+        let mut subscription = NewRfdChangelogSubscription {
+            email: "jane@example.com".to_string(),
+            cadence: "weekly".to_string(),
+            channel: "email".to_string(),
+            rfd_numbers: Default::default(),
+            states: Default::default(),
+            relevant_components: vec!["control plane".to_string()],
+            last_sent_at: None,
+            token: Default::default(),
+            confirmed: true,
+            cio_company_id: 1,
+        };
+
+        // RFD 1 does not match, and nothing happened to RFD 3 since the last digest.
+        let entries = get_digest(&subscription, &rfds, &state_changes, &commits, since);
+        assert_eq!(entries.len(), 1);
+
+        // Unless we go back further.
+        subscription.states = vec!["published".to_string()];
+        assert_eq!(
+            get_digest(
+                &subscription,
+                &rfds,
+                &state_changes,
+                &commits,
+                since - Duration::days(2)
+            )
+            .len(),
+            2
+        );
+
+        entries
+    }
+
+    #[test]
+    fn test_get_digest() {
+        let entries = digest();
+        let entry = &entries[0];
+        assert_eq!(entry.name, "RFD 2 Things");
+        assert_eq!(entry.state_changes.len(), 1);
+        assert_eq!(entry.state_changes[0].to_state, "discussion");
+        assert_eq!(entry.commits.len(), 1);
+        assert_eq!(entry.commits[0].message, "Open <it> for discussion");
+        assert!(entry.is_new_discussion());
+    }
+
+    #[test]
+    fn test_render_digest_html() {
+        let entries = digest();
+
+        let html = render_digest_html("RFD changelog", &entries, None);
+        assert!(html.starts_with("<h1>RFD changelog</h1>\n<h2>New discussions</h2>\n"));
+        assert!(html.contains(
+            "<li><a href=\"https://rfd.shared.oxide.computer/rfd/0002\">RFD 2 Things</a> (<a \
+             href=\"https://github.com/oxidecomputer/rfd/pull/2\">discussion</a>)</li>"
+        ));
+        assert!(html
+            .contains("<li>State changed from <strong>ideation</strong> to <strong>discussion</strong> by @jane</li>"));
+        assert!(html.contains("Open &lt;it&gt; for discussion</a> by @jane</li>"));
+        assert!(!html.contains("Unsubscribe"));
+
+        let html = render_digest_html(
+            "RFD changelog",
+            &entries,
+            Some("https://example.com/unsubscribe?token=a&b"),
+        );
+        assert!(html.ends_with(
+            "<p><small><a href=\"https://example.com/unsubscribe?token=a&amp;b\">Unsubscribe</a></small></p>\n"
+        ));
+    }
+
+    #[test]
+    fn test_can_subscribe() {
+        assert!(can_subscribe("Jane@Example.com ", "example.com"));
+        assert!(!can_subscribe("jane@example.com.evil", "example.com"));
+        assert!(!can_subscribe("jane@notexample.com", "example.com"));
+
+        let mut subscription = NewRfdChangelogSubscription {
+            email: "jane@example.com".to_string(),
+            cadence: "weekly".to_string(),
+            channel: "email".to_string(),
+            rfd_numbers: Default::default(),
+            states: Default::default(),
+            relevant_components: Default::default(),
+            last_sent_at: None,
+            token: Default::default(),
+            confirmed: true,
+            cio_company_id: 1,
+        };
+        assert_eq!(subscription.unsubscribe_link(), None);
+        subscription.token = "abc".to_string();
+        assert_eq!(
+            subscription.unsubscribe_link().unwrap(),
+            format!("{}/unsubscribe?token=abc", CHANGELOG_URL)
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use diffy::{DiffOptions, Hunk, Line};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{asciidoc::escape, companies::Company, rfds::RFD, utils::get_file_content_from_repo};
+
+/// How many lines of context to show around each change.
+const DIFF_CONTEXT_LINES: usize = 3;
+
+/// The section for the changes before the first heading of an RFD.
+const PREAMBLE_SECTION: &str = "Preamble";
+
+/// How to lay out a diff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffView {
+    /// Deleted and inserted lines one after the other.
+    Inline,
+    /// The old and new lines next to each other.
+    SideBySide,
+}
+
+impl ToString for DiffView {
+    fn to_string(&self) -> String {
+        match self {
+            DiffView::Inline => "inline".to_string(),
+            DiffView::SideBySide => "side-by-side".to_string(),
+        }
+    }
+}
+
+impl FromStr for DiffView {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "inline" | "unified" => Ok(DiffView::Inline),
+            "side-by-side" | "split" => Ok(DiffView::SideBySide),
+            _ => bail!("unknown diff view `{}`", s),
+        }
+    }
+}
+
+/// The parameters for getting the diff of an RFD, either between two commits or for
+/// a pull request.
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct RfdDiffParams {
+    #[serde(default)]
+    pub base: Option<String>,
+    #[serde(default)]
+    pub head: Option<String>,
+    #[serde(default)]
+    pub pull_request: Option<i64>,
+    /// `inline` or `side-by-side`, defaults to `inline`.
+    #[serde(default)]
+    pub view: Option<String>,
+}
+
+/// The rendered diff of an RFD between two commits.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RfdDiff {
+    pub number: i32,
+    pub base: String,
+    pub head: String,
+    pub view: String,
+    pub html: String,
+}
+
+/// Get the content of an RFD at a commit, empty if the RFD did not exist yet.
+async fn get_rfd_content_at(github: &octorust::Client, company: &Company, rfd: &RFD, sha: &str) -> Result<String> {
+    for file in &["README.adoc", "README.md"] {
+        match get_file_content_from_repo(
+            github,
+            &company.github_org,
+            "rfd",
+            sha,
+            &format!("/rfd/{}/{}", rfd.number_string, file),
+        )
+        .await
+        {
+            Ok((content, _)) => return Ok(String::from_utf8(content)?),
+            // The file does not exist at this commit, try the next one.
+            Err(e) if e.to_string().contains("404") => continue,
+            Err(e) => bail!("getting RFD {} at `{}` failed: {}", rfd.number_string, sha, e),
+        }
+    }
+
+    Ok(String::new())
+}
+
+/// Get the rendered diff of an RFD between two commits, or for a pull request.
+pub async fn get_rfd_diff(company: &Company, rfd: &RFD, params: &RfdDiffParams) -> Result<RfdDiff> {
+    let github = company.authenticate_github()?;
+    let view: DiffView = params.view.as_deref().unwrap_or_default().parse()?;
+
+    let (base, head) = match (&params.base, &params.head, params.pull_request) {
+        (Some(base), Some(head), _) => (base.to_string(), head.to_string()),
+        (_, _, Some(pull_request)) => {
+            let pull = github.pulls().get(&company.github_org, "rfd", pull_request).await?;
+            (pull.base.sha, pull.head.sha)
+        }
+        _ => bail!("a diff needs a `base` and `head` commit or a `pull_request`"),
+    };
+
+    let old = get_rfd_content_at(&github, company, rfd, &base).await?;
+    let new = get_rfd_content_at(&github, company, rfd, &head).await?;
+
+    Ok(RfdDiff {
+        number: rfd.number,
+        html: render_diff_html(&old, &new, view)?,
+        base,
+        head,
+        view: view.to_string(),
+    })
+}
+
+/// Get the sections of an RFD as the line number (starting at 1) and title of
+/// each heading below the document title.
+fn get_sections(content: &str) -> Result<Vec<(usize, String)>> {
+    let re = Regex::new(r"^(?:={2,6}|#{2,6})\s+(.+?)\s*$")?;
+
+    Ok(content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| re.captures(line).map(|c| (i + 1, c[1].to_string())))
+        .collect())
+}
+
+/// Get the title of the section a line is in.
+fn section_for_line(sections: &[(usize, String)], line: usize) -> String {
+    sections
+        .iter()
+        .take_while(|(n, _)| *n <= line)
+        .last()
+        .map(|(_, title)| title.to_string())
+        .unwrap_or_else(|| PREAMBLE_SECTION.to_string())
+}
+
+/// Get the line in the new content where the first change of a hunk is.
+fn first_change_line(hunk: &Hunk<'_, str>) -> usize {
+    let context = hunk
+        .lines()
+        .iter()
+        .take_while(|l| matches!(l, Line::Context(_)))
+        .count();
+
+    hunk.new_range().start() + context
+}
+
+/// Render the diff between the old and new content of an RFD as HTML. The hunks are
+/// grouped by the section of the RFD they change.
+pub fn render_diff_html(old: &str, new: &str, view: DiffView) -> Result<String> {
+    let patch = DiffOptions::new()
+        .set_context_len(DIFF_CONTEXT_LINES)
+        .create_patch(old, new);
+    let sections = get_sections(new)?;
+
+    let mut html = format!("<div class=\"rfd-diff rfd-diff-{}\">\n", view.to_string());
+    if patch.hunks().is_empty() {
+        html += "<p>No changes.</p>\n</div>";
+        return Ok(html);
+    }
+
+    let mut current: Option<String> = None;
+    for hunk in patch.hunks() {
+        let section = section_for_line(&sections, first_change_line(hunk));
+        if current.as_ref() != Some(&section) {
+            if current.is_some() {
+                html += "</div>\n";
+            }
+            html += &format!("<div class=\"rfd-diff-section\">\n<h3>{}</h3>\n", escape(&section));
+            current = Some(section);
+        }
+
+        html += &match view {
+            DiffView::Inline => render_hunk_inline(hunk),
+            DiffView::SideBySide => render_hunk_side_by_side(hunk),
+        };
+    }
+    html += "</div>\n</div>";
+
+    Ok(html)
+}
+
+/// The header of a hunk, like `@@ -1,3 +1,4 @@`.
+fn hunk_header(hunk: &Hunk<'_, str>, columns: usize) -> String {
+    format!(
+        "<tr class=\"rfd-diff-hunk-header\"><td colspan=\"{}\">@@ -{},{} +{},{} @@</td></tr>\n",
+        columns,
+        hunk.old_range().start(),
+        hunk.old_range().len(),
+        hunk.new_range().start(),
+        hunk.new_range().len()
+    )
+}
+
+fn line_number(n: Option<usize>) -> String {
+    n.map(|n| n.to_string()).unwrap_or_default()
+}
+
+fn render_hunk_inline(hunk: &Hunk<'_, str>) -> String {
+    let mut html = format!("<table class=\"rfd-diff-hunk\">\n{}", hunk_header(hunk, 3));
+
+    let mut old_line = hunk.old_range().start();
+    let mut new_line = hunk.new_range().start();
+    for line in hunk.lines() {
+        let (class, old_n, new_n, text) = match line {
+            Line::Context(text) => {
+                old_line += 1;
+                new_line += 1;
+                ("context", Some(old_line - 1), Some(new_line - 1), text)
+            }
+            Line::Delete(text) => {
+                old_line += 1;
+                ("delete", Some(old_line - 1), None, text)
+            }
+            Line::Insert(text) => {
+                new_line += 1;
+                ("insert", None, Some(new_line - 1), text)
+            }
+        };
+
+        html += &format!(
+            "<tr class=\"rfd-diff-{}\"><td class=\"rfd-diff-line-number\">{}</td><td \
+             class=\"rfd-diff-line-number\">{}</td><td class=\"rfd-diff-text\">{}</td></tr>\n",
+            class,
+            line_number(old_n),
+            line_number(new_n),
+            escape(text.trim_end_matches('\n'))
+        );
+    }
+    html += "</table>\n";
+
+    html
+}
+
+/// A row of a side by side diff, with the line number and text of each side.
+type SideBySideRow<'a> = (Option<(usize, &'a str)>, Option<(usize, &'a str)>);
+
+fn render_hunk_side_by_side(hunk: &Hunk<'_, str>) -> String {
+    let mut rows: Vec<SideBySideRow> = Default::default();
+    let mut deleted: Vec<(usize, &str)> = Default::default();
+    let mut inserted: Vec<(usize, &str)> = Default::default();
+
+    // Pair up the runs of deleted and inserted lines so changed lines are next to
+    // each other.
+    fn flush<'a>(
+        rows: &mut Vec<SideBySideRow<'a>>,
+        deleted: &mut Vec<(usize, &'a str)>,
+        inserted: &mut Vec<(usize, &'a str)>,
+    ) {
+        for i in 0..deleted.len().max(inserted.len()) {
+            rows.push((deleted.get(i).copied(), inserted.get(i).copied()));
+        }
+        deleted.clear();
+        inserted.clear();
+    }
+
+    let mut old_line = hunk.old_range().start();
+    let mut new_line = hunk.new_range().start();
+    for line in hunk.lines() {
+        match line {
+            Line::Context(text) => {
+                flush(&mut rows, &mut deleted, &mut inserted);
+                rows.push((Some((old_line, text)), Some((new_line, text))));
+                old_line += 1;
+                new_line += 1;
+            }
+            Line::Delete(text) => {
+                // A delete after inserts starts a new change.
+                if !inserted.is_empty() {
+                    flush(&mut rows, &mut deleted, &mut inserted);
+                }
+                deleted.push((old_line, text));
+                old_line += 1;
+            }
+            Line::Insert(text) => {
+                inserted.push((new_line, text));
+                new_line += 1;
+            }
+        }
+    }
+    flush(&mut rows, &mut deleted, &mut inserted);
+
+    let mut html = format!("<table class=\"rfd-diff-hunk\">\n{}", hunk_header(hunk, 4));
+    for (old, new) in rows {
+        let changed = old.map(|(_, t)| t) != new.map(|(_, t)| t);
+        let side = |cell: Option<(usize, &str)>, class: &str| match cell {
+            Some((n, text)) => format!(
+                "<td class=\"rfd-diff-line-number\">{}</td><td class=\"rfd-diff-text{}\">{}</td>",
+                n,
+                if changed {
+                    format!(" rfd-diff-{}", class)
+                } else {
+                    String::new()
+                },
+                escape(text.trim_end_matches('\n'))
+            ),
+            None => {
+                "<td class=\"rfd-diff-line-number\"></td><td class=\"rfd-diff-text rfd-diff-empty\"></td>".to_string()
+            }
+        };
+
+        html += &format!("<tr>{}{}</tr>\n", side(old, "delete"), side(new, "insert"));
+    }
+    html += "</table>\n";
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_diff_html, DiffView};
+
+    #[test]
+    fn test_render_diff_html() {
+        let old = r#"= RFD 1 Things
+
+== Background
+
+We have <some> things.
+
+== Proposal
+
+Do the thing.
+"#;
+        let new = r#"= RFD 1 Things
+
+== Background
+
+We have <some> things.
+
+== Proposal
+
+Do the other thing.
+Then stop.
+"#;
+
+        let html = render_diff_html(old, new, DiffView::Inline).unwrap();
+        assert!(html.contains("<h3>Proposal</h3>"));
+        assert!(!html.contains("<h3>Background</h3>"));
+        assert!(html.contains(
+            "<tr class=\"rfd-diff-delete\"><td class=\"rfd-diff-line-number\">9</td><td \
+             class=\"rfd-diff-line-number\"></td><td class=\"rfd-diff-text\">Do the thing.</td></tr>"
+        ));
+        assert!(html.contains(
+            "<tr class=\"rfd-diff-insert\"><td class=\"rfd-diff-line-number\"></td><td \
+             class=\"rfd-diff-line-number\">10</td><td class=\"rfd-diff-text\">Then stop.</td></tr>"
+        ));
+
+        let html = render_diff_html(old, new, DiffView::SideBySide).unwrap();
+        assert!(html.contains(
+            "<tr><td class=\"rfd-diff-line-number\">9</td><td class=\"rfd-diff-text rfd-diff-delete\">Do the \
+             thing.</td><td class=\"rfd-diff-line-number\">9</td><td class=\"rfd-diff-text rfd-diff-insert\">Do the \
+             other thing.</td></tr>"
+        ));
+
+        assert_eq!(
+            render_diff_html(old, old, DiffView::Inline).unwrap(),
+            "<div class=\"rfd-diff rfd-diff-inline\">\n<p>No changes.</p>\n</div>"
+        );
+    }
+}
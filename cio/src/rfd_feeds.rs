@@ -0,0 +1,500 @@
+use std::{env, path::PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::info;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asciidoc::escape,
+    companies::Company,
+    db::Database,
+    rfds::{RFDs, RfdState, RfdStateChange, RfdStateChanges, RFD},
+    utils::{create_or_update_file_in_github_repo, write_file},
+};
+
+/// The most entries we put in an Atom feed.
+const MAX_FEED_ENTRIES: usize = 100;
+
+/// An RFD in the feed index.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RfdFeedItem {
+    pub number: i32,
+    pub number_string: String,
+    pub title: String,
+    pub name: String,
+    pub state: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub authors: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub link: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub discussion: String,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub state_changes: Vec<RfdFeedStateChange>,
+}
+
+/// A change in the state of an RFD in the feed index.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RfdFeedStateChange {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub from_state: String,
+    pub to_state: String,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// The feeds of the RFDs, all of them or only the public ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RfdFeeds {
+    pub json: String,
+    pub atom: String,
+}
+
+/// Returns if an RFD in a state goes in the public feeds, only published and
+/// committed RFDs do.
+pub fn is_public(state: &str) -> bool {
+    matches!(
+        state.parse::<RfdState>(),
+        Ok(RfdState::Published) | Ok(RfdState::Committed)
+    )
+}
+
+/// Strip the email addresses from a list of authors, `Jane Doe <jane@example.com>`
+/// becomes `Jane Doe`.
+pub fn strip_author_emails(authors: &str) -> String {
+    authors
+        .split(',')
+        .map(|a| match a.find('<') {
+            Some(i) => a[..i].trim(),
+            None => a.trim(),
+        })
+        .filter(|a| !a.is_empty() && !a.contains('@'))
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+/// Make an item safe for the public feeds. This drops the discussion link, which
+/// points at our private repo, the email addresses of the authors, and any history
+/// from before the RFD was published. The link to the internal rendered RFD is
+/// replaced with one under `public_url`, or dropped if there is none.
+pub fn public_feed_item(item: RfdFeedItem, public_url: &str) -> RfdFeedItem {
+    let state_changes = item
+        .state_changes
+        .into_iter()
+        .filter(|c| is_public(&c.to_state))
+        .map(|c| RfdFeedStateChange {
+            from_state: if is_public(&c.from_state) {
+                c.from_state
+            } else {
+                Default::default()
+            },
+            to_state: c.to_state,
+            changed_at: c.changed_at,
+        })
+        .collect();
+
+    let link = if public_url.is_empty() {
+        Default::default()
+    } else {
+        format!("{}/rfd/{}", public_url.trim_end_matches('/'), item.number_string)
+    };
+
+    RfdFeedItem {
+        authors: strip_author_emails(&item.authors),
+        link,
+        discussion: Default::default(),
+        state_changes,
+        ..item
+    }
+}
+
+/// Generate the JSON index and the Atom feed of the RFDs. For the public feeds the
+/// RFDs link to `public_url`.
+pub fn generate_rfd_feeds(
+    feed_id: &str,
+    title: &str,
+    author: &str,
+    rfds: &[RFD],
+    state_changes: &[RfdStateChange],
+    public: bool,
+    public_url: &str,
+) -> Result<RfdFeeds> {
+    let mut items: Vec<RfdFeedItem> = Default::default();
+    for rfd in rfds.iter().filter(|r| !public || is_public(&r.state)) {
+        let mut changes: Vec<RfdFeedStateChange> = state_changes
+            .iter()
+            .filter(|c| c.rfd_number == rfd.number)
+            .map(|c| RfdFeedStateChange {
+                from_state: c.from_state.to_string(),
+                to_state: c.to_state.to_string(),
+                changed_at: c.changed_at,
+            })
+            .collect();
+        changes.sort_by_key(|c| c.changed_at);
+
+        let item = RfdFeedItem {
+            number: rfd.number,
+            number_string: rfd.number_string.to_string(),
+            title: rfd.title.to_string(),
+            name: rfd.name.to_string(),
+            state: rfd.state.to_string(),
+            authors: rfd.authors.to_string(),
+            link: rfd.rendered_link.to_string(),
+            discussion: rfd.discussion.to_string(),
+            updated_at: rfd.commit_date,
+            state_changes: changes,
+        };
+
+        items.push(if public {
+            public_feed_item(item, public_url)
+        } else {
+            item
+        });
+    }
+    items.sort_by_key(|i| i.number);
+
+    Ok(RfdFeeds {
+        json: serde_json::to_string_pretty(&items)?,
+        atom: render_atom_feed(feed_id, title, author, &items),
+    })
+}
+
+/// Render the Atom feed of the RFDs. There is an entry for every change in the
+/// state of an RFD, or one for the RFD in its current state if we never saw it
+/// change. The feed's author stands in for the entries without authors.
+pub fn render_atom_feed(feed_id: &str, title: &str, author: &str, items: &[RfdFeedItem]) -> String {
+    // Build the entries, newest first.
+    let mut entries: Vec<(DateTime<Utc>, String)> = Default::default();
+    for item in items {
+        if item.state_changes.is_empty() {
+            entries.push((
+                item.updated_at,
+                atom_entry(
+                    &format!("{}:rfd:{}", feed_id, item.number_string),
+                    &format!("{} is {}", item.name, item.state),
+                    item,
+                    item.updated_at,
+                ),
+            ));
+        }
+        for change in &item.state_changes {
+            entries.push((
+                change.changed_at,
+                atom_entry(
+                    &format!(
+                        "{}:rfd:{}:{}:{}",
+                        feed_id,
+                        item.number_string,
+                        change.to_state,
+                        change.changed_at.timestamp()
+                    ),
+                    &format!("{} is now {}", item.name, change.to_state),
+                    item,
+                    change.changed_at,
+                ),
+            ));
+        }
+    }
+    entries.sort_by(|a, b| b.0.cmp(&a.0));
+    entries.truncate(MAX_FEED_ENTRIES);
+
+    let updated = entries.first().map(|(u, _)| *u).unwrap_or_else(Utc::now);
+    let mut atom = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed \
+         xmlns=\"http://www.w3.org/2005/Atom\">\n<id>{}</id>\n<title>{}</title>\n<updated>{}</updated>\n<author><name>{}</name></author>\n",
+        escape(feed_id),
+        escape(title),
+        updated.to_rfc3339(),
+        escape(author)
+    );
+    for (_, entry) in entries {
+        atom += &entry;
+    }
+    atom += "</feed>\n";
+
+    atom
+}
+
+fn atom_entry(id: &str, title: &str, item: &RfdFeedItem, updated: DateTime<Utc>) -> String {
+    let mut entry = format!(
+        "<entry>\n<id>{}</id>\n<title>{}</title>\n<updated>{}</updated>\n",
+        escape(id),
+        escape(title),
+        updated.to_rfc3339()
+    );
+    if !item.link.is_empty() {
+        entry += &format!("<link href=\"{}\"/>\n", escape(&item.link));
+    }
+    if !item.authors.is_empty() {
+        entry += &format!("<author><name>{}</name></author>\n", escape(&item.authors));
+    }
+    // An entry without a link has to have content.
+    entry += &format!(
+        "<category term=\"{}\"/>\n<{tag}>{}</{tag}>\n</entry>\n",
+        escape(&item.state),
+        escape(&format!(
+            "RFD {} {} is in the {} state.",
+            item.number, item.title, item.state
+        )),
+        tag = if item.link.is_empty() { "content" } else { "summary" }
+    );
+
+    entry
+}
+
+/// Where a feed is published: a repo in the company's GitHub org and a directory,
+/// either can be empty.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FeedDestination {
+    repo: String,
+    storage_path: String,
+}
+
+impl FeedDestination {
+    /// Read the destination from the `<prefix>_GITHUB_REPO` and
+    /// `<prefix>_STORAGE_PATH` environment variables.
+    fn from_env(prefix: &str) -> Self {
+        FeedDestination {
+            repo: env::var(format!("{}_GITHUB_REPO", prefix)).unwrap_or_default(),
+            storage_path: env::var(format!("{}_STORAGE_PATH", prefix)).unwrap_or_default(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.repo.is_empty() && self.storage_path.is_empty()
+    }
+
+    async fn publish(&self, company: &Company, files: &[(String, String)]) -> Result<()> {
+        if !self.repo.is_empty() {
+            let github = company.authenticate_github()?;
+            let r = github.repos().get(&company.github_org, &self.repo).await?;
+            for (path, content) in files {
+                create_or_update_file_in_github_repo(
+                    &github,
+                    &company.github_org,
+                    &self.repo,
+                    &r.default_branch,
+                    &format!("/{}", path),
+                    content.as_bytes().to_vec(),
+                )
+                .await?;
+            }
+            info!("published rfd feeds to the `{}` repo", self.repo);
+        }
+
+        if !self.storage_path.is_empty() {
+            for (path, content) in files {
+                write_file(&PathBuf::from(&self.storage_path).join(path), content.as_bytes()).await?;
+            }
+            info!("published rfd feeds to `{}`", self.storage_path);
+        }
+
+        Ok(())
+    }
+}
+
+/// Publish the feeds of the RFDs. The feeds of all the RFDs go to the repo in
+/// `RFD_FEED_GITHUB_REPO`, in the company's GitHub org, and to the directory in
+/// `RFD_FEED_STORAGE_PATH`. The public feeds have their own destination, in
+/// `RFD_PUBLIC_FEED_GITHUB_REPO` and `RFD_PUBLIC_FEED_STORAGE_PATH`, so they never
+/// end up next to the internal ones. The public feeds link to the RFDs under
+/// `RFD_PUBLIC_URL`, never to our internal site. Feeds without a destination are
+/// not published.
+pub async fn publish_rfd_feeds(db: &Database, company: &Company) -> Result<()> {
+    let internal = FeedDestination::from_env("RFD_FEED");
+    let public = FeedDestination::from_env("RFD_PUBLIC_FEED");
+    if internal.is_empty() && public.is_empty() {
+        // Return early, there is nowhere to publish the feeds.
+        return Ok(());
+    }
+
+    let public_url = env::var("RFD_PUBLIC_URL").unwrap_or_default();
+
+    let rfds: Vec<RFD> = RFDs::get_from_db(db, company.id).await?.into();
+    let state_changes: Vec<RfdStateChange> = RfdStateChanges::get_from_db(db, company.id).await?.into();
+
+    for (destination, is_public) in &[(internal, false), (public, true)] {
+        if destination.is_empty() {
+            continue;
+        }
+
+        let title = if *is_public {
+            format!("{} public RFDs", company.name)
+        } else {
+            format!("{} RFDs", company.name)
+        };
+        let feeds = generate_rfd_feeds(
+            &format!(
+                "tag:{},2022:rfds{}",
+                company.gsuite_domain,
+                if *is_public { ":public" } else { "" }
+            ),
+            &title,
+            &company.name,
+            &rfds,
+            &state_changes,
+            *is_public,
+            &public_url,
+        )?;
+
+        destination
+            .publish(
+                company,
+                &[
+                    ("rfds.json".to_string(), feeds.json),
+                    ("rfds.atom".to_string(), feeds.atom),
+                ],
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{is_public, public_feed_item, render_atom_feed, strip_author_emails, RfdFeedItem, RfdFeedStateChange};
+
+    #[test]
+    fn test_render_atom_feed() {
+        assert!(is_public("published"));
+        assert!(is_public("Committed"));
+        assert!(!is_public("discussion"));
+        assert!(!is_public(""));
+
+        let items = vec![
+            RfdFeedItem {
+                number: 1,
+                number_string: "0001".to_string(),
+                title: "Requests for Discussion".to_string(),
+                name: "RFD 1 Requests for Discussion".to_string(),
+                state: "published".to_string(),
+                authors: "Jane Doe <jane@example.com>".to_string(),
+                link: "https://rfd.example.com/rfd/0001".to_string(),
+                discussion: Default::default(),
+                updated_at: Utc.ymd(2022, 1, 1).and_hms(0, 0, 0),
+                state_changes: vec![
+                    RfdFeedStateChange {
+                        from_state: "discussion".to_string(),
+                        to_state: "published".to_string(),
+                        changed_at: Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+                    },
+                    RfdFeedStateChange {
+                        from_state: Default::default(),
+                        to_state: "discussion".to_string(),
+                        changed_at: Utc.ymd(2022, 2, 1).and_hms(0, 0, 0),
+                    },
+                ],
+            },
+            RfdFeedItem {
+                number: 2,
+                number_string: "0002".to_string(),
+                title: "Things & Stuff".to_string(),
+                name: "RFD 2 Things & Stuff".to_string(),
+                state: "committed".to_string(),
+                authors: Default::default(),
+                link: "https://rfd.example.com/rfd/0002".to_string(),
+                discussion: Default::default(),
+                updated_at: Utc.ymd(2022, 2, 15).and_hms(0, 0, 0),
+                state_changes: Default::default(),
+            },
+        ];
+
+        let atom = render_atom_feed("tag:example.com,2022:rfds", "Example RFDs", "Example", &items);
+        assert!(atom
+            .contains("<updated>2022-03-01T00:00:00+00:00</updated>\n<author><name>Example</name></author>\n<entry>"));
+        assert!(atom.contains("<link href=\"https://rfd.example.com/rfd/0002\"/>"));
+        assert!(atom.contains("<title>RFD 2 Things &amp; Stuff is committed</title>"));
+        assert!(atom.contains("<author><name>Jane Doe &lt;jane@example.com&gt;</name></author>"));
+
+        // The entries are newest first.
+        let published = atom.find("RFD 1 Requests for Discussion is now published").unwrap();
+        let committed = atom.find("RFD 2 Things &amp; Stuff is committed").unwrap();
+        let discussion = atom.find("RFD 1 Requests for Discussion is now discussion").unwrap();
+        assert!(published < committed && committed < discussion);
+    }
+
+    #[test]
+    fn test_public_feed_item() {
+        assert_eq!(
+            strip_author_emails("Jane Doe <jane@example.com>, John Smith <john@example.com>"),
+            "Jane Doe, John Smith"
+        );
+        assert_eq!(strip_author_emails("jane@example.com"), "");
+
+        let internal = RfdFeedItem {
+            number: 3,
+            number_string: "0003".to_string(),
+            title: "Secrets".to_string(),
+            name: "RFD 3 Secrets".to_string(),
+            state: "published".to_string(),
+            authors: "Jane Doe <jane@example.com>".to_string(),
+            link: "https://rfd.example.com/rfd/0003".to_string(),
+            discussion: "https://github.com/example/rfd/pull/3".to_string(),
+            updated_at: Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+            state_changes: vec![
+                RfdFeedStateChange {
+                    from_state: Default::default(),
+                    to_state: "ideation".to_string(),
+                    changed_at: Utc.ymd(2022, 1, 1).and_hms(0, 0, 0),
+                },
+                RfdFeedStateChange {
+                    from_state: "ideation".to_string(),
+                    to_state: "discussion".to_string(),
+                    changed_at: Utc.ymd(2022, 2, 1).and_hms(0, 0, 0),
+                },
+                RfdFeedStateChange {
+                    from_state: "discussion".to_string(),
+                    to_state: "published".to_string(),
+                    changed_at: Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+                },
+            ],
+        };
+
+        let item = public_feed_item(internal.clone(), "https://rfd.example.org/");
+        assert_eq!(item.link, "https://rfd.example.org/rfd/0003");
+        assert_eq!(item.authors, "Jane Doe");
+        assert!(item.discussion.is_empty());
+        assert_eq!(
+            item.state_changes,
+            vec![RfdFeedStateChange {
+                from_state: Default::default(),
+                to_state: "published".to_string(),
+                changed_at: Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+            }]
+        );
+
+        // None of the internal fields make it into the feeds.
+        let json = serde_json::to_string(&vec![item.clone()]).unwrap();
+        let atom = render_atom_feed(
+            "tag:example.com,2022:rfds:public",
+            "Example public RFDs",
+            "Example",
+            &[item],
+        );
+        for feed in &[json, atom] {
+            assert!(!feed.contains("example.com>") && !feed.contains("jane@example.com"));
+            assert!(!feed.contains("github.com/example/rfd/pull/3"));
+            assert!(!feed.contains("rfd.example.com/rfd/0003"));
+            assert!(!feed.contains("discussion") && !feed.contains("ideation"));
+        }
+
+        // Without a public site the entries have no link, so they have content instead.
+        let item = public_feed_item(internal, "");
+        assert!(item.link.is_empty());
+        let json = serde_json::to_string(&vec![item.clone()]).unwrap();
+        assert!(!json.contains("\"link\""));
+        let atom = render_atom_feed(
+            "tag:example.com,2022:rfds:public",
+            "Example public RFDs",
+            "Example",
+            &[item],
+        );
+        assert!(!atom.contains("<link"));
+        assert!(atom.contains("<content>RFD 3 Secrets is in the published state.</content>"));
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use async_trait::async_trait;
+use macros::db;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    airtable::AIRTABLE_RFD_REFERENCES_TABLE,
+    asciidoc::escape,
+    companies::Company,
+    core::UpdateAirtableRecord,
+    db::Database,
+    rfds::RFD,
+    schema::{rfd_references, rfds},
+};
+
+/// The id of the section with the RFDs that cite an RFD, at the end of its HTML.
+const CITED_BY_SECTION_ID: &str = "rfd-cited-by";
+
+/// A reference from one RFD to another.
+#[db {
+    new_struct_name = "RfdReference",
+    airtable_base = "roadmap",
+    airtable_table = "AIRTABLE_RFD_REFERENCES_TABLE",
+    match_on = {
+        "cio_company_id" = "i32",
+        "rfd_number" = "i32",
+        "referenced_rfd_number" = "i32",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[diesel(table_name = rfd_references)]
+pub struct NewRfdReference {
+    /// The number of the RFD that has the reference.
+    pub rfd_number: i32,
+    /// The number of the RFD it references.
+    pub referenced_rfd_number: i32,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// Implement updating the Airtable record for an RfdReference.
+#[async_trait]
+impl UpdateAirtableRecord<RfdReference> for RfdReference {
+    async fn update_airtable_record(&mut self, _record: RfdReference) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An RFD that cites, or is cited by, another RFD.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RfdCitation {
+    pub number: i32,
+    pub number_string: String,
+    pub name: String,
+    pub state: String,
+    pub rendered_link: String,
+}
+
+impl From<&RFD> for RfdCitation {
+    fn from(rfd: &RFD) -> Self {
+        RfdCitation {
+            number: rfd.number,
+            number_string: rfd.number_string.to_string(),
+            name: rfd.name.to_string(),
+            state: rfd.state.to_string(),
+            rendered_link: rfd.rendered_link.to_string(),
+        }
+    }
+}
+
+/// The RFDs an RFD cites and the RFDs that cite it.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RfdCitations {
+    pub number: i32,
+    pub cites: Vec<RfdCitation>,
+    pub cited_by: Vec<RfdCitation>,
+}
+
+/// Get the numbers of the other RFDs the content of an RFD references, like `RFD 123`,
+/// links to `rfd/0123` or short links like `https://123.rfd.oxide.computer`.
+pub fn extract_references(content: &str, number: i32) -> Result<Vec<i32>> {
+    let mentions = Regex::new(r"(?i)\brfd[ \-/#]?0*([1-9][0-9]{0,3})\b")?;
+    let short_links = Regex::new(r"(?i)\b0*([1-9][0-9]{0,3})\.rfd\.")?;
+
+    let mut references: BTreeSet<i32> = Default::default();
+    for re in &[mentions, short_links] {
+        for cap in re.captures_iter(content) {
+            let n = cap[1].parse::<i32>()?;
+            if n != number {
+                references.insert(n);
+            }
+        }
+    }
+
+    Ok(references.into_iter().collect())
+}
+
+/// Update the references of an RFD from its content, along with its `cites` and
+/// `cited_by` fields and the backlinks in its HTML. The RFDs that it started or
+/// stopped citing get their backlinks updated as well.
+///
+/// This does not save the RFD itself, the caller should do that.
+pub async fn update_rfd_references(db: &Database, company: &Company, rfd: &mut RFD) -> Result<()> {
+    let cites: BTreeSet<i32> = extract_references(&rfd.content, rfd.number)?.into_iter().collect();
+
+    let references = rfd_references::dsl::rfd_references
+        .filter(rfd_references::dsl::cio_company_id.eq(company.id))
+        .filter(rfd_references::dsl::rfd_number.eq(rfd.number))
+        .load_async::<RfdReference>(db.pool())
+        .await?;
+    let mut existing: BTreeSet<i32> = Default::default();
+    for reference in &references {
+        existing.insert(reference.referenced_rfd_number);
+
+        // Delete the references the RFD no longer has.
+        if !cites.contains(&reference.referenced_rfd_number) {
+            reference.delete_from_db(db).await?;
+        }
+    }
+
+    for n in cites.difference(&existing) {
+        NewRfdReference {
+            rfd_number: rfd.number,
+            referenced_rfd_number: *n,
+            cio_company_id: company.id,
+        }
+        .upsert_in_db(db)
+        .await?;
+    }
+
+    rfd.cites = cites.iter().map(|n| format!("{:04}", n)).collect();
+    update_backlinks(db, company, rfd).await?;
+
+    // Update the backlinks of the RFDs whose citations changed.
+    for n in cites.symmetric_difference(&existing) {
+        if let Some(mut other) = RFD::get_from_db(db, *n).await {
+            update_backlinks(db, company, &mut other).await?;
+            other.update(db).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Set the `cited_by` field of an RFD and the backlinks in its HTML.
+async fn update_backlinks(db: &Database, company: &Company, rfd: &mut RFD) -> Result<()> {
+    let citing = get_citing_rfds(db, company, rfd.number).await?;
+
+    rfd.cited_by = citing.iter().map(|r| r.number_string.to_string()).collect();
+    rfd.html = html_with_backlinks(&rfd.html, &citing);
+
+    Ok(())
+}
+
+/// Get the RFDs with the given numbers, in order.
+async fn get_rfds(db: &Database, company: &Company, numbers: Vec<i32>) -> Result<Vec<RFD>> {
+    let rfds = rfds::dsl::rfds
+        .filter(rfds::dsl::cio_company_id.eq(company.id))
+        .filter(rfds::dsl::number.eq_any(numbers))
+        .order_by(rfds::dsl::number)
+        .load_async::<RFD>(db.pool())
+        .await?;
+
+    Ok(rfds)
+}
+
+/// Get the RFDs that cite an RFD.
+async fn get_citing_rfds(db: &Database, company: &Company, number: i32) -> Result<Vec<RFD>> {
+    let numbers = rfd_references::dsl::rfd_references
+        .filter(rfd_references::dsl::cio_company_id.eq(company.id))
+        .filter(rfd_references::dsl::referenced_rfd_number.eq(number))
+        .select(rfd_references::dsl::rfd_number)
+        .load_async::<i32>(db.pool())
+        .await?;
+
+    get_rfds(db, company, numbers).await
+}
+
+/// Get the RFDs an RFD cites and the RFDs that cite it.
+pub async fn get_rfd_citations(db: &Database, company: &Company, number: i32) -> Result<RfdCitations> {
+    let numbers = rfd_references::dsl::rfd_references
+        .filter(rfd_references::dsl::cio_company_id.eq(company.id))
+        .filter(rfd_references::dsl::rfd_number.eq(number))
+        .select(rfd_references::dsl::referenced_rfd_number)
+        .load_async::<i32>(db.pool())
+        .await?;
+    let cites = get_rfds(db, company, numbers).await?.iter().map(|r| r.into()).collect();
+
+    let cited_by = get_citing_rfds(db, company, number)
+        .await?
+        .iter()
+        .map(|r| r.into())
+        .collect();
+
+    Ok(RfdCitations {
+        number,
+        cites,
+        cited_by,
+    })
+}
+
+/// Replace the section with the RFDs that cite an RFD at the end of its HTML.
+pub fn html_with_backlinks(html: &str, citing: &[RFD]) -> String {
+    let mut html = match html.find(&format!("<div id=\"{}\"", CITED_BY_SECTION_ID)) {
+        Some(start) => html[..start].trim_end().to_string(),
+        None => html.trim_end().to_string(),
+    };
+    if citing.is_empty() {
+        return html;
+    }
+
+    let items: Vec<String> = citing
+        .iter()
+        .map(|r| {
+            format!(
+                "<li>\n<p><a href=\"{}\">{}</a></p>\n</li>",
+                escape(&r.rendered_link),
+                escape(&r.name)
+            )
+        })
+        .collect();
+    html += &format!(
+        "\n<div id=\"{}\" class=\"sect1\">\n<h2 id=\"_cited_by\">Cited By</h2>\n<div \
+         class=\"sectionbody\">\n<div class=\"ulist\">\n<ul>\n{}\n</ul>\n</div>\n</div>\n</div>",
+        CITED_BY_SECTION_ID,
+        items.join("\n")
+    );
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_references() {
+        let content = r#"= RFD 42 Things
+
+This builds on RFD 1 and rfd 0007, see also RFD-123 and
+https://rfd.shared.oxide.computer/rfd/0063 or https://9.rfd.oxide.computer.
+This is RFD 42 itself, and RFD 1 again. RFD 12345 is not a number we use.
+"#;
+
+        assert_eq!(extract_references(content, 42).unwrap(), vec![1, 7, 9, 63, 123]);
+        assert!(extract_references("No references here.", 1).unwrap().is_empty());
+    }
+
+    fn rfd(number: i32, name: &str) -> RFD {
+        serde_json::from_value(serde_json::json!({
+            "id": number,
+            "number": number,
+            "title": name,
+            "name": format!("RFD {} {}", number, name),
+            "state": "published",
+            "link": "",
+            "rendered_link": format!("https://rfd.shared.oxide.computer/rfd/{:04}", number),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_html_with_backlinks() {
+        let html = "<div class=\"sect1\">\n<p>Hello</p>\n</div>\n";
+
+        // Nothing cites the RFD, so there is no section.
+        assert_eq!(html_with_backlinks(html, &[]), html.trim_end());
+
+        let citing = vec![rfd(1, "Things"), rfd(7, "<Stuff> & more")];
+        let with_backlinks = html_with_backlinks(html, &citing);
+        assert!(with_backlinks.starts_with(html.trim_end()));
+        assert!(with_backlinks.contains(&format!("<div id=\"{}\"", CITED_BY_SECTION_ID)));
+        assert!(with_backlinks.contains("<a href=\"https://rfd.shared.oxide.computer/rfd/0001\">RFD 1 Things</a>"));
+        assert!(with_backlinks.contains("RFD 7 &lt;Stuff&gt; &amp; more"));
+
+        // The section is replaced, not added again.
+        let updated = html_with_backlinks(&with_backlinks, &citing[..1]);
+        assert_eq!(updated.matches(CITED_BY_SECTION_ID).count(), 1);
+        assert!(!updated.contains("RFD 7"));
+        assert_eq!(updated, html_with_backlinks(html, &citing[..1]));
+
+        // And removed once nothing cites the RFD anymore.
+        assert_eq!(html_with_backlinks(&with_backlinks, &[]), html.trim_end());
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Result};
+use async_bb8_diesel::AsyncRunQueryDsl;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    airtable::AIRTABLE_RFD_RESERVATIONS_TABLE, companies::Company, core::UpdateAirtableRecord, db::Database,
+    rfds::RFDs, schema::rfd_reservations, utils::create_or_update_file_in_github_repo,
+};
+
+/// How many times we try to allocate a number when someone else took the one we picked.
+const MAX_RESERVATION_ATTEMPTS: usize = 5;
+
+/// A reserved RFD number.
+#[db {
+    new_struct_name = "RfdReservation",
+    airtable_base = "roadmap",
+    airtable_table = "AIRTABLE_RFD_RESERVATIONS_TABLE",
+    match_on = {
+        "cio_company_id" = "i32",
+        "number" = "i32",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[diesel(table_name = rfd_reservations)]
+pub struct NewRfdReservation {
+    pub number: i32,
+    pub title: String,
+    /// Who reserved the number, used as the author of the RFD.
+    pub reserved_by: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub branch: String,
+    /// A link to the RFD on its branch.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub link: String,
+    #[serde(default)]
+    pub pull_request_number: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pull_request_url: String,
+    pub reserved_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// Implement updating the Airtable record for an RfdReservation.
+#[async_trait]
+impl UpdateAirtableRecord<RfdReservation> for RfdReservation {
+    async fn update_airtable_record(&mut self, _record: RfdReservation) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A request to reserve an RFD number.
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct RfdReservationRequest {
+    pub title: String,
+    /// Who is reserving the number, like `Jane Doe <jane@example.com>`.
+    pub reserved_by: String,
+}
+
+/// The next free RFD number, after all the ones that are taken.
+pub fn next_rfd_number(taken: &BTreeSet<i32>) -> i32 {
+    taken.iter().next_back().map(|n| n + 1).unwrap_or(1).max(1)
+}
+
+/// The README.adoc a new RFD starts out with.
+pub fn rfd_template(number: i32, title: &str, authors: &str) -> String {
+    format!(
+        r#":showtitle:
+:toc: left
+:numbered:
+:icons: font
+:state: prediscussion
+:discussion:
+:revremark: State: {{state}}
+:authors: {}
+
+= RFD {} {}
+{{authors}}
+
+== Background
+
+What is the problem and why does it matter?
+
+== Proposal
+
+What do we want to do about it?
+
+== Open Questions
+
+What do we still need to figure out?
+"#,
+        authors.trim(),
+        number,
+        title.trim()
+    )
+}
+
+/// Reserve the next free RFD number. The number is allocated in the database, so two
+/// people reserving at the same time get different numbers. Then a branch with a
+/// README.adoc from the template and a draft pull request are created for it.
+pub async fn reserve_rfd(db: &Database, company: &Company, title: &str, reserved_by: &str) -> Result<RfdReservation> {
+    let title = title.trim();
+    if title.is_empty() {
+        bail!("an RFD needs a title");
+    }
+
+    let github = company.authenticate_github()?;
+    let owner = &company.github_org;
+    let repo = "rfd";
+
+    // Numbers are also taken by branches that were pushed without a reservation.
+    let mut branch_numbers: BTreeSet<i32> = Default::default();
+    for branch in github.repos().list_all_branches(owner, repo, false).await? {
+        if let Ok(n) = branch.name.parse::<i32>() {
+            branch_numbers.insert(n);
+        }
+    }
+
+    let mut reservation: Option<RfdReservation> = None;
+    for _ in 0..MAX_RESERVATION_ATTEMPTS {
+        let mut taken = branch_numbers.clone();
+        for rfd in RFDs::get_from_db(db, company.id).await? {
+            taken.insert(rfd.number);
+        }
+        for r in RfdReservations::get_from_db(db, company.id).await? {
+            taken.insert(r.number);
+        }
+
+        let number = next_rfd_number(&taken);
+        let new = NewRfdReservation {
+            number,
+            title: title.to_string(),
+            reserved_by: reserved_by.to_string(),
+            branch: Default::default(),
+            link: Default::default(),
+            pull_request_number: 0,
+            pull_request_url: Default::default(),
+            reserved_at: Utc::now(),
+            cio_company_id: company.id,
+        };
+
+        // The unique index on the number makes this fail if someone else got there first.
+        match new.create_in_db(db).await {
+            Ok(r) => {
+                reservation = Some(r);
+                break;
+            }
+            Err(e) => warn!("reserving RFD number `{}` failed, trying the next one: {}", number, e),
+        }
+    }
+    let mut reservation = match reservation {
+        Some(r) => r,
+        None => bail!(
+            "could not reserve an RFD number after {} attempts",
+            MAX_RESERVATION_ATTEMPTS
+        ),
+    };
+
+    // Give the number back if we could not set it up, so the reservation does not
+    // point at a branch or pull request that does not exist. If the branch was
+    // created, it keeps the number taken.
+    if let Err(e) = create_rfd_branch(&github, owner, repo, &mut reservation, title, reserved_by).await {
+        if let Err(de) = reservation.delete_from_db(db).await {
+            warn!("deleting the reservation of RFD {} failed: {}", reservation.number, de);
+        }
+        bail!("setting up RFD {} in GitHub failed: {}", reservation.number, e);
+    }
+    let reservation = reservation.update(db).await?;
+
+    info!(
+        "reserved RFD {} `{}` for {}: {}",
+        reservation.number, reservation.title, reservation.reserved_by, reservation.pull_request_url
+    );
+
+    Ok(reservation)
+}
+
+/// Create the branch with the README.adoc from the template and the draft pull
+/// request for a reservation.
+async fn create_rfd_branch(
+    github: &octorust::Client,
+    owner: &str,
+    repo: &str,
+    reservation: &mut RfdReservation,
+    title: &str,
+    reserved_by: &str,
+) -> Result<()> {
+    let number_string = format!("{:04}", reservation.number);
+    let r = github.repos().get(owner, repo).await?;
+
+    // Create the branch from the default branch.
+    let default_ref = github
+        .git()
+        .get_ref(owner, repo, &format!("heads/{}", r.default_branch))
+        .await?;
+    github
+        .git()
+        .create_ref(
+            owner,
+            repo,
+            &octorust::types::GitCreateRefRequest {
+                key: Default::default(),
+                ref_: format!("refs/heads/{}", number_string),
+                sha: default_ref.object.sha,
+            },
+        )
+        .await?;
+
+    create_or_update_file_in_github_repo(
+        github,
+        owner,
+        repo,
+        &number_string,
+        &format!("/rfd/{}/README.adoc", number_string),
+        rfd_template(reservation.number, title, reserved_by).as_bytes().to_vec(),
+    )
+    .await?;
+
+    let pull_request = github
+        .pulls()
+        .create(
+            owner,
+            repo,
+            &octorust::types::PullsCreateRequest {
+                base: r.default_branch.to_string(),
+                body: format!("RFD {} {}, reserved by {}.", reservation.number, title, reserved_by),
+                draft: Some(true),
+                head: number_string.to_string(),
+                issue: 0,
+                maintainer_can_modify: None,
+                title: format!("RFD {} {}", reservation.number, title),
+            },
+        )
+        .await?;
+
+    reservation.branch = number_string.to_string();
+    reservation.link = format!(
+        "https://github.com/{}/{}/tree/{}/rfd/{}",
+        owner, repo, number_string, number_string
+    );
+    reservation.pull_request_number = pull_request.number as i32;
+    reservation.pull_request_url = pull_request.html_url.to_string();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{next_rfd_number, rfd_template};
+
+    #[test]
+    fn test_next_rfd_number() {
+        assert_eq!(next_rfd_number(&BTreeSet::new()), 1);
+
+        // Gaps are left alone, the numbers only go up.
+        let taken: BTreeSet<i32> = vec![1, 2, 4, 300].into_iter().collect();
+        assert_eq!(next_rfd_number(&taken), 301);
+
+        let template = rfd_template(301, " Things ", "Jane Doe <jane@example.com>");
+        assert!(template.contains(":authors: Jane Doe <jane@example.com>\n"));
+        assert!(template.contains("\n= RFD 301 Things\n{authors}\n"));
+        assert!(template.contains(":state: prediscussion\n"));
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use diesel::{ExpressionMethods, QueryDsl};
+use lazy_static::lazy_static;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    companies::Company,
+    db::Database,
+    rfds::{RFDs, RFD},
+    schema::rfds,
+};
+
+/// How much a match in each field counts towards the score of an RFD.
+const TITLE_WEIGHT: f64 = 4.0;
+const METADATA_WEIGHT: f64 = 2.0;
+const CONTENT_WEIGHT: f64 = 1.0;
+
+/// The BM25 parameters for scoring matches in the content.
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+/// The number of results to return if the query does not set a limit.
+pub const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+/// The number of bytes of content to show around the first match in a snippet.
+const SNIPPET_BEFORE: usize = 80;
+const SNIPPET_AFTER: usize = 160;
+
+/// A single result of searching the RFDs, the best match comes first.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RFDSearchResult {
+    pub number: i32,
+    pub number_string: String,
+    pub title: String,
+    pub name: String,
+    pub state: String,
+    pub authors: String,
+    pub short_link: String,
+    pub rendered_link: String,
+    pub score: f64,
+    /// The part of the content around the first match, if the content matched.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub snippet: String,
+}
+
+/// The query parameters for searching the RFDs.
+#[derive(Debug, Default, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct RFDSearchParams {
+    /// The words to search for, an RFD must match all of them.
+    pub q: String,
+    /// The maximum number of results to return, defaults to 10.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// The `(number, sha)` of each RFD an index was built from.
+type IndexVersion = Vec<(i32, String)>;
+
+lazy_static! {
+    /// The search index of each company's RFDs, so we only build it again when the
+    /// RFDs change.
+    static ref SEARCH_INDEXES: Mutex<BTreeMap<i32, (IndexVersion, Arc<RFDSearchIndex>)>> = Default::default();
+}
+
+/// An RFD split up into the terms we search.
+struct IndexedRFD {
+    /// The result for the RFD, without a score or snippet.
+    result: RFDSearchResult,
+    /// The content, for snippets.
+    text: String,
+    title: BTreeSet<String>,
+    metadata: BTreeSet<String>,
+    content: BTreeMap<String, usize>,
+    content_length: usize,
+}
+
+/// An in-memory full-text index over a company's RFDs. It is cached between searches
+/// and built again when the RFDs change.
+pub struct RFDSearchIndex {
+    rfds: Vec<IndexedRFD>,
+    /// The number of RFDs each term appears in.
+    document_frequency: BTreeMap<String, usize>,
+    average_content_length: f64,
+}
+
+impl RFDSearchIndex {
+    /// Index the title, authors, state, relevant components and content of the RFDs.
+    pub fn new(rfds: &[RFD]) -> Self {
+        let mut document_frequency: BTreeMap<String, usize> = Default::default();
+        let mut total_length = 0;
+
+        let rfds: Vec<IndexedRFD> = rfds
+            .iter()
+            .map(|rfd| {
+                let title: BTreeSet<String> = tokenize(&rfd.title).collect();
+
+                let mut metadata: BTreeSet<String> = tokenize(&rfd.authors).collect();
+                metadata.extend(tokenize(&rfd.state));
+                for component in &rfd.relevant_components {
+                    metadata.extend(tokenize(component));
+                }
+
+                let mut content: BTreeMap<String, usize> = Default::default();
+                let mut content_length = 0;
+                for term in tokenize(&rfd.content) {
+                    *content.entry(term).or_default() += 1;
+                    content_length += 1;
+                }
+                total_length += content_length;
+
+                let terms: BTreeSet<&String> = title.iter().chain(metadata.iter()).chain(content.keys()).collect();
+                for term in terms {
+                    *document_frequency.entry(term.to_string()).or_default() += 1;
+                }
+
+                IndexedRFD {
+                    result: RFDSearchResult {
+                        number: rfd.number,
+                        number_string: rfd.number_string.to_string(),
+                        title: rfd.title.to_string(),
+                        name: rfd.name.to_string(),
+                        state: rfd.state.to_string(),
+                        authors: rfd.authors.to_string(),
+                        short_link: rfd.short_link.to_string(),
+                        rendered_link: rfd.rendered_link.to_string(),
+                        score: 0.0,
+                        snippet: Default::default(),
+                    },
+                    text: rfd.content.to_string(),
+                    title,
+                    metadata,
+                    content,
+                    content_length,
+                }
+            })
+            .collect();
+
+        let average_content_length = if rfds.is_empty() {
+            0.0
+        } else {
+            total_length as f64 / rfds.len() as f64
+        };
+
+        RFDSearchIndex {
+            rfds,
+            document_frequency,
+            average_content_length,
+        }
+    }
+
+    /// Search for RFDs matching all the words in the query, ranked best match first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<RFDSearchResult> {
+        let terms: BTreeSet<String> = tokenize(query).collect();
+        if terms.is_empty() {
+            return vec![];
+        }
+
+        let mut results: Vec<RFDSearchResult> = self
+            .rfds
+            .iter()
+            .filter_map(|indexed| {
+                let mut score = 0.0;
+                for term in &terms {
+                    let in_title = indexed.title.contains(term);
+                    let in_metadata = indexed.metadata.contains(term);
+                    let frequency = indexed.content.get(term).copied().unwrap_or_default();
+                    if !in_title && !in_metadata && frequency == 0 {
+                        // Every term has to match.
+                        return None;
+                    }
+
+                    let idf = self.idf(term);
+                    if in_title {
+                        score += TITLE_WEIGHT * idf;
+                    }
+                    if in_metadata {
+                        score += METADATA_WEIGHT * idf;
+                    }
+                    if frequency > 0 {
+                        let frequency = frequency as f64;
+                        let length_norm = if self.average_content_length > 0.0 {
+                            indexed.content_length as f64 / self.average_content_length
+                        } else {
+                            1.0
+                        };
+                        score += CONTENT_WEIGHT * idf * (frequency * (BM25_K1 + 1.0))
+                            / (frequency + BM25_K1 * (1.0 - BM25_B + BM25_B * length_norm));
+                    }
+                }
+
+                Some(RFDSearchResult {
+                    score,
+                    snippet: snippet(&indexed.text, &terms),
+                    ..indexed.result.clone()
+                })
+            })
+            .collect();
+
+        // Sort by score, then the newest RFD first.
+        results.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.number.cmp(&a.number))
+        });
+        results.truncate(limit);
+
+        results
+    }
+
+    fn idf(&self, term: &str) -> f64 {
+        let n = self.rfds.len() as f64;
+        let df = self.document_frequency.get(term).copied().unwrap_or_default() as f64;
+
+        ((n - df + 0.5) / (df + 0.5) + 1.0).ln()
+    }
+}
+
+/// Search a company's RFDs.
+pub async fn search_rfds(db: &Database, company: &Company, query: &str, limit: usize) -> Result<Vec<RFDSearchResult>> {
+    if tokenize(query).next().is_none() {
+        return Ok(vec![]);
+    }
+
+    // Only the number and sha of each RFD are loaded to check if the cached index
+    // is up to date, the content only when we have to build it again.
+    let version = rfds::dsl::rfds
+        .filter(rfds::dsl::cio_company_id.eq(company.id))
+        .select((rfds::dsl::number, rfds::dsl::sha))
+        .order_by(rfds::dsl::number)
+        .load_async::<(i32, String)>(db.pool())
+        .await?;
+    let cached = SEARCH_INDEXES
+        .lock()
+        .unwrap()
+        .get(&company.id)
+        .filter(|(v, _)| *v == version)
+        .map(|(_, index)| index.clone());
+
+    let index = match cached {
+        Some(index) => index,
+        None => {
+            let rfds: Vec<RFD> = RFDs::get_from_db(db, company.id).await?.into();
+            let mut version: IndexVersion = rfds.iter().map(|r| (r.number, r.sha.to_string())).collect();
+            version.sort();
+
+            let index = Arc::new(RFDSearchIndex::new(&rfds));
+            SEARCH_INDEXES
+                .lock()
+                .unwrap()
+                .insert(company.id, (version, index.clone()));
+            index
+        }
+    };
+
+    Ok(index.search(query, limit))
+}
+
+/// Split text into lowercase words.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    words(text).map(|(_, word)| word.to_lowercase())
+}
+
+/// Split text into words with their byte offsets.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(move |w| (w.as_ptr() as usize - text.as_ptr() as usize, w))
+}
+
+/// Get the part of the content around the first word matching one of the terms.
+fn snippet(content: &str, terms: &BTreeSet<String>) -> String {
+    let start = match words(content).find(|(_, w)| terms.contains(&w.to_lowercase())) {
+        Some((start, _)) => start,
+        None => return String::new(),
+    };
+
+    // Find the closest whitespace around the window so we don't cut a word in half.
+    let mut from = start.saturating_sub(SNIPPET_BEFORE);
+    while !content.is_char_boundary(from) {
+        from -= 1;
+    }
+    if from > 0 {
+        from = content[from..start]
+            .find(char::is_whitespace)
+            .map(|i| from + i + 1)
+            .unwrap_or(from);
+    }
+
+    let mut to = (start + SNIPPET_AFTER).min(content.len());
+    while !content.is_char_boundary(to) {
+        to += 1;
+    }
+    if to < content.len() {
+        to = content[start..to]
+            .rfind(char::is_whitespace)
+            .map(|i| start + i)
+            .unwrap_or(to);
+    }
+
+    let mut snippet = content[from..to].split_whitespace().collect::<Vec<&str>>().join(" ");
+    if from > 0 {
+        snippet = format!("…{}", snippet);
+    }
+    if to < content.len() {
+        snippet = format!("{}…", snippet);
+    }
+
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rfd(number: i32, title: &str, authors: &str, content: &str) -> RFD {
+        let mut rfd: RFD = serde_json::from_value(serde_json::json!({
+            "id": number,
+            "number": number,
+            "title": title,
+            "state": "published",
+            "link": "",
+            "authors": authors,
+            "content": content,
+            "commit_date": "2022-04-01T00:00:00Z",
+        }))
+        .unwrap();
+        rfd.number_string = format!("{:04}", number);
+        rfd
+    }
+
+    #[test]
+    fn test_search_rfds() {
+        let rfds = vec![
+            rfd(
+                1,
+                "Requests for Discussion",
+                "Bryan Cantrill",
+                "Writing down ideas so we can discuss them.",
+            ),
+            rfd(
+                2,
+                "Networking Considerations",
+                "Jane Doe",
+                "This covers the network switch and how packets move between sleds in the rack.",
+            ),
+            rfd(3, "Rack Power", "John Smith", "The rack power shelf feeds every sled."),
+        ];
+        let index = RFDSearchIndex::new(&rfds);
+
+        // Titles rank above content.
+        let results = index.search("Rack", 10);
+        assert_eq!(results.iter().map(|r| r.number).collect::<Vec<i32>>(), vec![3, 2]);
+
+        // All terms have to match.
+        let results = index.search("rack switch", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].number, 2);
+        assert!(results[0].snippet.contains("switch"));
+
+        // Authors are searched too.
+        assert_eq!(index.search("cantrill", 10)[0].number, 1);
+
+        assert!(index.search("", 10).is_empty());
+        assert!(index.search("storage", 10).is_empty());
+    }
+
+    #[test]
+    fn test_snippet() {
+        let terms: BTreeSet<String> = tokenize("needle").collect();
+        let content = format!("{} the Needle is here {}", "hay ".repeat(50), "stack ".repeat(50));
+
+        let s = snippet(&content, &terms);
+        assert!(s.starts_with('…'));
+        assert!(s.ends_with('…'));
+        assert!(s.contains("the Needle is here"));
+        assert!(s.len() < content.len());
+
+        assert_eq!(snippet("no match", &terms), "");
+    }
+}
@@ -5,12 +5,13 @@ use std::{
     path::{Path, PathBuf},
     process::Command,
     str::from_utf8,
+    str::FromStr,
 };
 
 use anyhow::{anyhow, bail, Result};
 use async_bb8_diesel::AsyncRunQueryDsl;
 use async_trait::async_trait;
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Utc};
 use comrak::{markdown_to_html, ComrakOptions};
 use csv::ReaderBuilder;
 use google_drive::traits::{DriveOps, FileOps};
@@ -18,19 +19,20 @@ use log::{info, warn};
 use macros::db;
 use regex::Regex;
 use schemars::JsonSchema;
-use sendgrid_api::{traits::MailOps, Client as SendGrid};
 use serde::{Deserialize, Serialize};
 use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
 
 use crate::{
-    airtable::AIRTABLE_RFD_TABLE,
+    airtable::{AIRTABLE_RFD_STATE_CHANGES_TABLE, AIRTABLE_RFD_TABLE},
+    asciidoc,
     companies::Company,
     core::{GitHubPullRequest, UpdateAirtableRecord},
     db::Database,
-    schema::rfds as r_f_ds,
-    schema::rfds,
+    rfd_changelogs::RfdChangelogCommit,
+    rfd_feeds::publish_rfd_feeds,
+    rfd_references::update_rfd_references,
+    schema::{rfd_state_changes, rfds as r_f_ds, rfds},
     utils::{
         create_or_update_file_in_github_repo, decode_base64, decode_base64_to_string, get_file_content_from_repo,
         truncate, write_file,
@@ -95,6 +97,12 @@ pub struct NewRFD {
     pub pdf_link_github: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub pdf_link_google_drive: String,
+    /// (generated) cites is the number strings of the RFDs this RFD references.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cites: Vec<String>,
+    /// (generated) cited_by is the number strings of the RFDs that reference this RFD.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cited_by: Vec<String>,
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
@@ -159,6 +167,8 @@ impl NewRFD {
             relevant_components: Default::default(),
             pdf_link_github: Default::default(),
             pdf_link_google_drive: Default::default(),
+            cites: Default::default(),
+            cited_by: Default::default(),
             cio_company_id: company.id,
         })
     }
@@ -354,74 +364,40 @@ impl RFD {
     ) -> Result<String> {
         let dir = format!("rfd/{}", self.number_string);
 
-        // Create the temporary directory.
-        let mut path = env::temp_dir();
-        path.push(&format!("asciidoc-{}-temp/", self.number_string));
-        let pparent = path.clone();
-        let parent = pparent.as_path().to_str().unwrap().trim_end_matches('/').to_string();
-        path.push(&format!("contents-{}.adoc", self.number_string));
-
-        // Write the contents to a temporary file.
-        write_file(&path, deunicode::deunicode(&self.content).as_bytes()).await?;
-
-        // If the file contains inline images, we need to save those images locally.
-        // TODO: we don't need to save all the images, only the inline ones, clean this up
-        // eventually.
-        if self.content.contains("[opts=inline]") {
+        // If the file contains inline images, we need their contents so we can embed them.
+        let mut inline_images: BTreeMap<String, String> = Default::default();
+        if self.content.contains("opts=inline") {
             let images = get_images_in_branch(github, owner, repo, &dir, branch).await?;
             for image in images {
-                // Save the image to our temporary directory.
-                let image_path = format!("{}/{}", parent, image.path.replace(&dir, "").trim_start_matches('/'));
+                if !image.name.ends_with(".svg") {
+                    continue;
+                }
 
-                write_file(&PathBuf::from(image_path), &decode_base64(&image.content)).await?;
+                let image_path = image.path.replace(&dir, "").trim_start_matches('/').to_string();
+                inline_images.insert(image_path, decode_base64_to_string(&image.content));
             }
         }
 
-        let cmd_output = tokio::task::spawn_blocking(enclose! { (parent, path) move || {
-            Command::new("asciidoctor")
-                .current_dir(&parent)
-                .args(&["-o", "-", "--no-header-footer", path.to_str().unwrap()])
-                .output()
-        }})
-        .await??;
-
-        let result = if cmd_output.status.success() {
-            from_utf8(&cmd_output.stdout)?
-        } else {
-            bail!(
-                "[rfds] running asciidoctor failed: {} {}",
-                from_utf8(&cmd_output.stdout)?,
-                from_utf8(&cmd_output.stderr)?
-            );
-        };
-
-        // Delete the parent directory.
-        let pdir = Path::new(&parent);
-        if pdir.exists() && pdir.is_dir() {
-            fs::remove_dir_all(pdir).await?;
-        }
-
-        Ok(result.to_string())
+        Ok(asciidoc::render(&deunicode::deunicode(&self.content), &inline_images))
     }
 
-    /// Get a changelog for the RFD.
-    pub async fn get_weekly_changelog(
+    /// Get the commits to the RFD since a date.
+    pub async fn get_changelog_commits(
         &self,
         github: &octorust::Client,
         since: DateTime<Utc>,
         company: &Company,
-    ) -> Result<String> {
+    ) -> Result<Vec<RfdChangelogCommit>> {
         let owner = &company.github_org;
         let repo = "rfd";
         let r = github.repos().get(owner, repo).await?;
-        let mut changelog = String::new();
 
         let mut branch = self.number_string.to_string();
         if self.link.contains(&format!("/{}/", r.default_branch)) {
             branch = r.default_branch.to_string();
         }
 
-        // Get the commits from the last seven days to the file.
+        // Get the commits since the date to the file.
         let commits = match github
             .repos()
             .list_all_commits(
@@ -442,20 +418,16 @@ impl RFD {
             }
         };
 
+        let mut changelog: Vec<RfdChangelogCommit> = Default::default();
         for commit in commits {
-            let message: Vec<&str> = commit.commit.message.lines().collect();
-            if !message.is_empty() {
-                if let Some(author) = commit.author {
-                    changelog += &format!(
-                        "\t- \"{}\" by @{}\n\t\thttps://github.com/oxidecomputer/rfd/commit/{}\n",
-                        message[0], author.login, commit.sha
-                    );
-                } else {
-                    changelog += &format!(
-                        "\t- \"{}\"\n\t\thttps://github.com/oxidecomputer/rfd/commit/{}\n",
-                        message[0], commit.sha
-                    );
-                }
+            if let Some(message) = commit.commit.message.lines().next() {
+                changelog.push(RfdChangelogCommit {
+                    message: message.to_string(),
+                    author: commit.author.map(|a| a.login).unwrap_or_default(),
+                    committed_at: commit.commit.committer.as_ref().and_then(|c| c.date.parse().ok()),
+                    html_url: format!("https://github.com/{}/{}/commit/{}", owner, repo, commit.sha),
+                    sha: commit.sha,
+                });
             }
         }
 
@@ -471,9 +443,14 @@ impl RFD {
         )
     }
 
+    /// Get the state of the RFD.
+    pub fn get_state(&self) -> Result<RfdState> {
+        self.state.parse()
+    }
+
     /// Update an RFDs state.
-    pub fn update_state(&mut self, state: &str, is_markdown: bool) -> Result<()> {
-        self.content = update_state(&self.content, state, is_markdown)?;
+    pub fn update_state(&mut self, state: RfdState, is_markdown: bool) -> Result<()> {
+        self.content = update_state(&self.content, &state.to_string(), is_markdown)?;
         self.state = state.to_string();
 
         Ok(())
@@ -504,15 +481,6 @@ impl RFD {
         let rfd_repo = "rfd";
         let repo = github.repos().get(owner, rfd_repo).await?;
 
-        let mut path = env::temp_dir();
-        path.push(format!("pdfcontents{}.adoc", self.number_string));
-
-        let rfd_content = self.content.to_string();
-
-        // Write the contents to a temporary file.
-        let mut file = fs::File::create(path.clone()).await?;
-        file.write_all(rfd_content.as_bytes()).await?;
-
         let file_name = self.get_pdf_filename();
         let rfd_path = format!("/pdfs/{}", file_name);
 
@@ -521,39 +489,131 @@ impl RFD {
             branch = repo.default_branch.to_string();
         }
 
-        // Create the dir where to save images.
-        let temp_dir = env::temp_dir();
-        let temp_dir_str = temp_dir.to_str().unwrap();
+        // Create the temporary directory, the HTML and the images go in here. It is
+        // deleted whether or not we could make the PDF.
+        let mut dir = env::temp_dir();
+        dir.push(format!("rfd-pdf-{}", self.number_string));
+        let result = self
+            .render_pdf(github, owner, rfd_repo, &branch, &dir, &file_name)
+            .await;
+        if dir.exists() && dir.is_dir() {
+            if let Err(e) = fs::remove_dir_all(&dir).await {
+                warn!("deleting `{}` failed: {}", dir.display(), e);
+            }
+        }
+        let pdf = result?;
+
+        // Create or update the file in the github repository.
+        create_or_update_file_in_github_repo(github, owner, rfd_repo, &repo.default_branch, &rfd_path, pdf.clone())
+            .await?;
 
-        // We need to save the images locally as well.
-        // This ensures that
+        // Figure out where our directory is.
+        // It should be in the shared drive : "Automated Documents"/"rfds"
+        let shared_drive = drive_client.drives().get_by_name("Automated Documents").await?;
+        let drive_id = shared_drive.id.to_string();
+
+        // Get the directory by the name.
+        let parent_id = drive_client.files().create_folder(&drive_id, "", "rfds").await?;
+
+        // Create or update the file in the google_drive.
+        let drive_file = drive_client
+            .files()
+            .create_or_update(&drive_id, &parent_id, &file_name, "application/pdf", &pdf)
+            .await?;
+        self.pdf_link_google_drive = format!("https://drive.google.com/open?id={}", drive_file.id);
+
+        Ok(())
+    }
+
+    /// Render the RFD as a PDF in a temporary directory, with its images and
+    /// diagrams, and return its contents.
+    async fn render_pdf(
+        &self,
+        github: &octorust::Client,
+        owner: &str,
+        rfd_repo: &str,
+        branch: &str,
+        dir: &Path,
+        file_name: &str,
+    ) -> Result<Vec<u8>> {
+        let dir_str = dir.to_str().unwrap().to_string();
+
+        // We need to save the images locally so they end up in the PDF.
         let old_dir = format!("rfd/{}", self.number_string);
-        let images = get_images_in_branch(github, owner, rfd_repo, &old_dir, &branch).await?;
-        let mut image_paths: Vec<String> = Vec::new();
+        let images = get_images_in_branch(github, owner, rfd_repo, &old_dir, branch).await?;
         for image in images {
-            // Save the image to our temporary directory.
             let image_path = format!(
                 "{}/{}",
-                temp_dir_str.trim_end_matches('/'),
+                dir_str,
                 image.path.replace(&old_dir, "").trim_start_matches('/')
             );
 
-            image_paths.push(image_path.to_string());
-
             write_file(&PathBuf::from(image_path), &decode_base64(&image.content)).await?;
         }
 
-        let cmd_output = tokio::task::spawn_blocking(enclose! { (path) move || {
-            Command::new("asciidoctor-pdf")
-                .current_dir(env::temp_dir())
+        // Render the RFD as HTML, then convert it to a PDF with pandoc.
+        let mut html = asciidoc::render(&deunicode::deunicode(&self.content), &Default::default());
+
+        // Draw the mermaid diagrams, the ones we can't draw stay as their source.
+        let mut diagrams: Vec<Option<String>> = Default::default();
+        for (i, diagram) in asciidoc::mermaid_diagrams(&html).iter().enumerate() {
+            let input = dir.join(format!("mermaid-{}.mmd", i));
+            let output = dir.join(format!("mermaid-{}.svg", i));
+            write_file(&input, diagram.as_bytes()).await?;
+
+            let cmd_output = tokio::task::spawn_blocking(enclose! { (input, output) move || {
+                Command::new("mmdc")
+                    .args(&["-i", input.to_str().unwrap(), "-o", output.to_str().unwrap()])
+                    .output()
+            }})
+            .await?;
+            match cmd_output {
+                Ok(o) if o.status.success() => diagrams.push(Some(format!("mermaid-{}.svg", i))),
+                Ok(o) => {
+                    warn!(
+                        "drawing mermaid diagram {} of RFD {} failed: {}",
+                        i,
+                        self.number_string,
+                        from_utf8(&o.stderr)?
+                    );
+                    diagrams.push(None);
+                }
+                Err(e) => {
+                    warn!("running mmdc for RFD {} failed: {}", self.number_string, e);
+                    diagrams.push(None);
+                }
+            }
+        }
+        html = asciidoc::replace_mermaid_diagrams(&html, &diagrams);
+        html = asciidoc::with_source_languages(&html);
+
+        let mut html_path = dir.to_path_buf();
+        html_path.push("contents.html");
+        write_file(
+            &html_path,
+            format!(
+                "<html><body><h1>{}</h1>\n{}\n</body></html>",
+                asciidoc::escape(&self.name),
+                html
+            )
+            .as_bytes(),
+        )
+        .await?;
+
+        let mut pdf_path = dir.to_path_buf();
+        pdf_path.push(file_name);
+
+        let cmd_output = tokio::task::spawn_blocking(enclose! { (dir_str, html_path, pdf_path) move || {
+            Command::new("pandoc")
+                .current_dir(&dir_str)
                 .args(&[
+                    "--resource-path",
+                    &dir_str,
+                    "--highlight-style",
+                    "pygments",
                     "-o",
-                    "-",
-                    "-r",
-                    "asciidoctor-mermaid/pdf",
-                    "-a",
-                    "source-highlighter=rouge",
-                    path.to_str().unwrap(),
+                    pdf_path.to_str().unwrap(),
+                    html_path.to_str().unwrap(),
                 ])
                 .output()
         }})
@@ -561,52 +621,13 @@ impl RFD {
 
         if !cmd_output.status.success() {
             bail!(
-                "running asciidoctor failed: {} {}",
+                "running pandoc failed: {} {}",
                 from_utf8(&cmd_output.stdout)?,
                 from_utf8(&cmd_output.stderr)?
             );
         }
 
-        // Create or update the file in the github repository.
-        create_or_update_file_in_github_repo(
-            github,
-            owner,
-            rfd_repo,
-            &repo.default_branch,
-            &rfd_path,
-            cmd_output.stdout.clone(),
-        )
-        .await?;
-
-        // Figure out where our directory is.
-        // It should be in the shared drive : "Automated Documents"/"rfds"
-        let shared_drive = drive_client.drives().get_by_name("Automated Documents").await?;
-        let drive_id = shared_drive.id.to_string();
-
-        // Get the directory by the name.
-        let parent_id = drive_client.files().create_folder(&drive_id, "", "rfds").await?;
-
-        // Create or update the file in the google_drive.
-        let drive_file = drive_client
-            .files()
-            .create_or_update(&drive_id, &parent_id, &file_name, "application/pdf", &cmd_output.stdout)
-            .await?;
-        self.pdf_link_google_drive = format!("https://drive.google.com/open?id={}", drive_file.id);
-
-        // Delete our temporary file.
-        if path.exists() && !path.is_dir() {
-            fs::remove_file(path).await?;
-        }
-
-        // Cleanup our images.
-        for image_path in image_paths {
-            // Ignore the error we don't care.
-            // TODO: One thing we are doing on every RFD push is updating these images twoce, once
-            // to parse the asciidoc and again for PDF, should probably combine into one thing.
-            fs::remove_file(PathBuf::from(&image_path)).await.unwrap_or_default();
-        }
-
-        Ok(())
+        Ok(fs::read(&pdf_path).await?)
     }
 
     /// Update the pull request information for an RFD.
@@ -658,10 +679,10 @@ impl RFD {
 
         // Update the labels for the pull request.
         let mut labels: Vec<String> = Default::default();
-        if self.state == "discussion" {
-            labels.push(":thought_balloon: discussion".to_string());
-        } else if self.state == "ideation" {
-            labels.push(":hatching_chick: ideation".to_string());
+        match self.get_state() {
+            Ok(RfdState::Discussion) => labels.push(":thought_balloon: discussion".to_string()),
+            Ok(RfdState::Ideation) => labels.push(":hatching_chick: ideation".to_string()),
+            _ => (),
         }
         github
             .issues()
@@ -676,15 +697,6 @@ impl RFD {
         Ok(())
     }
 
-    /// Trigger updating the search index for the RFD.
-    pub async fn update_search_index(&self) -> Result<()> {
-        let client = reqwest::Client::new();
-        let req = client.put(&format!("https://rfd.shared.oxide.computer/api/search/{}", self.number));
-        req.send().await?;
-
-        Ok(())
-    }
-
     /// Expand the fields in the RFD.
     /// This will get the content, html, sha, commit_date as well as fill in all generated fields.
     pub async fn expand(&mut self, github: &octorust::Client, company: &Company) -> Result<()> {
@@ -764,6 +776,187 @@ impl UpdateAirtableRecord<RFD> for RFD {
     }
 }
 
+/// The states an RFD can be in, from RFD 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RfdState {
+    Prediscussion,
+    Ideation,
+    Discussion,
+    Published,
+    Committed,
+    Abandoned,
+}
+
+impl ToString for RfdState {
+    fn to_string(&self) -> String {
+        match self {
+            RfdState::Prediscussion => "prediscussion".to_string(),
+            RfdState::Ideation => "ideation".to_string(),
+            RfdState::Discussion => "discussion".to_string(),
+            RfdState::Published => "published".to_string(),
+            RfdState::Committed => "committed".to_string(),
+            RfdState::Abandoned => "abandoned".to_string(),
+        }
+    }
+}
+
+impl FromStr for RfdState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            // An RFD without a state has not been discussed yet.
+            "" | "prediscussion" => Ok(RfdState::Prediscussion),
+            "ideation" => Ok(RfdState::Ideation),
+            "discussion" => Ok(RfdState::Discussion),
+            "published" => Ok(RfdState::Published),
+            "committed" => Ok(RfdState::Committed),
+            "abandoned" => Ok(RfdState::Abandoned),
+            _ => bail!("unknown RFD state `{}`", s),
+        }
+    }
+}
+
+impl RfdState {
+    /// The states an RFD in this state can move to.
+    pub fn next_states(&self) -> Vec<RfdState> {
+        match self {
+            RfdState::Prediscussion => vec![
+                RfdState::Ideation,
+                RfdState::Discussion,
+                RfdState::Published,
+                RfdState::Abandoned,
+            ],
+            RfdState::Ideation => vec![
+                RfdState::Prediscussion,
+                RfdState::Discussion,
+                RfdState::Published,
+                RfdState::Abandoned,
+            ],
+            RfdState::Discussion => vec![
+                RfdState::Prediscussion,
+                RfdState::Ideation,
+                RfdState::Published,
+                RfdState::Abandoned,
+            ],
+            RfdState::Published => vec![RfdState::Committed, RfdState::Abandoned],
+            RfdState::Committed => vec![RfdState::Abandoned],
+            // An abandoned RFD can be picked back up.
+            RfdState::Abandoned => vec![RfdState::Prediscussion, RfdState::Ideation, RfdState::Discussion],
+        }
+    }
+
+    /// Returns if an RFD can move from this state to the next one. Staying in the
+    /// same state is always allowed.
+    pub fn can_transition_to(&self, next: &RfdState) -> bool {
+        self == next || self.next_states().contains(next)
+    }
+}
+
+/// Check the change of an RFD from one state to another, returns the old state, if
+/// there was one, and the new state if the state changed. An old state that is empty
+/// or that we can't parse is from before we enforced the states, so the RFD can move
+/// to any valid state from it.
+pub fn validate_rfd_state_change(old: &str, new: &str) -> Result<Option<(Option<RfdState>, RfdState)>> {
+    let to: RfdState = new.parse()?;
+    if old.trim().is_empty() {
+        return Ok(Some((None, to)));
+    }
+    let from: RfdState = match old.parse() {
+        Ok(from) => from,
+        Err(_) => return Ok(Some((None, to))),
+    };
+
+    if from == to {
+        return Ok(None);
+    }
+
+    if !from.can_transition_to(&to) {
+        bail!(
+            "an RFD cannot move from `{}` to `{}`, it can move to: {}",
+            from.to_string(),
+            to.to_string(),
+            from.next_states()
+                .iter()
+                .map(|s| format!("`{}`", s.to_string()))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+
+    Ok(Some((Some(from), to)))
+}
+
+/// A change in the state of an RFD.
+#[db {
+    new_struct_name = "RfdStateChange",
+    airtable_base = "roadmap",
+    airtable_table = "AIRTABLE_RFD_STATE_CHANGES_TABLE",
+    match_on = {
+        "cio_company_id" = "i32",
+        "rfd_number" = "i32",
+        "commit_sha" = "String",
+        "to_state" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[diesel(table_name = rfd_state_changes)]
+pub struct NewRfdStateChange {
+    pub rfd_number: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub from_state: String,
+    pub to_state: String,
+    /// The GitHub login of the person who changed the state.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub actor: String,
+    /// The commit that changed the state.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub commit_sha: String,
+    /// The number of the pull request for the RFD, 0 if there is none.
+    #[serde(default)]
+    pub pull_request_number: i32,
+    pub changed_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// Implement updating the Airtable record for an RfdStateChange.
+#[async_trait]
+impl UpdateAirtableRecord<RfdStateChange> for RfdStateChange {
+    async fn update_airtable_record(&mut self, _record: RfdStateChange) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl NewRfdStateChange {
+    /// Record a change in the state of an RFD.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn record(
+        db: &Database,
+        company: &Company,
+        rfd_number: i32,
+        from: Option<RfdState>,
+        to: RfdState,
+        actor: &str,
+        commit_sha: &str,
+        pull_request_number: i64,
+    ) -> Result<RfdStateChange> {
+        NewRfdStateChange {
+            rfd_number,
+            from_state: from.map(|s| s.to_string()).unwrap_or_default(),
+            to_state: to.to_string(),
+            actor: actor.to_string(),
+            commit_sha: commit_sha.to_string(),
+            pull_request_number: pull_request_number as i32,
+            changed_at: Utc::now(),
+            cio_company_id: company.id,
+        }
+        .upsert_in_db(db)
+        .await
+    }
+}
+
 /// Get the RFDs from the rfd GitHub repo.
 pub async fn get_rfds_from_repo(github: &octorust::Client, company: &Company) -> Result<BTreeMap<i32, NewRFD>> {
     let owner = &company.github_org;
@@ -1037,6 +1230,11 @@ pub async fn refresh_db_rfds(db: &Database, company: &Company) -> Result<()> {
     // Update rfds in airtable.
     RFDs::get_from_db(db, company.id).await?.update_airtable(db).await?;
 
+    // Publish the feeds, a failure here should not fail the sync.
+    if let Err(e) = publish_rfd_feeds(db, company).await {
+        warn!("[rfd] publishing the feeds failed: {}", e);
+    }
+
     Ok(())
 }
 
@@ -1052,6 +1250,8 @@ impl NewRFD {
             self.sha = existing.sha.to_string();
             self.pdf_link_github = existing.pdf_link_github.to_string();
             self.pdf_link_google_drive = existing.pdf_link_google_drive;
+            self.cites = existing.cites;
+            self.cited_by = existing.cited_by;
         }
 
         let mut new_rfd = self.upsert(db).await?;
@@ -1059,12 +1259,12 @@ impl NewRFD {
         // Expand the fields in the RFD.
         new_rfd.expand(github, company).await?;
 
+        // Update the references to other RFDs, and the backlinks from them.
+        update_rfd_references(db, company, &mut new_rfd).await?;
+
         // Update the RFD here just in case the PDF conversion fails.
         let mut new_rfd = new_rfd.update(db).await?;
 
-        // Now that the database is updated, update the search index.
-        new_rfd.update_search_index().await?;
-
         // Make and update the PDF versions.
         if let Err(err) = new_rfd.convert_and_upload_pdf(db, github, company).await {
             warn!(
@@ -1198,58 +1398,15 @@ pub async fn cleanup_rfd_pdfs(db: &Database, company: &Company) -> Result<()> {
     Ok(())
 }
 
-/// Create a changelog email for the RFDs.
-pub async fn send_rfd_changelog(db: &Database, company: &Company) -> Result<()> {
-    let rfds = RFDs::get_from_db(db, company.id).await?;
-
-    if rfds.0.is_empty() {
-        // Return early.
-        return Ok(());
-    }
-
-    let github = company.authenticate_github()?;
-    let seven_days_ago = Utc::now() - Duration::days(7);
-    let week_format = format!(
-        "from {} to {}",
-        seven_days_ago.format("%m-%d-%Y"),
-        Utc::now().format("%m-%d-%Y")
-    );
-
-    let mut changelog = format!("Changes to RFDs for the week {}:\n", week_format);
-
-    // Iterate over the RFDs.
-    for rfd in rfds {
-        let changes = rfd.get_weekly_changelog(&github, seven_days_ago, company).await?;
-        if !changes.is_empty() {
-            changelog += &format!("\n{} {}\n{}", rfd.name, rfd.short_link, changes);
-        }
-    }
-
-    // Initialize the SendGrid clVient.
-    let sendgrid_client = SendGrid::new_from_env();
-
-    // Send the message.
-    sendgrid_client
-        .mail_send()
-        .send_plain_text(
-            &format!("RFD changelog for the week from {}", week_format),
-            &changelog,
-            &[format!("all@{}", company.gsuite_domain)],
-            &[],
-            &[],
-            &format!("rfds@{}", company.gsuite_domain),
-        )
-        .await?;
-
-    Ok(())
-}
-
 #[cfg(test)]
 mod tests {
     use crate::{
         companies::Company,
         db::Database,
-        rfds::{clean_rfd_html_links, send_rfd_changelog, update_discussion_link, update_state, NewRFD},
+        rfd_changelogs::send_rfd_changelog,
+        rfds::{
+            clean_rfd_html_links, update_discussion_link, update_state, validate_rfd_state_change, NewRFD, RfdState,
+        },
     };
 
     #[ignore]
@@ -1451,6 +1608,29 @@ sdf
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn test_validate_rfd_state_change() {
+        assert_eq!(
+            validate_rfd_state_change("prediscussion", "discussion").unwrap(),
+            Some((Some(RfdState::Prediscussion), RfdState::Discussion))
+        );
+        assert_eq!(validate_rfd_state_change("published", "published").unwrap(), None);
+
+        // New RFDs and RFDs with a state we don't know can move to any state.
+        assert_eq!(
+            validate_rfd_state_change("", "committed").unwrap(),
+            Some((None, RfdState::Committed))
+        );
+        assert_eq!(
+            validate_rfd_state_change("nope", "ideation").unwrap(),
+            Some((None, RfdState::Ideation))
+        );
+
+        assert!(validate_rfd_state_change("committed", "discussion").is_err());
+        assert!(validate_rfd_state_change("published", "ideation").is_err());
+        assert!(validate_rfd_state_change("discussion", "nope").is_err());
+    }
+
     #[test]
     fn test_update_state() {
         crate::utils::setup_logger();
@@ -318,6 +318,11 @@ table! {
         phone -> Varchar,
         employees -> Array<Text>,
         conference_rooms -> Array<Text>,
+        timezone -> Varchar,
+        holidays_ical_url -> Varchar,
+        pickup_window_start -> Varchar,
+        pickup_window_end -> Varchar,
+        carrier_cutoffs -> Array<Text>,
         geocode_cache -> Varchar,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
@@ -328,6 +333,8 @@ table! {
     certificates (id) {
         id -> Int4,
         domain -> Varchar,
+        alt_names -> Array<Text>,
+        key_type -> Varchar,
         certificate -> Text,
         private_key -> Text,
         valid_days_left -> Int4,
@@ -357,6 +364,10 @@ table! {
         mailchimp_list_id -> Varchar,
         github_app_installation_id -> Int4,
         cloudflare_api_key -> Varchar,
+        dns_provider -> Varchar,
+        dns_server -> Varchar,
+        dns_tsig_key -> Varchar,
+        acme_directory_url -> Varchar,
         checkr_api_key -> Varchar,
         printer_url -> Varchar,
         tailscale_api_key -> Varchar,
@@ -461,6 +472,8 @@ table! {
         completed_at -> Nullable<Timestamptz>,
         logs -> Text,
         saga_id -> Varchar,
+        owner -> Varchar,
+        heartbeat_at -> Nullable<Timestamptz>,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -559,6 +572,18 @@ table! {
         who_can_view_group -> Varchar,
         who_can_view_membership -> Varchar,
         enable_collaborative_inbox -> Bool,
+        slack_channels -> Array<Text>,
+        cio_company_id -> Int4,
+        airtable_record_id -> Varchar,
+    }
+}
+
+table! {
+    holidays (id) {
+        id -> Int4,
+        name -> Varchar,
+        date -> Date,
+        building -> Varchar,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -658,6 +683,35 @@ table! {
     }
 }
 
+table! {
+    managed_dns_records (id) {
+        id -> Int4,
+        name -> Varchar,
+        content -> Varchar,
+        source -> Varchar,
+        last_ensured_at -> Timestamptz,
+        cio_company_id -> Int4,
+        airtable_record_id -> Varchar,
+    }
+}
+
+table! {
+    offboarding_events (id) {
+        id -> Int4,
+        offboarding_id -> Varchar,
+        username -> Varchar,
+        email -> Varchar,
+        reason -> Varchar,
+        step -> Varchar,
+        status -> Varchar,
+        message -> Text,
+        created_at -> Timestamptz,
+        start_date -> Date,
+        cio_company_id -> Int4,
+        airtable_record_id -> Varchar,
+    }
+}
+
 table! {
     outbound_shipments (id) {
         id -> Int4,
@@ -694,6 +748,10 @@ table! {
         geocode_cache -> Varchar,
         local_pickup -> Bool,
         link_to_package_pickup -> Array<Text>,
+        is_return -> Bool,
+        parcels -> Array<Text>,
+        commercial_invoice_link -> Varchar,
+        address_validation -> Varchar,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -775,6 +833,64 @@ table! {
     }
 }
 
+table! {
+    rfd_changelog_subscriptions (id) {
+        id -> Int4,
+        email -> Varchar,
+        cadence -> Varchar,
+        channel -> Varchar,
+        rfd_numbers -> Array<Int4>,
+        states -> Array<Text>,
+        relevant_components -> Array<Text>,
+        last_sent_at -> Nullable<Timestamptz>,
+        token -> Varchar,
+        confirmed -> Bool,
+        cio_company_id -> Int4,
+        airtable_record_id -> Varchar,
+    }
+}
+
+table! {
+    rfd_references (id) {
+        id -> Int4,
+        rfd_number -> Int4,
+        referenced_rfd_number -> Int4,
+        cio_company_id -> Int4,
+        airtable_record_id -> Varchar,
+    }
+}
+
+table! {
+    rfd_reservations (id) {
+        id -> Int4,
+        number -> Int4,
+        title -> Varchar,
+        reserved_by -> Varchar,
+        branch -> Varchar,
+        link -> Varchar,
+        pull_request_number -> Int4,
+        pull_request_url -> Varchar,
+        reserved_at -> Timestamptz,
+        cio_company_id -> Int4,
+        airtable_record_id -> Varchar,
+    }
+}
+
+table! {
+    rfd_state_changes (id) {
+        id -> Int4,
+        rfd_number -> Int4,
+        from_state -> Varchar,
+        to_state -> Varchar,
+        actor -> Varchar,
+        commit_sha -> Varchar,
+        pull_request_number -> Int4,
+        changed_at -> Timestamptz,
+        cio_company_id -> Int4,
+        airtable_record_id -> Varchar,
+    }
+}
+
 table! {
     rfds (id) {
         id -> Int4,
@@ -796,6 +912,23 @@ table! {
         relevant_components -> Array<Text>,
         pdf_link_github -> Varchar,
         pdf_link_google_drive -> Varchar,
+        cites -> Array<Text>,
+        cited_by -> Array<Text>,
+        cio_company_id -> Int4,
+        airtable_record_id -> Varchar,
+    }
+}
+
+table! {
+    shipment_tracking_events (id) {
+        id -> Int4,
+        carrier -> Varchar,
+        tracking_number -> Varchar,
+        provider -> Varchar,
+        status -> Varchar,
+        status_details -> Varchar,
+        location -> Varchar,
+        status_date -> Timestamptz,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -839,12 +972,29 @@ table! {
         barcode_svg -> Varchar,
         barcode_pdf_label -> Varchar,
         print_barcode_label_quantity -> Int4,
+        reorder_threshold -> Int4,
         link_to_item -> Array<Text>,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
 }
 
+table! {
+    swag_inventory_reservations (id) {
+        id -> Int4,
+        swag_inventory_item_id -> Int4,
+        outbound_shipment_id -> Int4,
+        item -> Varchar,
+        size -> Varchar,
+        quantity -> Int4,
+        status -> Varchar,
+        reserved_time -> Timestamptz,
+        released_time -> Nullable<Timestamptz>,
+        cio_company_id -> Int4,
+        airtable_record_id -> Varchar,
+    }
+}
+
 table! {
     swag_items (id) {
         id -> Int4,
@@ -852,6 +1002,10 @@ table! {
         description -> Varchar,
         image -> Varchar,
         internal_only -> Bool,
+        hs_code -> Varchar,
+        customs_value -> Float4,
+        weight -> Float4,
+        origin_country -> Varchar,
         link_to_inventory -> Array<Text>,
         link_to_barcode_scans -> Array<Text>,
         link_to_order_january_2020 -> Array<Text>,
@@ -872,10 +1026,12 @@ table! {
         recovery_email -> Varchar,
         recovery_phone -> Varchar,
         gender -> Varchar,
+        pronouns -> Varchar,
         chat -> Varchar,
         github -> Varchar,
         twitter -> Varchar,
         department -> Varchar,
+        title -> Varchar,
         manager -> Varchar,
         link_to_manager -> Array<Text>,
         groups -> Array<Text>,
@@ -902,6 +1058,7 @@ table! {
         work_address_country_code -> Varchar,
         work_address_formatted -> Varchar,
         start_date -> Date,
+        end_date -> Date,
         birthday -> Date,
         public_ssh_keys -> Array<Text>,
         typev -> Varchar,
@@ -913,8 +1070,10 @@ table! {
         airtable_id -> Varchar,
         ramp_id -> Varchar,
         zoom_id -> Varchar,
+        slack_id -> Varchar,
         geocode_cache -> Varchar,
         working_on -> Array<Text>,
+        address_validation -> Varchar,
         cio_company_id -> Int4,
         airtable_record_id -> Varchar,
     }
@@ -939,19 +1098,29 @@ joinable!(expensed_items -> companys (cio_company_id));
 joinable!(functions -> companys (cio_company_id));
 joinable!(github_repos -> companys (cio_company_id));
 joinable!(groups -> companys (cio_company_id));
+joinable!(holidays -> companys (cio_company_id));
 joinable!(inbound_shipments -> companys (cio_company_id));
 joinable!(journal_club_meetings -> companys (cio_company_id));
 joinable!(journal_club_papers -> companys (cio_company_id));
 joinable!(links -> companys (cio_company_id));
 joinable!(mailing_list_subscribers -> companys (cio_company_id));
+joinable!(managed_dns_records -> companys (cio_company_id));
+joinable!(offboarding_events -> companys (cio_company_id));
 joinable!(outbound_shipments -> companys (cio_company_id));
 joinable!(package_pickups -> companys (cio_company_id));
 joinable!(page_views -> companys (cio_company_id));
 joinable!(rack_line_subscribers -> companys (cio_company_id));
 joinable!(recorded_meetings -> companys (cio_company_id));
+joinable!(rfd_changelog_subscriptions -> companys (cio_company_id));
+joinable!(rfd_references -> companys (cio_company_id));
+joinable!(rfd_reservations -> companys (cio_company_id));
+joinable!(rfd_state_changes -> companys (cio_company_id));
 joinable!(rfds -> companys (cio_company_id));
+joinable!(shipment_tracking_events -> companys (cio_company_id));
 joinable!(software_vendors -> companys (cio_company_id));
 joinable!(swag_inventory_items -> companys (cio_company_id));
+joinable!(swag_inventory_reservations -> companys (cio_company_id));
+joinable!(swag_inventory_reservations -> swag_inventory_items (swag_inventory_item_id));
 joinable!(swag_items -> companys (cio_company_id));
 joinable!(users -> companys (cio_company_id));
 
@@ -976,19 +1145,28 @@ allow_tables_to_appear_in_same_query!(
     functions,
     github_repos,
     groups,
+    holidays,
     inbound_shipments,
     journal_club_meetings,
     journal_club_papers,
     links,
     mailing_list_subscribers,
+    managed_dns_records,
+    offboarding_events,
     outbound_shipments,
     package_pickups,
     page_views,
     rack_line_subscribers,
     recorded_meetings,
+    rfd_changelog_subscriptions,
+    rfd_references,
+    rfd_reservations,
+    rfd_state_changes,
     rfds,
+    shipment_tracking_events,
     software_vendors,
     swag_inventory_items,
+    swag_inventory_reservations,
     swag_items,
     users,
 );
@@ -0,0 +1,257 @@
+use anyhow::Result;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    airtable::AIRTABLE_SHIPMENT_TRACKING_EVENTS_TABLE, asciidoc::escape, core::UpdateAirtableRecord, db::Database,
+    schema::shipment_tracking_events, shipments::OutboundShipment, shipping::TrackingInfo,
+};
+
+/// An event in the tracking history of a shipment, from the webhooks of our shipping
+/// providers.
+#[db {
+    new_struct_name = "ShipmentTrackingEvent",
+    airtable_base = "shipments",
+    airtable_table = "AIRTABLE_SHIPMENT_TRACKING_EVENTS_TABLE",
+    match_on = {
+        "carrier" = "String",
+        "tracking_number" = "String",
+        "status" = "String",
+        "status_date" = "DateTime<Utc>",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[diesel(table_name = shipment_tracking_events)]
+pub struct NewShipmentTrackingEvent {
+    pub carrier: String,
+    pub tracking_number: String,
+    /// The provider that sent us the event.
+    pub provider: String,
+    /// One of `PRE_TRANSIT`, `TRANSIT`, `DELIVERED`, `RETURNED`, `FAILURE` or
+    /// `UNKNOWN`.
+    pub status: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status_details: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub location: String,
+    pub status_date: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// Implement updating the Airtable record for a ShipmentTrackingEvent.
+#[async_trait]
+impl UpdateAirtableRecord<ShipmentTrackingEvent> for ShipmentTrackingEvent {
+    async fn update_airtable_record(&mut self, _record: ShipmentTrackingEvent) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl OutboundShipment {
+    /// Save the tracking history from a provider's webhook, for the shipment's
+    /// tracking page.
+    pub async fn record_tracking_events(&self, db: &Database, provider: &str, tracking: &TrackingInfo) -> Result<()> {
+        for h in &tracking.history {
+            // Events without a date cannot be placed on the timeline.
+            let status_date = match h.status_date {
+                Some(d) => d,
+                None => continue,
+            };
+
+            let event = NewShipmentTrackingEvent {
+                carrier: self.carrier.to_string(),
+                tracking_number: self.tracking_number.to_string(),
+                provider: provider.to_string(),
+                status: h.status.to_string(),
+                status_details: h.status_details.to_string(),
+                location: h.location.to_string(),
+                status_date,
+                cio_company_id: self.cio_company_id,
+            };
+
+            // Webhooks send the whole history every time, only add the new events.
+            if ShipmentTrackingEvent::get_from_db(
+                db,
+                event.carrier.to_string(),
+                event.tracking_number.to_string(),
+                event.status.to_string(),
+                event.status_date,
+            )
+            .await
+            .is_none()
+            {
+                event.upsert_in_db(db).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the tracking history of the shipment, oldest first.
+    pub async fn tracking_events(&self, db: &Database) -> Result<Vec<ShipmentTrackingEvent>> {
+        let events = shipment_tracking_events::dsl::shipment_tracking_events
+            .filter(
+                shipment_tracking_events::dsl::carrier
+                    .eq(self.carrier.to_string())
+                    .and(shipment_tracking_events::dsl::tracking_number.eq(self.tracking_number.to_string())),
+            )
+            .order_by(shipment_tracking_events::dsl::status_date.asc())
+            .load_async::<ShipmentTrackingEvent>(db.pool())
+            .await?;
+
+        Ok(events)
+    }
+}
+
+fn escape_attr(s: &str) -> String {
+    escape(s).replace('"', "&quot;")
+}
+
+fn format_date(d: &DateTime<Utc>) -> String {
+    d.format("%a, %b %-d, %Y %-I:%M %p UTC").to_string()
+}
+
+fn status_label(status: &str) -> &'static str {
+    match status {
+        "PRE_TRANSIT" => "Label created",
+        "TRANSIT" => "In transit",
+        "DELIVERED" => "Delivered",
+        "RETURNED" => "Returned to sender",
+        "FAILURE" => "Delivery problem",
+        _ => "Update",
+    }
+}
+
+/// Render the public tracking page of a shipment. This is shown to anyone with the
+/// link, so it only has the tracking details and where the package is going, never
+/// who it is for, what is in it, or our internal fields like the cost or notes.
+pub fn render_tracking_page(shipment: &OutboundShipment, events: &[ShipmentTrackingEvent]) -> String {
+    let destination = [
+        shipment.city.as_str(),
+        shipment.state.as_str(),
+        shipment.country.as_str(),
+    ]
+    .iter()
+    .filter(|s| !s.is_empty())
+    .cloned()
+    .collect::<Vec<&str>>()
+    .join(", ");
+
+    let mut html = String::new();
+    html += "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n";
+    html += "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n";
+    html += "<meta name=\"robots\" content=\"noindex\">\n";
+    html += &format!("<title>Tracking {}</title>\n", escape(&shipment.tracking_number));
+    html += "<style>body{font-family:sans-serif;max-width:40em;margin:2em auto;padding:0 1em;color:#1c1c1c}\
+             .status{font-size:1.5em;font-weight:bold}.timeline{list-style:none;padding:0}\
+             .timeline li{border-left:2px solid #48d597;padding:0 0 1em 1em}\
+             .date,.location{color:#666;font-size:0.9em}</style>\n";
+    html += "</head>\n<body>\n";
+
+    html += &format!("<p class=\"status\">{}</p>\n", escape(&shipment.status));
+    html += "<dl>\n";
+    html += &format!(
+        "<dt>Tracking number</dt><dd>{} {}</dd>\n",
+        escape(&shipment.carrier),
+        escape(&shipment.tracking_number)
+    );
+    if !destination.is_empty() {
+        html += &format!("<dt>Shipping to</dt><dd>{}</dd>\n", escape(&destination));
+    }
+    if let Some(delivered) = shipment.delivered_time {
+        html += &format!("<dt>Delivered</dt><dd>{}</dd>\n", format_date(&delivered));
+    } else if let Some(eta) = shipment.eta {
+        html += &format!("<dt>Estimated delivery</dt><dd>{}</dd>\n", format_date(&eta));
+    }
+    html += "</dl>\n";
+    if !shipment.tracking_link.is_empty() {
+        html += &format!(
+            "<p><a href=\"{}\">Track on the {} website</a></p>\n",
+            escape_attr(&shipment.tracking_link),
+            escape(&shipment.carrier)
+        );
+    }
+
+    html += "<ol class=\"timeline\">\n";
+    if events.is_empty() {
+        html += "<li>There are no updates from the carrier yet.</li>\n";
+    }
+    // Show the latest update first.
+    for e in events.iter().rev() {
+        html += &format!("<li><strong>{}</strong>", status_label(&e.status));
+        if !e.status_details.is_empty() {
+            html += &format!(": {}", escape(&e.status_details));
+        }
+        html += &format!("<div class=\"date\">{}</div>", format_date(&e.status_date));
+        if !e.location.is_empty() {
+            html += &format!("<div class=\"location\">{}</div>", escape(&e.location));
+        }
+        html += "</li>\n";
+    }
+    html += "</ol>\n</body>\n</html>\n";
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::render_tracking_page;
+
+    #[test]
+    fn test_render_tracking_page_is_public() {
+        let shipment = serde_json::from_value(json!({
+            "name": "Jane Doe",
+            "email": "jane@example.com",
+            "phone": "+15105551234",
+            "contents": "1 x Prototype board",
+            "street_1": "1251 Park Ave",
+            "city": "Emeryville",
+            "state": "CA",
+            "country": "US",
+            "status": "Shipped",
+            "carrier": "USPS",
+            "tracking_number": "9400111899223197428490",
+            "cost": 42.17,
+            "notes": "Do not tell the recipient",
+            "messages": "Internal message",
+            "created_time": "2022-04-01T17:00:00Z",
+        }))
+        .unwrap();
+        let events = vec![serde_json::from_value(json!({
+            "carrier": "USPS",
+            "tracking_number": "9400111899223197428490",
+            "provider": "Shippo",
+            "status": "TRANSIT",
+            "status_details": "Departed USPS facility",
+            "location": "Oakland, CA",
+            "status_date": "2022-04-02T17:00:00Z",
+        }))
+        .unwrap()];
+
+        let html = render_tracking_page(&shipment, &events);
+
+        assert!(html.contains("9400111899223197428490"));
+        assert!(html.contains("Emeryville, CA, US"));
+        assert!(html.contains("Departed USPS facility"));
+        for private in &[
+            "Jane Doe",
+            "jane@example.com",
+            "5105551234",
+            "Prototype board",
+            "1251 Park Ave",
+            "42.17",
+            "Do not tell the recipient",
+            "Internal message",
+        ] {
+            assert!(!html.contains(private), "tracking page shows `{}`", private);
+        }
+    }
+}
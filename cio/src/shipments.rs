@@ -4,8 +4,9 @@ use std::convert::From;
 use anyhow::{bail, Result};
 use async_bb8_diesel::AsyncRunQueryDsl;
 use async_trait::async_trait;
-use chrono::{naive::NaiveDate, offset::Utc, DateTime, Duration, NaiveTime};
+use chrono::{naive::NaiveDate, offset::Utc, DateTime};
 use chrono_humanize::HumanTime;
+use google_drive::traits::{DriveOps, FileOps};
 use google_geocode::Geocode;
 use log::{info, warn};
 use macros::db;
@@ -13,20 +14,36 @@ use reqwest::StatusCode;
 use schemars::JsonSchema;
 use sendgrid_api::{traits::MailOps, Client as SendGrid};
 use serde::{Deserialize, Serialize};
-use shippo::{Address, CustomsDeclaration, CustomsItem, NewShipment, NewTransaction, Parcel, Shippo};
+use shippo::Shippo;
 use slack_chat_api::{
-    FormattedMessage, MessageAttachment, MessageBlock, MessageBlockText, MessageBlockType, MessageType,
+    FormattedMessage, MessageAttachment, MessageBlock, MessageBlockAccessory, MessageBlockText, MessageBlockType,
+    MessageType,
 };
 
 use crate::{
+    address_validation::{validate_address, AddressValidation, AddressVerdict, PostalAddress},
     airtable::{AIRTABLE_INBOUND_TABLE, AIRTABLE_OUTBOUND_TABLE, AIRTABLE_PACKAGE_PICKUPS_TABLE},
+    business_days::BusinessCalendar,
     companies::Company,
     configs::User,
     core::UpdateAirtableRecord,
     db::Database,
     schema::{inbound_shipments, outbound_shipments, package_pickups},
+    shipping::{
+        get_label_providers, get_shipping_provider, shop_rates, CommercialInvoice, PickupRequest, RatePolicy,
+        RefundStatus, ShipmentRequest, ShippingAddress, ShippingCustoms, ShippingCustomsItem, ShippingParcel,
+        ShippingProvider, EASYPOST, SHIPPO,
+    },
+    swag_inventory::SwagItem,
 };
 
+/// The Slack action to ship to the suggested address of a shipment.
+pub const SLACK_ACTION_USE_SUGGESTED_ADDRESS: &str = "shipment_use_suggested_address";
+/// The Slack action to ship to the address of a shipment as it was entered.
+pub const SLACK_ACTION_USE_ADDRESS_AS_ENTERED: &str = "shipment_use_address_as_entered";
+/// The Slack action to cancel a shipment we have not bought the label for.
+pub const SLACK_ACTION_CANCEL_SHIPMENT: &str = "shipment_cancel";
+
 /// The data type for an inbound shipment.
 #[db {
     new_struct_name = "InboundShipment",
@@ -463,6 +480,21 @@ pub struct NewOutboundShipment {
     /// This is automatically filled in by Airtbale.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub link_to_package_pickup: Vec<String>,
+    /// Denotes this is a prepaid return label, shipping from the address on the
+    /// shipment back to us.
+    #[serde(default)]
+    pub is_return: bool,
+    /// The boxes in the shipment, like `12x12x6 in, 2 lb`. If this is empty, the
+    /// shipment is a single default box for swag.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parcels: Vec<String>,
+    /// A link to the commercial invoice for an international shipment.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub commercial_invoice_link: String,
+    /// The validation of the address, as JSON, so we only validate it again when
+    /// it changes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub address_validation: String,
     /// The CIO company ID.
     #[serde(default)]
     pub cio_company_id: i32,
@@ -504,6 +536,10 @@ impl From<User> for NewOutboundShipment {
             geocode_cache: Default::default(),
             local_pickup: Default::default(),
             link_to_package_pickup: Default::default(),
+            is_return: false,
+            parcels: Default::default(),
+            commercial_invoice_link: Default::default(),
+            address_validation: Default::default(),
             cio_company_id: user.cio_company_id,
         }
     }
@@ -590,6 +626,10 @@ impl From<shipbob::types::Order> for NewOutboundShipment {
             geocode_cache: Default::default(),
             local_pickup: Default::default(),
             link_to_package_pickup: Default::default(),
+            is_return: false,
+            parcels: Default::default(),
+            commercial_invoice_link: Default::default(),
+            address_validation: Default::default(),
             cio_company_id: Default::default(),
         }
     }
@@ -645,7 +685,12 @@ impl NewOutboundShipment {
 impl From<NewOutboundShipment> for FormattedMessage {
     fn from(item: NewOutboundShipment) -> Self {
         let mut status_msg = format!(
-            "Outbound shipment | *{}* | _{}_ | <{}|{}>",
+            "{} | *{}* | _{}_ | <{}|{}>",
+            if item.is_return {
+                "Return shipment"
+            } else {
+                "Outbound shipment"
+            },
             item.tracking_status,
             item.status,
             item.oxide_tracking_link,
@@ -786,16 +831,28 @@ impl UpdateAirtableRecord<PackagePickup> for PackagePickup {
 
 impl OutboundShipments {
     // Always schedule the pickup for the next business day.
-    // It will create a pickup for all the shipments that have "Label printed"
-    // status and no pickup date currently.
+    // It will create a pickup, with each provider we bought labels from, for all the
+    // shipments that have "Label printed" status and no pickup date currently.
     pub async fn create_pickup(db: &Database, company: &Company) -> Result<()> {
+        for provider in get_label_providers() {
+            OutboundShipments::create_pickup_with_provider(db, company, provider.as_ref()).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn create_pickup_with_provider(
+        db: &Database,
+        company: &Company,
+        provider: &dyn ShippingProvider,
+    ) -> Result<()> {
         // We should only do this for USPS, OR if we use DHL in the future.
         let shipments = outbound_shipments::dsl::outbound_shipments
             .filter(
                 outbound_shipments::dsl::status
                     .eq(crate::shipment_status::Status::LabelPrinted.to_string())
                     .and(outbound_shipments::dsl::carrier.eq("USPS".to_string()))
-                    .and(outbound_shipments::dsl::provider.eq("Shippo".to_string()))
+                    .and(outbound_shipments::dsl::provider.eq(provider.name().to_string()))
                     .and(outbound_shipments::dsl::pickup_date.is_null()),
             )
             .load_async::<OutboundShipment>(db.pool())
@@ -806,11 +863,11 @@ impl OutboundShipments {
             return Ok(());
         }
 
-        // Get the transaction ids, these should be the same as the provider_id.
+        // Get the label ids, these should be the same as the provider_id.
         let mut transaction_ids: Vec<String> = Default::default();
         let mut link_to_outbound_shipments: Vec<String> = Default::default();
         for shipment in shipments.clone() {
-            info!("adding {} shipment to our pickup", shipment.name);
+            info!("adding {} shipment to our {} pickup", shipment.name, provider.name());
             transaction_ids.push(shipment.provider_id.to_string());
             link_to_outbound_shipments.push(shipment.airtable_record_id.to_string());
         }
@@ -820,59 +877,35 @@ impl OutboundShipments {
             return Ok(());
         }
 
-        // Get the carrier ID for USPS.
-        // Create the shippo client.
-        let shippo_client = Shippo::new_from_env();
-        let carrier_accounts = shippo_client.list_carrier_accounts().await?;
-        let mut carrier_account_id = "".to_string();
-        for ca in carrier_accounts {
-            if ca.carrier.to_lowercase() == "usps" {
-                // Shippo docs say this is the object ID.
-                carrier_account_id = ca.object_id;
-                break;
-            }
-        }
-
-        if carrier_account_id.is_empty() {
-            // We can return early.
-            // This should not happen.
-            warn!("create pickup carrier account id for usps cannot be empty.");
-            return Ok(());
-        }
-
-        // Get the next buisness day for pickup.
-        let (start_time, end_time) = get_next_business_day();
+        // Get the next business day for pickup, skipping weekends and holidays.
+        let calendar = BusinessCalendar::for_hq(db, company).await?;
+        let (start_time, end_time) = calendar.next_pickup_window(Utc::now());
 
         let pickup_date = start_time.date().naive_utc();
 
-        let new_pickup = shippo::NewPickup {
-            carrier_account: carrier_account_id.to_string(),
-            location: shippo::Location {
-                building_location_type: "Office".to_string(),
-                building_type: "building".to_string(),
+        let pickup = match provider
+            .schedule_pickup(&PickupRequest {
+                carrier: "USPS".to_string(),
+                address: company.hq_shipping_address(db).await?.into(),
+                label_ids: transaction_ids.clone(),
+                start_time,
+                end_time,
                 instructions: "Knock on the glass door and someone will come open it.".to_string(),
-                address: company.hq_shipping_address(db).await?,
-            },
-            transactions: transaction_ids.clone(),
-            requested_start_time: start_time,
-            requested_end_time: end_time,
-            metadata: "".to_string(),
-            is_test: false,
-        };
-
-        let pickup = shippo_client.create_pickup(&new_pickup).await?;
-
-        let mut messages = "".to_string();
-        if let Some(msg) = pickup.messages {
-            for m in msg {
-                messages = format!("{}\n{} {} {}", messages, m.code, m.source, m.text);
+            })
+            .await
+        {
+            Ok(pickup) => pickup,
+            Err(e) => {
+                // We can return early.
+                // This should not happen.
+                warn!("creating {} pickup for usps failed: {}", provider.name(), e);
+                return Ok(());
             }
-        }
-        messages = messages.trim().to_string();
+        };
 
         // Let's create the new pickup in the database.
         let np = NewPackagePickup {
-            shippo_id: pickup.object_id.to_string(),
+            shippo_id: pickup.id.to_string(),
             confirmation_code: pickup.confirmation_code.to_string(),
             carrier: "USPS".to_string(),
             status: pickup.status.to_string(),
@@ -884,7 +917,7 @@ impl OutboundShipments {
             confirmed_start_time: pickup.confirmed_start_time,
             confirmed_end_time: pickup.confirmed_end_time,
             cancel_by_time: pickup.cancel_by_time,
-            messages,
+            messages: pickup.messages,
             cio_company_id: company.id,
         };
 
@@ -904,28 +937,6 @@ impl OutboundShipments {
     }
 }
 
-/// Returns the next buisness day in terms of start and end.
-pub fn get_next_business_day() -> (DateTime<Utc>, DateTime<Utc>) {
-    let now = Utc::now();
-    let pacific_time = now.with_timezone(&chrono_tz::US::Pacific);
-
-    let mut next_day = pacific_time.checked_add_signed(Duration::days(1)).unwrap();
-    let day_of_week_string = next_day.format("%A").to_string();
-    if day_of_week_string == "Saturday" {
-        next_day = pacific_time.checked_add_signed(Duration::days(3)).unwrap();
-    } else if day_of_week_string == "Sunday" {
-        next_day = pacific_time.checked_add_signed(Duration::days(2)).unwrap();
-    }
-
-    // Let's create the start time, which should be around 9am.
-    let start_time = next_day.date().and_time(NaiveTime::from_hms(8, 59, 59)).unwrap();
-
-    // Let's create the end time, which should be around 5pm.
-    let end_time = next_day.date().and_time(NaiveTime::from_hms(16, 59, 59)).unwrap();
-
-    (start_time.with_timezone(&Utc), end_time.with_timezone(&Utc))
-}
-
 /// Implement updating the Airtable record for an OutboundShipment.
 #[async_trait]
 impl UpdateAirtableRecord<OutboundShipment> for OutboundShipment {
@@ -967,12 +978,19 @@ impl UpdateAirtableRecord<OutboundShipment> for OutboundShipment {
         if self.eta.is_none() {
             self.eta = record.eta;
         }
-        if self.cost == 0.0 {
+        // A voided label has no cost, since it was refunded.
+        if self.cost == 0.0 && self.status != crate::shipment_status::Status::Cancelled.to_string() {
             self.cost = record.cost;
         }
         if self.notes.is_empty() {
             self.notes = record.notes;
         }
+        if self.parcels.is_empty() {
+            self.parcels = record.parcels;
+        }
+        if self.commercial_invoice_link.is_empty() {
+            self.commercial_invoice_link = record.commercial_invoice_link;
+        }
 
         Ok(())
     }
@@ -984,6 +1002,173 @@ impl OutboundShipment {
         n.send_slack_notification(db, company).await
     }
 
+    /// The address we are shipping to.
+    pub fn postal_address(&self) -> PostalAddress {
+        PostalAddress {
+            street_1: self.street_1.to_string(),
+            street_2: self.street_2.to_string(),
+            city: self.city.to_string(),
+            state: self.state.to_string(),
+            zipcode: self.zipcode.to_string(),
+            country: self.country.to_string(),
+        }
+    }
+
+    fn set_postal_address(&mut self, address: &PostalAddress) {
+        self.street_1 = address.street_1.to_string();
+        self.street_2 = address.street_2.to_string();
+        self.city = address.city.to_string();
+        self.state = address.state.to_string();
+        self.zipcode = address.zipcode.to_string();
+        self.country = address.country.to_string();
+
+        self.populate_formatted_address();
+    }
+
+    /// Validate the address of the shipment, normalizing it if it is valid. If it is
+    /// not, we post what is wrong with it and the suggested address to Slack, so
+    /// someone can decide what to do before we buy a label.
+    pub async fn validate_address(&mut self, db: &Database, company: &Company) -> Result<AddressValidation> {
+        let validation = validate_address(&self.postal_address(), &self.address_validation).await?;
+        if validation.to_cache() == self.address_validation {
+            // Nothing changed since we last checked.
+            return Ok(validation);
+        }
+
+        if validation.verdict == AddressVerdict::Valid {
+            // The validation stays keyed on the address as it was entered.
+            self.set_postal_address(&validation.suggestion);
+        } else {
+            self.messages = format!(
+                "The address is {}: {}.",
+                validation.verdict.to_string(),
+                validation.messages.join(", ")
+            );
+            self.send_address_validation_slack_notification(db, company, &validation)
+                .await?;
+        }
+
+        if self.latitude == 0.0 || self.longitude == 0.0 {
+            self.latitude = validation.latitude;
+            self.longitude = validation.longitude;
+        }
+        self.address_validation = validation.to_cache();
+        self.update(db).await?;
+
+        Ok(validation)
+    }
+
+    /// Ship to the address the geocoder suggested, from the button in Slack.
+    pub async fn use_suggested_address(&mut self, db: &Database) -> Result<()> {
+        if !self.provider_id.is_empty() {
+            bail!(
+                "we already bought the label for shipment {}, the address can't change",
+                self.id
+            );
+        }
+
+        let validation = match AddressValidation::from_cache(&self.address_validation, &self.postal_address()) {
+            Some(v) => v,
+            None => bail!("shipment {} has no suggested address", self.id),
+        };
+
+        self.set_postal_address(&validation.suggestion);
+        self.address_validation = validation.accept_suggestion().to_cache();
+        self.messages = Default::default();
+        self.update(db).await?;
+
+        self.create_or_get_shipment(db).await
+    }
+
+    /// Ship to the address as it was entered, from the button in Slack.
+    pub async fn use_address_as_entered(&mut self, db: &Database) -> Result<()> {
+        if !self.provider_id.is_empty() {
+            bail!(
+                "we already bought the label for shipment {}, the address can't change",
+                self.id
+            );
+        }
+
+        let mut validation = match AddressValidation::from_cache(&self.address_validation, &self.postal_address()) {
+            Some(v) => v,
+            None => bail!("shipment {} has no address validation", self.id),
+        };
+
+        validation.confirmed = true;
+        self.address_validation = validation.to_cache();
+        self.messages = Default::default();
+        self.update(db).await?;
+
+        self.create_or_get_shipment(db).await
+    }
+
+    async fn send_address_validation_slack_notification(
+        &self,
+        db: &Database,
+        company: &Company,
+        validation: &AddressValidation,
+    ) -> Result<()> {
+        let button = |text: &str, action_id: &str| MessageBlockAccessory {
+            accessory_type: MessageType::Button,
+            text: Some(MessageBlockText {
+                text_type: MessageType::PlainText,
+                text: text.to_string(),
+            }),
+            action_id: action_id.to_string(),
+            value: self.id.to_string(),
+            image_url: Default::default(),
+            alt_text: Default::default(),
+        };
+        let section = |text: String, accessory: Option<MessageBlockAccessory>| MessageBlock {
+            block_type: MessageBlockType::Section,
+            text: Some(MessageBlockText {
+                text_type: MessageType::Markdown,
+                text,
+            }),
+            elements: Default::default(),
+            accessory,
+            block_id: Default::default(),
+            fields: Default::default(),
+        };
+
+        let mut blocks = vec![section(
+            format!(
+                "We have not bought the label for *{}* yet, the address is {}: {}.\n>{}",
+                self.name,
+                validation.verdict.to_string(),
+                validation.messages.join(", "),
+                self.postal_address().to_string()
+            ),
+            None,
+        )];
+        if validation.verdict != AddressVerdict::Undeliverable {
+            blocks.push(section(
+                format!("Suggested address:\n>{}", validation.suggestion.to_string()),
+                Some(button("Use suggested address", SLACK_ACTION_USE_SUGGESTED_ADDRESS)),
+            ));
+        }
+        blocks.push(section(
+            "Or fix the address in Airtable.".to_string(),
+            Some(button(
+                "Ship to address as entered",
+                SLACK_ACTION_USE_ADDRESS_AS_ENTERED,
+            )),
+        ));
+        blocks.push(section(
+            "If the order should not ship at all, cancel it and put the swag back in stock.".to_string(),
+            Some(button("Cancel order", SLACK_ACTION_CANCEL_SHIPMENT)),
+        ));
+
+        let msg = FormattedMessage {
+            channel: company.slack_channel_shipments.to_string(),
+            blocks,
+            attachments: Default::default(),
+        };
+        company.post_to_slack_channel(db, &msg).await?;
+
+        Ok(())
+    }
+
     fn populate_formatted_address(&mut self) {
         let mut street_address = self.street_1.to_string();
         if !self.street_2.is_empty() {
@@ -999,6 +1184,9 @@ impl OutboundShipment {
         .to_string();
     }
 
+    /// The link to the shipment's tracking page. This is the same link we have
+    /// always emailed out, track.oxide.computer forwards it to webhooky at
+    /// `/track/{carrier}/{id}`.
     pub fn oxide_tracking_link(&self) -> String {
         format!("https://track.oxide.computer/{}/{}", self.carrier, self.tracking_number)
     }
@@ -1204,7 +1392,7 @@ xoxo,
 
 **Tracking link:**
 {}
-
+{}
 The label should already be printed on the cart with the label printers. Please
 take the label and affix it to the package with the specified contents. It can
 then be dropped off for {}.
@@ -1223,6 +1411,15 @@ The Shipping Bot",
                     self.name,
                     self.format_address(),
                     self.oxide_tracking_link,
+                    if self.commercial_invoice_link.is_empty() {
+                        "".to_string()
+                    } else {
+                        format!(
+                            "\n**Commercial invoice:**\n{}\n\nPrint three copies of the commercial invoice and \
+                             put them in a pouch on the package.\n",
+                            self.commercial_invoice_link
+                        )
+                    },
                     self.carrier,
                 ),
                 &[format!("packages@{}", &company.gsuite_domain)],
@@ -1237,6 +1434,7 @@ The Shipping Bot",
 
     /// Sends a Slack notification if the status of the shipment changed.
     /// And changes the status of the shipment.
+    /// If the shipment was cancelled, the swag reserved for it goes back in stock.
     pub async fn set_status(
         &mut self,
         db: &Database,
@@ -1250,6 +1448,11 @@ The Shipping Bot",
 
         if send_notification {
             self.send_slack_notification(db, company).await?;
+
+            if status == crate::shipment_status::Status::Cancelled {
+                // Put any swag we took out of stock for the order back.
+                crate::swag_inventory::SwagInventoryReservation::release(db, self).await?;
+            }
         }
 
         Ok(())
@@ -1351,10 +1554,49 @@ The Shipping Bot",
         Ok(())
     }
 
-    /// Create or get a shipment in shippo that matches this shipment.
-    pub async fn create_or_get_shippo_shipment(&mut self, db: &Database) -> Result<()> {
-        if self.provider != "Shippo" {
-            // Return early it's not a shippo shipment.
+    /// The shipment to get rates for, between our HQ and the recipient.
+    fn label_request(
+        &self,
+        hq: ShippingAddress,
+        parcels: Vec<ShippingParcel>,
+        customs: Option<ShippingCustoms>,
+    ) -> ShipmentRequest {
+        let mut request = ShipmentRequest {
+            from: hq,
+            to: ShippingAddress {
+                name: self.name.to_string(),
+                company: Default::default(),
+                street_1: self.street_1.to_string(),
+                street_2: self.street_2.to_string(),
+                city: self.city.to_string(),
+                state: self.state.to_string(),
+                zipcode: self.zipcode.to_string(),
+                country: self.country.to_string(),
+                phone: self.phone.to_string(),
+                email: self.email.to_string(),
+            },
+            parcels,
+            customs,
+        };
+        if self.is_return {
+            // Return labels ship from the recipient back to us.
+            std::mem::swap(&mut request.from, &mut request.to);
+        }
+
+        request
+    }
+
+    /// Create or get the label for this shipment. If we do not have a label yet, we
+    /// get rates from all the providers we buy labels from and buy the label for the
+    /// rate the `SHIPPING_RATE_POLICY` chooses.
+    pub async fn create_or_get_shipment(&mut self, db: &Database) -> Result<()> {
+        if self.provider != SHIPPO && self.provider != EASYPOST {
+            // Return early, we don't buy labels for this shipment.
+            return Ok(());
+        }
+
+        if self.status == crate::shipment_status::Status::Cancelled.to_string() {
+            // Return early, we voided the label.
             return Ok(());
         }
 
@@ -1366,9 +1608,6 @@ The Shipping Bot",
         // Update the lat and lng.
         self.set_lat_lng(db).await?;
 
-        // Create the shippo client.
-        let shippo_client = Shippo::new_from_env();
-
         // If we did local_pickup, we can return early here.
         if self.local_pickup {
             self.set_status(db, crate::shipment_status::Status::PickedUp, &company)
@@ -1378,41 +1617,34 @@ The Shipping Bot",
             return Ok(());
         }
 
-        // If we already have a shippo id, get the information for the label.
+        // If we already have a label, get the information for it.
         if !self.provider_id.is_empty() {
-            let label = shippo_client.get_shipping_label(&self.provider_id).await?;
+            let provider = get_shipping_provider(&self.provider)?;
+            let label = provider.get_label(&self.provider_id).await?;
 
             // Set the additional fields.
+            self.set_parcel_tracking_numbers(&label.parcel_tracking_numbers)?;
             self.tracking_number = label.tracking_number;
-            self.tracking_link = label.tracking_url_provider;
+            self.tracking_link = label.tracking_link;
             self.tracking_status = label.tracking_status;
-            self.label_link = label.label_url;
+            self.label_link = label.label_link;
             self.eta = label.eta;
-            self.provider_id = label.object_id;
-            if label.status != "SUCCESS" {
-                // Print the messages in the messages field.
-                let mut messages = "".to_string();
-                for m in label.messages {
-                    messages = format!("{}\n{} {} {}", messages, m.code, m.source, m.text);
-                }
-                self.messages = messages.trim().to_string();
+            self.provider_id = label.id;
+            if !label.messages.is_empty() {
+                self.messages = label.messages;
             }
             self.oxide_tracking_link = self.oxide_tracking_link();
 
-            // Register a tracking webhook for this shipment.
-            let status = shippo_client
-                .register_tracking_webhook(&self.carrier, &self.tracking_number)
-                .await?;
-
-            let tracking_status = status.tracking_status.unwrap_or_default();
+            // Track the shipment, this also registers for updates to it.
+            let tracking = provider.track(&self.carrier, &self.tracking_number).await?;
             if self.messages.is_empty() {
-                self.messages = tracking_status.status_details;
+                self.messages = tracking.status_details.to_string();
             }
 
             // Iterate over the tracking history and set the shipped_time.
             // Get the first date it was maked as in transit and use that as the shipped
             // time.
-            for h in status.tracking_history {
+            for h in &tracking.history {
                 if h.status == *"TRANSIT" {
                     if let Some(shipped_time) = h.status_date {
                         let current_shipped_time = if let Some(s) = self.shipped_time { s } else { Utc::now() };
@@ -1425,29 +1657,29 @@ The Shipping Bot",
             }
 
             // Get the status of the shipment.
-            if tracking_status.status == *"TRANSIT" || tracking_status.status == "IN_TRANSIT" {
-                if self.status != crate::shipment_status::Status::Shipped.to_string() {
+            if tracking.status == *"TRANSIT" {
+                if self.status != crate::shipment_status::Status::Shipped.to_string() && !self.is_return {
                     // Send an email to the recipient with their tracking link.
                     // Wait until it is in transit to do this.
                     self.send_email_to_recipient(db).await?;
                     // We make sure it only does this one time.
                     // Set the shipped date as this first date.
-                    self.shipped_time = tracking_status.status_date;
+                    self.shipped_time = tracking.status_date;
                 }
 
                 self.set_status(db, crate::shipment_status::Status::Shipped, &company)
                     .await?;
             }
-            if tracking_status.status == *"DELIVERED" {
-                self.delivered_time = tracking_status.status_date;
+            if tracking.status == *"DELIVERED" {
+                self.delivered_time = tracking.status_date;
                 self.set_status(db, crate::shipment_status::Status::Delivered, &company)
                     .await?;
             }
-            if tracking_status.status == *"RETURNED" {
+            if tracking.status == *"RETURNED" {
                 self.set_status(db, crate::shipment_status::Status::Returned, &company)
                     .await?;
             }
-            if tracking_status.status == *"FAILURE" {
+            if tracking.status == *"FAILURE" {
                 self.set_status(db, crate::shipment_status::Status::Failure, &company)
                     .await?;
             }
@@ -1456,48 +1688,6 @@ The Shipping Bot",
             return Ok(());
         }
 
-        // We need to create the label since we don't have one already.
-        let address_from = company.hq_shipping_address(db).await?;
-
-        // If this is an international shipment, we need to define our customs
-        // declarations.
-        let mut cd: Option<CustomsDeclaration> = None;
-        if self.country != "US" {
-            let mut cd_inner: CustomsDeclaration = Default::default();
-            // Create customs items for each item in our order.
-            for line in self.contents.lines() {
-                let mut ci: CustomsItem = Default::default();
-                ci.description = line.to_string();
-                let (prefix, _suffix) = line.split_once(" x ").unwrap_or(("1", ""));
-                // TODO: this will break if more than 9, fix for the future.
-                ci.quantity = prefix.parse()?;
-                ci.net_weight = "0.25".to_string();
-                ci.mass_unit = "lb".to_string();
-                ci.value_amount = "100.00".to_string();
-                ci.value_currency = "USD".to_string();
-                ci.origin_country = "US".to_string();
-                let c = shippo_client.create_customs_item(ci).await?;
-
-                // Add the item to our array of items.
-                cd_inner.items.push(c.object_id);
-            }
-
-            // Fill out the rest of the customs declaration fields.
-            // TODO: make this modifiable.
-            cd_inner.certify_signer = "Jess Frazelle".to_string();
-            cd_inner.certify = true;
-            cd_inner.non_delivery_option = "RETURN".to_string();
-            cd_inner.contents_type = "GIFT".to_string();
-            // This can only have a max of 200 chars.
-            // Weird I know.
-            cd_inner.contents_explanation = crate::utils::truncate(&self.contents, 200);
-            // TODO: I think this needs to change for Canada.
-            cd_inner.eel_pfc = "NOEEI_30_37_a".to_string();
-
-            // Set the customs declarations.
-            cd = Some(cd_inner);
-        }
-
         if self.country == "Great Britain" {
             self.country = "GB".to_string();
         } else if self.country == "United States" {
@@ -1510,106 +1700,486 @@ The Shipping Bot",
             self.phone = company.phone.to_string();
         }
 
-        // Create our shipment.
-        let shipment = shippo_client
-            .create_shipment(NewShipment {
-                address_from,
-                address_to: Address {
-                    name: self.name.to_string(),
-                    street1: self.street_1.to_string(),
-                    street2: self.street_2.to_string(),
-                    city: self.city.to_string(),
-                    state: self.state.to_string(),
-                    zip: self.zipcode.to_string(),
-                    country: self.country.to_string(),
-                    phone: self.phone.to_string(),
-                    email: self.email.to_string(),
-                    is_complete: Default::default(),
-                    object_id: Default::default(),
-                    test: Default::default(),
-                    company: Default::default(),
-                    validation_results: Default::default(),
-                },
-                parcels: vec![Parcel {
-                    metadata: "Default box for swag".to_string(),
-                    length: "12".to_string(),
-                    width: "12".to_string(),
-                    height: "6".to_string(),
-                    distance_unit: "in".to_string(),
-                    weight: "2".to_string(),
-                    mass_unit: "lb".to_string(),
-                    object_id: Default::default(),
-                    object_owner: Default::default(),
-                    object_created: None,
-                    object_updated: None,
-                    object_state: Default::default(),
-                    test: Default::default(),
-                }],
-                customs_declaration: cd,
-            })
+        // Make sure we can deliver to the address before we buy a label.
+        if !self.validate_address(db, &company).await?.is_deliverable() {
+            // Return early, we asked in Slack what to do about the address.
+            return Ok(());
+        }
+
+        // We need to create the label since we don't have one already.
+        let request = self.label_request(
+            company.hq_shipping_address(db).await?.into(),
+            self.parcels()?,
+            self.customs(db).await?,
+        );
+
+        let providers = get_label_providers();
+        let rates = shop_rates(&providers, &request).await;
+        let policy = RatePolicy::from_env()?;
+        let rate = match policy.choose(&rates) {
+            Some(rate) => rate,
+            None => bail!(
+                "no rates for shipment to {} from any of the shipping providers",
+                self.name
+            ),
+        };
+        let provider = match providers.iter().find(|p| p.name() == rate.provider) {
+            Some(provider) => provider,
+            None => bail!("no shipping provider `{}` for the chosen rate", rate.provider),
+        };
+        info!(
+            "buying {} {} label for {} from {} for ${:.2}, rate policy {}",
+            rate.carrier,
+            rate.service,
+            self.name,
+            rate.provider,
+            rate.amount,
+            policy.to_string()
+        );
+
+        // Get the calendar before we pay for the label, so a bad building config
+        // can't make us throw away a label we already bought.
+        let calendar = match BusinessCalendar::for_hq(db, &company).await {
+            Ok(calendar) => calendar,
+            Err(e) => {
+                warn!(
+                    "getting the business calendar for {} failed, using the default: {}",
+                    company.name, e
+                );
+                BusinessCalendar::default()
+            }
+        };
+
+        // Create the shipping label.
+        let label = provider.buy_label(rate).await?;
+
+        // Set the additional fields.
+        self.provider = provider.name().to_string();
+        self.carrier = clean_carrier_name(&label.carrier);
+        self.cost = label.cost;
+        self.tracking_number = label.tracking_number.to_string();
+        self.set_parcel_tracking_numbers(&label.parcel_tracking_numbers)?;
+        self.tracking_link = label.tracking_link.to_string();
+        self.tracking_status = label.tracking_status.to_string();
+        self.label_link = label.label_link.to_string();
+        self.eta = label.eta;
+        if let (None, Some(days), false) = (self.eta, rate.estimated_days, self.is_return) {
+            // The provider did not give us an eta, estimate it from when we can hand
+            // the package to the carrier.
+            self.eta = Some(calendar.estimate_delivery(&self.carrier, Utc::now(), days));
+        }
+        self.provider_id = label.id.to_string();
+        self.oxide_tracking_link = self.oxide_tracking_link();
+        if !label.messages.is_empty() {
+            // Print the messages in the messages field.
+            self.messages = label.messages.to_string();
+
+            // Save it and return early, we don't have a label to print.
+            self.update(db).await?;
+            return Ok(());
+        }
+
+        self.set_status(db, crate::shipment_status::Status::LabelCreated, &company)
             .await?;
 
-        // Now we can create our label from the available rates.
-        // Try to find the rate that is "BESTVALUE" or "CHEAPEST".
-        for rate in shipment.rates {
-            if rate.attributes.contains(&"BESTVALUE".to_string()) || rate.attributes.contains(&"CHEAPEST".to_string()) {
-                // Use this rate.
-                // Create the shipping label.
-                let label = shippo_client
-                    .create_shipping_label_from_rate(NewTransaction {
-                        rate: rate.object_id,
-                        r#async: false,
-                        label_file_type: "".to_string(),
-                        metadata: "".to_string(),
-                    })
-                    .await?;
+        // Save it in Airtable here, in case one of the below steps fails.
+        self.update(db).await?;
 
-                // Set the additional fields.
-                self.carrier = clean_carrier_name(&rate.provider);
-                self.cost = rate.amount_local.parse()?;
-                self.tracking_number = label.tracking_number.to_string();
-                self.tracking_link = label.tracking_url_provider.to_string();
-                self.tracking_status = label.tracking_status.to_string();
-                self.label_link = label.label_url.to_string();
-                self.eta = label.eta;
-                self.provider_id = label.object_id.to_string();
-                self.oxide_tracking_link = self.oxide_tracking_link();
-                if label.status != "SUCCESS" {
-                    // Print the messages in the messages field.
-                    let mut messages = "".to_string();
-                    for m in label.messages {
-                        messages = format!("{}\n{} {} {}", messages, m.code, m.source, m.text);
-                    }
-                    self.messages = messages.trim().to_string();
-                } else {
-                    self.set_status(db, crate::shipment_status::Status::LabelCreated, &company)
-                        .await?;
+        // International shipments need a commercial invoice to clear customs. We
+        // already paid for the label, so if this fails we keep going and the invoice
+        // can be made by hand.
+        if let Some(customs) = &request.customs {
+            match self.generate_commercial_invoice(db, &request, customs).await {
+                Ok(_) => {
+                    self.update(db).await?;
+                }
+                Err(e) => {
+                    warn!(
+                        "generating commercial invoice for shipment to {} failed: {}",
+                        self.name, e
+                    );
+                    self.messages = format!("Generating the commercial invoice failed: {}", e);
+                    self.update(db).await?;
                 }
+            }
+        }
 
-                // Save it in Airtable here, in case one of the below steps fails.
-                self.update(db).await?;
+        // Track the shipment, this registers for updates to it.
+        provider.track(&self.carrier, &self.tracking_number).await?;
 
-                // Register a tracking webhook for this shipment.
-                shippo_client
-                    .register_tracking_webhook(&self.carrier, &self.tracking_number)
-                    .await?;
+        if self.is_return {
+            // The recipient prints the return label, so send it to them.
+            self.send_return_label_email_to_recipient(db).await?;
+            return Ok(());
+        }
 
-                // Print the label.
-                self.print_label(db).await?;
-                // Print the receipt.
-                self.print_receipt(db).await?;
-                self.set_status(db, crate::shipment_status::Status::LabelPrinted, &company)
-                    .await?;
+        // Print the label.
+        self.print_label(db).await?;
+        // Print the receipt.
+        self.print_receipt(db).await?;
+        self.set_status(db, crate::shipment_status::Status::LabelPrinted, &company)
+            .await?;
+
+        // Send an email to us that we need to package the shipment.
+        self.send_email_internally(db).await?;
+
+        Ok(())
+    }
+
+    /// Returns true if we can void the label for this shipment, false if we already
+    /// did, and an error if it has no label or already shipped.
+    fn can_void_label(&self) -> Result<bool> {
+        if self.provider_id.is_empty() {
+            bail!("shipment to {} has no label to void", self.name);
+        }
 
-                // Send an email to us that we need to package the shipment.
-                self.send_email_internally(db).await?;
+        if self.status == crate::shipment_status::Status::Cancelled.to_string() {
+            return Ok(false);
+        }
+
+        if self.status == crate::shipment_status::Status::Shipped.to_string()
+            || self.status == crate::shipment_status::Status::Delivered.to_string()
+            || self.status == crate::shipment_status::Status::PickedUp.to_string()
+            || self.status == crate::shipment_status::Status::Returned.to_string()
+        {
+            bail!(
+                "cannot void the label for shipment to {}, it is already `{}`",
+                self.name,
+                self.status
+            );
+        }
+
+        Ok(true)
+    }
+
+    /// Void the label for this shipment, if it has not shipped yet. The provider
+    /// refunds the cost of the label, we only zero the cost once it has.
+    pub async fn void_label(&mut self, db: &Database) -> Result<()> {
+        if !self.can_void_label()? {
+            // Return early, we already voided the label.
+            return Ok(());
+        }
 
-                break;
+        let company = self.company(db).await?;
+        let provider = get_shipping_provider(&self.provider)?;
+        match provider.void_label(&self.provider_id).await? {
+            RefundStatus::Refunded => {
+                info!(
+                    "voided {} label {} for shipment to {}, refunded ${:.2}",
+                    self.provider, self.provider_id, self.name, self.cost
+                );
+                self.messages = format!("Label voided, ${:.2} refunded", self.cost);
+                self.cost = 0.0;
+            }
+            RefundStatus::Pending => {
+                // The label can't be used anymore, but we have not got the money back
+                // yet, so keep the cost.
+                info!(
+                    "voided {} label {} for shipment to {}, the ${:.2} refund is pending",
+                    self.provider, self.provider_id, self.name, self.cost
+                );
+                self.messages = format!(
+                    "Label voided, the ${:.2} refund is pending with {}",
+                    self.cost, self.provider
+                );
             }
         }
+        self.pickup_date = None;
+        self.set_status(db, crate::shipment_status::Status::Cancelled, &company)
+            .await?;
+        self.update(db).await?;
+
+        Ok(())
+    }
+
+    /// Cancel this shipment. If we already bought a label it is voided, otherwise the
+    /// shipment is just marked cancelled. Either way any swag reserved for it goes
+    /// back in stock.
+    pub async fn cancel(&mut self, db: &Database) -> Result<()> {
+        if !self.provider_id.is_empty() {
+            return self.void_label(db).await;
+        }
+
+        if self.status == crate::shipment_status::Status::Cancelled.to_string() {
+            // It might have been cancelled by hand in Airtable, so make sure the swag
+            // is back in stock. This does nothing if it already is.
+            crate::swag_inventory::SwagInventoryReservation::release(db, self).await?;
+            return Ok(());
+        }
+
+        info!("cancelling shipment to {} before a label was bought", self.name);
+        let company = self.company(db).await?;
+        if self.messages.is_empty() {
+            self.messages = "Cancelled before a label was bought".to_string();
+        }
+        self.set_status(db, crate::shipment_status::Status::Cancelled, &company)
+            .await?;
+        self.update(db).await?;
+
+        Ok(())
+    }
+
+    /// Create a prepaid return label for this shipment, from the address it was
+    /// sent to back to us. The label is emailed to the recipient.
+    pub async fn create_return_shipment(&self, db: &Database) -> Result<OutboundShipment> {
+        if self.is_return {
+            bail!("shipment from {} is already a return", self.name);
+        }
+
+        let mut new_shipment: NewOutboundShipment = self.into();
+        new_shipment.created_time = Utc::now();
+        new_shipment.is_return = true;
+        // The rest will be populated when we create the label.
+        new_shipment.status = crate::shipment_status::Status::Queued.to_string();
+        new_shipment.carrier = Default::default();
+        new_shipment.tracking_number = Default::default();
+        new_shipment.tracking_link = Default::default();
+        new_shipment.oxide_tracking_link = Default::default();
+        new_shipment.tracking_status = Default::default();
+        new_shipment.label_link = Default::default();
+        new_shipment.cost = Default::default();
+        new_shipment.pickup_date = None;
+        new_shipment.shipped_time = None;
+        new_shipment.delivered_time = None;
+        new_shipment.eta = None;
+        new_shipment.provider_id = Default::default();
+        new_shipment.messages = Default::default();
+        new_shipment.link_to_package_pickup = Default::default();
+        new_shipment.commercial_invoice_link = Default::default();
+        if new_shipment.provider != EASYPOST {
+            new_shipment.provider = SHIPPO.to_string();
+        }
+
+        // It has no tracking number yet, so don't match it against the other
+        // shipments without a label.
+        let mut shipment = new_shipment.create_in_db(db).await?;
+        // Create the shipment label.
+        shipment.create_or_get_shipment(db).await?;
+        // Update airtable and the database again.
+        let shipment = shipment.update(db).await?;
+
+        Ok(shipment)
+    }
+
+    /// Send an email to the recipient with their return label.
+    pub async fn send_return_label_email_to_recipient(&self, db: &Database) -> Result<()> {
+        if self.email.is_empty() {
+            return Ok(());
+        }
+
+        let company = self.company(db).await?;
+        // Initialize the SendGrid client.
+        let sendgrid_client = SendGrid::new_from_env();
+        // Send the message.
+        sendgrid_client
+            .mail_send()
+            .send_plain_text(
+                &format!("{}, here is your return label for {}", self.name, company.name),
+                &format!(
+                    "Below is the information for your return:
+
+**Contents:**
+{}
+
+**Label:**
+{}
+{}
+Print the label, tape it to the package, and drop it off with {}. The
+postage is already paid.
+
+**Tracking link:**
+{}
+
+If you have any questions or concerns, please respond to this email!
+Have a splendid day!
+
+xoxo,
+  The Shipping Bot",
+                    self.contents,
+                    self.label_link,
+                    if self.commercial_invoice_link.is_empty() {
+                        "".to_string()
+                    } else {
+                        format!(
+                            "\n**Commercial invoice:**\n{}\n\nPrint three copies of the commercial invoice and \
+                             put them in a pouch on the package.\n",
+                            self.commercial_invoice_link
+                        )
+                    },
+                    self.carrier,
+                    self.oxide_tracking_link
+                ),
+                &[self.email.to_string()],
+                &[format!("packages@{}", &company.gsuite_domain)],
+                &[],
+                &format!("packages@{}", &company.gsuite_domain),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// The boxes in this shipment.
+    fn parcels(&self) -> Result<Vec<ShippingParcel>> {
+        if self.parcels.is_empty() {
+            return Ok(vec![Default::default()]);
+        }
+
+        self.parcels.iter().map(|p| p.parse()).collect()
+    }
+
+    /// Keep the tracking number of each box with its parcel, when there is more than
+    /// one box.
+    fn set_parcel_tracking_numbers(&mut self, tracking_numbers: &[String]) -> Result<()> {
+        if tracking_numbers.len() <= 1 {
+            return Ok(());
+        }
+
+        let mut parcels = self.parcels()?;
+        for (parcel, tracking_number) in parcels.iter_mut().zip(tracking_numbers) {
+            parcel.tracking_number = tracking_number.to_string();
+        }
+        self.parcels = parcels.iter().map(|p| p.to_string()).collect();
+
+        Ok(())
+    }
+
+    /// The tracking numbers of all the boxes in this shipment.
+    pub fn tracking_numbers(&self) -> Vec<String> {
+        let mut tracking_numbers: Vec<String> = self
+            .parcels
+            .iter()
+            .filter_map(|p| p.parse::<ShippingParcel>().ok())
+            .map(|p| p.tracking_number)
+            .filter(|t| !t.is_empty())
+            .collect();
+        if !self.tracking_number.is_empty() && !tracking_numbers.contains(&self.tracking_number) {
+            tracking_numbers.insert(0, self.tracking_number.to_string());
+        }
+
+        tracking_numbers
+    }
+
+    /// Get a shipment by the tracking number of any of its boxes.
+    pub async fn get_by_tracking_number(db: &Database, carrier: &str, tracking_number: &str) -> Option<Self> {
+        if let Some(shipment) =
+            OutboundShipment::get_from_db(db, carrier.to_string(), tracking_number.to_string()).await
+        {
+            return Some(shipment);
+        }
+
+        OutboundShipment::get_by_parcel_tracking_number(db, tracking_number)
+            .await
+            .filter(|s| s.carrier == carrier)
+    }
+
+    /// Get a shipment with more than one box by the tracking number of one of the
+    /// boxes, the shipment's own tracking number is the one of the first box.
+    pub async fn get_by_parcel_tracking_number(db: &Database, tracking_number: &str) -> Option<Self> {
+        let shipments = outbound_shipments::dsl::outbound_shipments
+            .filter(diesel::dsl::sql::<diesel::sql_types::Bool>("cardinality(parcels) > 1"))
+            .load_async::<OutboundShipment>(db.pool())
+            .await
+            .ok()?;
+
+        shipments
+            .into_iter()
+            .find(|s| s.tracking_numbers().iter().any(|t| t == tracking_number.trim()))
+    }
+
+    /// The customs declaration for this shipment, if it is international. The HS
+    /// codes, values and weights come from the swag items in the contents.
+    async fn customs(&self, db: &Database) -> Result<Option<ShippingCustoms>> {
+        if self.country == "US" {
+            return Ok(None);
+        }
+
+        let mut items: Vec<ShippingCustomsItem> = Default::default();
+        // Create customs items for each item in our order.
+        for line in self.contents.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (quantity, item, _size) = parse_contents_line(line);
+            let mut customs_item = ShippingCustomsItem {
+                description: line.trim().to_string(),
+                hs_code: Default::default(),
+                quantity,
+                weight: 0.25,
+                value: 100.0,
+                origin_country: "US".to_string(),
+            };
+
+            if let Some(swag_item) = SwagItem::get_from_db(db, item.to_string()).await {
+                customs_item.description = swag_item.name.to_string();
+                customs_item.hs_code = swag_item.hs_code.to_string();
+                if swag_item.customs_value > 0.0 {
+                    customs_item.value = swag_item.customs_value * quantity as f32;
+                }
+                if swag_item.weight > 0.0 {
+                    customs_item.weight = swag_item.weight * quantity as f32;
+                }
+                if !swag_item.origin_country.is_empty() {
+                    customs_item.origin_country = swag_item.origin_country.to_string();
+                }
+            } else {
+                warn!(
+                    "no swag item `{}` for the customs declaration of shipment to {}, using the defaults",
+                    item, self.name
+                );
+            }
+
+            items.push(customs_item);
+        }
+
+        Ok(Some(ShippingCustoms {
+            items,
+            // TODO: make this modifiable.
+            signer: "Jess Frazelle".to_string(),
+            contents_explanation: self.contents.to_string(),
+        }))
+    }
+
+    /// Generate the commercial invoice for an international shipment and save it in
+    /// Google Drive.
+    async fn generate_commercial_invoice(
+        &mut self,
+        db: &Database,
+        request: &ShipmentRequest,
+        customs: &ShippingCustoms,
+    ) -> Result<()> {
+        let company = self.company(db).await?;
+
+        let invoice = CommercialInvoice {
+            date: Utc::now(),
+            from: request.from.clone(),
+            to: request.to.clone(),
+            carrier: self.carrier.to_string(),
+            tracking_number: self.tracking_number.to_string(),
+            customs: customs.clone(),
+        };
+        let pdf = invoice.generate_pdf()?;
+
+        // Initialize the Google Drive client.
+        let drive_client = company.authenticate_google_drive(db).await?;
+        // Figure out where our directory is.
+        // It should be in the shared drive : "Automated Documents"/"shipments"
+        let shared_drive = drive_client.drives().get_by_name("Automated Documents").await?;
+        let drive_id = shared_drive.id.to_string();
+        let parent_id = drive_client.files().create_folder(&drive_id, "", "shipments").await?;
+
+        let file_name = format!(
+            "{} - {} - Commercial Invoice.pdf",
+            self.name.replace('/', ""),
+            self.tracking_number
+        );
+        let file = drive_client
+            .files()
+            .create_or_update(&drive_id, &parent_id, &file_name, "application/pdf", &pdf)
+            .await?;
+        self.commercial_invoice_link = format!("https://drive.google.com/uc?export=download&id={}", file.id);
 
-        // TODO: do something if we don't find a rate.
-        // However we should always find a rate.
         Ok(())
     }
 }
@@ -1668,10 +2238,21 @@ pub async fn refresh_outbound_shipments(db: &Database, company: &Company) -> Res
         if let Some(existing) = s.get_existing_airtable_record(db).await {
             // Take the field from Airtable.
             s.local_pickup = existing.fields.local_pickup;
+
+            // If someone cancelled the shipment by hand in Airtable, cancel it here too
+            // so the label is voided and the swag goes back in stock. Otherwise our
+            // status would just overwrite theirs on the next update.
+            let cancelled = crate::shipment_status::Status::Cancelled.to_string();
+            if existing.fields.status == cancelled && s.status != cancelled {
+                if let Err(e) = s.cancel(db).await {
+                    warn!("cancelling shipment to {} from airtable failed: {}", s.name, e);
+                }
+                continue;
+            }
         }
 
-        // Update the shipment from shippo, this will only apply if the provider is set as "Shippo".
-        s.create_or_get_shippo_shipment(db).await?;
+        // Update the shipment label, this will only apply if we bought it from a label provider.
+        s.create_or_get_shipment(db).await?;
 
         // Update airtable and the database again.
         s.update(db).await?;
@@ -1749,6 +2330,10 @@ async fn update_manual_shippo_shipments(db: &Database, company: &Company) -> Res
             geocode_cache: Default::default(),
             local_pickup: Default::default(),
             link_to_package_pickup: Default::default(),
+            is_return: false,
+            parcels: Default::default(),
+            commercial_invoice_link: Default::default(),
+            address_validation: Default::default(),
             cio_company_id: company.id,
         };
 
@@ -1781,7 +2366,7 @@ async fn update_manual_shippo_shipments(db: &Database, company: &Company) -> Res
             .await?;
 
         // Update the shipment from shippo.
-        s.create_or_get_shippo_shipment(db).await?;
+        s.create_or_get_shipment(db).await?;
         // Update airtable and the database again.
         s.update(db).await?;
     }
@@ -1834,3 +2419,90 @@ pub fn clean_address_string(s: &str) -> String {
 
     s.to_string()
 }
+
+/// Parse a line of the contents of a shipment, like `2 x Hoodie, Size: M`, into
+/// the quantity, item and size.
+pub fn parse_contents_line(line: &str) -> (i32, String, String) {
+    let line = line.trim();
+    let (quantity, rest) = match line.split_once(" x ") {
+        Some((prefix, rest)) => match prefix.trim().parse::<i32>() {
+            Ok(quantity) => (quantity, rest),
+            Err(_) => (1, line),
+        },
+        None => (1, line),
+    };
+
+    let (item, size) = rest.split_once(", Size:").unwrap_or((rest, ""));
+
+    (quantity, item.trim().to_string(), size.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{parse_contents_line, OutboundShipment};
+    use crate::shipping::{ShippingAddress, ShippingParcel};
+
+    fn shipment(status: &str, provider_id: &str, is_return: bool) -> OutboundShipment {
+        serde_json::from_value(json!({
+            "name": "Jane Doe",
+            "street_1": "1 Main St",
+            "city": "Springfield",
+            "state": "IL",
+            "zipcode": "62701",
+            "country": "US",
+            "status": status,
+            "provider": "Shippo",
+            "provider_id": provider_id,
+            "is_return": is_return,
+            "created_time": "2022-04-01T17:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_contents_line() {
+        assert_eq!(
+            parse_contents_line("12 x Oxide Hoodie, Size: M"),
+            (12, "Oxide Hoodie".to_string(), "M".to_string())
+        );
+        assert_eq!(
+            parse_contents_line("Asset return kit: box and return label"),
+            (1, "Asset return kit: box and return label".to_string(), "".to_string())
+        );
+        assert_eq!(parse_contents_line("a x b"), (1, "a x b".to_string(), "".to_string()));
+    }
+
+    #[test]
+    fn test_can_void_label() {
+        assert!(shipment("Label printed", "label", false).can_void_label().unwrap());
+        // We already voided it.
+        assert!(!shipment("Cancelled", "label", false).can_void_label().unwrap());
+        // There is no label.
+        assert!(shipment("Queued", "", false).can_void_label().is_err());
+        // It is on its way.
+        assert!(shipment("Shipped", "label", false).can_void_label().is_err());
+        assert!(shipment("Delivered", "label", false).can_void_label().is_err());
+    }
+
+    #[test]
+    fn test_label_request() {
+        let hq = ShippingAddress {
+            name: "Oxide Computer Company".to_string(),
+            city: "Emeryville".to_string(),
+            ..Default::default()
+        };
+
+        let request = shipment("Queued", "", false).label_request(hq.clone(), vec![ShippingParcel::default()], None);
+        assert_eq!(request.from, hq);
+        assert_eq!(request.to.name, "Jane Doe");
+        assert_eq!(request.to.city, "Springfield");
+        assert_eq!(request.parcels, vec![ShippingParcel::default()]);
+
+        // A return ships from the recipient back to us.
+        let request = shipment("Queued", "", true).label_request(hq.clone(), vec![ShippingParcel::default()], None);
+        assert_eq!(request.from.name, "Jane Doe");
+        assert_eq!(request.to, hq);
+    }
+}
@@ -0,0 +1,1642 @@
+use std::{env, io::BufWriter, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::warn;
+use printpdf::{Mm, PdfDocument};
+use regex::Regex;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use shippo::{NewTransaction, Shippo};
+
+use crate::shipments::clean_carrier_name;
+
+/// The name of the Shippo provider, as stored on a shipment.
+pub const SHIPPO: &str = "Shippo";
+/// The name of the EasyPost provider, as stored on a shipment.
+pub const EASYPOST: &str = "EasyPost";
+/// The name of the ShipBob provider, as stored on a shipment.
+pub const SHIPBOB: &str = "ShipBob";
+
+const EASYPOST_API_URL: &str = "https://api.easypost.com/v2";
+const SHIPPO_API_URL: &str = "https://api.goshippo.com";
+
+/// An address to ship from or to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShippingAddress {
+    pub name: String,
+    pub company: String,
+    pub street_1: String,
+    pub street_2: String,
+    pub city: String,
+    pub state: String,
+    pub zipcode: String,
+    pub country: String,
+    pub phone: String,
+    pub email: String,
+}
+
+impl From<shippo::Address> for ShippingAddress {
+    fn from(item: shippo::Address) -> Self {
+        ShippingAddress {
+            name: item.name,
+            company: item.company,
+            street_1: item.street1,
+            street_2: item.street2,
+            city: item.city,
+            state: item.state,
+            zipcode: item.zip,
+            country: item.country,
+            phone: item.phone,
+            email: item.email,
+        }
+    }
+}
+
+impl From<&ShippingAddress> for shippo::Address {
+    fn from(item: &ShippingAddress) -> Self {
+        shippo::Address {
+            name: item.name.to_string(),
+            company: item.company.to_string(),
+            street1: item.street_1.to_string(),
+            street2: item.street_2.to_string(),
+            city: item.city.to_string(),
+            state: item.state.to_string(),
+            zip: item.zipcode.to_string(),
+            country: item.country.to_string(),
+            phone: item.phone.to_string(),
+            email: item.email.to_string(),
+            is_complete: Default::default(),
+            object_id: Default::default(),
+            test: Default::default(),
+            validation_results: Default::default(),
+        }
+    }
+}
+
+impl ShippingAddress {
+    /// The address as EasyPost and Shippo take it, they use the same fields.
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "company": self.company,
+            "street1": self.street_1,
+            "street2": self.street_2,
+            "city": self.city,
+            "state": self.state,
+            "zip": self.zipcode,
+            "country": self.country,
+            "phone": self.phone,
+            "email": self.email,
+        })
+    }
+}
+
+/// A box to ship, in inches and pounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShippingParcel {
+    pub length: f32,
+    pub width: f32,
+    pub height: f32,
+    pub weight: f32,
+    /// The tracking number of the box, once we have a label for it.
+    pub tracking_number: String,
+}
+
+impl Default for ShippingParcel {
+    /// The default box for swag.
+    fn default() -> Self {
+        ShippingParcel {
+            length: 12.0,
+            width: 12.0,
+            height: 6.0,
+            weight: 2.0,
+            tracking_number: Default::default(),
+        }
+    }
+}
+
+impl ToString for ShippingParcel {
+    fn to_string(&self) -> String {
+        let parcel = format!("{}x{}x{} in, {} lb", self.length, self.width, self.height, self.weight);
+        if self.tracking_number.is_empty() {
+            return parcel;
+        }
+
+        format!("{} #{}", parcel, self.tracking_number)
+    }
+}
+
+impl FromStr for ShippingParcel {
+    type Err = anyhow::Error;
+
+    /// Parse a parcel like `12x12x6 in, 2 lb`, the units are optional. Once the
+    /// parcel has a label it is followed by its tracking number, like
+    /// `12x12x6 in, 2 lb #1Z999AA10123456784`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let re = Regex::new(
+            r"^\s*([\d.]+)\s*x\s*([\d.]+)\s*x\s*([\d.]+)\s*(?:in)?\s*[,;]?\s*([\d.]+)\s*(?:lbs?)?\s*(?:#\s*(\S+))?\s*$",
+        )?;
+        let c = match re.captures(s) {
+            Some(c) => c,
+            None => bail!("parcel `{}` should look like `12x12x6 in, 2 lb`", s),
+        };
+
+        let parcel = ShippingParcel {
+            length: c[1].parse()?,
+            width: c[2].parse()?,
+            height: c[3].parse()?,
+            weight: c[4].parse()?,
+            tracking_number: c.get(5).map(|t| t.as_str().to_string()).unwrap_or_default(),
+        };
+        if parcel.length <= 0.0 || parcel.width <= 0.0 || parcel.height <= 0.0 || parcel.weight <= 0.0 {
+            bail!("parcel `{}` needs a size and weight", s);
+        }
+
+        Ok(parcel)
+    }
+}
+
+/// An item in a customs declaration, the weight is in pounds and the value in USD
+/// for all of the items.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShippingCustomsItem {
+    pub description: String,
+    /// The Harmonized System code for the item, if we know it.
+    pub hs_code: String,
+    pub quantity: i32,
+    pub weight: f32,
+    pub value: f32,
+    pub origin_country: String,
+}
+
+/// The customs declaration for an international shipment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShippingCustoms {
+    pub items: Vec<ShippingCustomsItem>,
+    /// The person certifying the declaration.
+    pub signer: String,
+    pub contents_explanation: String,
+}
+
+/// A shipment to get rates for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShipmentRequest {
+    pub from: ShippingAddress,
+    pub to: ShippingAddress,
+    pub parcels: Vec<ShippingParcel>,
+    pub customs: Option<ShippingCustoms>,
+}
+
+/// A shipment created with a provider.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderShipment {
+    pub id: String,
+    pub rates: Vec<ShippingRate>,
+}
+
+/// A rate to ship a shipment with a carrier and service.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShippingRate {
+    /// The name of the provider the rate is from.
+    pub provider: String,
+    pub shipment_id: String,
+    pub id: String,
+    pub carrier: String,
+    pub service: String,
+    pub amount: f32,
+    pub currency: String,
+    pub estimated_days: Option<i64>,
+}
+
+/// A label bought for a shipment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShippingLabel {
+    pub id: String,
+    pub carrier: String,
+    /// The tracking number of the first box.
+    pub tracking_number: String,
+    /// The tracking numbers of each box, in the order of the parcels in the request,
+    /// if there is more than one box.
+    pub parcel_tracking_numbers: Vec<String>,
+    pub tracking_link: String,
+    pub tracking_status: String,
+    pub label_link: String,
+    pub cost: f32,
+    pub eta: Option<DateTime<Utc>>,
+    /// Why the label could not be created, empty if it was.
+    pub messages: String,
+}
+
+/// An event in the tracking history of a shipment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingEvent {
+    pub status: String,
+    pub status_details: String,
+    pub status_date: Option<DateTime<Utc>>,
+    pub location: String,
+}
+
+/// The tracking status of a shipment. The statuses are the ones Shippo uses:
+/// `PRE_TRANSIT`, `TRANSIT`, `DELIVERED`, `RETURNED`, `FAILURE` or `UNKNOWN`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingInfo {
+    pub tracking_number: String,
+    pub status: String,
+    pub status_details: String,
+    pub status_date: Option<DateTime<Utc>>,
+    pub eta: Option<DateTime<Utc>>,
+    pub history: Vec<TrackingEvent>,
+}
+
+impl From<shippo::TrackingStatus> for TrackingInfo {
+    fn from(ts: shippo::TrackingStatus) -> Self {
+        let status = ts.tracking_status.unwrap_or_default();
+
+        TrackingInfo {
+            tracking_number: ts.tracking_number,
+            status: normalize_tracking_status(&status.status),
+            status_details: status.status_details,
+            status_date: status.status_date,
+            eta: ts.eta,
+            history: ts
+                .tracking_history
+                .into_iter()
+                .map(|h| TrackingEvent {
+                    status: normalize_tracking_status(&h.status),
+                    status_details: h.status_details,
+                    status_date: h.status_date,
+                    location: Default::default(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TrackingInfo {
+    /// Get the tracking info from an EasyPost tracker, from their API or a webhook.
+    pub fn from_easypost(tracker: &serde_json::Value) -> Self {
+        let history: Vec<TrackingEvent> = tracker["tracking_details"]
+            .as_array()
+            .map(|details| {
+                details
+                    .iter()
+                    .map(|d| TrackingEvent {
+                        status: normalize_tracking_status(d["status"].as_str().unwrap_or_default()),
+                        status_details: d["message"].as_str().unwrap_or_default().to_string(),
+                        status_date: EasyPostProvider::parse_date(&d["datetime"]),
+                        location: [
+                            d["tracking_location"]["city"].as_str().unwrap_or_default(),
+                            d["tracking_location"]["state"].as_str().unwrap_or_default(),
+                            d["tracking_location"]["country"].as_str().unwrap_or_default(),
+                        ]
+                        .iter()
+                        .filter(|s| !s.is_empty())
+                        .cloned()
+                        .collect::<Vec<&str>>()
+                        .join(", "),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        TrackingInfo {
+            tracking_number: tracker["tracking_code"].as_str().unwrap_or_default().to_string(),
+            status: normalize_tracking_status(tracker["status"].as_str().unwrap_or_default()),
+            status_details: tracker["status_detail"].as_str().unwrap_or_default().to_string(),
+            status_date: history.last().and_then(|h| h.status_date),
+            eta: EasyPostProvider::parse_date(&tracker["est_delivery_date"]),
+            history,
+        }
+    }
+}
+
+/// A pickup of labels by a carrier.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PickupRequest {
+    pub carrier: String,
+    pub address: ShippingAddress,
+    pub label_ids: Vec<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub instructions: String,
+}
+
+/// A scheduled pickup.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pickup {
+    pub id: String,
+    pub confirmation_code: String,
+    pub status: String,
+    pub confirmed_start_time: Option<DateTime<Utc>>,
+    pub confirmed_end_time: Option<DateTime<Utc>>,
+    pub cancel_by_time: Option<DateTime<Utc>>,
+    pub messages: String,
+}
+
+/// A provider we can buy shipping labels from.
+#[async_trait]
+pub trait ShippingProvider: Send + Sync {
+    /// The name of the provider, as stored on a shipment.
+    fn name(&self) -> &'static str;
+
+    /// Create a shipment, with the rates to ship it.
+    async fn create_shipment(&self, request: &ShipmentRequest) -> Result<ProviderShipment>;
+
+    /// List the rates for a shipment.
+    async fn list_rates(&self, shipment_id: &str) -> Result<Vec<ShippingRate>>;
+
+    /// Buy the label for a rate.
+    async fn buy_label(&self, rate: &ShippingRate) -> Result<ShippingLabel>;
+
+    /// Get a label we bought.
+    async fn get_label(&self, label_id: &str) -> Result<ShippingLabel>;
+
+    /// Void a label we bought and did not use, this returns whether the provider has
+    /// refunded it yet.
+    async fn void_label(&self, label_id: &str) -> Result<RefundStatus>;
+
+    /// Get the tracking status of a shipment, and get updates for it from the
+    /// provider's webhooks.
+    async fn track(&self, carrier: &str, tracking_number: &str) -> Result<TrackingInfo>;
+
+    /// Schedule a carrier to pick up labels.
+    async fn schedule_pickup(&self, request: &PickupRequest) -> Result<Pickup>;
+}
+
+/// Where the refund for a voided label is at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefundStatus {
+    Refunded,
+    /// The provider accepted the refund but has not refunded it yet.
+    Pending,
+}
+
+impl RefundStatus {
+    /// Get the status of a Shippo refund, one of `QUEUED`, `PENDING`, `SUCCESS` or
+    /// `ERROR`.
+    fn from_shippo(label_id: &str, refund: &Value) -> Result<Self> {
+        match refund["status"].as_str().unwrap_or_default() {
+            "SUCCESS" => Ok(RefundStatus::Refunded),
+            "QUEUED" | "PENDING" => Ok(RefundStatus::Pending),
+            _ => bail!("voiding shippo label `{}` failed: {}", label_id, refund),
+        }
+    }
+
+    /// Get the status of an EasyPost refund, one of `submitted`, `refunded`,
+    /// `rejected` or `not_applicable`.
+    fn from_easypost(shipment_id: &str, shipment: &Value) -> Result<Self> {
+        match shipment["refund_status"].as_str().unwrap_or_default() {
+            "refunded" => Ok(RefundStatus::Refunded),
+            "submitted" => Ok(RefundStatus::Pending),
+            s => bail!("voiding easypost label `{}` failed, the refund is `{}`", shipment_id, s),
+        }
+    }
+}
+
+/// How to pick the rate to ship a shipment with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RatePolicy {
+    Cheapest,
+    Fastest,
+    /// The cheapest rate from a carrier, or the cheapest rate if the carrier has none.
+    PreferredCarrier(String),
+}
+
+impl Default for RatePolicy {
+    fn default() -> Self {
+        RatePolicy::Cheapest
+    }
+}
+
+impl ToString for RatePolicy {
+    fn to_string(&self) -> String {
+        match self {
+            RatePolicy::Cheapest => "cheapest".to_string(),
+            RatePolicy::Fastest => "fastest".to_string(),
+            RatePolicy::PreferredCarrier(carrier) => format!("carrier:{}", carrier),
+        }
+    }
+}
+
+impl FromStr for RatePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(carrier) = s.strip_prefix("carrier:") {
+            return Ok(RatePolicy::PreferredCarrier(clean_carrier_name(carrier.trim())));
+        }
+
+        match s.to_lowercase().as_str() {
+            "" | "cheapest" => Ok(RatePolicy::Cheapest),
+            "fastest" => Ok(RatePolicy::Fastest),
+            _ => bail!("unknown rate policy `{}`", s),
+        }
+    }
+}
+
+impl RatePolicy {
+    /// Get the policy from `SHIPPING_RATE_POLICY`, the cheapest rate wins by default.
+    pub fn from_env() -> Result<Self> {
+        env::var("SHIPPING_RATE_POLICY").unwrap_or_default().parse()
+    }
+
+    /// Choose a rate.
+    pub fn choose<'a>(&self, rates: &'a [ShippingRate]) -> Option<&'a ShippingRate> {
+        let cheapest = |rates: &mut dyn Iterator<Item = &'a ShippingRate>| {
+            rates.min_by(|a, b| a.amount.partial_cmp(&b.amount).unwrap_or(std::cmp::Ordering::Equal))
+        };
+
+        match self {
+            RatePolicy::Cheapest => cheapest(&mut rates.iter()),
+            RatePolicy::Fastest => rates.iter().min_by(|a, b| {
+                // Rates without an estimate go last.
+                let a_days = a.estimated_days.unwrap_or(i64::MAX);
+                let b_days = b.estimated_days.unwrap_or(i64::MAX);
+                a_days
+                    .cmp(&b_days)
+                    .then(a.amount.partial_cmp(&b.amount).unwrap_or(std::cmp::Ordering::Equal))
+            }),
+            RatePolicy::PreferredCarrier(carrier) => {
+                cheapest(&mut rates.iter().filter(|r| clean_carrier_name(&r.carrier) == *carrier))
+                    .or_else(|| cheapest(&mut rates.iter()))
+            }
+        }
+    }
+}
+
+/// Get a provider by the name stored on a shipment.
+pub fn get_shipping_provider(name: &str) -> Result<Box<dyn ShippingProvider>> {
+    match name {
+        SHIPPO => Ok(Box::new(ShippoProvider::new_from_env())),
+        EASYPOST => Ok(Box::new(EasyPostProvider::new_from_env()?)),
+        SHIPBOB => bail!("shipbob ships its own orders, we have no labels from it"),
+        _ => bail!("unknown shipping provider `{}`", name),
+    }
+}
+
+/// Get the providers we can buy labels from.
+pub fn get_label_providers() -> Vec<Box<dyn ShippingProvider>> {
+    let mut providers: Vec<Box<dyn ShippingProvider>> = vec![Box::new(ShippoProvider::new_from_env())];
+    if let Ok(easypost) = EasyPostProvider::new_from_env() {
+        providers.push(Box::new(easypost));
+    }
+
+    providers
+}
+
+/// Get the rates for a shipment from all the providers. A provider that fails is
+/// skipped, so one being down does not stop us from shipping. Only rates in USD
+/// are returned, so the amounts can be compared.
+pub async fn shop_rates(providers: &[Box<dyn ShippingProvider>], request: &ShipmentRequest) -> Vec<ShippingRate> {
+    let mut rates: Vec<ShippingRate> = Default::default();
+    for provider in providers {
+        match provider.create_shipment(request).await {
+            Ok(shipment) => rates.extend(shipment.rates),
+            Err(e) => warn!("getting rates from {} failed: {}", provider.name(), e),
+        }
+    }
+
+    rates
+        .into_iter()
+        .filter(|r| {
+            if r.currency.eq_ignore_ascii_case("USD") {
+                return true;
+            }
+
+            warn!(
+                "skipping {} {} {} rate in `{}`, we can only compare rates in USD",
+                r.provider, r.carrier, r.service, r.currency
+            );
+            false
+        })
+        .collect()
+}
+
+/// Normalize the status of a tracker to the ones Shippo uses.
+fn normalize_tracking_status(s: &str) -> String {
+    match s.to_lowercase().as_str() {
+        "pre_transit" => "PRE_TRANSIT",
+        "transit" | "in_transit" | "out_for_delivery" | "available_for_pickup" => "TRANSIT",
+        "delivered" => "DELIVERED",
+        "returned" | "return_to_sender" => "RETURNED",
+        "failure" | "error" | "cancelled" => "FAILURE",
+        _ => "UNKNOWN",
+    }
+    .to_string()
+}
+
+/// Buy labels from Shippo.
+pub struct ShippoProvider {
+    client: Shippo,
+}
+
+impl ShippoProvider {
+    pub fn new_from_env() -> Self {
+        ShippoProvider {
+            client: Shippo::new_from_env(),
+        }
+    }
+
+    /// Make a request to the Shippo API for the things the client does not cover.
+    async fn request(&self, method: reqwest::Method, path: &str, body: Option<Value>) -> Result<Value> {
+        let token = env::var("SHIPPO_API_TOKEN").map_err(|e| anyhow!("expected SHIPPO_API_TOKEN to be set: {}", e))?;
+        let mut req = reqwest::Client::new()
+            .request(method, &format!("{}{}", SHIPPO_API_URL, path))
+            .header("Authorization", format!("ShippoToken {}", token));
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+
+        let resp = req.send().await?;
+        match resp.status() {
+            StatusCode::OK | StatusCode::CREATED => Ok(resp.json().await?),
+            s => bail!("shippo {} status_code: {}, body: {}", path, s, resp.text().await?),
+        }
+    }
+
+    /// Get a rate, in the currency of our account rather than the local currency of
+    /// the shipment, so we can compare it with the rates of other providers.
+    fn rate_from_json(shipment_id: &str, rate: &Value) -> ShippingRate {
+        ShippingRate {
+            provider: SHIPPO.to_string(),
+            shipment_id: shipment_id.to_string(),
+            id: rate["object_id"].as_str().unwrap_or_default().to_string(),
+            carrier: clean_carrier_name(rate["provider"].as_str().unwrap_or_default()),
+            service: rate["servicelevel"]["name"].as_str().unwrap_or_default().to_string(),
+            amount: rate["amount"].as_str().unwrap_or_default().parse().unwrap_or_default(),
+            currency: rate["currency"].as_str().unwrap_or_default().to_string(),
+            estimated_days: rate["estimated_days"].as_i64(),
+        }
+    }
+
+    fn rates_from_json(shipment: &Value) -> Vec<ShippingRate> {
+        let id = shipment["object_id"].as_str().unwrap_or_default();
+        shipment["rates"]
+            .as_array()
+            .map(|rates| rates.iter().map(|r| ShippoProvider::rate_from_json(id, r)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Map a carrier to the token Shippo uses for it.
+    fn carrier_token(carrier: &str) -> String {
+        let carrier = carrier.to_lowercase();
+        if carrier == "dhl" {
+            return "dhl_express".to_string();
+        }
+
+        carrier
+    }
+}
+
+#[async_trait]
+impl ShippingProvider for ShippoProvider {
+    fn name(&self) -> &'static str {
+        SHIPPO
+    }
+
+    async fn create_shipment(&self, request: &ShipmentRequest) -> Result<ProviderShipment> {
+        // If this is an international shipment, we need to define our customs
+        // declarations. We create them with the shipment, so we don't leave customs
+        // items behind for the shipments we don't buy a label for.
+        let customs = request.customs.as_ref().map(|customs| {
+            json!({
+                "certify": true,
+                "certify_signer": customs.signer,
+                "non_delivery_option": "RETURN",
+                "contents_type": "GIFT",
+                // This can only have a max of 200 chars.
+                // Weird I know.
+                "contents_explanation": crate::utils::truncate(&customs.contents_explanation, 200),
+                // TODO: I think this needs to change for Canada.
+                "eel_pfc": "NOEEI_30_37_a",
+                "items": customs.items.iter().map(|i| json!({
+                    "description": i.description,
+                    "quantity": i.quantity,
+                    "net_weight": format!("{:.2}", i.weight),
+                    "mass_unit": "lb",
+                    "value_amount": format!("{:.2}", i.value),
+                    "value_currency": "USD",
+                    "origin_country": i.origin_country,
+                    "tariff_number": i.hs_code,
+                })).collect::<Vec<Value>>(),
+            })
+        });
+
+        let shipment = self
+            .request(
+                reqwest::Method::POST,
+                "/shipments/",
+                Some(json!({
+                    "address_from": request.from.to_json(),
+                    "address_to": request.to.to_json(),
+                    "parcels": request.parcels.iter().map(|p| json!({
+                        "length": p.length.to_string(),
+                        "width": p.width.to_string(),
+                        "height": p.height.to_string(),
+                        "distance_unit": "in",
+                        "weight": p.weight.to_string(),
+                        "mass_unit": "lb",
+                    })).collect::<Vec<Value>>(),
+                    "customs_declaration": customs,
+                    // Wait for the rates, so we get them with the shipment.
+                    "async": false,
+                })),
+            )
+            .await?;
+
+        Ok(ProviderShipment {
+            id: shipment["object_id"].as_str().unwrap_or_default().to_string(),
+            rates: ShippoProvider::rates_from_json(&shipment),
+        })
+    }
+
+    async fn list_rates(&self, shipment_id: &str) -> Result<Vec<ShippingRate>> {
+        let shipment = self
+            .request(reqwest::Method::GET, &format!("/shipments/{}", shipment_id), None)
+            .await?;
+
+        Ok(ShippoProvider::rates_from_json(&shipment))
+    }
+
+    async fn buy_label(&self, rate: &ShippingRate) -> Result<ShippingLabel> {
+        let label = self
+            .client
+            .create_shipping_label_from_rate(NewTransaction {
+                rate: rate.id.to_string(),
+                r#async: false,
+                label_file_type: "".to_string(),
+                metadata: "".to_string(),
+            })
+            .await?;
+
+        let mut messages = "".to_string();
+        if label.status != "SUCCESS" {
+            for m in label.messages {
+                messages = format!("{}\n{} {} {}", messages, m.code, m.source, m.text);
+            }
+        }
+
+        Ok(ShippingLabel {
+            id: label.object_id,
+            carrier: rate.carrier.to_string(),
+            tracking_number: label.tracking_number,
+            parcel_tracking_numbers: Default::default(),
+            tracking_link: label.tracking_url_provider,
+            tracking_status: label.tracking_status,
+            label_link: label.label_url,
+            cost: rate.amount,
+            eta: label.eta,
+            messages: messages.trim().to_string(),
+        })
+    }
+
+    async fn get_label(&self, label_id: &str) -> Result<ShippingLabel> {
+        let label = self.client.get_shipping_label(label_id).await?;
+
+        // The rate will give us the carrier and the cost.
+        let rate = ShippoProvider::rate_from_json(
+            "",
+            &self
+                .request(reqwest::Method::GET, &format!("/rates/{}", label.rate), None)
+                .await?,
+        );
+
+        let mut messages = "".to_string();
+        if label.status != "SUCCESS" {
+            for m in label.messages {
+                messages = format!("{}\n{} {} {}", messages, m.code, m.source, m.text);
+            }
+        }
+
+        Ok(ShippingLabel {
+            id: label.object_id,
+            carrier: rate.carrier,
+            tracking_number: label.tracking_number,
+            parcel_tracking_numbers: Default::default(),
+            tracking_link: label.tracking_url_provider,
+            tracking_status: label.tracking_status,
+            label_link: label.label_url,
+            cost: rate.amount,
+            eta: label.eta,
+            messages: messages.trim().to_string(),
+        })
+    }
+
+    async fn void_label(&self, label_id: &str) -> Result<RefundStatus> {
+        let refund = self
+            .request(
+                reqwest::Method::POST,
+                "/refunds/",
+                Some(json!({
+                    "transaction": label_id,
+                    "async": false,
+                })),
+            )
+            .await?;
+
+        RefundStatus::from_shippo(label_id, &refund)
+    }
+
+    async fn track(&self, carrier: &str, tracking_number: &str) -> Result<TrackingInfo> {
+        // Registering the webhook also gives us the current status.
+        let ts = self
+            .client
+            .register_tracking_webhook(&ShippoProvider::carrier_token(carrier), tracking_number)
+            .await?;
+
+        Ok(ts.into())
+    }
+
+    async fn schedule_pickup(&self, request: &PickupRequest) -> Result<Pickup> {
+        // Get the carrier account for the carrier.
+        let carrier_accounts = self.client.list_carrier_accounts().await?;
+        let carrier_account_id = match carrier_accounts
+            .into_iter()
+            .find(|ca| ca.carrier.to_lowercase() == request.carrier.to_lowercase())
+        {
+            // Shippo docs say this is the object ID.
+            Some(ca) => ca.object_id,
+            None => bail!("no shippo carrier account for `{}`", request.carrier),
+        };
+
+        let pickup = self
+            .client
+            .create_pickup(&shippo::NewPickup {
+                carrier_account: carrier_account_id,
+                location: shippo::Location {
+                    building_location_type: "Office".to_string(),
+                    building_type: "building".to_string(),
+                    instructions: request.instructions.to_string(),
+                    address: (&request.address).into(),
+                },
+                transactions: request.label_ids.clone(),
+                requested_start_time: request.start_time,
+                requested_end_time: request.end_time,
+                metadata: "".to_string(),
+                is_test: false,
+            })
+            .await?;
+
+        let mut messages = "".to_string();
+        if let Some(msg) = pickup.messages {
+            for m in msg {
+                messages = format!("{}\n{} {} {}", messages, m.code, m.source, m.text);
+            }
+        }
+
+        Ok(Pickup {
+            id: pickup.object_id,
+            confirmation_code: pickup.confirmation_code,
+            status: pickup.status,
+            confirmed_start_time: pickup.confirmed_start_time,
+            confirmed_end_time: pickup.confirmed_end_time,
+            cancel_by_time: pickup.cancel_by_time,
+            messages: messages.trim().to_string(),
+        })
+    }
+}
+
+/// Buy labels from EasyPost. A shipment with more than one parcel is an EasyPost
+/// order, with a shipment for each parcel.
+pub struct EasyPostProvider {
+    api_key: String,
+}
+
+impl EasyPostProvider {
+    /// Create the provider from the `EASYPOST_API_KEY`.
+    pub fn new_from_env() -> Result<Self> {
+        let api_key =
+            env::var("EASYPOST_API_KEY").map_err(|e| anyhow!("expected EASYPOST_API_KEY to be set: {}", e))?;
+        if api_key.is_empty() {
+            bail!("EASYPOST_API_KEY is empty");
+        }
+
+        Ok(EasyPostProvider { api_key })
+    }
+
+    async fn request(&self, method: reqwest::Method, path: &str, body: Option<Value>) -> Result<Value> {
+        let mut req = reqwest::Client::new()
+            .request(method, &format!("{}{}", EASYPOST_API_URL, path))
+            .basic_auth(&self.api_key, Some(""));
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+
+        let resp = req.send().await?;
+        match resp.status() {
+            StatusCode::OK | StatusCode::CREATED => Ok(resp.json().await?),
+            s => bail!("easypost {} status_code: {}, body: {}", path, s, resp.text().await?),
+        }
+    }
+
+    fn is_order(id: &str) -> bool {
+        id.starts_with("order_")
+    }
+
+    fn parse_date(v: &Value) -> Option<DateTime<Utc>> {
+        v.as_str()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|d| d.with_timezone(&Utc))
+    }
+
+    fn rate_from_json(shipment_id: &str, rate: &Value) -> ShippingRate {
+        ShippingRate {
+            provider: EASYPOST.to_string(),
+            shipment_id: shipment_id.to_string(),
+            id: rate["id"].as_str().unwrap_or_default().to_string(),
+            carrier: clean_carrier_name(rate["carrier"].as_str().unwrap_or_default()),
+            service: rate["service"].as_str().unwrap_or_default().to_string(),
+            amount: rate["rate"].as_str().unwrap_or_default().parse().unwrap_or_default(),
+            currency: rate["currency"].as_str().unwrap_or_default().to_string(),
+            estimated_days: rate["delivery_days"].as_i64(),
+        }
+    }
+
+    fn rates_from_json(shipment: &Value) -> Vec<ShippingRate> {
+        let id = shipment["id"].as_str().unwrap_or_default();
+        shipment["rates"]
+            .as_array()
+            .map(|rates| rates.iter().map(|r| EasyPostProvider::rate_from_json(id, r)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the label of a shipment, or of all the shipments in an order.
+    fn label_from_json(v: &Value) -> ShippingLabel {
+        let shipments: Vec<&Value> = match v["shipments"].as_array() {
+            Some(shipments) => shipments.iter().collect(),
+            None => vec![v],
+        };
+
+        let mut label = ShippingLabel {
+            id: v["id"].as_str().unwrap_or_default().to_string(),
+            ..Default::default()
+        };
+        let mut tracking_numbers: Vec<String> = Default::default();
+        let mut label_links: Vec<String> = Default::default();
+        for shipment in shipments {
+            let rate = &shipment["selected_rate"];
+            label.carrier = clean_carrier_name(rate["carrier"].as_str().unwrap_or_default());
+            label.cost += rate["rate"]
+                .as_str()
+                .unwrap_or_default()
+                .parse::<f32>()
+                .unwrap_or_default();
+            tracking_numbers.push(shipment["tracking_code"].as_str().unwrap_or_default().to_string());
+            label_links.push(
+                shipment["postage_label"]["label_url"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+
+            let tracker = &shipment["tracker"];
+            if label.tracking_link.is_empty() {
+                label.tracking_link = tracker["public_url"].as_str().unwrap_or_default().to_string();
+                label.tracking_status = normalize_tracking_status(tracker["status"].as_str().unwrap_or_default());
+                label.eta = EasyPostProvider::parse_date(&tracker["est_delivery_date"]);
+            }
+        }
+        // Tracking and the webhooks use a single tracking number, the one of the first
+        // box, the shipment keeps the others with its parcels.
+        label.tracking_number = tracking_numbers.first().cloned().unwrap_or_default();
+        if tracking_numbers.len() > 1 {
+            label.parcel_tracking_numbers = tracking_numbers;
+        }
+        label.label_link = label_links.join(",");
+
+        label
+    }
+}
+
+#[async_trait]
+impl ShippingProvider for EasyPostProvider {
+    fn name(&self) -> &'static str {
+        EASYPOST
+    }
+
+    async fn create_shipment(&self, request: &ShipmentRequest) -> Result<ProviderShipment> {
+        let customs = request.customs.as_ref().map(|customs| {
+            json!({
+                "customs_certify": true,
+                "customs_signer": customs.signer,
+                "contents_type": "gift",
+                "contents_explanation": customs.contents_explanation,
+                "eel_pfc": "NOEEI 30.37(a)",
+                "non_delivery_option": "return",
+                "restriction_type": "none",
+                "customs_items": customs.items.iter().map(|i| json!({
+                    "description": i.description,
+                    "quantity": i.quantity,
+                    "value": i.value,
+                    // EasyPost weights are in ounces.
+                    "weight": i.weight * 16.0,
+                    "origin_country": i.origin_country,
+                    "hs_tariff_number": i.hs_code,
+                })).collect::<Vec<Value>>(),
+            })
+        });
+        let parcels: Vec<Value> = request
+            .parcels
+            .iter()
+            .map(|p| {
+                json!({
+                    "length": p.length,
+                    "width": p.width,
+                    "height": p.height,
+                    "weight": p.weight * 16.0,
+                })
+            })
+            .collect();
+
+        let shipment = if parcels.len() > 1 {
+            self.request(
+                reqwest::Method::POST,
+                "/orders",
+                Some(json!({
+                    "order": {
+                        "from_address": request.from.to_json(),
+                        "to_address": request.to.to_json(),
+                        "customs_info": customs,
+                        "shipments": parcels.into_iter().map(|p| json!({ "parcel": p })).collect::<Vec<Value>>(),
+                    }
+                })),
+            )
+            .await?
+        } else {
+            self.request(
+                reqwest::Method::POST,
+                "/shipments",
+                Some(json!({
+                    "shipment": {
+                        "from_address": request.from.to_json(),
+                        "to_address": request.to.to_json(),
+                        "customs_info": customs,
+                        "parcel": parcels.into_iter().next().unwrap_or_else(|| json!({})),
+                    }
+                })),
+            )
+            .await?
+        };
+
+        Ok(ProviderShipment {
+            id: shipment["id"].as_str().unwrap_or_default().to_string(),
+            rates: EasyPostProvider::rates_from_json(&shipment),
+        })
+    }
+
+    async fn list_rates(&self, shipment_id: &str) -> Result<Vec<ShippingRate>> {
+        if EasyPostProvider::is_order(shipment_id) {
+            let order = self
+                .request(reqwest::Method::GET, &format!("/orders/{}", shipment_id), None)
+                .await?;
+            return Ok(EasyPostProvider::rates_from_json(&order));
+        }
+
+        let rates = self
+            .request(reqwest::Method::GET, &format!("/shipments/{}/rates", shipment_id), None)
+            .await?;
+        Ok(rates["rates"]
+            .as_array()
+            .map(|rates| {
+                rates
+                    .iter()
+                    .map(|r| EasyPostProvider::rate_from_json(shipment_id, r))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn buy_label(&self, rate: &ShippingRate) -> Result<ShippingLabel> {
+        let shipment = if EasyPostProvider::is_order(&rate.shipment_id) {
+            self.request(
+                reqwest::Method::POST,
+                &format!("/orders/{}/buy", rate.shipment_id),
+                Some(json!({
+                    "carrier": rate.carrier,
+                    "service": rate.service,
+                })),
+            )
+            .await?
+        } else {
+            self.request(
+                reqwest::Method::POST,
+                &format!("/shipments/{}/buy", rate.shipment_id),
+                Some(json!({
+                    "rate": { "id": rate.id },
+                })),
+            )
+            .await?
+        };
+
+        Ok(EasyPostProvider::label_from_json(&shipment))
+    }
+
+    async fn get_label(&self, label_id: &str) -> Result<ShippingLabel> {
+        let path = if EasyPostProvider::is_order(label_id) {
+            format!("/orders/{}", label_id)
+        } else {
+            format!("/shipments/{}", label_id)
+        };
+        let shipment = self.request(reqwest::Method::GET, &path, None).await?;
+
+        Ok(EasyPostProvider::label_from_json(&shipment))
+    }
+
+    async fn void_label(&self, label_id: &str) -> Result<RefundStatus> {
+        let mut shipment_ids: Vec<String> = vec![label_id.to_string()];
+        if EasyPostProvider::is_order(label_id) {
+            let order = self
+                .request(reqwest::Method::GET, &format!("/orders/{}", label_id), None)
+                .await?;
+            shipment_ids = order["shipments"]
+                .as_array()
+                .map(|s| {
+                    s.iter()
+                        .map(|s| s["id"].as_str().unwrap_or_default().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+        }
+
+        // An order is only refunded once the label of every box is.
+        let mut status = RefundStatus::Refunded;
+        for id in shipment_ids {
+            let shipment = self
+                .request(reqwest::Method::POST, &format!("/shipments/{}/refund", id), None)
+                .await?;
+            if RefundStatus::from_easypost(&id, &shipment)? == RefundStatus::Pending {
+                status = RefundStatus::Pending;
+            }
+        }
+
+        Ok(status)
+    }
+
+    async fn track(&self, carrier: &str, tracking_number: &str) -> Result<TrackingInfo> {
+        // Creating a tracker also sends us its updates through our webhook.
+        let tracker = self
+            .request(
+                reqwest::Method::POST,
+                "/trackers",
+                Some(json!({
+                    "tracker": {
+                        "tracking_code": tracking_number,
+                        "carrier": carrier,
+                    }
+                })),
+            )
+            .await?;
+
+        Ok(TrackingInfo::from_easypost(&tracker))
+    }
+
+    async fn schedule_pickup(&self, request: &PickupRequest) -> Result<Pickup> {
+        // A pickup is for a shipment or a batch of them.
+        let mut shipment_ids: Vec<String> = Default::default();
+        for id in &request.label_ids {
+            if EasyPostProvider::is_order(id) {
+                let order = self
+                    .request(reqwest::Method::GET, &format!("/orders/{}", id), None)
+                    .await?;
+                if let Some(shipments) = order["shipments"].as_array() {
+                    shipment_ids.extend(
+                        shipments
+                            .iter()
+                            .map(|s| s["id"].as_str().unwrap_or_default().to_string()),
+                    );
+                }
+            } else {
+                shipment_ids.push(id.to_string());
+            }
+        }
+
+        let mut pickup = json!({
+            "address": request.address.to_json(),
+            "min_datetime": request.start_time.to_rfc3339(),
+            "max_datetime": request.end_time.to_rfc3339(),
+            "instructions": request.instructions,
+            "is_account_address": false,
+        });
+        if shipment_ids.len() == 1 {
+            pickup["shipment"] = json!({ "id": shipment_ids[0] });
+        } else {
+            let batch = self
+                .request(
+                    reqwest::Method::POST,
+                    "/batches",
+                    Some(json!({
+                        "batch": {
+                            "shipments": shipment_ids.iter().map(|id| json!({ "id": id })).collect::<Vec<Value>>(),
+                        }
+                    })),
+                )
+                .await?;
+            pickup["batch"] = json!({ "id": batch["id"] });
+        }
+
+        let pickup = self
+            .request(reqwest::Method::POST, "/pickups", Some(json!({ "pickup": pickup })))
+            .await?;
+        let id = pickup["id"].as_str().unwrap_or_default().to_string();
+
+        // Buy the pickup from the carrier.
+        let service = pickup["pickup_rates"]
+            .as_array()
+            .and_then(|rates| {
+                rates
+                    .iter()
+                    .find(|r| clean_carrier_name(r["carrier"].as_str().unwrap_or_default()) == request.carrier)
+            })
+            .map(|r| r["service"].as_str().unwrap_or_default().to_string());
+        let service = match service {
+            Some(service) => service,
+            None => bail!("easypost has no pickup rate for `{}`", request.carrier),
+        };
+        let pickup = self
+            .request(
+                reqwest::Method::POST,
+                &format!("/pickups/{}/buy", id),
+                Some(json!({
+                    "carrier": request.carrier,
+                    "service": service,
+                })),
+            )
+            .await?;
+
+        Ok(Pickup {
+            id,
+            confirmation_code: pickup["confirmation"].as_str().unwrap_or_default().to_string(),
+            status: pickup["status"].as_str().unwrap_or_default().to_string(),
+            confirmed_start_time: EasyPostProvider::parse_date(&pickup["min_datetime"]),
+            confirmed_end_time: EasyPostProvider::parse_date(&pickup["max_datetime"]),
+            cancel_by_time: None,
+            messages: pickup["messages"]
+                .as_array()
+                .map(|m| {
+                    m.iter()
+                        .map(|m| m["message"].as_str().unwrap_or_default().to_string())
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                })
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// ShipBob fulfills and ships its own orders, we get those through
+/// `refresh_outbound_shipments`, so there are no labels to buy and it is not a
+/// `ShippingProvider`. We only get its tracking updates.
+pub struct ShipBobProvider;
+
+impl ShipBobProvider {
+    /// Get the carrier and tracking info for the shipments in a ShipBob webhook. The
+    /// webhooks only tell us about the event they are for, so the history has just
+    /// that event in it.
+    pub fn tracking_from_webhook(topic: &str, event: &Value) -> Vec<(String, TrackingInfo)> {
+        let status = match topic {
+            "order.shipped" => "TRANSIT",
+            "order.shipment.delivered" => "DELIVERED",
+            "order.shipment.exception" | "order.shipment.cancelled" => "FAILURE",
+            "order.shipment.on_hold" => "PRE_TRANSIT",
+            _ => return Default::default(),
+        };
+
+        // Order events have the order's shipments, shipment events are the shipment.
+        let shipments = match event["shipments"].as_array() {
+            Some(shipments) => shipments.iter().collect::<Vec<&Value>>(),
+            None => vec![event],
+        };
+
+        let mut tracking: Vec<(String, TrackingInfo)> = Default::default();
+        for shipment in shipments {
+            let tracking_number = shipment["tracking"]["tracking_number"].as_str().unwrap_or_default();
+            let carrier = shipment["tracking"]["carrier"].as_str().unwrap_or_default();
+            if tracking_number.is_empty() || carrier.is_empty() {
+                continue;
+            }
+
+            let status_date = [
+                &shipment["delivery_date"],
+                &shipment["tracking"]["shipping_date"],
+                &shipment["actual_fulfillment_date"],
+            ]
+            .iter()
+            .find_map(|d| EasyPostProvider::parse_date(d))
+            .unwrap_or_else(Utc::now);
+            let status_details = shipment["status"].as_str().unwrap_or_default().to_string();
+            let location = shipment["location"]["name"].as_str().unwrap_or_default().to_string();
+
+            tracking.push((
+                carrier.to_string(),
+                TrackingInfo {
+                    tracking_number: tracking_number.to_string(),
+                    status: status.to_string(),
+                    status_details: status_details.to_string(),
+                    status_date: Some(status_date),
+                    eta: None,
+                    history: vec![TrackingEvent {
+                        status: status.to_string(),
+                        status_details,
+                        status_date: Some(status_date),
+                        location,
+                    }],
+                },
+            ));
+        }
+
+        tracking
+    }
+}
+
+/// A commercial invoice for an international shipment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommercialInvoice {
+    pub date: DateTime<Utc>,
+    pub from: ShippingAddress,
+    pub to: ShippingAddress,
+    pub carrier: String,
+    pub tracking_number: String,
+    pub customs: ShippingCustoms,
+}
+
+impl CommercialInvoice {
+    /// The lines of text on the invoice.
+    pub fn lines(&self) -> Vec<String> {
+        let address = |a: &ShippingAddress| {
+            let mut lines = vec![a.name.to_string()];
+            if !a.company.is_empty() {
+                lines.push(a.company.to_string());
+            }
+            lines.push(a.street_1.to_string());
+            if !a.street_2.is_empty() {
+                lines.push(a.street_2.to_string());
+            }
+            lines.push(format!("{}, {} {} {}", a.city, a.state, a.zipcode, a.country));
+            if !a.phone.is_empty() {
+                lines.push(a.phone.to_string());
+            }
+            lines
+        };
+
+        let mut lines = vec![
+            "COMMERCIAL INVOICE".to_string(),
+            "".to_string(),
+            format!("Date: {}", self.date.format("%Y-%m-%d")),
+            format!("Carrier: {}", self.carrier),
+            format!("Tracking number: {}", self.tracking_number),
+            "".to_string(),
+            "Shipper:".to_string(),
+        ];
+        lines.extend(address(&self.from));
+        lines.push("".to_string());
+        lines.push("Consignee:".to_string());
+        lines.extend(address(&self.to));
+        lines.push("".to_string());
+        lines.push(format!(
+            "{:<5} {:<36} {:<12} {:<7} {:>8} {:>10}",
+            "Qty", "Description", "HS code", "Origin", "Weight", "Value"
+        ));
+
+        let mut weight = 0.0;
+        let mut value = 0.0;
+        for item in &self.customs.items {
+            lines.push(format!(
+                "{:<5} {:<36} {:<12} {:<7} {:>5.2} lb {:>10.2}",
+                item.quantity,
+                crate::utils::truncate(&item.description, 36),
+                item.hs_code,
+                item.origin_country,
+                item.weight,
+                item.value
+            ));
+            weight += item.weight;
+            value += item.value;
+        }
+        lines.push(format!("{:<63} {:>5.2} lb {:>10.2}", "Total", weight, value));
+        lines.push("".to_string());
+        lines.push("Currency: USD".to_string());
+        lines.push("Reason for export: Gift".to_string());
+        lines.push("".to_string());
+        lines.push("I declare that the information on this invoice is true and correct.".to_string());
+        lines.push(format!("Signed: {}", self.customs.signer));
+
+        lines
+    }
+
+    /// Generate the bytes of the invoice as a letter size PDF.
+    pub fn generate_pdf(&self) -> Result<Vec<u8>> {
+        let pdf_margin = Mm(15.0);
+        let pdf_width = Mm(8.5 * 25.4);
+        let pdf_height = Mm(11.0 * 25.4);
+        let (doc, page1, layer1) = PdfDocument::new("Commercial Invoice", pdf_width, pdf_height, "Layer 1");
+        let current_layer = doc.get_page(page1).get_layer(layer1);
+
+        let font_bytes = include_bytes!("Inconsolata/Inconsolata-Regular.ttf").to_vec();
+        let font = doc.add_external_font(&*font_bytes)?;
+
+        let line_height = 12.0;
+        current_layer.begin_text_section();
+        current_layer.set_font(&font, line_height - 3.0);
+        current_layer.set_text_cursor(pdf_margin, pdf_height - pdf_margin);
+        current_layer.set_line_height(line_height);
+        for line in self.lines() {
+            current_layer.write_text(line, &font);
+            current_layer.add_line_break();
+        }
+        current_layer.end_text_section();
+
+        // Save the PDF
+        let mut bw = BufWriter::new(Vec::new());
+
+        doc.save(&mut bw)?;
+
+        Ok(bw.into_inner()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{bail, Result};
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    use super::{
+        shop_rates, EasyPostProvider, Pickup, PickupRequest, ProviderShipment, RatePolicy, RefundStatus,
+        ShipBobProvider, ShipmentRequest, ShippingLabel, ShippingParcel, ShippingProvider, ShippingRate,
+        ShippoProvider, TrackingInfo,
+    };
+
+    /// A provider that only quotes the rates it was given.
+    struct RatesProvider(Vec<ShippingRate>);
+
+    #[async_trait]
+    impl ShippingProvider for RatesProvider {
+        fn name(&self) -> &'static str {
+            "Rates"
+        }
+
+        async fn create_shipment(&self, _request: &ShipmentRequest) -> Result<ProviderShipment> {
+            if self.0.is_empty() {
+                bail!("no rates");
+            }
+
+            Ok(ProviderShipment {
+                id: "shipment".to_string(),
+                rates: self.0.clone(),
+            })
+        }
+
+        async fn list_rates(&self, _shipment_id: &str) -> Result<Vec<ShippingRate>> {
+            Ok(self.0.clone())
+        }
+
+        async fn buy_label(&self, _rate: &ShippingRate) -> Result<ShippingLabel> {
+            bail!("not implemented")
+        }
+
+        async fn get_label(&self, _label_id: &str) -> Result<ShippingLabel> {
+            bail!("not implemented")
+        }
+
+        async fn void_label(&self, _label_id: &str) -> Result<RefundStatus> {
+            bail!("not implemented")
+        }
+
+        async fn track(&self, _carrier: &str, _tracking_number: &str) -> Result<TrackingInfo> {
+            bail!("not implemented")
+        }
+
+        async fn schedule_pickup(&self, _request: &PickupRequest) -> Result<Pickup> {
+            bail!("not implemented")
+        }
+    }
+
+    fn rate(carrier: &str, amount: f32, estimated_days: Option<i64>) -> ShippingRate {
+        ShippingRate {
+            carrier: carrier.to_string(),
+            amount,
+            estimated_days,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rate_policy() {
+        let rates = vec![
+            rate("USPS", 8.5, Some(3)),
+            rate("UPS", 12.0, Some(1)),
+            rate("FedEx", 7.25, None),
+            rate("UPS", 9.0, Some(2)),
+        ];
+
+        assert_eq!("".parse::<RatePolicy>().unwrap(), RatePolicy::Cheapest);
+        assert_eq!(RatePolicy::Cheapest.choose(&rates).unwrap().carrier, "FedEx");
+        assert_eq!(RatePolicy::Fastest.choose(&rates).unwrap().amount, 12.0);
+
+        let ups: RatePolicy = "carrier:ups".parse().unwrap();
+        assert_eq!(ups, RatePolicy::PreferredCarrier("UPS".to_string()));
+        assert_eq!(ups.choose(&rates).unwrap().amount, 9.0);
+
+        // Fall back to the cheapest rate when the carrier has none.
+        let dhl: RatePolicy = "carrier:dhl".parse().unwrap();
+        assert_eq!(dhl.choose(&rates).unwrap().carrier, "FedEx");
+
+        assert!(RatePolicy::Cheapest.choose(&[]).is_none());
+        assert!("slowest".parse::<RatePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_parse_parcel() {
+        let parcel: ShippingParcel = "12x12x6 in, 2 lb".parse().unwrap();
+        assert_eq!(parcel, ShippingParcel::default());
+        assert_eq!(parcel.to_string(), "12x12x6 in, 2 lb");
+
+        let parcel: ShippingParcel = "20 x 14.5 x 8, 10.25lbs".parse().unwrap();
+        assert_eq!(parcel.width, 14.5);
+        assert_eq!(parcel.weight, 10.25);
+
+        let parcel: ShippingParcel = "12x12x6 in, 2 lb #1Z999AA10123456784".parse().unwrap();
+        assert_eq!(parcel.tracking_number, "1Z999AA10123456784");
+        assert_eq!(parcel.to_string(), "12x12x6 in, 2 lb #1Z999AA10123456784");
+
+        assert!("12x12 in, 2 lb".parse::<ShippingParcel>().is_err());
+        assert!("12x12x6 in, 0 lb".parse::<ShippingParcel>().is_err());
+    }
+
+    #[test]
+    fn test_shipbob_tracking_from_webhook() {
+        let order = json!({
+            "id": 1,
+            "shipments": [
+                {
+                    "status": "Completed",
+                    "actual_fulfillment_date": "2022-04-20T17:30:00+00:00",
+                    "location": { "name": "Cicero (IL)" },
+                    "tracking": { "carrier": "USPS", "tracking_number": "9400111" },
+                },
+                { "status": "Processing", "tracking": null },
+            ],
+        });
+
+        let tracking = ShipBobProvider::tracking_from_webhook("order.shipped", &order);
+        assert_eq!(tracking.len(), 1);
+        let (carrier, info) = &tracking[0];
+        assert_eq!(carrier, "USPS");
+        assert_eq!(info.tracking_number, "9400111");
+        assert_eq!(info.status, "TRANSIT");
+        assert_eq!(info.history[0].location, "Cicero (IL)");
+        assert_eq!(
+            info.history[0].status_date.unwrap().to_rfc3339(),
+            "2022-04-20T17:30:00+00:00"
+        );
+
+        assert!(ShipBobProvider::tracking_from_webhook("order.created", &order).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shop_rates() {
+        let usd = ShippingRate {
+            currency: "USD".to_string(),
+            ..rate("USPS", 8.5, Some(3))
+        };
+        let cad = ShippingRate {
+            currency: "CAD".to_string(),
+            ..rate("Canada Post", 4.0, Some(3))
+        };
+        let providers: Vec<Box<dyn ShippingProvider>> = vec![
+            Box::new(RatesProvider(vec![usd.clone(), cad])),
+            // A provider that fails does not stop us from getting the other rates.
+            Box::new(RatesProvider(vec![])),
+        ];
+
+        // We can't compare the rate in CAD, so it is left out.
+        assert_eq!(shop_rates(&providers, &Default::default()).await, vec![usd]);
+    }
+
+    #[test]
+    fn test_refund_status() {
+        assert_eq!(
+            RefundStatus::from_shippo("label", &json!({ "status": "SUCCESS" })).unwrap(),
+            RefundStatus::Refunded
+        );
+        // A refund that is not done yet is not counted as refunded.
+        for status in &["QUEUED", "PENDING"] {
+            assert_eq!(
+                RefundStatus::from_shippo("label", &json!({ "status": status })).unwrap(),
+                RefundStatus::Pending
+            );
+        }
+        assert!(RefundStatus::from_shippo("label", &json!({ "status": "ERROR" })).is_err());
+        assert!(RefundStatus::from_shippo("label", &json!({})).is_err());
+
+        assert_eq!(
+            RefundStatus::from_easypost("shp", &json!({ "refund_status": "refunded" })).unwrap(),
+            RefundStatus::Refunded
+        );
+        assert_eq!(
+            RefundStatus::from_easypost("shp", &json!({ "refund_status": "submitted" })).unwrap(),
+            RefundStatus::Pending
+        );
+        assert!(RefundStatus::from_easypost("shp", &json!({ "refund_status": "rejected" })).is_err());
+        assert!(RefundStatus::from_easypost("shp", &json!({ "refund_status": "not_applicable" })).is_err());
+    }
+
+    #[test]
+    fn test_shippo_rates_from_json() {
+        let shipment = json!({
+            "object_id": "shp_1",
+            "rates": [
+                {
+                    "object_id": "rate_1",
+                    "provider": "USPS",
+                    "servicelevel": { "name": "Priority Mail" },
+                    "amount": "7.60",
+                    "currency": "USD",
+                    "amount_local": "10.21",
+                    "currency_local": "CAD",
+                    "estimated_days": 2,
+                },
+            ],
+        });
+
+        let rates = ShippoProvider::rates_from_json(&shipment);
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates[0].provider, "Shippo");
+        assert_eq!(rates[0].shipment_id, "shp_1");
+        assert_eq!(rates[0].id, "rate_1");
+        assert_eq!(rates[0].carrier, "USPS");
+        assert_eq!(rates[0].service, "Priority Mail");
+        // We use the amount in the currency of our account, not the local one.
+        assert_eq!(rates[0].amount, 7.6);
+        assert_eq!(rates[0].currency, "USD");
+        assert_eq!(rates[0].estimated_days, Some(2));
+    }
+
+    #[test]
+    fn test_easypost_rates_from_json() {
+        let shipment = json!({
+            "id": "shp_1",
+            "rates": [
+                { "id": "rate_1", "carrier": "UPS", "service": "Ground", "rate": "12.30", "currency": "USD", "delivery_days": 4 },
+                { "id": "rate_2", "carrier": "USPS", "service": "Express", "rate": "30.00", "currency": "USD", "delivery_days": null },
+            ],
+        });
+
+        let rates = EasyPostProvider::rates_from_json(&shipment);
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[0].provider, "EasyPost");
+        assert_eq!(rates[0].shipment_id, "shp_1");
+        assert_eq!(rates[0].amount, 12.3);
+        assert_eq!(rates[0].estimated_days, Some(4));
+        assert_eq!(rates[1].id, "rate_2");
+        assert_eq!(rates[1].estimated_days, None);
+    }
+
+    #[test]
+    fn test_easypost_label_from_json() {
+        let shipment = |tracking_code: &str, rate: &str| {
+            json!({
+                "selected_rate": { "carrier": "UPS", "rate": rate },
+                "tracking_code": tracking_code,
+                "postage_label": { "label_url": format!("https://labels/{}.png", tracking_code) },
+                "tracker": {
+                    "public_url": format!("https://track/{}", tracking_code),
+                    "status": "pre_transit",
+                    "est_delivery_date": "2022-04-22T12:00:00Z",
+                },
+            })
+        };
+
+        let mut single = shipment("1Z1", "10.50");
+        single["id"] = json!("shp_1");
+        let label = EasyPostProvider::label_from_json(&single);
+        assert_eq!(label.id, "shp_1");
+        assert_eq!(label.carrier, "UPS");
+        assert_eq!(label.tracking_number, "1Z1");
+        assert!(label.parcel_tracking_numbers.is_empty());
+        assert_eq!(label.tracking_link, "https://track/1Z1");
+        assert_eq!(label.tracking_status, "PRE_TRANSIT");
+        assert_eq!(label.label_link, "https://labels/1Z1.png");
+        assert_eq!(label.cost, 10.5);
+        assert_eq!(label.eta.unwrap().to_rfc3339(), "2022-04-22T12:00:00+00:00");
+
+        // An order has a label for each box, and costs what they all do.
+        let order = json!({
+            "id": "order_1",
+            "shipments": [shipment("1Z1", "10.50"), shipment("1Z2", "9.25")],
+        });
+        let label = EasyPostProvider::label_from_json(&order);
+        assert_eq!(label.id, "order_1");
+        assert_eq!(label.tracking_number, "1Z1");
+        assert_eq!(
+            label.parcel_tracking_numbers,
+            vec!["1Z1".to_string(), "1Z2".to_string()]
+        );
+        assert_eq!(label.tracking_link, "https://track/1Z1");
+        assert_eq!(label.label_link, "https://labels/1Z1.png,https://labels/1Z2.png");
+        assert_eq!(label.cost, 19.75);
+    }
+}
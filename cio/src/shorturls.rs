@@ -1,17 +1,59 @@
 use anyhow::{bail, Result};
-use cloudflare::endpoints::dns;
-use serde::Serialize;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    airtable::AIRTABLE_MANAGED_DNS_RECORDS_TABLE,
     companies::{Company, Companys},
     configs::Links,
+    core::UpdateAirtableRecord,
     db::Database,
-    dns_providers::DNSProviderOps,
+    dns_providers::DnsContent,
     repos::GithubRepos,
     rfds::RFDs,
+    schema::managed_dns_records,
     templates::generate_nginx_files_for_shorturls,
 };
 
+/// A DNS record we created for a short URL. We only ever clean up records in this
+/// table, so records that were created by hand are left alone.
+#[db {
+    new_struct_name = "ManagedDnsRecord",
+    airtable_base = "misc",
+    airtable_table = "AIRTABLE_MANAGED_DNS_RECORDS_TABLE",
+    match_on = {
+        "cio_company_id" = "i32",
+        "name" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[diesel(table_name = managed_dns_records)]
+pub struct NewManagedDnsRecord {
+    /// The fully qualified domain name of the record.
+    pub name: String,
+    /// The IP address the record points to.
+    pub content: String,
+    /// The subdomain of the short URLs that created the record, for example `rfd`.
+    pub source: String,
+    pub last_ensured_at: DateTime<Utc>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// Implement updating the Airtable record for a ManagedDnsRecord.
+#[async_trait]
+impl UpdateAirtableRecord<ManagedDnsRecord> for ManagedDnsRecord {
+    async fn update_airtable_record(&mut self, _record: ManagedDnsRecord) -> Result<()> {
+        Ok(())
+    }
+}
+
 /// Generate the files for the GitHub repository short URLs.
 pub async fn generate_shorturls_for_repos(
     db: &Database,
@@ -20,6 +62,18 @@ pub async fn generate_shorturls_for_repos(
     repo: &str,
 ) -> Result<()> {
     let owner = &company.github_org;
+    let links = get_shorturls_for_repos(db, company).await?;
+
+    // Generate the files for the links.
+    generate_nginx_files_for_shorturls(github, owner, repo, links.clone()).await?;
+
+    create_dns_records_for_links(db, company, links).await?;
+
+    Ok(())
+}
+
+/// Get the short URLs for the GitHub repositories.
+pub async fn get_shorturls_for_repos(db: &Database, company: &Company) -> Result<Vec<ShortUrl>> {
     let subdomain = "git";
     // Initialize the array of links.
     let mut links: Vec<ShortUrl> = Default::default();
@@ -44,12 +98,7 @@ pub async fn generate_shorturls_for_repos(
         links.push(link.clone());
     }
 
-    // Generate the files for the links.
-    generate_nginx_files_for_shorturls(github, owner, repo, links.clone()).await?;
-
-    create_dns_records_for_links(company, links).await?;
-
-    Ok(())
+    Ok(links)
 }
 
 /// Generate the files for the RFD short URLs.
@@ -60,6 +109,18 @@ pub async fn generate_shorturls_for_rfds(
     repo: &str,
 ) -> Result<()> {
     let owner = &company.github_org;
+    let links = get_shorturls_for_rfds(db, company).await?;
+
+    // Generate the files for the links.
+    generate_nginx_files_for_shorturls(github, owner, repo, links.clone()).await?;
+
+    create_dns_records_for_links(db, company, links).await?;
+
+    Ok(())
+}
+
+/// Get the short URLs for the RFDs.
+pub async fn get_shorturls_for_rfds(db: &Database, company: &Company) -> Result<Vec<ShortUrl>> {
     let subdomain = "rfd";
     // Initialize the array of links.
     let mut links: Vec<ShortUrl> = Default::default();
@@ -90,12 +151,7 @@ pub async fn generate_shorturls_for_rfds(
         links.push(link.clone());
     }
 
-    // Generate the files for the links.
-    generate_nginx_files_for_shorturls(github, owner, repo, links.clone()).await?;
-
-    create_dns_records_for_links(company, links).await?;
-
-    Ok(())
+    Ok(links)
 }
 
 /// Generate the files for the configs links.
@@ -106,6 +162,18 @@ pub async fn generate_shorturls_for_configs_links(
     repo: &str,
 ) -> Result<()> {
     let owner = &company.github_org;
+    let links = get_shorturls_for_configs_links(db, company).await?;
+
+    // Generate the files for the links.
+    generate_nginx_files_for_shorturls(github, owner, repo, links.clone()).await?;
+
+    create_dns_records_for_links(db, company, links).await?;
+
+    Ok(())
+}
+
+/// Get the short URLs for the configs links.
+pub async fn get_shorturls_for_configs_links(db: &Database, company: &Company) -> Result<Vec<ShortUrl>> {
     let subdomain = "corp";
     // Initialize the array of links.
     let mut links: Vec<ShortUrl> = Default::default();
@@ -144,16 +212,20 @@ pub async fn generate_shorturls_for_configs_links(
         }
     }
 
-    // Generate the files for the links.
-    generate_nginx_files_for_shorturls(github, owner, repo, links.clone()).await?;
+    Ok(links)
+}
+
+/// Create the DNS records for the tailscale devices.
+pub async fn generate_dns_for_tailscale_devices(db: &Database, company: &Company) -> Result<()> {
+    let links = get_shorturls_for_tailscale_devices(company).await?;
 
-    create_dns_records_for_links(company, links).await?;
+    create_dns_records_for_links(db, company, links).await?;
 
     Ok(())
 }
 
-/// Generate the cloudflare terraform files for the tailscale devices.
-pub async fn generate_dns_for_tailscale_devices(company: &Company) -> Result<()> {
+/// Get the short URLs for the tailscale devices.
+pub async fn get_shorturls_for_tailscale_devices(company: &Company) -> Result<Vec<ShortUrl>> {
     let subdomain = "internal";
     // Initialize the array of links.
     let mut links: Vec<ShortUrl> = Default::default();
@@ -217,9 +289,7 @@ pub async fn generate_dns_for_tailscale_devices(company: &Company) -> Result<()>
         }
     }
 
-    create_dns_records_for_links(company, links).await?;
-
-    Ok(())
+    Ok(links)
 }
 
 /// Update all the short URLs and DNS.
@@ -237,15 +307,88 @@ pub async fn refresh_shorturls() -> Result<()> {
 
         // Only do this if we can auth with Tailscale.
         if !company.tailscale_api_key.is_empty() {
-            generate_dns_for_tailscale_devices(&company).await?;
+            generate_dns_for_tailscale_devices(&db, &company).await?;
         }
-    }
 
-    // TODO: cleanup any DNS records that no longer need to exist.
+        // Report any DNS records that no longer need to exist. We don't delete them
+        // here, since a bad sync could take down every short URL, someone has to run
+        // `cleanup-dns --delete` for that.
+        for record in cleanup_stale_dns_records(&db, &company, true).await? {
+            warn!(
+                "dns record `{}` -> `{}` from `{}` is stale, run `cleanup-dns --delete` to delete it",
+                record.name, record.content, record.source
+            );
+        }
+    }
 
     Ok(())
 }
 
+/// Get the DNS records we created for short URLs that we no longer generate.
+pub async fn get_stale_dns_records(db: &Database, company: &Company) -> Result<Vec<ManagedDnsRecord>> {
+    let mut links = get_shorturls_for_repos(db, company).await?;
+    links.append(&mut get_shorturls_for_rfds(db, company).await?);
+    links.append(&mut get_shorturls_for_configs_links(db, company).await?);
+    if !company.tailscale_api_key.is_empty() {
+        links.append(&mut get_shorturls_for_tailscale_devices(company).await?);
+    }
+
+    let managed = ManagedDnsRecords::get_from_db(db, company.id).await?;
+
+    Ok(stale_dns_records(company, &links, managed.into()))
+}
+
+/// Get the managed records that are not for any of the links.
+fn stale_dns_records(company: &Company, links: &[ShortUrl], managed: Vec<ManagedDnsRecord>) -> Vec<ManagedDnsRecord> {
+    let names: std::collections::BTreeSet<String> = links.iter().map(|l| l.dns_name(company)).collect();
+
+    managed.into_iter().filter(|r| !names.contains(&r.name)).collect()
+}
+
+/// Delete the DNS records we created for short URLs that we no longer generate.
+/// Returns the records that were deleted, or if `dry_run` is true, the records that
+/// would be deleted.
+pub async fn cleanup_stale_dns_records(
+    db: &Database,
+    company: &Company,
+    dry_run: bool,
+) -> Result<Vec<ManagedDnsRecord>> {
+    let stale = get_stale_dns_records(db, company).await?;
+    if dry_run || stale.is_empty() {
+        return Ok(stale);
+    }
+
+    let dns = company.authenticate_dns()?;
+    let mut deleted: Vec<ManagedDnsRecord> = Default::default();
+    for record in stale {
+        let was_deleted = dns
+            .delete_record(
+                &record.name,
+                DnsContent::A {
+                    content: record.content.parse()?,
+                },
+            )
+            .await?;
+        if !was_deleted {
+            // Keep tracking the record, so we try again next time instead of
+            // forgetting about a record that still exists.
+            warn!(
+                "dns record `{}` from `{}` was not deleted by the dns provider, keeping track of it",
+                record.name, record.source
+            );
+            continue;
+        }
+
+        record.delete(db).await?;
+
+        info!("deleted stale dns record `{}` from `{}`", record.name, record.source);
+
+        deleted.push(record);
+    }
+
+    Ok(deleted)
+}
+
 /// The data type for a short URL that will be used in a template.
 #[derive(Debug, Serialize, Clone)]
 pub struct ShortUrl {
@@ -264,21 +407,24 @@ pub struct ShortUrl {
     pub discussion: String,
 }
 
-async fn create_dns_records_for_links(company: &Company, shorturls: Vec<ShortUrl>) -> Result<()> {
-    let cf = company.authenticate_cloudflare()?;
-    for s in shorturls {
+impl ShortUrl {
+    /// The domain name of the DNS record for the short URL.
+    pub fn dns_name(&self, company: &Company) -> String {
         // Make sure the name does not start with a dot ".".
-        let mut name = if s.name.starts_with('.') {
-            s.name.trim_start_matches('.').to_string()
-        } else {
-            s.name.to_string()
-        };
+        let name = self.name.trim_start_matches('.');
+
+        format!("{}.{}.{}", name, self.subdomain, company.domain)
+    }
+}
 
-        name = format!("{}.{}.{}", name, s.subdomain, company.domain);
-        if cf
+async fn create_dns_records_for_links(db: &Database, company: &Company, shorturls: Vec<ShortUrl>) -> Result<()> {
+    let dns = company.authenticate_dns()?;
+    for s in shorturls {
+        let name = s.dns_name(company);
+        if dns
             .ensure_record(
                 &name,
-                dns::DnsContent::A {
+                DnsContent::A {
                     content: company.nginx_ip.parse()?,
                 },
             )
@@ -286,10 +432,10 @@ async fn create_dns_records_for_links(company: &Company, shorturls: Vec<ShortUrl
             .is_err()
         {
             // Try it again, it might just have been a time out error.
-            if let Err(e) = cf
+            if let Err(e) = dns
                 .ensure_record(
                     &name,
-                    dns::DnsContent::A {
+                    DnsContent::A {
                         content: company.nginx_ip.parse()?,
                     },
                 )
@@ -298,7 +444,70 @@ async fn create_dns_records_for_links(company: &Company, shorturls: Vec<ShortUrl
                 bail!("Error creating DNS record for `{}`: {}", name, e);
             }
         }
+
+        // Keep track of the record so we can clean it up when we no longer need it.
+        NewManagedDnsRecord {
+            name,
+            content: company.nginx_ip.to_string(),
+            source: s.subdomain.to_string(),
+            last_ensured_at: Utc::now(),
+            cio_company_id: company.id,
+        }
+        .upsert_in_db(db)
+        .await?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn link(name: &str, subdomain: &str) -> ShortUrl {
+        ShortUrl {
+            name: name.to_string(),
+            description: Default::default(),
+            link: Default::default(),
+            ip: Default::default(),
+            aliases: Default::default(),
+            subdomain: subdomain.to_string(),
+            domain: Default::default(),
+            discussion: Default::default(),
+        }
+    }
+
+    fn managed(name: &str, source: &str) -> ManagedDnsRecord {
+        serde_json::from_value(json!({
+            "name": name,
+            "content": "10.0.0.1",
+            "source": source,
+            "last_ensured_at": "2022-04-13T15:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_stale_dns_records() {
+        let company: Company = serde_json::from_value(json!({"domain": "oxide.computer"})).unwrap();
+
+        let links = vec![link("cio", "git"), link(".1", "rfd")];
+        let records = vec![
+            managed("cio.git.oxide.computer", "git"),
+            managed("1.rfd.oxide.computer", "rfd"),
+            managed("old-repo.git.oxide.computer", "git"),
+        ];
+
+        let stale = stale_dns_records(&company, &links, records);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].name, "old-repo.git.oxide.computer");
+
+        // Without any links, every record we made is stale.
+        assert_eq!(
+            stale_dns_records(&company, &[], vec![managed("cio.git.oxide.computer", "git")]).len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::{DateTime, Duration, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl};
+use log::info;
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
+
+use crate::{
+    companies::Company,
+    db::Database,
+    schema::{barcode_scans, swag_inventory_reservations},
+    swag_inventory::{BarcodeScan, ReservationStatus, SwagInventoryItem, SwagInventoryItems, SwagInventoryReservation},
+};
+
+/// How many weeks of barcode scans and orders we use to figure out how fast swag
+/// goes out.
+pub const FORECAST_LOOKBACK_WEEKS: i64 = 8;
+
+/// How many weeks it takes for more of an item to show up after we order it.
+pub const RESTOCK_LEAD_TIME_WEEKS: f64 = 4.0;
+
+/// How many weeks of swag we order at a time.
+pub const RESTOCK_COVER_WEEKS: f64 = 12.0;
+
+/// Slack only allows this many blocks in a message.
+const MAX_SLACK_BLOCKS: usize = 50;
+
+/// When we will need to order more of a swag inventory item, at the rate it has been
+/// going out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestockForecast {
+    pub current_stock: i32,
+    pub reorder_threshold: i32,
+    /// How many went out in the lookback window, from barcode scans and orders.
+    pub units_out: i32,
+    pub weekly_usage: f64,
+    /// How many weeks until the stock drops to the reorder threshold, `None` if none
+    /// of it is going out.
+    pub weeks_until_threshold: Option<f64>,
+    /// How many we should order now, 0 if we do not need to order any yet.
+    pub reorder_quantity: i32,
+}
+
+impl RestockForecast {
+    /// Forecast an item from how many of it went out over the last `lookback_weeks`.
+    pub fn new(current_stock: i32, reorder_threshold: i32, units_out: i32, lookback_weeks: i64) -> Self {
+        let weekly_usage = units_out.max(0) as f64 / lookback_weeks.max(1) as f64;
+
+        let above_threshold = (current_stock - reorder_threshold).max(0) as f64;
+        let weeks_until_threshold = if weekly_usage > 0.0 {
+            Some(above_threshold / weekly_usage)
+        } else {
+            None
+        };
+
+        // We order when we would hit the threshold before a new order shows up.
+        let needs_reorder = reorder_threshold > 0
+            && (current_stock <= reorder_threshold
+                || weeks_until_threshold.map_or(false, |w| w <= RESTOCK_LEAD_TIME_WEEKS));
+
+        let reorder_quantity = if needs_reorder {
+            // Order enough to last us a while, and at least enough to get back over
+            // the threshold.
+            ((weekly_usage * RESTOCK_COVER_WEEKS).ceil() as i32).max(reorder_threshold)
+        } else {
+            0
+        };
+
+        RestockForecast {
+            current_stock,
+            reorder_threshold,
+            units_out,
+            weekly_usage,
+            weeks_until_threshold,
+            reorder_quantity,
+        }
+    }
+
+    /// Returns if we need to order more now.
+    pub fn needs_reorder(&self) -> bool {
+        self.reorder_quantity > 0
+    }
+}
+
+/// Forecast every swag inventory item that has a reorder threshold, from the barcode
+/// scans and order reservations of the last `lookback_weeks`. Cancelled orders do
+/// not count.
+pub fn forecast_restocks(
+    items: &[SwagInventoryItem],
+    scans: &[BarcodeScan],
+    reservations: &[SwagInventoryReservation],
+    lookback_weeks: i64,
+) -> Vec<(SwagInventoryItem, RestockForecast)> {
+    let mut scanned: BTreeMap<(String, String), i32> = Default::default();
+    for scan in scans {
+        *scanned
+            .entry((scan.item.to_string(), scan.size.to_string()))
+            .or_default() += 1;
+    }
+
+    let mut ordered: BTreeMap<i32, i32> = Default::default();
+    for reservation in reservations {
+        if reservation.status == ReservationStatus::Released.to_string() {
+            // The order was cancelled.
+            continue;
+        }
+
+        *ordered.entry(reservation.swag_inventory_item_id).or_default() += reservation.quantity;
+    }
+
+    let mut forecasts = Vec::new();
+    for item in items {
+        if item.reorder_threshold <= 0 {
+            // We don't reorder this item.
+            continue;
+        }
+
+        let units_out = scanned
+            .get(&(item.item.to_string(), item.size.to_string()))
+            .cloned()
+            .unwrap_or_default()
+            + ordered.get(&item.id).cloned().unwrap_or_default();
+
+        forecasts.push((
+            item.clone(),
+            RestockForecast::new(item.current_stock, item.reorder_threshold, units_out, lookback_weeks),
+        ));
+    }
+
+    // Show what runs out soonest first.
+    forecasts.sort_by(|(_, a), (_, b)| {
+        b.needs_reorder().cmp(&a.needs_reorder()).then(
+            a.weeks_until_threshold
+                .unwrap_or(f64::MAX)
+                .partial_cmp(&b.weeks_until_threshold.unwrap_or(f64::MAX))
+                .unwrap_or(std::cmp::Ordering::Equal),
+        )
+    });
+
+    forecasts
+}
+
+fn section(text: String) -> MessageBlock {
+    MessageBlock {
+        block_type: MessageBlockType::Section,
+        text: Some(MessageBlockText {
+            text_type: MessageType::Markdown,
+            text,
+        }),
+        elements: Default::default(),
+        accessory: Default::default(),
+        block_id: Default::default(),
+        fields: Default::default(),
+    }
+}
+
+/// Render the restock forecast as a Slack message.
+pub fn render_restock_forecast_slack(forecasts: &[(SwagInventoryItem, RestockForecast)]) -> FormattedMessage {
+    let to_reorder = forecasts.iter().filter(|(_, f)| f.needs_reorder()).count();
+
+    let mut blocks = vec![section(format!(
+        "*Weekly swag restock forecast*\n{} of {} items need to be reordered, based on the last {} weeks of barcode scans and orders",
+        to_reorder,
+        forecasts.len(),
+        FORECAST_LOOKBACK_WEEKS
+    ))];
+
+    for (item, forecast) in forecasts.iter().take(MAX_SLACK_BLOCKS - 2) {
+        let mut text = format!(
+            "*{}*\nstock: `{}` | reorder at: `{}` | going out: `{:.1}`/week",
+            item.name, forecast.current_stock, forecast.reorder_threshold, forecast.weekly_usage
+        );
+        match forecast.weeks_until_threshold {
+            Some(w) if w > 0.0 => text += &format!(" | hits the reorder threshold in ~{:.0} weeks", w.ceil()),
+            Some(_) => text += " | at or below the reorder threshold",
+            None if forecast.current_stock <= forecast.reorder_threshold => {
+                text += " | at or below the reorder threshold"
+            }
+            None => text += " | none going out",
+        }
+        if forecast.needs_reorder() {
+            text += &format!("\n:warning: order `{}` more now", forecast.reorder_quantity);
+        }
+
+        blocks.push(section(text));
+    }
+
+    if forecasts.len() > MAX_SLACK_BLOCKS - 2 {
+        blocks.push(MessageBlock {
+            block_type: MessageBlockType::Context,
+            elements: vec![slack_chat_api::BlockOption::MessageBlockText(MessageBlockText {
+                text_type: MessageType::Markdown,
+                text: format!("and {} more items", forecasts.len() - (MAX_SLACK_BLOCKS - 2)),
+            })],
+            text: Default::default(),
+            accessory: Default::default(),
+            block_id: Default::default(),
+            fields: Default::default(),
+        });
+    }
+
+    FormattedMessage {
+        channel: Default::default(),
+        attachments: Default::default(),
+        blocks,
+    }
+}
+
+/// Forecast when we need to restock swag and post it to the swag channel.
+pub async fn send_swag_restock_forecast(db: &Database, company: &Company) -> Result<()> {
+    if company.slack_channel_swag.is_empty() {
+        // Return early.
+        return Ok(());
+    }
+
+    let since: DateTime<Utc> = Utc::now() - Duration::weeks(FORECAST_LOOKBACK_WEEKS);
+
+    let items: Vec<SwagInventoryItem> = SwagInventoryItems::get_from_db(db, company.id).await?.into();
+
+    let scans = barcode_scans::dsl::barcode_scans
+        .filter(
+            barcode_scans::dsl::cio_company_id
+                .eq(company.id)
+                .and(barcode_scans::dsl::time.ge(since)),
+        )
+        .load_async::<BarcodeScan>(db.pool())
+        .await?;
+
+    let reservations = swag_inventory_reservations::dsl::swag_inventory_reservations
+        .filter(
+            swag_inventory_reservations::dsl::cio_company_id
+                .eq(company.id)
+                .and(swag_inventory_reservations::dsl::reserved_time.ge(since)),
+        )
+        .load_async::<SwagInventoryReservation>(db.pool())
+        .await?;
+
+    let forecasts = forecast_restocks(&items, &scans, &reservations, FORECAST_LOOKBACK_WEEKS);
+    if forecasts.is_empty() {
+        info!("no swag inventory items have a reorder threshold, not sending a restock forecast");
+        return Ok(());
+    }
+
+    let mut msg = render_restock_forecast_slack(&forecasts);
+    msg.channel = company.slack_channel_swag.to_string();
+    company.post_to_slack_channel(db, &msg).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restock_forecast() {
+        // 40 out over 8 weeks is 5 a week, so 30 above the threshold lasts 6 weeks.
+        let forecast = RestockForecast::new(40, 10, 40, 8);
+        assert_eq!(forecast.weekly_usage, 5.0);
+        assert_eq!(forecast.weeks_until_threshold, Some(6.0));
+        assert!(!forecast.needs_reorder());
+
+        // 15 above the threshold lasts 3 weeks, which is less than the lead time.
+        let forecast = RestockForecast::new(25, 10, 40, 8);
+        assert!(forecast.needs_reorder());
+        assert_eq!(forecast.reorder_quantity, 60);
+
+        // Nothing is going out but we are already at the threshold.
+        let forecast = RestockForecast::new(10, 10, 0, 8);
+        assert_eq!(forecast.weeks_until_threshold, None);
+        assert!(forecast.needs_reorder());
+        assert_eq!(forecast.reorder_quantity, 10);
+
+        // We don't reorder items without a threshold.
+        let forecast = RestockForecast::new(0, 0, 40, 8);
+        assert!(!forecast.needs_reorder());
+    }
+}
@@ -1,7 +1,7 @@
-use std::io::BufWriter;
+use std::{collections::BTreeMap, io::BufWriter, str::FromStr};
 
 use anyhow::{bail, Result};
-use async_bb8_diesel::AsyncRunQueryDsl;
+use async_bb8_diesel::{AsyncConnection, AsyncRunQueryDsl};
 use async_trait::async_trait;
 use barcoders::{
     generators::{image::Image, svg::SVG},
@@ -24,11 +24,15 @@ use slack_chat_api::{
 };
 
 use crate::{
-    airtable::{AIRTABLE_BARCODE_SCANS_TABLE, AIRTABLE_SWAG_INVENTORY_ITEMS_TABLE, AIRTABLE_SWAG_ITEMS_TABLE},
+    airtable::{
+        AIRTABLE_BARCODE_SCANS_TABLE, AIRTABLE_SWAG_INVENTORY_ITEMS_TABLE, AIRTABLE_SWAG_INVENTORY_RESERVATIONS_TABLE,
+        AIRTABLE_SWAG_ITEMS_TABLE,
+    },
     companies::Company,
     core::UpdateAirtableRecord,
     db::Database,
-    schema::{barcode_scans, swag_inventory_items, swag_items},
+    schema::{barcode_scans, swag_inventory_items, swag_inventory_reservations, swag_items},
+    shipments::OutboundShipment,
 };
 
 // The zebra label printer's dpi is 300.
@@ -57,6 +61,18 @@ pub struct NewSwagItem {
     pub image: String,
     #[serde(default)]
     pub internal_only: bool,
+    /// The Harmonized System code for the item, for customs declarations.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hs_code: String,
+    /// The value of one of the item in USD, for customs declarations.
+    #[serde(default)]
+    pub customs_value: f32,
+    /// The weight of one of the item in pounds, for customs declarations.
+    #[serde(default)]
+    pub weight: f32,
+    /// The country the item was made in, for customs declarations.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub origin_country: String,
 
     /// This is populated by Airtable.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -169,6 +185,11 @@ pub struct NewSwagInventoryItem {
     /// This field will be set and updated in Airtable.
     #[serde(default)]
     pub print_barcode_label_quantity: i32,
+    /// When the stock drops to this many or fewer we need to order more, 0 means we
+    /// do not reorder the item.
+    /// This field will be set and updated in Airtable.
+    #[serde(default)]
+    pub reorder_threshold: i32,
 
     /// This is populated by Airtable.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -189,8 +210,9 @@ impl UpdateAirtableRecord<SwagInventoryItem> for SwagInventoryItem {
         // This is a funtion in Airtable so we can't update it.
         self.name = "".to_string();
 
-        // This is set in airtable so we need to keep it.
+        // These are set in airtable so we need to keep them.
         self.print_barcode_label_quantity = record.print_barcode_label_quantity;
+        self.reorder_threshold = record.reorder_threshold;
 
         Ok(())
     }
@@ -548,6 +570,11 @@ impl SwagInventoryItem {
         Ok(())
     }
 
+    /// Returns if we need to order more of the item when we have `stock` of it.
+    pub fn needs_reorder_at(&self, stock: i32) -> bool {
+        self.reorder_threshold > 0 && stock <= self.reorder_threshold
+    }
+
     pub async fn get_item(&self, db: &Database) -> Option<SwagItem> {
         SwagItem::get_from_db(db, self.item.to_string()).await
     }
@@ -564,34 +591,43 @@ impl SwagInventoryItem {
             // Send a slack notification since it changed.
             let mut msg: FormattedMessage = self.clone().into();
 
-            let item = self.get_item(db).await.unwrap();
-
-            // Add our image as an accessory.
-            let accessory = MessageBlockAccessory {
-                accessory_type: MessageType::Image,
-                image_url: item.image.to_string(),
-                alt_text: self.item.to_string(),
-                text: None,
-                action_id: Default::default(),
-                value: Default::default(),
-            };
+            // Orders change the stock too, so don't panic if the swag item is gone,
+            // just leave the image out.
+            if let Some(item) = self.get_item(db).await {
+                // Add our image as an accessory.
+                let accessory = MessageBlockAccessory {
+                    accessory_type: MessageType::Image,
+                    image_url: item.image.to_string(),
+                    alt_text: self.item.to_string(),
+                    text: None,
+                    action_id: Default::default(),
+                    value: Default::default(),
+                };
 
-            // Set our accessory.
-            msg.attachments[0].blocks[0].accessory = Some(accessory);
+                // Set our accessory.
+                msg.attachments[0].blocks[0].accessory = Some(accessory);
+            }
             // Set our text.
             let mut t = msg.attachments[0].blocks[0].text.as_ref().unwrap().clone();
             t.text = format!(
                 "*{}*\nstock changed from `{}` to `{}`",
                 self.name, self.current_stock, new
             );
-            msg.attachments[0].blocks[0].text = Some(t);
 
-            if self.current_stock > new {
+            if self.needs_reorder_at(new) && !self.needs_reorder_at(self.current_stock) {
+                // We just dropped to the reorder threshold, let people know we need more.
+                t.text = format!(
+                    "{}\n:warning: this is at or below the reorder threshold of `{}`, time to order more",
+                    t.text, self.reorder_threshold
+                );
+                msg.attachments[0].color = crate::colors::Colors::Red.to_string();
+            } else if self.current_stock > new {
                 msg.attachments[0].color = crate::colors::Colors::Yellow.to_string();
             } else {
                 // We increased in stock, show it as Green.
                 msg.attachments[0].color = crate::colors::Colors::Green.to_string();
             }
+            msg.attachments[0].blocks[0].text = Some(t);
 
             msg.channel = company.slack_channel_swag.to_string();
 
@@ -755,3 +791,308 @@ pub async fn refresh_barcode_scans(db: &Database, company: &Company) -> Result<(
 
     Ok(())
 }
+
+/// The state of a reservation of swag inventory for an order.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationStatus {
+    /// The items were taken out of stock for the order.
+    Reserved,
+    /// The order was cancelled and the items were put back in stock.
+    Released,
+}
+
+impl Default for ReservationStatus {
+    fn default() -> Self {
+        ReservationStatus::Reserved
+    }
+}
+
+impl ToString for ReservationStatus {
+    fn to_string(&self) -> String {
+        match self {
+            ReservationStatus::Reserved => "reserved".to_string(),
+            ReservationStatus::Released => "released".to_string(),
+        }
+    }
+}
+
+impl FromStr for ReservationStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "reserved" => Ok(ReservationStatus::Reserved),
+            "released" => Ok(ReservationStatus::Released),
+            _ => bail!("unknown reservation status `{}`", s),
+        }
+    }
+}
+
+/// A quantity of a swag inventory item taken out of stock for the shipment of an
+/// order.
+#[db {
+    new_struct_name = "SwagInventoryReservation",
+    airtable_base = "swag",
+    airtable_table = "AIRTABLE_SWAG_INVENTORY_RESERVATIONS_TABLE",
+    match_on = {
+        "outbound_shipment_id" = "i32",
+        "swag_inventory_item_id" = "i32",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[diesel(table_name = swag_inventory_reservations)]
+pub struct NewSwagInventoryReservation {
+    pub swag_inventory_item_id: i32,
+    pub outbound_shipment_id: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub item: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub size: String,
+    #[serde(default)]
+    pub quantity: i32,
+    /// One of `reserved` or `released`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status: String,
+    pub reserved_time: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub released_time: Option<DateTime<Utc>>,
+    /// The CIO company ID.
+    #[serde(default)]
+    pub cio_company_id: i32,
+}
+
+/// Implement updating the Airtable record for a SwagInventoryReservation.
+#[async_trait]
+impl UpdateAirtableRecord<SwagInventoryReservation> for SwagInventoryReservation {
+    async fn update_airtable_record(&mut self, _record: SwagInventoryReservation) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Add up the quantities of `(swag inventory item id, quantity)` pairs. An order can
+/// have the same item more than once, but there is only one reservation per item
+/// for a shipment.
+fn reservation_quantities(items: &[(i32, i32)]) -> Result<BTreeMap<i32, i32>> {
+    let mut quantities: BTreeMap<i32, i32> = Default::default();
+    for (id, quantity) in items {
+        if *quantity <= 0 {
+            bail!("cannot reserve `{}` of swag inventory item `{}`", quantity, id);
+        }
+
+        *quantities.entry(*id).or_default() += quantity;
+    }
+
+    Ok(quantities)
+}
+
+impl SwagInventoryReservation {
+    /// Take `(swag inventory item id, quantity)` pairs out of stock for a shipment.
+    /// This is done in a transaction that fails instead of letting the stock of any
+    /// item go negative, so either every item is reserved or none are, even when
+    /// orders come in at the same time.
+    pub async fn reserve(db: &Database, shipment: &OutboundShipment, items: &[(i32, i32)]) -> Result<Vec<Self>> {
+        let shipment_id = shipment.id;
+        let cio_company_id = shipment.cio_company_id;
+        let quantities = reservation_quantities(items)?;
+        // Get this first, everything after the transaction must not fail.
+        let company = shipment.company(db).await?;
+
+        let reserved: Vec<(SwagInventoryItem, SwagInventoryReservation)> = db
+            .pool()
+            .transaction(move |conn| {
+                let reserved_time = Utc::now();
+                let mut reserved = Vec::new();
+                for (id, quantity) in quantities {
+                    // Only take the items if we have enough of them, the database checks
+                    // this as it updates the row.
+                    let swag_inventory_item = diesel::update(swag_inventory_items::dsl::swag_inventory_items)
+                        .filter(
+                            swag_inventory_items::dsl::id
+                                .eq(id)
+                                .and(swag_inventory_items::dsl::current_stock.ge(quantity)),
+                        )
+                        .set(
+                            swag_inventory_items::dsl::current_stock
+                                .eq(swag_inventory_items::dsl::current_stock - quantity),
+                        )
+                        .get_result::<SwagInventoryItem>(conn)
+                        .optional()?;
+                    let swag_inventory_item = match swag_inventory_item {
+                        Some(i) => i,
+                        None => bail!(
+                            "swag inventory item `{}` does not have `{}` left in stock, not reserving the order",
+                            id,
+                            quantity
+                        ),
+                    };
+
+                    let reservation = diesel::insert_into(swag_inventory_reservations::table)
+                        .values(NewSwagInventoryReservation {
+                            swag_inventory_item_id: swag_inventory_item.id,
+                            outbound_shipment_id: shipment_id,
+                            item: swag_inventory_item.item.to_string(),
+                            size: swag_inventory_item.size.to_string(),
+                            quantity,
+                            status: ReservationStatus::Reserved.to_string(),
+                            reserved_time,
+                            released_time: None,
+                            cio_company_id,
+                        })
+                        .get_result::<SwagInventoryReservation>(conn)?;
+
+                    reserved.push((swag_inventory_item, reservation));
+                }
+
+                Ok(reserved)
+            })
+            .await?;
+
+        let mut reservations = Vec::new();
+        for (swag_inventory_item, reservation) in reserved {
+            info!(
+                "reserved `{}` of `{}` for shipment `{}` making the stock now `{}`",
+                reservation.quantity, swag_inventory_item.name, shipment_id, swag_inventory_item.current_stock
+            );
+            // The stock already changed in the database, so don't fail because of
+            // Slack or Airtable.
+            if let Err(e) = sync_reserved_stock(db, &company, swag_inventory_item, -reservation.quantity).await {
+                warn!(
+                    "updating the stock of reserved swag in slack and airtable failed: {}",
+                    e
+                );
+            }
+            match reservation.update(db).await {
+                Ok(r) => reservations.push(r),
+                Err(e) => {
+                    warn!(
+                        "updating swag inventory reservation `{}` in airtable failed: {}",
+                        reservation.id, e
+                    );
+                    reservations.push(reservation);
+                }
+            }
+        }
+
+        Ok(reservations)
+    }
+
+    /// Put everything reserved for a shipment back in stock, for when it is
+    /// cancelled. Reservations that were already released are left alone.
+    pub async fn release(db: &Database, shipment: &OutboundShipment) -> Result<Vec<Self>> {
+        let shipment_id = shipment.id;
+
+        let released: Vec<(SwagInventoryItem, SwagInventoryReservation)> = db
+            .pool()
+            .transaction(move |conn| {
+                let reservations = diesel::update(swag_inventory_reservations::dsl::swag_inventory_reservations)
+                    .filter(
+                        swag_inventory_reservations::dsl::outbound_shipment_id
+                            .eq(shipment_id)
+                            .and(swag_inventory_reservations::dsl::status.eq(ReservationStatus::Reserved.to_string())),
+                    )
+                    .set((
+                        swag_inventory_reservations::dsl::status.eq(ReservationStatus::Released.to_string()),
+                        swag_inventory_reservations::dsl::released_time.eq(Some(Utc::now())),
+                    ))
+                    .get_results::<SwagInventoryReservation>(conn)?;
+
+                let mut released = Vec::new();
+                for reservation in reservations {
+                    let swag_inventory_item = diesel::update(swag_inventory_items::dsl::swag_inventory_items)
+                        .filter(swag_inventory_items::dsl::id.eq(reservation.swag_inventory_item_id))
+                        .set(
+                            swag_inventory_items::dsl::current_stock
+                                .eq(swag_inventory_items::dsl::current_stock + reservation.quantity),
+                        )
+                        .get_result::<SwagInventoryItem>(conn)?;
+
+                    released.push((swag_inventory_item, reservation));
+                }
+
+                Ok::<_, anyhow::Error>(released)
+            })
+            .await?;
+
+        if released.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let company = shipment.company(db).await?;
+        let mut reservations = Vec::new();
+        for (swag_inventory_item, reservation) in released {
+            info!(
+                "released `{}` of `{}` from cancelled shipment `{}` making the stock now `{}`",
+                reservation.quantity, swag_inventory_item.name, shipment_id, swag_inventory_item.current_stock
+            );
+            // The stock already changed in the database, so don't fail because of
+            // Slack or Airtable.
+            if let Err(e) = sync_reserved_stock(db, &company, swag_inventory_item, reservation.quantity).await {
+                warn!(
+                    "updating the stock of reserved swag in slack and airtable failed: {}",
+                    e
+                );
+            }
+            match reservation.update(db).await {
+                Ok(r) => reservations.push(r),
+                Err(e) => {
+                    warn!(
+                        "updating swag inventory reservation `{}` in airtable failed: {}",
+                        reservation.id, e
+                    );
+                    reservations.push(reservation);
+                }
+            }
+        }
+
+        Ok(reservations)
+    }
+}
+
+/// Let the swag channel know about a stock change made by a reservation, and send
+/// the new stock to Airtable. This does not write the item back to the database,
+/// since the stock there might have changed again since we reserved.
+async fn sync_reserved_stock(
+    db: &Database,
+    company: &Company,
+    mut swag_inventory_item: SwagInventoryItem,
+    change: i32,
+) -> Result<()> {
+    let new = swag_inventory_item.current_stock;
+    swag_inventory_item.current_stock = new - change;
+    swag_inventory_item
+        .send_slack_notification_if_inventory_changed(db, company, new)
+        .await?;
+    swag_inventory_item.upsert_in_airtable(db).await?;
+
+    Ok(())
+}
+
+pub async fn refresh_swag_inventory_reservations(db: &Database, company: &Company) -> Result<()> {
+    if company.airtable_base_id_swag.is_empty() {
+        // Return early.
+        return Ok(());
+    }
+
+    SwagInventoryReservations::get_from_db(db, company.id)
+        .await?
+        .update_airtable(db)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reservation_quantities;
+
+    #[test]
+    fn test_reservation_quantities() {
+        let quantities = reservation_quantities(&[(1, 1), (2, 3), (1, 2)]).unwrap();
+        assert_eq!(quantities.into_iter().collect::<Vec<_>>(), vec![(1, 3), (2, 3)]);
+
+        assert!(reservation_quantities(&[]).unwrap().is_empty());
+        assert!(reservation_quantities(&[(1, 1), (2, 0)]).is_err());
+        assert!(reservation_quantities(&[(1, -1)]).is_err());
+    }
+}
@@ -1,11 +1,17 @@
 use anyhow::Result;
 
 use chrono::Utc;
-use log::info;
+use log::warn;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use slack_chat_api::{FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType, MessageType};
 
-use crate::{companies::Company, db::Database, shipments::NewOutboundShipment, swag_inventory::SwagInventoryItem};
+use crate::{
+    companies::Company,
+    db::Database,
+    shipments::{NewOutboundShipment, OutboundShipment},
+    swag_inventory::{SwagInventoryItem, SwagInventoryReservation},
+};
 
 #[derive(Debug, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
 pub struct Order {
@@ -62,49 +68,73 @@ impl Order {
         Ok(contents.trim().to_string())
     }
 
-    pub async fn create_shipment_for_order(&self, db: &Database) -> Result<()> {
-        // Convert the shipment to an order.
-        let shipment: NewOutboundShipment = self.to_outbound_shipment().await?;
+    pub async fn create_shipment_for_order(&self, db: &Database, mut shipment: OutboundShipment) -> Result<()> {
+        // Create or update the shipment label.
+        if let Err(e) = shipment.create_or_get_shipment(db).await {
+            if shipment.provider_id.is_empty() {
+                // We never bought the label, so cancel the order and put the swag we
+                // reserved for it back in stock.
+                shipment.messages = format!("Buying the label failed: {}", e);
+                if let Err(err) = shipment.cancel(db).await {
+                    warn!(
+                        "cancelling shipment {} after buying the label failed: {}",
+                        shipment.id, err
+                    );
+                }
+            }
 
-        // Add the shipment to the database.
-        let mut new_shipment = shipment.upsert_in_db(db).await?;
-        // Create or update the shipment from shippo.
-        new_shipment.create_or_get_shippo_shipment(db).await?;
+            return Err(e);
+        }
         // Update airtable and the database again.
-        new_shipment.update(db).await?;
+        shipment.update(db).await?;
 
         // Send an email to the person that we recieved their order and what they are
         // getting.
-        new_shipment.send_email_to_recipient_pre_shipping(db).await?;
+        shipment.send_email_to_recipient_pre_shipping(db).await?;
 
         Ok(())
     }
 
-    pub async fn subtract_order_from_inventory(&self, db: &Database) -> Result<()> {
-        for item in &self.items {
-            // Get the swag item from the database.
-            let mut swag_inventory_item = SwagInventoryItem::get_by_id(db, item.id).await?;
-            let mut new = swag_inventory_item.current_stock - item.quantity;
-            if swag_inventory_item.current_stock < 0 {
-                // TODO: Hopefully this never happens. The store code _should_ only allow people
-                // to order what is in stock but just in case let's make sure this does not
-                // go negative.
-                new = 0;
-            }
+    async fn send_out_of_stock_slack_notification(
+        &self,
+        db: &Database,
+        shipment: &OutboundShipment,
+        err: &anyhow::Error,
+    ) -> Result<()> {
+        let company = shipment.company(db).await?;
+        let msg = FormattedMessage {
+            channel: company.slack_channel_swag.to_string(),
+            blocks: vec![MessageBlock {
+                block_type: MessageBlockType::Section,
+                text: Some(MessageBlockText {
+                    text_type: MessageType::Markdown,
+                    text: format!(
+                        "We could not take the order from *{}* <mailto:{}|{}> out of stock, so it will not \
+                         ship: {}\n>{}",
+                        self.name,
+                        self.email,
+                        self.email,
+                        err,
+                        shipment.contents.replace('\n', "\n>")
+                    ),
+                }),
+                elements: Default::default(),
+                accessory: Default::default(),
+                block_id: Default::default(),
+                fields: Default::default(),
+            }],
+            attachments: Default::default(),
+        };
+        company.post_to_slack_channel(db, &msg).await?;
 
-            let company = swag_inventory_item.company(db).await?;
+        Ok(())
+    }
 
-            // This will also set the value.
-            swag_inventory_item
-                .send_slack_notification_if_inventory_changed(db, &company, new)
-                .await?;
-
-            info!(
-                "subtracted `{}` from current stock of `{}` making the total now `{}`",
-                item.quantity, swag_inventory_item.name, swag_inventory_item.current_stock
-            );
-            swag_inventory_item.update(db).await?;
-        }
+    /// Take the items in the order out of stock for its shipment. If we do not have
+    /// enough of any of them nothing is taken and this returns an error.
+    pub async fn reserve_inventory(&self, db: &Database, shipment: &OutboundShipment) -> Result<()> {
+        let items: Vec<(i32, i32)> = self.items.iter().map(|item| (item.id, item.quantity)).collect();
+        SwagInventoryReservation::reserve(db, shipment, &items).await?;
 
         Ok(())
     }
@@ -125,8 +155,24 @@ impl Order {
             return Ok(());
         }
 
-        self.create_shipment_for_order(db).await?;
-        self.subtract_order_from_inventory(db).await?;
+        // Convert the order to a shipment and add it to the database. Every order is
+        // its own shipment, so we don't match it against the others.
+        let shipment: NewOutboundShipment = self.to_outbound_shipment().await?;
+        let shipment = shipment.create_in_db(db).await?;
+
+        // Reserve the items before we buy a label, so we never ship something we
+        // don't have.
+        if let Err(e) = self.reserve_inventory(db, &shipment).await {
+            shipment.delete_from_db(db).await?;
+            // Someone ordered something we ran out of, tell the people that can
+            // restock it or reach out to them.
+            if let Err(err) = self.send_out_of_stock_slack_notification(db, &shipment, &e).await {
+                warn!("posting order from `{}` to slack failed: {}", self.email, err);
+            }
+            return Err(e);
+        }
+
+        self.create_shipment_for_order(db, shipment).await?;
 
         Ok(())
     }
@@ -174,7 +220,106 @@ impl Order {
             geocode_cache: Default::default(),
             local_pickup: false,
             link_to_package_pickup: Default::default(),
+            is_return: false,
+            parcels: Default::default(),
+            commercial_invoice_link: Default::default(),
+            address_validation: Default::default(),
             cio_company_id: self.cio_company_id,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        companies::Company,
+        db::Database,
+        swag_inventory::{NewSwagInventoryItem, SwagInventoryItem, SwagInventoryReservation},
+        swag_store::{Order, OrderItem},
+    };
+
+    async fn current_stock(db: &Database, id: i32) -> i32 {
+        SwagInventoryItem::get_by_id(db, id).await.unwrap().current_stock
+    }
+
+    #[ignore]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reserve_and_release_inventory() {
+        crate::utils::setup_logger();
+
+        let db = Database::new().await;
+
+        // Get the company id for Oxide.
+        let oxide = Company::get_from_db(&db, "Oxide".to_string()).await.unwrap();
+
+        let item = NewSwagInventoryItem {
+            name: "Reservation Test Tee - XS".to_string(),
+            size: "XS".to_string(),
+            current_stock: 3,
+            item: "Reservation Test Tee".to_string(),
+            barcode: Default::default(),
+            barcode_png: Default::default(),
+            barcode_svg: Default::default(),
+            barcode_pdf_label: Default::default(),
+            print_barcode_label_quantity: 0,
+            reorder_threshold: 0,
+            link_to_item: Default::default(),
+            cio_company_id: oxide.id,
+        }
+        .create_in_db(&db)
+        .await
+        .unwrap();
+
+        let order = Order {
+            name: "Reservation Test".to_string(),
+            email: "reservation-test@example.com".to_string(),
+            phone: "+15555555555".to_string(),
+            street_1: "1 Test St".to_string(),
+            street_2: Default::default(),
+            city: "Emeryville".to_string(),
+            state: "CA".to_string(),
+            zipcode: "94608".to_string(),
+            country: "US".to_string(),
+            notes: Default::default(),
+            // The same item twice is reserved as one.
+            items: vec![
+                OrderItem {
+                    id: item.id,
+                    quantity: 1,
+                },
+                OrderItem {
+                    id: item.id,
+                    quantity: 1,
+                },
+            ],
+            cio_company_id: oxide.id,
+        };
+        let shipment = order
+            .to_outbound_shipment()
+            .await
+            .unwrap()
+            .create_in_db(&db)
+            .await
+            .unwrap();
+
+        order.reserve_inventory(&db, &shipment).await.unwrap();
+        assert_eq!(current_stock(&db, item.id).await, 1);
+
+        // We can't take more than we have left, and nothing changes when we try.
+        assert!(order.reserve_inventory(&db, &shipment).await.is_err());
+        assert_eq!(current_stock(&db, item.id).await, 1);
+
+        let released = SwagInventoryReservation::release(&db, &shipment).await.unwrap();
+        assert_eq!(released.len(), 1);
+        assert_eq!(current_stock(&db, item.id).await, 3);
+
+        // Releasing again does not put it back twice.
+        let released = SwagInventoryReservation::release(&db, &shipment).await.unwrap();
+        assert!(released.is_empty());
+        assert_eq!(current_stock(&db, item.id).await, 3);
+
+        // Deleting the item deletes its reservations too.
+        shipment.delete_from_db(&db).await.unwrap();
+        item.delete_from_db(&db).await.unwrap();
+    }
+}
@@ -2,8 +2,6 @@ use std::collections::BTreeMap;
 
 use anyhow::Result;
 use chrono::{Duration, Utc};
-use cloudflare::endpoints::dns;
-use cloudflare::framework::async_api::ApiClient;
 use log::info;
 
 use crate::companies::Company;
@@ -53,8 +51,8 @@ pub async fn cleanup_old_tailscale_devices(company: &Company) -> Result<()> {
 }
 
 /// When we generate VMs for the console repo, we leave behind a lot of DNS records
-/// in Cloudflare. This function cleans these up when the tailscale device is no longer
-/// active.
+/// with our DNS provider. This function cleans these up when the tailscale device
+/// is no longer active.
 pub async fn cleanup_old_tailscale_cloudflare_dns(company: &Company) -> Result<()> {
     if company.tailscale_api_key.is_empty() || company.name != "Oxide" {
         info!(
@@ -83,23 +81,11 @@ pub async fn cleanup_old_tailscale_cloudflare_dns(company: &Company) -> Result<(
         })
         .collect();
 
-    // Initialize the Cloudflare API.
-    let cloudflare = company.authenticate_cloudflare()?;
+    // Initialize the DNS provider.
+    let dns = company.authenticate_dns()?;
 
     // List the DNS records.
-    let domain = "oxide.computer";
-    let zone_identifier = &crate::dns_providers::get_zone_identifier(&cloudflare, domain).await?;
-    let dns_records = cloudflare
-        .request(&dns::ListDnsRecords {
-            zone_identifier,
-            params: dns::ListDnsRecordsParams {
-                // From: https://api.cloudflare.com/#dns-records-for-a-zone-list-dns-records
-                per_page: Some(5000),
-                ..Default::default()
-            },
-        })
-        .await?
-        .result;
+    let dns_records = dns.list_records("oxide.computer").await?;
 
     for dns_record in dns_records {
         if !dns_record.name.starts_with("console-git-") {
@@ -115,16 +101,11 @@ pub async fn cleanup_old_tailscale_cloudflare_dns(company: &Company) -> Result<(
         // If it does not exist in Tailscale, delete it.
         if !tailscale_devices.contains_key(&name) {
             info!("deleting dns record {}", name);
-            cloudflare
-                .request(&dns::DeleteDnsRecord {
-                    zone_identifier,
-                    identifier: &dns_record.id,
-                })
-                .await?;
+            dns.delete_record(&dns_record.name, dns_record.content).await?;
         }
     }
 
-    info!("cleaned up old tailscale dns records in cloudflare successfully");
+    info!("cleaned up old tailscale dns records successfully");
 
     Ok(())
 }
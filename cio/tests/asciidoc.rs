@@ -0,0 +1,34 @@
+//! Compatibility tests for the AsciiDoc renderer. Each `<case>.adoc` in
+//! `tests/asciidoc` is rendered and compared to `<case>.html`, which matches what
+//! `asciidoctor --no-header-footer` produces for the same document.
+use std::{collections::BTreeMap, fs, path::Path};
+
+#[test]
+fn test_asciidoc_corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("asciidoc");
+
+    // SVGs in the corpus can be inlined by the documents.
+    let mut images: BTreeMap<String, String> = Default::default();
+    let mut cases: Vec<String> = Default::default();
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("svg") => {
+                images.insert(name, fs::read_to_string(&path).unwrap());
+            }
+            Some("adoc") => cases.push(name.trim_end_matches(".adoc").to_string()),
+            _ => {}
+        }
+    }
+    cases.sort();
+    assert!(!cases.is_empty());
+
+    for case in cases {
+        let content = fs::read_to_string(dir.join(format!("{}.adoc", case))).unwrap();
+        let expected = fs::read_to_string(dir.join(format!("{}.html", case))).unwrap();
+
+        let html = cio_api::asciidoc::render(&content, &images);
+        assert_eq!(html.trim_end(), expected.trim_end(), "rendering {}.adoc", case);
+    }
+}
@@ -109,6 +109,13 @@ impl Geocode {
 
     /// Get information for an address.
     pub async fn get(&self, address: &str) -> Result<Reply, APIError> {
+        let results = self.get_all(address).await?;
+        Ok(results.get(0).unwrap().clone())
+    }
+
+    /// Get all the places that match an address, more than one means the
+    /// address is ambiguous.
+    pub async fn get_all(&self, address: &str) -> Result<Vec<Reply>, APIError> {
         // Build the request.
         let request = self.request(
             Method::GET,
@@ -135,7 +142,7 @@ impl Geocode {
                 body: "".to_string(),
             });
         }
-        Ok(r.results.get(0).unwrap().clone())
+        Ok(r.results)
     }
 }
 
@@ -178,14 +185,14 @@ impl error::Error for APIError {
 pub struct AddressComponent {
     /// The full text description or name of the address component as returned by the Geocoder.
     #[serde(default)]
-    long_name: String,
+    pub long_name: String,
     /// An abbreviated textual name for the address component, if available.
     /// For example, an address component for the state of Alaska may have a long_name of "Alaska" and a short_name of "AK" using the 2-letter postal abbreviation.
     #[serde(default)]
-    short_name: String,
+    pub short_name: String,
     /// The type of the address component.
     #[serde(default)]
-    types: Vec<String>,
+    pub types: Vec<String>,
 }
 
 /// Position information
@@ -263,6 +270,10 @@ pub struct Reply {
     pub geometry: Geometry,
     /// A unique identifier that can be used with other Google APIs.
     pub place_id: PlaceId,
+    /// Indicates that the geocoder did not return an exact match for the original request,
+    /// though it was able to match part of the requested address.
+    #[serde(default)]
+    pub partial_match: bool,
     /// All the localities contained in a postal code.
     /// This is only present when the result is a postal code that contains multiple localities.
     #[serde(default)]
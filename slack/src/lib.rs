@@ -25,7 +25,7 @@
  */
 #![allow(clippy::field_reassign_with_default)]
 #![allow(clippy::nonstandard_macro_braces)]
-use std::{collections::HashMap, env, sync::Arc};
+use std::{collections::HashMap, env, fmt, sync::Arc};
 
 use anyhow::{bail, Result};
 use reqwest::{header, Body, Client, Method, Request, StatusCode, Url};
@@ -164,12 +164,17 @@ impl Slack {
             "https://slack.com/oauth/v2/authorize?scope={}&client_id={}&user_scope={}&redirect_uri={}&state={}",
             "commands,team:read,users:read,users:read.email,users.profile:read,channels:read,chat:write,channels:join",
             self.client_id,
-            "admin,identify",
+            "admin,identify,usergroups:read,usergroups:write,users.profile:write",
             self.redirect_uri,
             state
         )
     }
 
+    /// The ID of the workspace this client is for.
+    pub fn workspace_id(&self) -> &str {
+        &self.workspace_id
+    }
+
     pub async fn get_access_token(&mut self, code: &str) -> Result<AccessToken> {
         let mut headers = header::HeaderMap::new();
         headers.append(header::ACCEPT, header::HeaderValue::from_static("application/json"));
@@ -203,27 +208,59 @@ impl Slack {
     /// List users on a workspace.
     /// FROM: https://api.slack.com/methods/users.list
     pub async fn list_users(&self) -> Result<Vec<User>> {
-        // Build the request.
-        // TODO: paginate.
-        let request = self.request(
-            &self.token,
-            Method::GET,
-            "users.list",
-            (),
-            Some(vec![("limit", "100".to_string())]),
-        )?;
+        let mut users: Vec<User> = Default::default();
+        let mut cursor = String::new();
 
-        let resp = self.client.execute(request).await?;
-        match resp.status() {
-            StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+        loop {
+            let mut query = vec![("limit", "100".to_string())];
+            if !cursor.is_empty() {
+                query.push(("cursor", cursor.to_string()));
             }
-        };
 
-        let r: APIResponse = resp.json().await?;
+            let mut r: APIResponse = self
+                .call(&self.token, Method::GET, "users.list", (), Some(query))
+                .await?;
+            users.append(&mut r.users);
 
-        Ok(r.users)
+            cursor = r.response_metadata.next_cursor;
+            if cursor.is_empty() {
+                break;
+            }
+        }
+
+        Ok(users)
+    }
+
+    /// Find a user by their email address, returns `None` if there is no user in
+    /// the workspace with that email.
+    /// FROM: https://api.slack.com/methods/users.lookupByEmail
+    pub async fn lookup_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        match self
+            .call::<_, UserResponse>(
+                &self.token,
+                Method::GET,
+                "users.lookupByEmail",
+                (),
+                Some(vec![("email", email.to_string())]),
+            )
+            .await
+        {
+            Ok(r) => Ok(Some(r.user)),
+            Err(e) => match e.downcast_ref::<APIError>() {
+                Some(api_error) if api_error.error == "users_not_found" => Ok(None),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Get the ID of the user the user token belongs to.
+    /// FROM: https://api.slack.com/methods/auth.test
+    pub async fn user_token_owner_id(&self) -> Result<String> {
+        let r: AuthTestResponse = self
+            .call(&self.user_token, Method::POST, "auth.test", (), None)
+            .await?;
+
+        Ok(r.user_id)
     }
 
     /// Get the current user's identity.
@@ -503,6 +540,126 @@ impl Slack {
         Ok(())
     }
 
+    /// List the user groups in the workspace, including disabled user groups and
+    /// their members.
+    /// FROM: https://api.slack.com/methods/usergroups.list
+    pub async fn list_user_groups(&self) -> Result<Vec<UserGroup>> {
+        let r: UserGroupsResponse = self
+            .call(
+                &self.user_token,
+                Method::GET,
+                "usergroups.list",
+                (),
+                Some(vec![
+                    ("include_disabled", "true".to_string()),
+                    ("include_users", "true".to_string()),
+                ]),
+            )
+            .await?;
+
+        Ok(r.usergroups)
+    }
+
+    /// Create a user group.
+    /// FROM: https://api.slack.com/methods/usergroups.create
+    pub async fn create_user_group(&self, group: &UserGroupRequest) -> Result<UserGroup> {
+        let r: UserGroupResponse = self
+            .call(&self.user_token, Method::POST, "usergroups.create", group, None)
+            .await?;
+
+        Ok(r.usergroup)
+    }
+
+    /// Update a user group.
+    /// FROM: https://api.slack.com/methods/usergroups.update
+    pub async fn update_user_group(&self, group: &UserGroupRequest) -> Result<UserGroup> {
+        let r: UserGroupResponse = self
+            .call(&self.user_token, Method::POST, "usergroups.update", group, None)
+            .await?;
+
+        Ok(r.usergroup)
+    }
+
+    /// Enable a disabled user group.
+    /// FROM: https://api.slack.com/methods/usergroups.enable
+    pub async fn enable_user_group(&self, usergroup_id: &str) -> Result<UserGroup> {
+        let mut body: HashMap<&str, &str> = HashMap::new();
+        body.insert("usergroup", usergroup_id);
+
+        let r: UserGroupResponse = self
+            .call(&self.user_token, Method::POST, "usergroups.enable", body, None)
+            .await?;
+
+        Ok(r.usergroup)
+    }
+
+    /// Disable a user group.
+    /// FROM: https://api.slack.com/methods/usergroups.disable
+    pub async fn disable_user_group(&self, usergroup_id: &str) -> Result<UserGroup> {
+        let mut body: HashMap<&str, &str> = HashMap::new();
+        body.insert("usergroup", usergroup_id);
+
+        let r: UserGroupResponse = self
+            .call(&self.user_token, Method::POST, "usergroups.disable", body, None)
+            .await?;
+
+        Ok(r.usergroup)
+    }
+
+    /// Replace the members of a user group. Slack requires at least one member.
+    /// FROM: https://api.slack.com/methods/usergroups.users.update
+    pub async fn update_user_group_users(&self, usergroup_id: &str, user_ids: &[String]) -> Result<UserGroup> {
+        let users = user_ids.join(",");
+        let mut body: HashMap<&str, &str> = HashMap::new();
+        body.insert("usergroup", usergroup_id);
+        body.insert("users", &users);
+
+        let r: UserGroupResponse = self
+            .call(&self.user_token, Method::POST, "usergroups.users.update", body, None)
+            .await?;
+
+        Ok(r.usergroup)
+    }
+
+    /// Get the custom profile fields for the workspace.
+    /// FROM: https://api.slack.com/methods/team.profile.get
+    pub async fn get_team_profile_fields(&self) -> Result<Vec<TeamProfileField>> {
+        let r: TeamProfileResponse = self
+            .call(&self.token, Method::GET, "team.profile.get", (), None)
+            .await?;
+
+        Ok(r.profile.fields)
+    }
+
+    /// Execute a request and deserialize the response, failing if Slack does not
+    /// return `ok`.
+    async fn call<B, T>(
+        &self,
+        token: &str,
+        method: Method,
+        path: &str,
+        body: B,
+        query: Option<Vec<(&str, String)>>,
+    ) -> Result<T>
+    where
+        B: Serialize,
+        T: serde::de::DeserializeOwned,
+    {
+        let request = self.request(token, method, path, body, query)?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                bail!("status code: {}, body: {}", s, resp.text().await?);
+            }
+        };
+
+        let v: Value = resp.json().await?;
+
+        parse_response(v)
+    }
+
     /// Post text to a channel.
     pub async fn post_to_channel(url: &str, v: &Value) -> Result<()> {
         let client = Client::new();
@@ -1070,6 +1227,8 @@ pub struct UserProfile {
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub phone: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pronouns: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub real_name: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub real_name_normalized: String,
@@ -1130,6 +1289,27 @@ pub struct APIResponse {
 
     #[serde(default, skip_serializing_if = "Vec::is_empty", alias = "members")]
     pub users: Vec<User>,
+
+    #[serde(default)]
+    pub response_metadata: ResponseMetadata,
+}
+
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct AuthTestResponse {
+    #[serde(default)]
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub user_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub team_id: String,
+}
+
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct UserResponse {
+    #[serde(default)]
+    pub ok: bool,
+    #[serde(default)]
+    pub user: User,
 }
 
 /// The data type for a User.
@@ -1185,6 +1365,105 @@ pub struct UpdateUserProfileRequest {
     pub profile: UserProfile,
 }
 
+/// The data type for a user group.
+/// FROM: https://api.slack.com/types/usergroup
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct UserGroup {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub team_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub handle: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// The time the user group was disabled, this is zero if it is enabled.
+    #[serde(default)]
+    pub date_delete: i64,
+    /// The ID of the user who disabled the user group.
+    #[serde(
+        default,
+        skip_serializing_if = "String::is_empty",
+        deserialize_with = "deserialize_null_string::deserialize"
+    )]
+    pub deleted_by: String,
+    #[serde(default)]
+    pub prefs: UserGroupPrefs,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+}
+
+impl UserGroup {
+    pub fn is_disabled(&self) -> bool {
+        self.date_delete != 0
+    }
+}
+
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct UserGroupPrefs {
+    /// The channels members of the user group are added to when they join it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+}
+
+/// The data type for creating or updating a user group.
+/// FROM: https://api.slack.com/methods/usergroups.create
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct UserGroupRequest {
+    /// The ID of the user group to update, this is empty when creating one.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub usergroup: String,
+    pub name: String,
+    pub handle: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// A comma-separated list of channel IDs for the user group to use as defaults.
+    #[serde(default)]
+    pub channels: String,
+}
+
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct UserGroupResponse {
+    #[serde(default)]
+    pub ok: bool,
+    #[serde(default)]
+    pub usergroup: UserGroup,
+}
+
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct UserGroupsResponse {
+    #[serde(default)]
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usergroups: Vec<UserGroup>,
+}
+
+/// The data type for a custom profile field.
+/// FROM: https://api.slack.com/methods/team.profile.get
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct TeamProfileField {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    #[serde(default, rename = "type", skip_serializing_if = "String::is_empty")]
+    pub type_: String,
+}
+
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct TeamProfile {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<TeamProfileField>,
+}
+
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct TeamProfileResponse {
+    #[serde(default)]
+    pub ok: bool,
+    #[serde(default)]
+    pub profile: TeamProfile,
+}
+
 #[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
 pub struct BillableInfoResponse {
     #[serde(default)]
@@ -1347,6 +1626,43 @@ pub struct Container {
     pub is_app_unfurl: bool,
 }
 
+/// An error Slack returned in the body of a response, these come back with a
+/// `200 OK` and `ok` set to false.
+/// FROM: https://api.slack.com/web#evaluating_responses
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct APIError {
+    /// The error code, for example `users_not_found`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub body: String,
+}
+
+impl fmt::Display for APIError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "slack error: {}, body: {}", self.error, self.body)
+    }
+}
+
+impl std::error::Error for APIError {}
+
+/// Deserialize the body of a Slack response, returning an `APIError` if Slack
+/// does not return `ok`.
+fn parse_response<T>(v: Value) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    if !v["ok"].as_bool().unwrap_or_default() {
+        return Err(APIError {
+            error: v["error"].as_str().unwrap_or_default().to_string(),
+            body: v.to_string(),
+        }
+        .into());
+    }
+
+    Ok(serde_json::from_value(v)?)
+}
+
 pub mod deserialize_null_string {
     use serde::{self, Deserialize, Deserializer};
 
@@ -1366,3 +1682,36 @@ pub mod deserialize_null_string {
         Ok(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_response_error() {
+        let err = parse_response::<UserResponse>(json!({"ok": false, "error": "users_not_found"})).unwrap_err();
+
+        let api_error = err.downcast_ref::<APIError>().unwrap();
+        assert_eq!(api_error.error, "users_not_found");
+    }
+
+    #[test]
+    fn test_parse_response_user_groups() {
+        let r: UserGroupsResponse = parse_response(json!({
+            "ok": true,
+            "usergroups": [
+                {"id": "S1", "handle": "eng", "date_delete": 0, "deleted_by": null, "users": ["U1"]},
+                {"id": "S2", "handle": "old", "date_delete": 1650000000, "deleted_by": "U2"},
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(r.usergroups.len(), 2);
+        assert!(!r.usergroups[0].is_disabled());
+        assert_eq!(r.usergroups[0].deleted_by, "");
+        assert!(r.usergroups[1].is_disabled());
+        assert_eq!(r.usergroups[1].deleted_by, "U2");
+    }
+}
@@ -32,7 +32,12 @@ pub struct Opts {
 pub enum SubCommand {
     Server(Server),
 
+    CleanupDns(CleanupDns),
+    OffboardUser(OffboardUser),
+    PlanConfigs(PlanConfigs),
+
     SendRFDChangelog(SendRFDChangelog),
+    SendSwagRestockForecast(SendSwagRestockForecast),
     SyncAnalytics(SyncAnalytics),
     #[clap(name = "sync-api-tokens")]
     SyncAPITokens(SyncAPITokens),
@@ -71,12 +76,56 @@ pub struct Server {
     /// Sets if the server should run cron jobs in the background
     #[clap(long)]
     pub do_cron: bool,
+
+    /// Sets an optional TOML file with the schedule for the cron jobs, the
+    /// built-in schedule is used if this is not set
+    #[clap(long, env = "WEBHOOKY_SCHEDULE_FILE", parse(from_os_str), value_hint = clap::ValueHint::FilePath)]
+    pub schedule_file: Option<std::path::PathBuf>,
 }
 
-/// A subcommand for sending the RFD changelog.
+/// A subcommand for offboarding a user from every provider.
+#[derive(Parser, Debug, Clone)]
+pub struct OffboardUser {
+    /// The username of the user to offboard.
+    #[clap(long)]
+    pub username: String,
+
+    /// The id of the company the user belongs to.
+    #[clap(long, default_value = "1")]
+    pub cio_company_id: i32,
+}
+
+/// A subcommand for listing, and with `--delete` deleting, the DNS records we
+/// created for short URLs that we no longer generate.
+#[derive(Parser, Debug, Clone)]
+pub struct CleanupDns {
+    /// Delete the stale records, otherwise they are only printed.
+    #[clap(long)]
+    pub delete: bool,
+}
+
+/// A subcommand for printing the changes syncing configs would make, without
+/// making any of them.
+#[derive(Parser, Debug, Clone)]
+pub struct PlanConfigs {
+    /// The branch or commit of the configs repo to plan against, defaults to the
+    /// default branch.
+    #[clap(long, default_value = "")]
+    pub git_ref: String,
+
+    /// The format to print the plan in.
+    #[clap(long, default_value = "text", possible_values = &["text", "json"])]
+    pub format: String,
+}
+
+/// A subcommand for sending the RFD changelog digests that are due.
 #[derive(Parser, Clone, Debug)]
 pub struct SendRFDChangelog {}
 
+/// A subcommand for sending the weekly swag restock forecast.
+#[derive(Parser, Clone, Debug)]
+pub struct SendSwagRestockForecast {}
+
 /// A subcommand for running the background job of syncing analytics.
 #[derive(Parser, Debug, Clone)]
 pub struct SyncAnalytics {}
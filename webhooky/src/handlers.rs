@@ -11,18 +11,32 @@ use cio_api::{
     certs::Certificate,
     companies::Company,
     configs::User,
+    db::Database,
     journal_clubs::JournalClubMeeting,
     mailing_list::MailingListSubscriber,
+    offboarding::{offboard_user, OffboardingReason},
     rack_line::RackLineSubscriber,
-    rfds::RFD,
-    schema::{applicants, inbound_shipments, journal_club_meetings, outbound_shipments, rfds},
-    shipments::{InboundShipment, NewInboundShipment, OutboundShipment, OutboundShipments},
+    rfd_changelogs::{
+        confirm_subscription, subscribe, unsubscribe, NewRfdChangelogSubscription, RfdChangelogTokenParams,
+    },
+    rfd_diffs::{get_rfd_diff, RfdDiff, RfdDiffParams},
+    rfd_references::{get_rfd_citations, update_rfd_references, RfdCitations},
+    rfd_reservations::{reserve_rfd, RfdReservation, RfdReservationRequest},
+    rfd_search::{search_rfds, RFDSearchParams, RFDSearchResult, DEFAULT_SEARCH_LIMIT},
+    rfds::{RfdState, RFD},
+    schema::{applicants, inbound_shipments, journal_club_meetings, outbound_shipments, rfds, users},
+    shipment_tracking::render_tracking_page,
+    shipments::{
+        clean_carrier_name, InboundShipment, NewInboundShipment, OutboundShipment, OutboundShipments,
+        SLACK_ACTION_CANCEL_SHIPMENT, SLACK_ACTION_USE_ADDRESS_AS_ENTERED, SLACK_ACTION_USE_SUGGESTED_ADDRESS,
+    },
+    shipping::{ShipBobProvider, TrackingInfo, EASYPOST, SHIPBOB, SHIPPO},
     swag_inventory::SwagInventoryItem,
     swag_store::Order,
     utils::{decode_base64, merge_json},
 };
 use diesel::{BoolExpressionMethods, ExpressionMethods, PgTextExpressionMethods, QueryDsl};
-use dropshot::{Path, RequestContext, TypedBody, UntypedBody};
+use dropshot::{Path, Query, RequestContext, TypedBody, UntypedBody};
 use google_drive::traits::{DriveOps, FileOps};
 use log::{info, warn};
 use mailchimp_api::Webhook as MailChimpWebhook;
@@ -35,9 +49,10 @@ use slack_chat_api::{
 };
 
 use crate::{
+    onboarding::OnboardingParams,
     server::{
-        AirtableRowEvent, ApplicationFileUploadData, Context, CounterResponse, GitHubRateLimit, RFDPathParams,
-        ShippoTrackingUpdateEvent,
+        AirtableRowEvent, ApplicationFileUploadData, Context, CounterResponse, GitHubRateLimit, OffboardUserParams,
+        RFDPathParams, ShippoTrackingUpdateEvent, TrackingPathParams,
     },
     slack_commands::SlackCommand,
 };
@@ -97,7 +112,7 @@ pub async fn handle_rfd_update_by_number(
             // And likely something messed up, so let's try again.
             // And no worries if it's not merged into master it will just fail again.
             // And won't save it back to the database.
-            rfd.state = "published".to_string();
+            rfd.state = RfdState::Published.to_string();
             // Set the link since thats how we figure out if it's published on master.
             rfd.link = format!(
                 "https://github.com/oxidecomputer/rfd/tree/master/rfd/{}",
@@ -110,13 +125,13 @@ pub async fn handle_rfd_update_by_number(
     }
     info!("updated  RFD {}", rfd.number_string);
 
+    // Update the references to other RFDs, and the backlinks from them.
+    update_rfd_references(db, &oxide, &mut rfd).await?;
+
     // Save the rfd back to our database.
     // Do the save before the pdf in case something goes wrong.
     let mut rfd = rfd.update(db).await?;
 
-    // Now that the database is updated, update the search index.
-    rfd.update_search_index().await?;
-
     rfd.convert_and_upload_pdf(db, &github, &oxide).await?;
     info!("updated pdf `{}` for RFD {}", rfd.get_pdf_filename(), rfd.number_string);
 
@@ -149,6 +164,205 @@ pub async fn handle_github_rate_limit(rqctx: Arc<RequestContext<Context>>) -> Re
     })
 }
 
+pub async fn handle_rfd_search(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<RFDSearchParams>,
+) -> Result<Vec<RFDSearchResult>> {
+    let params = query_args.into_inner();
+    let db = &rqctx.context().db;
+
+    // Get the company id for Oxide.
+    // TODO: split this out per company.
+    let oxide = Company::get_from_db(db, "Oxide".to_string()).await.unwrap();
+
+    search_rfds(db, &oxide, &params.q, params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT)).await
+}
+
+pub async fn handle_rfd_references(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<RFDPathParams>,
+) -> Result<RfdCitations> {
+    let num = path_params.into_inner().num;
+    let db = &rqctx.context().db;
+
+    // Get the company id for Oxide.
+    // TODO: split this out per company.
+    let oxide = Company::get_from_db(db, "Oxide".to_string()).await.unwrap();
+
+    if RFD::get_from_db(db, num).await.is_none() {
+        bail!("no RFD was found with number `{}`", num);
+    }
+
+    get_rfd_citations(db, &oxide, num).await
+}
+
+pub async fn handle_rfd_diff(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<RFDPathParams>,
+    query_args: Query<RfdDiffParams>,
+) -> Result<RfdDiff> {
+    let num = path_params.into_inner().num;
+    let params = query_args.into_inner();
+    let db = &rqctx.context().db;
+
+    // Get the company id for Oxide.
+    // TODO: split this out per company.
+    let oxide = Company::get_from_db(db, "Oxide".to_string()).await.unwrap();
+
+    let rfd = match RFD::get_from_db(db, num).await {
+        Some(rfd) => rfd,
+        None => bail!("no RFD was found with number `{}`", num),
+    };
+
+    get_rfd_diff(&oxide, &rfd, &params).await
+}
+
+pub async fn handle_rfd_reserve(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<RfdReservationRequest>,
+) -> Result<RfdReservation> {
+    let request = body_param.into_inner();
+    let db = &rqctx.context().db;
+
+    if request.reserved_by.trim().is_empty() {
+        bail!("reserving an RFD needs someone to reserve it");
+    }
+
+    // Get the company id for Oxide.
+    // TODO: split this out per company.
+    let oxide = Company::get_from_db(db, "Oxide".to_string()).await.unwrap();
+
+    reserve_rfd(db, &oxide, &request.title, &request.reserved_by).await
+}
+
+pub async fn handle_rfd_changelog_subscribe(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<NewRfdChangelogSubscription>,
+) -> Result<()> {
+    let subscription = body_param.into_inner();
+    let db = &rqctx.context().db;
+
+    // Get the company id for Oxide.
+    // TODO: split this out per company.
+    let oxide = Company::get_from_db(db, "Oxide".to_string()).await.unwrap();
+
+    subscribe(db, &oxide, subscription).await?;
+
+    Ok(())
+}
+
+pub async fn handle_rfd_changelog_confirm(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<RfdChangelogTokenParams>,
+) -> Result<String> {
+    let params = query_args.into_inner();
+    let db = &rqctx.context().db;
+
+    // Get the company id for Oxide.
+    // TODO: split this out per company.
+    let oxide = Company::get_from_db(db, "Oxide".to_string()).await.unwrap();
+
+    let subscription = confirm_subscription(db, &oxide, &params.token).await?;
+
+    Ok(format!(
+        "{} is subscribed to the {} RFD changelog over {}.",
+        subscription.email, subscription.cadence, subscription.channel
+    ))
+}
+
+pub async fn handle_rfd_changelog_unsubscribe(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<RfdChangelogTokenParams>,
+) -> Result<String> {
+    let params = query_args.into_inner();
+    let db = &rqctx.context().db;
+
+    // Get the company id for Oxide.
+    // TODO: split this out per company.
+    let oxide = Company::get_from_db(db, "Oxide".to_string()).await.unwrap();
+
+    unsubscribe(db, &oxide, &params.token).await?;
+
+    Ok("You are unsubscribed from the RFD changelog.".to_string())
+}
+
+/// Format the results of an RFD search as Slack markdown.
+fn format_rfd_search_results(query: &str, results: &[RFDSearchResult]) -> String {
+    let mut text = format!("RFDs matching `{}`:", query);
+    for result in results {
+        text += &format!("\n• <{}|{}> (_*{}*_)", result.short_link, result.name, result.state);
+        if !result.snippet.is_empty() {
+            text += &format!("\n> {}", result.snippet);
+        }
+    }
+
+    text
+}
+
+/// Reserve the next RFD number, using the name and email of the person running the
+/// command from our users if we know them.
+async fn reserve_rfd_for_slack_user(
+    db: &Database,
+    company: &Company,
+    bot_command: &BotCommand,
+    title: &str,
+) -> Result<RfdReservation> {
+    let reserved_by = match users::dsl::users
+        .filter(
+            users::dsl::cio_company_id
+                .eq(company.id)
+                .and(users::dsl::slack_id.eq(bot_command.user_id.to_string())),
+        )
+        .first_async::<User>(db.pool())
+        .await
+    {
+        Ok(user) => format!("{} <{}>", user.full_name(), user.email),
+        Err(_) => bot_command.user_name.to_string(),
+    };
+
+    reserve_rfd(db, company, title, &reserved_by).await
+}
+
+/// Post the response to a slash command we ran in the background to the command's
+/// response url.
+async fn post_slack_command_response(bot_command: &BotCommand, response: MessageResponse) {
+    if let Err(e) = slack_chat_api::Slack::post_to_channel(&bot_command.response_url, &json!(response)).await {
+        warn!(
+            "posting the response to `{} {}` to slack failed: {}",
+            bot_command.command, bot_command.text, e
+        );
+    }
+}
+
+/// Create a return shipment for `/shipments return`. An email is an employee sending
+/// back their equipment, otherwise it is the tracking number of the shipment to
+/// return.
+async fn create_return_shipment_for_slack(
+    db: &Database,
+    company: &Company,
+    arg: &str,
+) -> Result<Option<OutboundShipment>> {
+    if arg.contains('@') {
+        return match users::dsl::users
+            .filter(
+                users::dsl::cio_company_id
+                    .eq(company.id)
+                    .and(users::dsl::email.eq(arg.to_string())),
+            )
+            .first_async::<User>(db.pool())
+            .await
+        {
+            Ok(user) => user.create_return_shipment_from_home_address(db).await,
+            Err(_) => Ok(None),
+        };
+    }
+
+    match get_outbound_shipment_by_tracking_number(db, company, arg).await {
+        Some(shipment) => Ok(Some(shipment.create_return_shipment(db).await?)),
+        None => Ok(None),
+    }
+}
+
 pub async fn handle_slack_commands(
     rqctx: Arc<RequestContext<Context>>,
     body_param: UntypedBody,
@@ -198,7 +412,78 @@ pub async fn handle_slack_commands(
     let response = match command {
         SlackCommand::RFD => {
             let num = text.parse::<i32>().unwrap_or(0);
-            if num > 0 {
+            if let Some(title) = text.strip_prefix("new ") {
+                // Reserving an RFD makes a few calls to GitHub, which can take longer
+                // than Slack waits for a response. Do it in the background and post
+                // the result to the response url when it is done.
+                let db = db.clone();
+                let company = company.clone();
+                let bot_command = bot_command.clone();
+                let title = title.to_string();
+                let acknowledgement = json!(MessageResponse {
+                    response_type: MessageResponseType::Ephemeral,
+                    text: format!("Reserving an RFD for `{}`, this will take a moment...", title),
+                });
+                tokio::spawn(async move {
+                    let response = match reserve_rfd_for_slack_user(&db, &company, &bot_command, &title).await {
+                        Ok(reservation) => MessageResponse {
+                            response_type: MessageResponseType::InChannel,
+                            text: format!(
+                                "<@{}> reserved *RFD {} {}* :tada:\n• branch: <{}|{}>\n• draft pull request: <{}|#{}>",
+                                bot_command.user_id,
+                                reservation.number,
+                                reservation.title,
+                                reservation.link,
+                                reservation.branch,
+                                reservation.pull_request_url,
+                                reservation.pull_request_number
+                            ),
+                        },
+                        Err(e) => {
+                            warn!("reserving RFD `{}` failed: {:?}", title, e);
+                            MessageResponse {
+                                response_type: MessageResponseType::Ephemeral,
+                                text: format!(
+                                    "Sorry <@{}> :scream: reserving `{}` failed: {}",
+                                    bot_command.user_id, title, e
+                                ),
+                            }
+                        }
+                    };
+
+                    if let Err(e) =
+                        slack_chat_api::Slack::post_to_channel(&bot_command.response_url, &json!(response)).await
+                    {
+                        warn!("posting RFD reservation for `{}` to slack failed: {}", title, e);
+                    }
+                });
+
+                acknowledgement
+            } else if text == "search" || text.starts_with("search ") {
+                let query = text.trim_start_matches("search").trim();
+                if query.is_empty() {
+                    json!(MessageResponse {
+                        response_type: MessageResponseType::Ephemeral,
+                        text: "Tell me what to search for, like `/rfd search networking`".to_string(),
+                    })
+                } else {
+                    let results = search_rfds(db, &company, query, 5).await?;
+                    if results.is_empty() {
+                        json!(MessageResponse {
+                            response_type: MessageResponseType::InChannel,
+                            text: format!(
+                                "Sorry <@{}> :scream: I could not find any RFDs matching `{}`",
+                                bot_command.user_id, query
+                            ),
+                        })
+                    } else {
+                        json!(MessageResponse {
+                            response_type: MessageResponseType::InChannel,
+                            text: format_rfd_search_results(query, &results),
+                        })
+                    }
+                }
+            } else if num > 0 {
                 if let Ok(rfd) = rfds::dsl::rfds
                     .filter(rfds::dsl::cio_company_id.eq(company.id).and(rfds::dsl::number.eq(num)))
                     .first_async::<RFD>(db.pool())
@@ -237,6 +522,18 @@ pub async fn handle_slack_commands(
             {
                 let r: FormattedMessage = rfd.into();
                 json!(r)
+            } else if let Some(result) = search_rfds(db, &company, text, 1).await?.into_iter().next() {
+                // Fall back to the best match from a full-text search.
+                let rfd = rfds::dsl::rfds
+                    .filter(
+                        rfds::dsl::cio_company_id
+                            .eq(company.id)
+                            .and(rfds::dsl::number.eq(result.number)),
+                    )
+                    .first_async::<RFD>(db.pool())
+                    .await?;
+                let r: FormattedMessage = rfd.into();
+                json!(r)
             } else {
                 json!(MessageResponse {
                     response_type: MessageResponseType::InChannel,
@@ -354,11 +651,102 @@ pub async fn handle_slack_commands(
             }
         }
         SlackCommand::Shipments => {
-            let msg = if !text.is_empty() && text != "outbound" && text != "inbound" {
+            let msg = if let Some(tracking_number) = text.strip_prefix("void ") {
+                // Voiding a label waits on the provider, which can take longer than
+                // Slack waits for a response. Do it in the background and post the
+                // result to the response url when it is done.
+                let db = db.clone();
+                let company = company.clone();
+                let bot_command = bot_command.clone();
+                let tracking_number = tracking_number.trim().to_string();
+                let acknowledgement = json!(MessageResponse {
+                    response_type: MessageResponseType::Ephemeral,
+                    text: format!(
+                        "Voiding the label for `{}`, this will take a moment...",
+                        tracking_number
+                    ),
+                });
+                tokio::spawn(async move {
+                    let text = match get_outbound_shipment_by_tracking_number(&db, &company, &tracking_number).await {
+                        Some(mut shipment) => match shipment.void_label(&db).await {
+                            Ok(_) => format!(
+                                "<@{}> voided the {} label for the shipment to *{}* :wastebasket:\n{}",
+                                bot_command.user_id, shipment.carrier, shipment.name, shipment.messages
+                            ),
+                            Err(e) => format!(
+                                "Sorry <@{}> :scream: I could not void that label: {}",
+                                bot_command.user_id, e
+                            ),
+                        },
+                        None => format!(
+                            "Sorry <@{}> :scream: I could not find an `outbound` shipment with tracking number `{}`",
+                            bot_command.user_id, tracking_number
+                        ),
+                    };
+
+                    post_slack_command_response(
+                        &bot_command,
+                        MessageResponse {
+                            response_type: MessageResponseType::InChannel,
+                            text,
+                        },
+                    )
+                    .await;
+                });
+
+                acknowledgement
+            } else if let Some(arg) = text.strip_prefix("return ") {
+                // Buying the return label can take longer than Slack waits for a
+                // response, so do it in the background too.
+                let db = db.clone();
+                let company = company.clone();
+                let bot_command = bot_command.clone();
+                let arg = arg.trim().to_string();
+                let acknowledgement = json!(MessageResponse {
+                    response_type: MessageResponseType::Ephemeral,
+                    text: format!("Creating a return label for `{}`, this will take a moment...", arg),
+                });
+                tokio::spawn(async move {
+                    let text = match create_return_shipment_for_slack(&db, &company, &arg).await {
+                        Ok(Some(shipment)) => format!(
+                            "<@{}> created a {} return label from *{}*, it was emailed to {} :package:\n• tracking: <{}|{}>",
+                            bot_command.user_id,
+                            shipment.carrier,
+                            shipment.name,
+                            shipment.email,
+                            shipment.oxide_tracking_link,
+                            shipment.tracking_number
+                        ),
+                        Ok(None) => format!(
+                            "Sorry <@{}> :scream: I could not find a shipment or an employee with a home address for `{}`",
+                            bot_command.user_id, arg
+                        ),
+                        Err(e) => {
+                            warn!("creating a return shipment for `{}` failed: {:?}", arg, e);
+                            format!(
+                                "Sorry <@{}> :scream: I could not create a return label for `{}`: {}",
+                                bot_command.user_id, arg, e
+                            )
+                        }
+                    };
+
+                    post_slack_command_response(
+                        &bot_command,
+                        MessageResponse {
+                            response_type: MessageResponseType::InChannel,
+                            text,
+                        },
+                    )
+                    .await;
+                });
+
+                acknowledgement
+            } else if !text.is_empty() && text != "outbound" && text != "inbound" {
                 json!(MessageResponse {
                     response_type: MessageResponseType::InChannel,
                     text: format!(
-                        "Sorry <@{}> :scream: `{}` is valid, try `outbound` or `inbound` or leave blank for both",
+                        "Sorry <@{}> :scream: `{}` is valid, try `outbound` or `inbound` or leave blank for both, \
+                         `void <tracking number>` or `return <tracking number or email>`",
                         bot_command.user_id, text
                     ),
                 })
@@ -669,7 +1057,7 @@ pub async fn handle_slack_interactive(
         return Ok(interactive_response);
     }
 
-    // Handle the actions for re-running functions.
+    // Handle the actions for re-running functions and fixing shipment addresses.
     for action in payload.actions {
         // Trigger the action if it's a function.
         if action.action_id == "function" {
@@ -677,12 +1065,93 @@ pub async fn handle_slack_interactive(
             if let Err(e) = crate::handlers_cron::handle_reexec_cmd(ctx, &action.value, true).await {
                 sentry::integrations::anyhow::capture_anyhow(&anyhow::anyhow!("{:?}", e));
             }
+        } else if action.action_id == SLACK_ACTION_USE_SUGGESTED_ADDRESS
+            || action.action_id == SLACK_ACTION_USE_ADDRESS_AS_ENTERED
+            || action.action_id == SLACK_ACTION_CANCEL_SHIPMENT
+        {
+            let mut shipment = OutboundShipment::get_by_id(db, action.value.parse()?).await?;
+            if shipment.cio_company_id != company.id {
+                bail!("shipment {} is not for company {}", shipment.id, company.name);
+            }
+
+            // Buying the label talks to the shipping provider, so do it in the
+            // background, Slack only waits a few seconds for us.
+            let db = db.clone();
+            let company = company.clone();
+            tokio::spawn(async move {
+                let result = if action.action_id == SLACK_ACTION_USE_SUGGESTED_ADDRESS {
+                    shipment.use_suggested_address(&db).await
+                } else if action.action_id == SLACK_ACTION_CANCEL_SHIPMENT {
+                    shipment.cancel(&db).await
+                } else {
+                    shipment.use_address_as_entered(&db).await
+                };
+
+                if let Err(e) = result {
+                    warn!("`{}` for shipment {} failed: {:?}", action.action_id, shipment.id, e);
+                    let msg = FormattedMessage {
+                        channel: company.slack_channel_shipments.to_string(),
+                        blocks: vec![MessageBlock {
+                            block_type: MessageBlockType::Section,
+                            text: Some(MessageBlockText {
+                                text_type: MessageType::Markdown,
+                                text: format!("Updating the shipment for *{}* failed: {}", shipment.name, e),
+                            }),
+                            elements: Default::default(),
+                            accessory: Default::default(),
+                            block_id: Default::default(),
+                            fields: Default::default(),
+                        }],
+                        attachments: Default::default(),
+                    };
+                    if let Err(e) = company.post_to_slack_channel(&db, &msg).await {
+                        warn!("posting to slack failed: {}", e);
+                    }
+                }
+            });
         }
     }
 
     Ok(interactive_response)
 }
 
+pub async fn handle_onboard_user(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<OnboardingParams>,
+) -> Result<uuid::Uuid> {
+    let api_context = rqctx.context();
+    let params = body_param.into_inner();
+    info!("onboarding user `{}`", params.username);
+
+    let id = uuid::Uuid::new_v4();
+    crate::onboarding::run_onboarding(&api_context.db, &api_context.sec, &id, params).await?;
+
+    Ok(id)
+}
+
+pub async fn handle_offboard_user(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<OffboardUserParams>,
+) -> Result<()> {
+    let db = rqctx.context().db.clone();
+    let params = body_param.into_inner();
+
+    let company = Company::get_by_id(&db, params.cio_company_id).await?;
+    let user = match User::get_from_db(&db, company.id, params.username.to_string()).await {
+        Some(user) => user,
+        None => bail!("no user was found with username `{}`", params.username),
+    };
+
+    // Offboarding talks to a lot of providers, so don't make the caller wait.
+    tokio::spawn(async move {
+        if let Err(e) = offboard_user(&db, &company, &user, OffboardingReason::Manual).await {
+            warn!("offboarding user `{}` failed: {:?}", user.username, e);
+        }
+    });
+
+    Ok(())
+}
+
 pub async fn handle_airtable_employees_print_home_address_label(
     rqctx: Arc<RequestContext<Context>>,
     body_param: TypedBody<AirtableRowEvent>,
@@ -704,6 +1173,27 @@ pub async fn handle_airtable_employees_print_home_address_label(
     Ok(())
 }
 
+pub async fn handle_airtable_employees_create_return_label(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<AirtableRowEvent>,
+) -> Result<()> {
+    let api_context = rqctx.context();
+
+    let event = body_param.into_inner();
+
+    if event.record_id.is_empty() {
+        bail!("record id is empty");
+    }
+
+    // Get the row from airtable.
+    let user = User::get_from_airtable(&event.record_id, &api_context.db, event.cio_company_id).await?;
+
+    // Create a return label from the employee's home address and email it to them.
+    user.create_return_shipment_from_home_address(&api_context.db).await?;
+
+    Ok(())
+}
+
 pub async fn handle_airtable_certificates_renew(
     rqctx: Arc<RequestContext<Context>>,
     body_param: TypedBody<AirtableRowEvent>,
@@ -903,8 +1393,8 @@ pub async fn handle_airtable_shipments_outbound_create(
 
     // Update the row in our database.
     let mut new_shipment = shipment.update(&api_context.db).await?;
-    // Create the shipment in shippo.
-    new_shipment.create_or_get_shippo_shipment(&api_context.db).await?;
+    // Create the shipment label.
+    new_shipment.create_or_get_shipment(&api_context.db).await?;
     // Update airtable again.
     new_shipment.update(&api_context.db).await?;
 
@@ -941,6 +1431,81 @@ pub async fn handle_airtable_shipments_outbound_reprint_label(
     Ok(())
 }
 
+/// Get an outbound shipment by its tracking number.
+async fn get_outbound_shipment_by_tracking_number(
+    db: &Database,
+    company: &Company,
+    tracking_number: &str,
+) -> Option<OutboundShipment> {
+    if let Ok(shipment) = outbound_shipments::dsl::outbound_shipments
+        .filter(
+            outbound_shipments::dsl::cio_company_id
+                .eq(company.id)
+                .and(outbound_shipments::dsl::tracking_number.eq(tracking_number.trim().to_string())),
+        )
+        .first_async::<OutboundShipment>(db.pool())
+        .await
+    {
+        return Some(shipment);
+    }
+
+    // It might be one of the other boxes of a shipment with more than one.
+    OutboundShipment::get_by_parcel_tracking_number(db, tracking_number)
+        .await
+        .filter(|s| s.cio_company_id == company.id)
+}
+
+pub async fn handle_airtable_shipments_outbound_void_label(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<AirtableRowEvent>,
+) -> Result<()> {
+    let event = body_param.into_inner();
+
+    if event.record_id.is_empty() {
+        bail!("record id is empty");
+    }
+
+    let api_context = rqctx.context();
+
+    // Get the row from airtable.
+    let shipment = OutboundShipment::get_from_airtable(&event.record_id, &api_context.db, event.cio_company_id).await?;
+    // Use our status, not the one in Airtable, since someone might have already set
+    // it to cancelled by hand.
+    let mut shipment = OutboundShipment::get_by_id(&api_context.db, shipment.id).await?;
+
+    // Cancel the shipment, this voids the label if we bought one and puts any
+    // reserved swag back in stock.
+    shipment.cancel(&api_context.db).await?;
+    info!("shipment {} cancelled", shipment.email);
+
+    Ok(())
+}
+
+pub async fn handle_airtable_shipments_outbound_create_return_label(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<AirtableRowEvent>,
+) -> Result<()> {
+    let event = body_param.into_inner();
+
+    if event.record_id.is_empty() {
+        bail!("record id is empty");
+    }
+
+    let api_context = rqctx.context();
+
+    // Get the row from airtable.
+    let shipment = OutboundShipment::get_from_airtable(&event.record_id, &api_context.db, event.cio_company_id).await?;
+
+    // Create the return label and email it to the recipient.
+    let return_shipment = shipment.create_return_shipment(&api_context.db).await?;
+    info!(
+        "shipment {} created return label {}",
+        shipment.email, return_shipment.tracking_number
+    );
+
+    Ok(())
+}
+
 pub async fn handle_airtable_shipments_outbound_reprint_receipt(
     rqctx: Arc<RequestContext<Context>>,
     body_param: TypedBody<AirtableRowEvent>,
@@ -1316,15 +1881,51 @@ pub async fn handle_store_order_create(
 }
 
 pub async fn handle_easypost_tracking_update(
-    _rqctx: Arc<RequestContext<Context>>,
+    rqctx: Arc<RequestContext<Context>>,
     body_param: TypedBody<crate::server::EasyPostTrackingUpdateEvent>,
 ) -> Result<()> {
-    //let api_context = rqctx.context();
+    let api_context = rqctx.context();
 
     let event = body_param.into_inner();
 
-    sentry::capture_message(&format!("easypost webhook: {:#?}", event), sentry::Level::Info);
+    if !event.description.starts_with("tracker.") {
+        // We can return early.
+        // We only care about tracker events.
+        info!("ignoring easypost `{}` event", event.description);
+        return Ok(());
+    }
+
+    let tracking_number = event.result["tracking_code"].as_str().unwrap_or_default().to_string();
+    let carrier = clean_carrier_name(event.result["carrier"].as_str().unwrap_or_default());
+    if tracking_number.is_empty() || carrier.is_empty() {
+        // We can reaturn early.
+        // It's too early to get anything good from this event.
+        info!("tracking_number and carrier are empty, ignoring");
+        return Ok(());
+    }
+
+    // Update the outbound shipment if it exists.
+    if let Some(mut shipment) =
+        OutboundShipment::get_by_tracking_number(&api_context.db, &carrier, &tracking_number).await
+    {
+        // Save the tracking history for the shipment's tracking page. Keep going if
+        // this fails, so we still update the status of the shipment.
+        if let Err(e) = shipment
+            .record_tracking_events(&api_context.db, EASYPOST, &TrackingInfo::from_easypost(&event.result))
+            .await
+        {
+            warn!(
+                "recording tracking events for shipment {} failed: {}",
+                tracking_number, e
+            );
+        }
+
+        // Update the shipment from its label provider.
+        shipment.create_or_get_shipment(&api_context.db).await?;
+        shipment.update(&api_context.db).await?;
+    }
 
+    info!("shipment {} tracking status updated successfully", tracking_number);
     Ok(())
 }
 
@@ -1359,12 +1960,24 @@ pub async fn handle_shippo_tracking_update(
 
     // Update the outbound shipment if it exists.
     if let Some(mut shipment) =
-        OutboundShipment::get_from_db(&api_context.db, ts.carrier.to_string(), ts.tracking_number.to_string()).await
+        OutboundShipment::get_by_tracking_number(&api_context.db, &ts.carrier, &ts.tracking_number).await
     {
-        // Update the shipment in shippo.
+        // Save the tracking history for the shipment's tracking page. Keep going if
+        // this fails, so we still update the status of the shipment.
+        if let Err(e) = shipment
+            .record_tracking_events(&api_context.db, SHIPPO, &ts.clone().into())
+            .await
+        {
+            warn!(
+                "recording tracking events for shipment {} failed: {}",
+                ts.tracking_number, e
+            );
+        }
+
+        // Update the shipment from its label provider.
         // TODO: we likely don't need the extra request here, but it makes the code more DRY.
         // Clean this up eventually.
-        shipment.create_or_get_shippo_shipment(&api_context.db).await?;
+        shipment.create_or_get_shipment(&api_context.db).await?;
         shipment.update(&api_context.db).await?;
     }
 
@@ -1372,6 +1985,25 @@ pub async fn handle_shippo_tracking_update(
     Ok(())
 }
 
+pub async fn handle_tracking_page(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<TrackingPathParams>,
+) -> Result<Option<String>> {
+    let params = path_params.into_inner();
+    let db = &rqctx.context().db;
+
+    // TODO: split this out per company.
+    let oxide = Company::get_from_db(db, "Oxide".to_string()).await.unwrap();
+
+    let shipment = match get_outbound_shipment_by_tracking_number(db, &oxide, &params.id).await {
+        Some(shipment) if shipment.carrier.eq_ignore_ascii_case(&params.carrier) => shipment,
+        _ => return Ok(None),
+    };
+    let events = shipment.tracking_events(db).await?;
+
+    Ok(Some(render_tracking_page(&shipment, &events)))
+}
+
 pub async fn handle_checkr_background_update(
     rqctx: Arc<RequestContext<Context>>,
     body_param: TypedBody<checkr::WebhookEvent>,
@@ -1630,6 +2262,21 @@ pub async fn handle_shipbob(
         sentry::Level::Info,
     );
 
+    // Save the tracking history of the shipments we have, for their tracking pages.
+    let db = &rqctx.context().db;
+    for (carrier, tracking) in ShipBobProvider::tracking_from_webhook(shipbob_topic, &event) {
+        if let Some(shipment) =
+            OutboundShipment::get_by_tracking_number(db, &clean_carrier_name(&carrier), &tracking.tracking_number).await
+        {
+            if let Err(e) = shipment.record_tracking_events(db, SHIPBOB, &tracking).await {
+                warn!(
+                    "recording tracking events for shipment {} failed: {}",
+                    tracking.tracking_number, e
+                );
+            }
+        }
+    }
+
     Ok(())
 }
 
@@ -3,13 +3,19 @@ use std::sync::Arc;
 use anyhow::{bail, Result};
 use async_bb8_diesel::AsyncRunQueryDsl;
 use chrono::Utc;
-use chrono_humanize::HumanTime;
-use cio_api::{functions::Function, schema::functions};
+use cio_api::{
+    functions::{Function, FunctionFilter, FunctionStats, FunctionSummary},
+    schema::functions,
+};
 use diesel::{ExpressionMethods, QueryDsl};
-use dropshot::{Path, RequestContext};
+use dropshot::{Path, Query, RequestContext};
 use log::info;
 
-use crate::server::{Context, FunctionPathParams};
+use crate::{
+    sagas::StopReason,
+    schedule::{job_health, list_scheduled_jobs, JobHealth, ScheduledJob},
+    server::{Context, FunctionPathParams},
+};
 
 pub async fn handle_get_function_by_uuid(
     rqctx: Arc<RequestContext<Context>>,
@@ -39,6 +45,80 @@ pub async fn handle_get_function_logs_by_uuid(
     Ok(f.logs)
 }
 
+pub async fn handle_cancel_function(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<Function> {
+    let uuid = path_params.into_inner().uuid;
+    info!("cancelling function uuid `{}`", uuid);
+
+    let api_context = rqctx.context();
+    let db = &api_context.db;
+
+    let f = match Function::get_from_db(db, uuid.to_string()).await {
+        Some(f) => f,
+        None => bail!("no function was found with uuid `{}`", uuid),
+    };
+
+    if f.status == octorust::types::JobStatus::Completed.to_string() {
+        bail!("function `{}` `{}` has already completed", f.name, uuid);
+    }
+
+    let u = uuid::Uuid::parse_str(&f.saga_id)?;
+    if crate::sagas::stop_job(&u, StopReason::Cancelled)? {
+        // The runner records the conclusion once the process has exited, return
+        // whatever it has recorded so far.
+        return match Function::get_from_db(db, f.saga_id.to_string()).await {
+            Some(f) => Ok(f),
+            None => bail!("no function was found with uuid `{}`", uuid),
+        };
+    }
+
+    // The job is not running in this process. Mark it as cancelled, if another process
+    // is running it, that process stops the job when it next updates the heartbeat.
+    let logs = format!("{}\n\njob `{}` was cancelled", f.logs, f.name);
+    Function::complete_with_conclusion(db, &u, &logs, &octorust::types::Conclusion::Cancelled).await
+}
+
+pub async fn handle_list_functions(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<FunctionFilter>,
+) -> Result<Vec<FunctionSummary>> {
+    let filter = query_args.into_inner();
+    info!("listing functions with filter: {:?}", filter);
+
+    Function::list(&rqctx.context().db, &filter).await
+}
+
+pub async fn handle_get_function_stats(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<FunctionFilter>,
+) -> Result<Vec<FunctionStats>> {
+    Function::stats(&rqctx.context().db, &query_args.into_inner()).await
+}
+
+pub async fn handle_get_function_last_success(rqctx: Arc<RequestContext<Context>>) -> Result<Vec<JobHealth>> {
+    let api_context = rqctx.context();
+
+    let stats = Function::stats(&api_context.db, &Default::default()).await?;
+    let state = api_context.schedule.read().await;
+
+    Ok(job_health(&state.config, &stats, Utc::now()))
+}
+
+pub async fn handle_get_schedule(rqctx: Arc<RequestContext<Context>>) -> Result<Vec<ScheduledJob>> {
+    Ok(list_scheduled_jobs(rqctx.context()).await)
+}
+
+pub async fn handle_reload_schedule(rqctx: Arc<RequestContext<Context>>) -> Result<Vec<ScheduledJob>> {
+    let api_context = rqctx.context();
+
+    api_context.schedule.write().await.reload()?;
+    info!("reloaded the cron job schedule");
+
+    Ok(list_scheduled_jobs(api_context).await)
+}
+
 pub async fn handle_reexec_cmd(api_context: &Context, cmd_name: &str, background: bool) -> Result<uuid::Uuid> {
     let db = &api_context.db;
 
@@ -52,27 +132,41 @@ pub async fn handle_reexec_cmd(api_context: &Context, cmd_name: &str, background
     {
         let u = uuid::Uuid::parse_str(&f.saga_id)?;
 
-        // If the server stopped and restarted, we might have a lingering job
-        // that we want to ignore and instead start a new one.
-        // Check if the duration it was started is longer than a few hours ago.
-        let hours = -1;
-        let duration_from_now = f.created_at.signed_duration_since(Utc::now());
-        if (duration_from_now.num_hours()) > hours {
+        // Whichever process runs the job keeps its heartbeat up to date, so this
+        // works no matter which process it is running in.
+        if f.is_alive(Utc::now()) {
             info!(
-                "existing job for `{}` was created `{}`, returning that job",
-                cmd_name,
-                HumanTime::from(duration_from_now),
+                "job `{}` is already running as `{}` in `{}`, returning that job",
+                cmd_name, u, f.owner
             );
-            // TODO: a better way to be to check if we know about the saga.
-            // Return that uuid versus starting another.
             return Ok(u);
         }
+
+        // If the server stopped and restarted, we have a lingering job that is not
+        // actually running, since nothing updates its heartbeat. Mark it as cancelled
+        // so it stops blocking new runs.
+        info!(
+            "existing job `{}` for `{}` is not running, marking it as cancelled",
+            u, cmd_name
+        );
+        let logs = format!("{}\n\nthe server restarted before the job finished", f.logs);
+        Function::complete_with_conclusion(db, &u, &logs, &octorust::types::Conclusion::Cancelled).await?;
     }
 
+    // Use the max runtime from the schedule, if the job has one.
+    let max_runtime = api_context
+        .schedule
+        .read()
+        .await
+        .config
+        .jobs
+        .get(cmd_name)
+        .and_then(|j| j.max_runtime());
+
     let id = uuid::Uuid::new_v4();
 
     // Run the saga.
-    crate::sagas::run_cmd(db, &api_context.sec, &id, cmd_name, background).await?;
+    crate::sagas::run_cmd(db, &api_context.sec, &id, cmd_name, background, max_runtime).await?;
 
     Ok(id)
 }
@@ -3,15 +3,21 @@ use std::{str::FromStr, sync::Arc};
 use anyhow::Result;
 use chrono::offset::Utc;
 use cio_api::{
+    business_days::sync_holidays,
     companies::Company,
     configs::{
-        get_configs_from_repo, sync_buildings, sync_certificates, sync_conference_rooms,
+        get_configs_from_repo, get_configs_from_repo_at_ref, sync_buildings, sync_certificates, sync_conference_rooms,
         sync_github_outside_collaborators, sync_groups, sync_links, sync_users,
     },
+    plan::plan_configs,
     repos::NewRepo,
-    rfds::{is_image, NewRFD, RFD},
+    rfd_references::update_rfd_references,
+    rfds::{is_image, validate_rfd_state_change, NewRFD, NewRfdStateChange, RfdState, RFD},
     shorturls::{generate_shorturls_for_configs_links, generate_shorturls_for_repos, generate_shorturls_for_rfds},
-    utils::{create_or_update_file_in_github_repo, decode_base64_to_string, get_file_content_from_repo},
+    utils::{
+        add_comment_to_commit, create_or_update_file_in_github_repo, decode_base64_to_string,
+        get_file_content_from_repo,
+    },
 };
 use dropshot::{RequestContext, TypedBody};
 use google_drive::traits::{DriveOps, FileOps};
@@ -152,8 +158,8 @@ pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, body_param: Type
                 EventType::CheckSuite => {}
                 _ => (),
             },
-            Repo::Configs => {
-                if let EventType::Push = event_type {
+            Repo::Configs => match event_type {
+                EventType::Push => {
                     sentry::configure_scope(|scope| {
                         scope.set_context("github.webhook", sentry::protocol::Context::Other(event.clone().into()));
                         scope.set_tag("github.event.type", &event_type_string);
@@ -171,7 +177,34 @@ pub async fn handle_github(rqctx: Arc<RequestContext<Context>>, body_param: Type
                         }
                     }
                 }
-            }
+                EventType::PullRequest => {
+                    sentry::configure_scope(|scope| {
+                        scope.set_context("github.webhook", sentry::protocol::Context::Other(event.clone().into()));
+                        scope.set_tag("github.event.type", &event_type_string);
+                    });
+                    // Let's create the check run.
+                    let check_run_id = event.create_check_run(&github).await?;
+
+                    match handle_configs_pull_request(&github, api_context, event.clone(), &company).await {
+                        Ok((conclusion, message)) => {
+                            event
+                                .update_check_run(&github, check_run_id, &message, conclusion)
+                                .await?;
+                        }
+                        Err(e) => {
+                            event
+                                .update_check_run(
+                                    &github,
+                                    check_run_id,
+                                    &event.get_error_string("planning configs on `pull_request`", e),
+                                    octorust::types::ChecksCreateRequestConclusion::Failure,
+                                )
+                                .await?;
+                        }
+                    }
+                }
+                _ => (),
+            },
             _ => {
                 // We can throw this out, log it and return early.
                 info!(
@@ -321,10 +354,45 @@ pub async fn handle_rfd_pull_request(
     //  - discussion: it is in discussion
     //  - ideation: it is in ideation
     // We can update the state if it is not currently in an acceptable state.
-    if rfd.state != "discussion" && rfd.state != "published" && rfd.state != "ideation" {
-        //  Update the state of the RFD in GitHub to show it as `discussion`.
-        rfd.update_state("discussion", path.ends_with(".md"))?;
-        a("[SUCCESS]: updated RFD state to `discussion`");
+    if !matches!(
+        rfd.get_state(),
+        Ok(RfdState::Discussion) | Ok(RfdState::Published) | Ok(RfdState::Ideation)
+    ) {
+        match validate_rfd_state_change(&rfd.state, &RfdState::Discussion.to_string()) {
+            Ok(change) => {
+                //  Update the state of the RFD in GitHub to show it as `discussion`.
+                rfd.update_state(RfdState::Discussion, path.ends_with(".md"))?;
+                a("[SUCCESS]: updated RFD state to `discussion`");
+
+                if let Some((from, _)) = change {
+                    // The state already changed in GitHub, so don't stop if we can't
+                    // record it.
+                    match NewRfdStateChange::record(
+                        db,
+                        company,
+                        rfd.number,
+                        from,
+                        RfdState::Discussion,
+                        &event.sender.login,
+                        &event.pull_request.head.sha,
+                        event.pull_request.number,
+                    )
+                    .await
+                    {
+                        Ok(_) => a("[SUCCESS]: recorded RFD state change to `discussion`"),
+                        Err(e) => {
+                            warn!("recording RFD {} state change failed: {}", rfd.number_string, e);
+                            a(&format!("[ERROR]: recording RFD state change to `discussion`: {}", e));
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                // Leave the state alone, the author needs to sort this out.
+                a(&format!("[ERROR]: not moving RFD to `discussion`: {}", e));
+                has_errors = true;
+            }
+        }
     }
 
     // Update the RFD to show the new state and link in the database.
@@ -349,6 +417,33 @@ pub async fn handle_rfd_pull_request(
     Ok((octorust::types::ChecksCreateRequestConclusion::Success, message))
 }
 
+/// Get the number of the pull request for a branch, or 0 if there isn't one.
+async fn get_pull_request_number_for_branch(
+    github: &octorust::Client,
+    owner: &str,
+    repo: &str,
+    branch: &str,
+) -> Result<i64> {
+    let pulls = github
+        .pulls()
+        .list_all(
+            owner,
+            repo,
+            octorust::types::IssuesListState::All,
+            // head
+            &format!("{}:{}", owner, branch),
+            // base
+            "",
+            // sort
+            Default::default(),
+            // direction
+            Default::default(),
+        )
+        .await?;
+
+    Ok(pulls.first().map(|p| p.number).unwrap_or_default())
+}
+
 /// Handle a `push` event for the rfd repo.
 pub async fn handle_rfd_push(
     github: &octorust::Client,
@@ -557,12 +652,97 @@ pub async fn handle_rfd_push(
                 new_rfd.sha = o.sha.to_string();
                 new_rfd.pdf_link_github = o.pdf_link_github.to_string();
                 new_rfd.pdf_link_google_drive = o.pdf_link_google_drive;
+                new_rfd.cites = o.cites;
+                new_rfd.cited_by = o.cited_by;
+            }
+
+            // Make sure the state of the RFD moved in a way that is allowed.
+            let mut state_change: Option<(Option<RfdState>, RfdState)> = None;
+            let mut rejected_state = false;
+            match validate_rfd_state_change(&old_rfd_state, &new_rfd.state) {
+                Ok(change) => state_change = change,
+                Err(e) => {
+                    // Keep the old state, and let the author know on the commit so it
+                    // shows up on the pull request.
+                    let comment = format!(
+                        "The state of RFD {} was changed from `{}` to `{}`, but {}. \
+                         The state was changed back, please change it to an allowed state.",
+                        new_rfd.number_string, old_rfd_state, new_rfd.state, e
+                    );
+                    add_comment_to_commit(github, owner, &repo, &commit.id, &comment).await?;
+                    a(&format!(
+                        "[ERROR]: RFD {} has an invalid state change from `{}` to `{}`: {}",
+                        new_rfd.number_string, old_rfd_state, new_rfd.state, e
+                    ));
+
+                    new_rfd.state = old_rfd_state.to_string();
+                    rejected_state = true;
+                }
             }
 
             // Update the RFD in the database.
             let mut rfd = new_rfd.upsert(db).await?;
+
+            // Record the change in state.
+            if let Some((from, to)) = state_change {
+                let pull_request_number = get_pull_request_number_for_branch(github, owner, &repo, branch).await?;
+                match NewRfdStateChange::record(
+                    db,
+                    company,
+                    rfd.number,
+                    from,
+                    to,
+                    &event.sender.login,
+                    &commit.id,
+                    pull_request_number,
+                )
+                .await
+                {
+                    Ok(_) => a(&format!(
+                        "[SUCCESS]: recorded RFD {} state change from `{}` to `{}`",
+                        rfd.number_string,
+                        old_rfd_state,
+                        to.to_string()
+                    )),
+                    Err(e) => {
+                        warn!("recording RFD {} state change failed: {}", rfd.number_string, e);
+                        a(&format!(
+                            "[ERROR]: recording RFD {} state change from `{}` to `{}`: {}",
+                            rfd.number_string,
+                            old_rfd_state,
+                            to.to_string(),
+                            e
+                        ));
+                    }
+                }
+            }
+
             // Update all the fields for the RFD.
             rfd.expand(github, company).await?;
+
+            if rejected_state {
+                // The content we just got from GitHub still has the state we didn't
+                // allow, put the old state back in the file too so the two agree.
+                // Keep in mind: this push will kick off another webhook.
+                if let Ok(state) = old_rfd_state.parse::<RfdState>() {
+                    rfd.update_state(state, file.ends_with(".md"))?;
+                    create_or_update_file_in_github_repo(
+                        github,
+                        owner,
+                        &repo,
+                        branch,
+                        &file,
+                        rfd.content.as_bytes().to_vec(),
+                    )
+                    .await?;
+                    a(&format!(
+                        "[SUCCESS]: changed the state of RFD {} back to `{}` in GitHub",
+                        rfd.number_string, old_rfd_state
+                    ));
+                }
+            }
+            // Update the references to other RFDs, and the backlinks from them.
+            update_rfd_references(db, company, &mut rfd).await?;
             rfd.update(db).await?;
             a(&format!(
                 "[SUCCESS]: updated RFD {} in the database",
@@ -573,10 +753,6 @@ pub async fn handle_rfd_push(
                 new_rfd.number_string
             ));
 
-            // Now that the database is updated, update the search index.
-            rfd.update_search_index().await?;
-            a("[SUCCESS]: triggered update of the search index");
-
             // Create all the shorturls for the RFD if we need to,
             // this would be on added files, only.
             generate_shorturls_for_rfds(db, github, company, "configs").await?;
@@ -693,10 +869,17 @@ pub async fn handle_rfd_push(
 
             // If the RFD was merged into the default branch, but the RFD state is not `published`,
             // update the state of the RFD in GitHub to show it as `published`.
-            if branch == event.repository.default_branch && rfd.state != "published" {
+            // Committed and abandoned RFDs can't go back to `published`, so we leave those.
+            let state = rfd.get_state();
+            if branch == event.repository.default_branch
+                && state
+                    .as_ref()
+                    .map(|s| *s != RfdState::Published && s.can_transition_to(&RfdState::Published))
+                    .unwrap_or(true)
+            {
                 //  Update the state of the RFD in GitHub to show it as `published`.
                 let mut rfd_mut = rfd.clone();
-                rfd_mut.update_state("published", file.ends_with(".md"))?;
+                rfd_mut.update_state(RfdState::Published, file.ends_with(".md"))?;
 
                 // Update the RFD to show the new state in the database.
                 rfd_mut.update(db).await?;
@@ -716,6 +899,27 @@ pub async fn handle_rfd_push(
                     "[SUCCESS]: updated state to `published` for RFD {}, since it was merged into branch {}",
                     new_rfd.number_string, event.repository.default_branch
                 ));
+
+                let pull_request_number =
+                    get_pull_request_number_for_branch(github, owner, &repo, &rfd.number_string).await?;
+                if let Err(e) = NewRfdStateChange::record(
+                    db,
+                    company,
+                    rfd.number,
+                    state.ok(),
+                    RfdState::Published,
+                    &event.sender.login,
+                    &commit.id,
+                    pull_request_number,
+                )
+                .await
+                {
+                    warn!("recording RFD {} state change failed: {}", rfd.number_string, e);
+                    a(&format!(
+                        "[ERROR]: recording RFD {} state change to `published`: {}",
+                        rfd.number_string, e
+                    ));
+                }
             }
 
             // If the title of the RFD changed, delete the old PDF file so it
@@ -863,8 +1067,19 @@ pub async fn handle_configs_push(
 
     // Check if the users.toml file changed.
     if commit.file_changed("configs/users.toml") {
-        sync_users(&api_context.db, github, configs.users, company).await?;
+        let needs_onboarding = sync_users(&api_context.db, github, configs.users, company).await?;
         a("[SUCCESS]: users");
+
+        // Set up the accounts for any new users.
+        for username in needs_onboarding {
+            let id = uuid::Uuid::new_v4();
+            let params = crate::onboarding::OnboardingParams {
+                cio_company_id: company.id,
+                username: username.to_string(),
+            };
+            crate::onboarding::run_onboarding(&api_context.db, &api_context.sec, &id, params).await?;
+            a(&format!("[SUCCESS]: started onboarding `{}` ({})", username, id));
+        }
     }
 
     // Check if the buildings.toml file changed.
@@ -880,6 +1095,13 @@ pub async fn handle_configs_push(
         a("[SUCCESS]: conference rooms");
     }
 
+    // Check if the holidays.toml or buildings.toml file changed, since buildings
+    // can have their own holiday feeds.
+    if commit.file_changed("configs/holidays.toml") || commit.file_changed("configs/buildings.toml") {
+        sync_holidays(&api_context.db, configs.holidays, company).await?;
+        a("[SUCCESS]: holidays");
+    }
+
     // Check if the certificates.toml file changed.
     if commit.file_changed("configs/certificates.toml") {
         sync_certificates(&api_context.db, github, configs.certificates, company).await?;
@@ -906,6 +1128,37 @@ pub async fn handle_configs_push(
     Ok(message)
 }
 
+/// Handle a `pull_request` event for the configs repo.
+/// This computes the changes merging the pull request would make to every
+/// provider, without making any of them, so reviewers can see them on the check run.
+pub async fn handle_configs_pull_request(
+    github: &octorust::Client,
+    api_context: &Context,
+    event: GitHubWebhook,
+    company: &Company,
+) -> Result<(octorust::types::ChecksCreateRequestConclusion, String)> {
+    // We only care about the pull request if the code changed.
+    if event.action != "opened" && event.action != "synchronize" && event.action != "reopened" {
+        return Ok((
+            octorust::types::ChecksCreateRequestConclusion::Skipped,
+            format!("ignoring `{}` action on pull request", event.action),
+        ));
+    }
+
+    let sha = event.pull_request.head.sha.to_string();
+
+    // Get the configs at the head of the pull request.
+    let configs = get_configs_from_repo_at_ref(github, company, &sha).await?;
+
+    let plan = plan_configs(&api_context.db, github, &configs, company, &sha).await?;
+    info!("[configs] {}", plan.summary());
+
+    Ok((
+        octorust::types::ChecksCreateRequestConclusion::Success,
+        plan.as_markdown(),
+    ))
+}
+
 /// Handle the `repository` event for all repos.
 pub async fn handle_repository_event(
     github: &octorust::Client,
@@ -8,8 +8,10 @@ mod handlers;
 mod handlers_auth;
 mod handlers_cron;
 mod handlers_github;
+mod onboarding;
 mod repos;
 mod sagas;
+mod schedule;
 pub mod server;
 mod slack_commands;
 mod tracking_numbers;
@@ -8,8 +8,10 @@ mod handlers;
 mod handlers_auth;
 mod handlers_cron;
 mod handlers_github;
+mod onboarding;
 mod repos;
 mod sagas;
+mod schedule;
 mod server;
 mod slack_commands;
 mod tracking_numbers;
@@ -23,7 +25,12 @@ extern crate cio_api;
 use std::env;
 
 use anyhow::{bail, Result};
-use cio_api::{companies::Companys, db::Database};
+use cio_api::{
+    companies::{Company, Companys},
+    configs::User,
+    db::Database,
+    offboarding::OffboardingReason,
+};
 use clap::Parser;
 use sentry::IntoDsn;
 use slog::Drain;
@@ -110,13 +117,70 @@ async fn run_cmd(opts: crate::core::Opts, logger: slog::Logger) -> Result<()> {
             });
             crate::server::server(s, logger, opts.debug).await?;
         }
+        crate::core::SubCommand::OffboardUser(o) => {
+            let db = Database::new().await;
+            let company = Company::get_by_id(&db, o.cio_company_id).await?;
+
+            let user = match User::get_from_db(&db, company.id, o.username.to_string()).await {
+                Some(user) => user,
+                None => bail!("no user was found with username `{}`", o.username),
+            };
+
+            cio_api::offboarding::offboard_user(&db, &company, &user, OffboardingReason::Manual).await?;
+        }
+        crate::core::SubCommand::CleanupDns(c) => {
+            let db = Database::new().await;
+            let companies = Companys::get_from_db(&db, 1).await?;
+
+            // Iterate over the companies and cleanup.
+            for company in companies {
+                let records = cio_api::shorturls::cleanup_stale_dns_records(&db, &company, !c.delete).await?;
+
+                for record in records {
+                    if !c.delete {
+                        println!(
+                            "would delete `{}` -> `{}` ({})",
+                            record.name, record.content, record.source
+                        );
+                    } else {
+                        println!("deleted `{}` -> `{}` ({})", record.name, record.content, record.source);
+                    }
+                }
+            }
+        }
+        crate::core::SubCommand::PlanConfigs(p) => {
+            let db = Database::new().await;
+            let companies = Companys::get_from_db(&db, 1).await?;
+
+            // Iterate over the companies and print the plan for each.
+            for company in companies {
+                let github = company.authenticate_github()?;
+                let configs = cio_api::configs::get_configs_from_repo_at_ref(&github, &company, &p.git_ref).await?;
+                let plan = cio_api::plan::plan_configs(&db, &github, &configs, &company, &p.git_ref).await?;
+
+                if p.format == "json" {
+                    println!("{}", serde_json::to_string_pretty(&plan)?);
+                } else {
+                    println!("{}", plan.as_diff());
+                }
+            }
+        }
         crate::core::SubCommand::SendRFDChangelog(_) => {
             let db = Database::new().await;
             let companies = Companys::get_from_db(&db, 1).await?;
 
             // Iterate over the companies and send.
             for company in companies {
-                cio_api::rfds::send_rfd_changelog(&db, &company).await?;
+                cio_api::rfd_changelogs::send_rfd_changelog(&db, &company).await?;
+            }
+        }
+        crate::core::SubCommand::SendSwagRestockForecast(_) => {
+            let db = Database::new().await;
+            let companies = Companys::get_from_db(&db, 1).await?;
+
+            // Iterate over the companies and send.
+            for company in companies {
+                cio_api::swag_forecast::send_swag_restock_forecast(&db, &company).await?;
             }
         }
         crate::core::SubCommand::SyncAnalytics(_) => {
@@ -392,6 +456,7 @@ async fn run_cmd(opts: crate::core::Opts, logger: slog::Logger) -> Result<()> {
                 cio_api::swag_inventory::refresh_swag_items(&db, &company).await?;
                 cio_api::swag_inventory::refresh_swag_inventory_items(&db, &company).await?;
                 cio_api::swag_inventory::refresh_barcode_scans(&db, &company).await?;
+                cio_api::swag_inventory::refresh_swag_inventory_reservations(&db, &company).await?;
             }
         }
         crate::core::SubCommand::SyncTravel(_) => {
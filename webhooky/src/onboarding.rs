@@ -0,0 +1,578 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use cio_api::{
+    companies::Company, configs::User, db::Database, functions::Function, providers::ProviderOps,
+    shipments::OutboundShipment,
+};
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The name of the function we record onboarding runs as.
+pub const ONBOARDING_SAGA_NAME: &str = "onboard-user";
+
+/// The steps of onboarding a user, in the order they run. If a step fails, every
+/// step before it is undone in reverse order.
+const STEPS: &[&str] = &[
+    "gsuite",
+    "okta",
+    "zoom",
+    "github",
+    "ramp",
+    "slack",
+    "gusto",
+    "welcome-shipment",
+];
+
+/// Define our saga for onboarding a user.
+#[derive(Debug)]
+pub struct OnboardingSaga;
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct OnboardingParams {
+    pub cio_company_id: i32,
+    /// The username of the user, they must already exist in the database.
+    pub username: String,
+}
+
+#[derive(Debug)]
+pub struct OnboardingContext {
+    db: Database,
+}
+
+impl steno::SagaType for OnboardingSaga {
+    type SagaParamsType = OnboardingParams;
+
+    type ExecContextType = Arc<OnboardingContext>;
+}
+
+/// The output of each step, this tells the undo action if there is anything to undo.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StepOutput {
+    /// True if the step created something for the user, as opposed to the user
+    /// already existing or the step not applying to this company.
+    pub created: bool,
+    /// The id of the shipment the welcome shipment step created.
+    #[serde(default)]
+    pub shipment_id: Option<i32>,
+}
+
+/// Create the onboarding saga for the user and start it running in the background.
+pub async fn run_onboarding(
+    db: &Database,
+    sec: &steno::SecClient,
+    id: &uuid::Uuid,
+    params: OnboardingParams,
+) -> Result<()> {
+    // Make sure the user exists before we start anything.
+    if User::get_from_db(db, params.cio_company_id, params.username.to_string())
+        .await
+        .is_none()
+    {
+        anyhow::bail!("no user was found with username `{}`", params.username);
+    }
+
+    let mut builder = steno::SagaTemplateBuilder::new();
+    builder.append(
+        "gsuite",
+        "EnsureGSuiteUser",
+        steno::ActionFunc::new_action(ensure_gsuite, undo_gsuite),
+    );
+    builder.append(
+        "okta",
+        "EnsureOktaUser",
+        steno::ActionFunc::new_action(ensure_okta, undo_okta),
+    );
+    builder.append(
+        "zoom",
+        "EnsureZoomUser",
+        steno::ActionFunc::new_action(ensure_zoom, undo_zoom),
+    );
+    builder.append(
+        "github",
+        "EnsureGitHubUser",
+        steno::ActionFunc::new_action(ensure_github, undo_github),
+    );
+    builder.append(
+        "ramp",
+        "EnsureRampUser",
+        steno::ActionFunc::new_action(ensure_ramp, undo_ramp),
+    );
+    builder.append(
+        "slack",
+        "EnsureSlackUser",
+        steno::ActionFunc::new_action(ensure_slack, undo_slack),
+    );
+    builder.append(
+        "gusto",
+        "EnsureGustoUser",
+        steno::ActionFunc::new_action(ensure_gusto, undo_gusto),
+    );
+    builder.append(
+        "welcome-shipment",
+        "CreateWelcomeShipment",
+        steno::ActionFunc::new_action(create_welcome_shipment, undo_welcome_shipment),
+    );
+
+    let context = Arc::new(OnboardingContext { db: db.clone() });
+    let saga_id = steno::SagaId(*id);
+
+    let saga_future = sec
+        .saga_create(
+            saga_id,
+            Arc::new(context),
+            Arc::new(builder.build()),
+            ONBOARDING_SAGA_NAME.to_string(),
+            params.clone(),
+        )
+        .await?;
+
+    sec.saga_start(saga_id).await?;
+
+    // Wait for the saga in the background so we can record the result.
+    let db = db.clone();
+    tokio::spawn(async move {
+        let result = saga_future.await;
+        if let Err(e) = on_onboarding_complete(&db, &saga_id, &result, &params).await {
+            warn!("recording onboarding result for `{}` failed: {}", params.username, e);
+        }
+    });
+
+    Ok(())
+}
+
+async fn on_onboarding_complete(
+    db: &Database,
+    saga_id: &steno::SagaId,
+    result: &steno::SagaResult,
+    params: &OnboardingParams,
+) -> Result<()> {
+    let mut f = match Function::get_from_db(db, saga_id.to_string()).await {
+        Some(f) => f,
+        None => return Err(anyhow!("no function was found for saga `{}`", saga_id)),
+    };
+
+    match &result.kind {
+        Ok(s) => {
+            let mut outputs: Vec<(&str, StepOutput)> = Default::default();
+            for step in STEPS {
+                outputs.push((step, s.lookup_output::<StepOutput>(step)?));
+            }
+
+            f.logs = onboarding_summary(&params.username, &outputs);
+            f.conclusion = octorust::types::Conclusion::Success.to_string();
+        }
+        Err(e) => {
+            // Every step that completed before the failure has been undone.
+            f.logs = format!(
+                "{}\n\nonboarding user `{}` failed and was rolled back: {:?}",
+                f.logs, params.username, e
+            )
+            .trim()
+            .to_string();
+            f.conclusion = octorust::types::Conclusion::Failure.to_string();
+        }
+    }
+
+    f.status = octorust::types::JobStatus::Completed.to_string();
+    f.completed_at = Some(Utc::now());
+    f.update(db).await?;
+
+    Ok(())
+}
+
+/// Describe what each step of a successful onboarding did.
+fn onboarding_summary(username: &str, outputs: &[(&str, StepOutput)]) -> String {
+    let mut logs = format!("onboarded user `{}`\n", username);
+    for (step, output) in outputs {
+        logs.push_str(&format!(
+            "{}: {}\n",
+            step,
+            if output.created { "created" } else { "already set up" }
+        ));
+    }
+
+    logs.trim().to_string()
+}
+
+fn action_failed(err: anyhow::Error) -> steno::ActionError {
+    steno::ActionError::action_failed(format!("ERROR:\n\n{:?}", err))
+}
+
+/// Get the database, company and user the saga is running for.
+async fn load(action_context: &steno::ActionContext<OnboardingSaga>) -> Result<(Database, Company, User)> {
+    let db = action_context.user_data().db.clone();
+    let params = action_context.saga_params();
+
+    let company = Company::get_by_id(&db, params.cio_company_id).await?;
+    let user = User::get_from_db(&db, params.cio_company_id, params.username.to_string())
+        .await
+        .ok_or_else(|| anyhow!("no user was found with username `{}`", params.username))?;
+
+    Ok((db, company, user))
+}
+
+/// Returns true if the step created something that needs to be undone.
+fn step_created(action_context: &steno::ActionContext<OnboardingSaga>, step: &str) -> Result<bool> {
+    let output = action_context
+        .lookup::<StepOutput>(step)
+        .map_err(|e| anyhow!("getting output of step `{}` failed: {:?}", step, e))?;
+
+    Ok(output.created)
+}
+
+async fn ensure_gsuite(action_context: steno::ActionContext<OnboardingSaga>) -> Result<StepOutput, steno::ActionError> {
+    let (db, company, mut user) = load(&action_context).await.map_err(action_failed)?;
+
+    // If the company uses Okta, Okta provisions the GSuite account.
+    if company.authenticate_okta().is_some() {
+        return Ok(Default::default());
+    }
+
+    let gsuite = company.authenticate_google_admin(&db).await.map_err(action_failed)?;
+
+    // Check if they already have an account, so we don't delete it if we have to
+    // undo this step.
+    let existed = gsuite.check_user_exists(&company, &user).await.map_err(action_failed)?;
+
+    user.google_id = gsuite.ensure_user(&db, &company, &user).await.map_err(action_failed)?;
+    user.update(&db).await.map_err(action_failed)?;
+
+    Ok(StepOutput {
+        created: !existed && !user.google_id.is_empty(),
+        ..Default::default()
+    })
+}
+
+async fn undo_gsuite(action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    if !step_created(&action_context, "gsuite")? {
+        return Ok(());
+    }
+
+    let (db, company, mut user) = load(&action_context).await?;
+    info!("undoing gsuite user for `{}`", user.username);
+
+    let gsuite = company.authenticate_google_admin(&db).await?;
+    gsuite.delete_user(&db, &company, &user).await?;
+
+    user.google_id = "".to_string();
+    user.update(&db).await?;
+
+    Ok(())
+}
+
+async fn ensure_okta(action_context: steno::ActionContext<OnboardingSaga>) -> Result<StepOutput, steno::ActionError> {
+    let (db, company, mut user) = load(&action_context).await.map_err(action_failed)?;
+
+    let okta = match company.authenticate_okta() {
+        Some(okta) => okta,
+        None => return Ok(Default::default()),
+    };
+
+    // Check if they already have an account, so we don't delete it if we have to
+    // undo this step.
+    let existed = okta.check_user_exists(&company, &user).await.map_err(action_failed)?;
+
+    user.okta_id = okta.ensure_user(&db, &company, &user).await.map_err(action_failed)?;
+    user.update(&db).await.map_err(action_failed)?;
+
+    Ok(StepOutput {
+        created: !existed && !user.okta_id.is_empty(),
+        ..Default::default()
+    })
+}
+
+async fn undo_okta(action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    if !step_created(&action_context, "okta")? {
+        return Ok(());
+    }
+
+    let (db, company, mut user) = load(&action_context).await?;
+    info!("undoing okta user for `{}`", user.username);
+
+    if let Some(okta) = company.authenticate_okta() {
+        okta.delete_user(&db, &company, &user).await?;
+    }
+
+    user.okta_id = "".to_string();
+    user.update(&db).await?;
+
+    Ok(())
+}
+
+async fn ensure_zoom(action_context: steno::ActionContext<OnboardingSaga>) -> Result<StepOutput, steno::ActionError> {
+    let (db, company, mut user) = load(&action_context).await.map_err(action_failed)?;
+
+    // If the company uses Okta, Okta provisions the Zoom account.
+    if company.authenticate_okta().is_some() {
+        return Ok(Default::default());
+    }
+
+    let zoom = match company.authenticate_zoom(&db).await {
+        Ok(zoom) => zoom,
+        Err(_) => return Ok(Default::default()),
+    };
+
+    // Check if they already have an account or a pending invite, so we don't
+    // delete it if we have to undo this step.
+    let existed = zoom.check_user_exists(&company, &user).await.map_err(action_failed)?;
+
+    user.zoom_id = zoom.ensure_user(&db, &company, &user).await.map_err(action_failed)?;
+    user.update(&db).await.map_err(action_failed)?;
+
+    Ok(StepOutput {
+        created: !existed && !user.zoom_id.is_empty(),
+        ..Default::default()
+    })
+}
+
+async fn undo_zoom(action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    if !step_created(&action_context, "zoom")? {
+        return Ok(());
+    }
+
+    let (db, company, mut user) = load(&action_context).await?;
+    info!("undoing zoom user for `{}`", user.username);
+
+    let zoom = company.authenticate_zoom(&db).await?;
+    zoom.delete_user(&db, &company, &user).await?;
+
+    user.zoom_id = "".to_string();
+    user.update(&db).await?;
+
+    Ok(())
+}
+
+async fn ensure_github(action_context: steno::ActionContext<OnboardingSaga>) -> Result<StepOutput, steno::ActionError> {
+    let (db, company, user) = load(&action_context).await.map_err(action_failed)?;
+
+    if user.github.is_empty() {
+        return Ok(Default::default());
+    }
+
+    let github = company.authenticate_github().map_err(action_failed)?;
+
+    // Check if they are already a member of the org, so we don't remove them if
+    // we have to undo this step.
+    let existed = github.check_user_exists(&company, &user).await.map_err(action_failed)?;
+
+    // Add them to the org and any teams they need to be added to.
+    github.ensure_user(&db, &company, &user).await.map_err(action_failed)?;
+
+    Ok(StepOutput {
+        created: !existed,
+        ..Default::default()
+    })
+}
+
+async fn undo_github(action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    if !step_created(&action_context, "github")? {
+        return Ok(());
+    }
+
+    let (db, company, user) = load(&action_context).await?;
+    info!("undoing github org membership for `{}`", user.username);
+
+    let github = company.authenticate_github()?;
+    github.delete_user(&db, &company, &user).await?;
+
+    Ok(())
+}
+
+async fn ensure_ramp(action_context: steno::ActionContext<OnboardingSaga>) -> Result<StepOutput, steno::ActionError> {
+    let (db, company, mut user) = load(&action_context).await.map_err(action_failed)?;
+
+    let ramp = match company.authenticate_ramp(&db).await {
+        Ok(ramp) => ramp,
+        Err(_) => return Ok(Default::default()),
+    };
+
+    // Check if they already have an account, so we don't delete it if we have to
+    // undo this step.
+    let existed = ramp.check_user_exists(&company, &user).await.map_err(action_failed)?;
+
+    user.ramp_id = ramp.ensure_user(&db, &company, &user).await.map_err(action_failed)?;
+    user.update(&db).await.map_err(action_failed)?;
+
+    Ok(StepOutput {
+        created: !existed && !user.ramp_id.is_empty(),
+        ..Default::default()
+    })
+}
+
+async fn undo_ramp(action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    if !step_created(&action_context, "ramp")? {
+        return Ok(());
+    }
+
+    let (db, company, mut user) = load(&action_context).await?;
+    info!("undoing ramp user for `{}`", user.username);
+
+    let ramp = company.authenticate_ramp(&db).await?;
+    ramp.delete_user(&db, &company, &user).await?;
+
+    user.ramp_id = "".to_string();
+    user.update(&db).await?;
+
+    Ok(())
+}
+
+async fn ensure_slack(action_context: steno::ActionContext<OnboardingSaga>) -> Result<StepOutput, steno::ActionError> {
+    let (db, company, mut user) = load(&action_context).await.map_err(action_failed)?;
+
+    let slack = match company.authenticate_slack(&db).await {
+        Ok(slack) => slack,
+        Err(_) => return Ok(Default::default()),
+    };
+
+    // Check if they already have an account, so we don't deactivate it if we have
+    // to undo this step.
+    let existed = slack.check_user_exists(&company, &user).await.map_err(action_failed)?;
+
+    // This is empty if the user was invited, we get their id on the next sync once
+    // they have accepted.
+    user.slack_id = slack.ensure_user(&db, &company, &user).await.map_err(action_failed)?;
+    user.update(&db).await.map_err(action_failed)?;
+
+    Ok(StepOutput {
+        created: !existed,
+        ..Default::default()
+    })
+}
+
+async fn undo_slack(action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    if !step_created(&action_context, "slack")? {
+        return Ok(());
+    }
+
+    let (db, company, mut user) = load(&action_context).await?;
+    info!("undoing slack user for `{}`", user.username);
+
+    let slack = company.authenticate_slack(&db).await?;
+    slack.delete_user(&db, &company, &user).await?;
+
+    user.slack_id = "".to_string();
+    user.update(&db).await?;
+
+    Ok(())
+}
+
+async fn ensure_gusto(action_context: steno::ActionContext<OnboardingSaga>) -> Result<StepOutput, steno::ActionError> {
+    let (db, company, mut user) = load(&action_context).await.map_err(action_failed)?;
+
+    let (gusto, gusto_company_id) = match company.authenticate_gusto(&db).await {
+        Ok(g) => g,
+        Err(_) => return Ok(Default::default()),
+    };
+
+    // The sync matches them against the employees already in Gusto, and we only
+    // create them if it found no one, so we don't delete them if we have to undo
+    // this step.
+    let existed = !user.gusto_id.is_empty();
+
+    user.create_in_gusto_if_needed(&gusto, &gusto_company_id)
+        .await
+        .map_err(action_failed)?;
+    user.update(&db).await.map_err(action_failed)?;
+
+    Ok(StepOutput {
+        created: !existed && !user.gusto_id.is_empty(),
+        ..Default::default()
+    })
+}
+
+async fn undo_gusto(action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    if !step_created(&action_context, "gusto")? {
+        return Ok(());
+    }
+
+    let (db, company, mut user) = load(&action_context).await?;
+    info!("undoing gusto employee for `{}`", user.username);
+
+    let (gusto, _) = company.authenticate_gusto(&db).await?;
+    // Gusto lets us delete employees that have not finished onboarding.
+    gusto.employees().delete(&user.gusto_id).await?;
+
+    user.gusto_id = "".to_string();
+    user.update(&db).await?;
+
+    Ok(())
+}
+
+async fn create_welcome_shipment(
+    action_context: steno::ActionContext<OnboardingSaga>,
+) -> Result<StepOutput, steno::ActionError> {
+    let (db, _company, user) = load(&action_context).await.map_err(action_failed)?;
+
+    if user.home_address_formatted.is_empty() {
+        // We will send it once we know their address.
+        return Ok(Default::default());
+    }
+
+    let shipment = user.create_shipment_to_home_address(&db).await.map_err(action_failed)?;
+
+    Ok(StepOutput {
+        created: shipment.is_some(),
+        shipment_id: shipment.map(|s| s.id),
+    })
+}
+
+async fn undo_welcome_shipment(action_context: steno::ActionContext<OnboardingSaga>) -> Result<()> {
+    let output = action_context
+        .lookup::<StepOutput>("welcome-shipment")
+        .map_err(|e| anyhow!("getting output of step `welcome-shipment` failed: {:?}", e))?;
+    let shipment_id = match output.shipment_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let db = action_context.user_data().db.clone();
+    let mut shipment = OutboundShipment::get_by_id(&db, shipment_id).await?;
+    info!("cancelling welcome shipment to `{}`", shipment.name);
+
+    // This voids the label if we already bought one.
+    shipment.cancel(&db).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onboarding_summary() {
+        let outputs = vec![
+            (
+                "gsuite",
+                StepOutput {
+                    created: true,
+                    ..Default::default()
+                },
+            ),
+            ("okta", Default::default()),
+            (
+                "welcome-shipment",
+                StepOutput {
+                    created: true,
+                    shipment_id: Some(4),
+                },
+            ),
+        ];
+
+        assert_eq!(
+            onboarding_summary("jess", &outputs),
+            "onboarded user `jess`\ngsuite: created\nokta: already set up\nwelcome-shipment: created"
+        );
+    }
+
+    #[test]
+    fn test_step_output_without_shipment_id() {
+        // Outputs of sagas that started before we recorded the shipment id.
+        let output: StepOutput = serde_json::from_str(r#"{"created":true}"#).unwrap();
+        assert!(output.created);
+        assert_eq!(output.shipment_id, None);
+    }
+}
@@ -1,18 +1,91 @@
 use std::{
-    env,
-    sync::Arc,
+    collections::HashMap,
+    env, fmt,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use chrono::Utc;
 use cio_api::{
     db::Database,
-    functions::{FnOutput, Function},
+    functions::{FnOutput, Function, FUNCTION_HEARTBEAT_INTERVAL_SECS, FUNCTION_OWNER},
 };
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::io::BufRead;
 
+lazy_static! {
+    // The jobs running in this process, keyed by their saga id.
+    static ref RUNNING_JOBS: Mutex<HashMap<uuid::Uuid, RunningJob>> = Mutex::new(HashMap::new());
+}
+
+/// Why a job was stopped before it could finish.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    Cancelled,
+    TimedOut,
+}
+
+impl StopReason {
+    pub fn conclusion(&self) -> octorust::types::Conclusion {
+        match self {
+            StopReason::Cancelled => octorust::types::Conclusion::Cancelled,
+            StopReason::TimedOut => octorust::types::Conclusion::TimedOut,
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Cancelled => write!(f, "was cancelled"),
+            StopReason::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// A job that is running in this process.
+#[derive(Debug, Default)]
+struct RunningJob {
+    /// The handle to the re-exec-ed process, this is empty until the process has started.
+    handle: Option<Arc<duct::ReaderHandle>>,
+    stopped: Option<StopReason>,
+}
+
+/// Returns true if the job with the given saga id is running in this process. To
+/// know if it is running in any process, use `Function::is_alive`.
+pub fn is_running(saga_id: &uuid::Uuid) -> bool {
+    RUNNING_JOBS.lock().unwrap().contains_key(saga_id)
+}
+
+/// Stop a running job, killing its process. Returns false if the job is not running
+/// in this process.
+pub fn stop_job(saga_id: &uuid::Uuid, reason: StopReason) -> Result<bool> {
+    let mut jobs = RUNNING_JOBS.lock().unwrap();
+
+    let job = match jobs.get_mut(saga_id) {
+        Some(job) => job,
+        None => return Ok(false),
+    };
+
+    if job.stopped.is_some() {
+        // We already stopped it.
+        return Ok(true);
+    }
+
+    info!("stopping job `{}` since it {}", saga_id, reason);
+    job.stopped = Some(reason);
+
+    // If the process has not started yet, the runner will see it was stopped
+    // before it starts it.
+    if let Some(handle) = &job.handle {
+        handle.kill()?;
+    }
+
+    Ok(true)
+}
+
 /// Define our saga for syncing repos.
 #[derive(Debug)]
 pub struct Saga;
@@ -21,6 +94,9 @@ pub struct Saga;
 pub struct Params {
     cmd_name: String,
     saga_id: uuid::Uuid,
+    /// The longest the job may run before it is killed, 0 means there is no limit.
+    #[serde(default)]
+    max_runtime_secs: u64,
 }
 
 #[derive(Debug)]
@@ -49,11 +125,13 @@ pub async fn do_saga(
     template: steno::SagaTemplate<Saga>,
     cmd_name: &str,
     background: bool,
+    max_runtime: Option<Duration>,
 ) -> Result<()> {
     let context = Arc::new(Context { db: db.clone() });
     let params = Params {
         cmd_name: cmd_name.to_string(),
         saga_id: *id,
+        max_runtime_secs: max_runtime.map(|d| d.as_secs()).unwrap_or_default(),
     };
 
     let saga_template = Arc::new(template);
@@ -107,8 +185,12 @@ pub async fn on_saga_complete(
         Err(e) => {
             // Save the error to the logs.
             f.logs = format!("{}\n\n{:?}", f.logs, e).trim().to_string();
-            f.conclusion = octorust::types::Conclusion::Failure.to_string();
+            // Keep the conclusion if the job was cancelled or timed out.
+            if !f.was_stopped() {
+                f.conclusion = octorust::types::Conclusion::Failure.to_string();
+            }
             f.completed_at = Some(Utc::now());
+            f.update(db).await?;
 
             bail!("action failed: {:#?}", e);
         }
@@ -125,6 +207,7 @@ pub async fn run_cmd(
     id: &uuid::Uuid,
     cmd_name: &str,
     background: bool,
+    max_runtime: Option<Duration>,
 ) -> Result<()> {
     let mut builder = steno::SagaTemplateBuilder::new();
     builder.append(
@@ -140,18 +223,28 @@ pub async fn run_cmd(
         ),
     );
 
-    do_saga(db, sec, id, builder.build(), cmd_name, background).await
+    // Track the job from before the saga is created, so no one mistakes its
+    // in-progress function for a stale one before the process starts.
+    RUNNING_JOBS.lock().unwrap().insert(*id, Default::default());
+
+    let result = do_saga(db, sec, id, builder.build(), cmd_name, background, max_runtime).await;
+    if result.is_err() || !background {
+        RUNNING_JOBS.lock().unwrap().remove(id);
+    }
+
+    result
 }
 
 async fn action_run_cmd(action_context: steno::ActionContext<Saga>) -> Result<FnOutput, steno::ActionError> {
     let db = &action_context.user_data().db;
     let cmd_name = &action_context.saga_params().cmd_name;
     let saga_id = &action_context.saga_params().saga_id;
+    let max_runtime_secs = action_context.saga_params().max_runtime_secs;
 
     // We use spawn_blocking here since the BufReader etc from duct will otherwise,
     // block the main thread.
     let result = tokio::task::spawn_blocking(
-        enclose! { (db, cmd_name, saga_id) async move || { reexec(&db, &cmd_name, &saga_id).await } },
+        enclose! { (db, cmd_name, saga_id) async move || { reexec(&db, &cmd_name, &saga_id, max_runtime_secs).await } },
     )
     .await
     .map_err(|err| steno::ActionError::action_failed(format!("ERROR:\n\n{:?}", err)))?
@@ -170,58 +263,172 @@ async fn action_run_cmd(action_context: steno::ActionContext<Saga>) -> Result<Fn
 
 // We re-exec our current binary so we can get the best log output.
 // The only downside is we are creating more connections to the database.
-async fn reexec(db: &Database, cmd: &str, saga_id: &uuid::Uuid) -> Result<String> {
+async fn reexec(db: &Database, cmd: &str, saga_id: &uuid::Uuid, max_runtime_secs: u64) -> Result<String> {
     let exe = env::current_exe()?;
 
-    let child = duct::cmd!(exe, cmd);
-    let reader = child.stderr_to_stdout().reader()?;
+    let started = {
+        let mut jobs = RUNNING_JOBS.lock().unwrap();
+        let job = jobs.entry(*saga_id).or_default();
 
-    let mut output = String::new();
+        match job.stopped {
+            Some(reason) => {
+                jobs.remove(saga_id);
+                Err(reason)
+            }
+            None => {
+                let handle = Arc::new(duct::cmd!(exe, cmd).stderr_to_stdout().reader()?);
+                job.handle = Some(handle.clone());
+                Ok(handle)
+            }
+        }
+    };
 
-    let out = std::io::BufReader::new(reader);
+    let handle = match started {
+        Ok(handle) => handle,
+        Err(reason) => {
+            // The job was stopped before we could start it.
+            let logs = format!("job `{}` {} before it started", cmd, reason);
+            Function::complete_with_conclusion(db, saga_id, &logs, &reason.conclusion()).await?;
+            bail!(logs);
+        }
+    };
 
-    let mut start = Instant::now();
+    // Keep the heartbeat up to date while the job runs. If the job was cancelled
+    // from another process, that is how we find out.
+    {
+        let db = db.clone();
+        let saga_id = *saga_id;
+        tokio::spawn(async move {
+            while is_running(&saga_id) {
+                match Function::heartbeat(&db, &saga_id, &FUNCTION_OWNER).await {
+                    Ok(f) => {
+                        if f.status == octorust::types::JobStatus::Completed.to_string()
+                            && f.conclusion == octorust::types::Conclusion::Cancelled.to_string()
+                        {
+                            if let Err(e) = stop_job(&saga_id, StopReason::Cancelled) {
+                                warn!("failed to stop job `{}` after it was cancelled: {}", saga_id, e);
+                            }
+                        }
+                    }
+                    Err(e) => warn!("failed to update the heartbeat of job `{}`: {}", saga_id, e),
+                }
 
-    //let mut lines = out.lines();
+                tokio::time::sleep(Duration::from_secs(FUNCTION_HEARTBEAT_INTERVAL_SECS as u64)).await;
+            }
+        });
+    }
 
-    for line in out.lines() {
-        match line {
-            Ok(l) => {
-                /*loop {
-                match lines.next_line().await {
-                    Ok(tl) => {
-                        if tl.is_none() {
-                            // Break the loop there are no more lines.
-                            break;
-                        }
+    // Kill the job if it runs longer than its max runtime.
+    if max_runtime_secs > 0 {
+        let saga_id = *saga_id;
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(max_runtime_secs)).await;
+            if let Err(e) = stop_job(&saga_id, StopReason::TimedOut) {
+                warn!("failed to stop job `{}` after it timed out: {}", saga_id, e);
+            }
+        });
+    }
 
-                        let l = tl.unwrap();*/
-                output.push_str(&l);
-                output.push('\n');
+    let mut output = String::new();
+    let result = read_output(db, cmd, saga_id, &handle, &mut output).await;
 
-                slog::info!(crate::core::LOGGER, "{}", l;"cmd" => cmd.to_string(), "saga_id" => saga_id.to_string());
+    // We are done running, so forget the job.
+    let stopped = RUNNING_JOBS.lock().unwrap().remove(saga_id).and_then(|job| job.stopped);
 
-                // Only save the logs when we have time, just do it async and don't
-                // wait on it, else we will be waiting forever.
-                // Update our start time after saving.
-                if start.elapsed() > Duration::from_secs(15) {
-                    // Save the logs.
-                    Function::add_logs(db, saga_id, &output).await?;
+    if let Some(reason) = stopped {
+        let message = match reason {
+            StopReason::Cancelled => format!("job `{}` was cancelled", cmd),
+            StopReason::TimedOut => format!("job `{}` timed out after {}s", cmd, max_runtime_secs),
+        };
+        output.push_str(&format!("\n{}\n", message));
 
-                    // Reset our start time to now.
-                    start = Instant::now();
-                }
-            }
-            Err(e) => {
-                // Save the logs.
-                Function::add_logs_with_conclusion(db, saga_id, &output, &octorust::types::Conclusion::Failure).await?;
+        Function::complete_with_conclusion(db, saga_id, &output, &reason.conclusion()).await?;
+        bail!(message);
+    }
 
-                bail!(e);
-            }
-        }
+    if let Err(e) = result {
+        // Save the logs.
+        Function::add_logs_with_conclusion(db, saga_id, &output, &octorust::types::Conclusion::Failure).await?;
+
+        bail!(e);
     }
 
     // We do this here because sometimes the saga fails to update.
     Function::add_logs_with_conclusion(db, saga_id, &output, &octorust::types::Conclusion::Success).await?;
     Ok(output)
 }
+
+/// Read the output of the re-exec-ed process into `output` until it exits.
+async fn read_output(
+    db: &Database,
+    cmd: &str,
+    saga_id: &uuid::Uuid,
+    handle: &duct::ReaderHandle,
+    output: &mut String,
+) -> Result<()> {
+    let out = std::io::BufReader::new(handle);
+
+    let mut start = Instant::now();
+
+    for line in out.lines() {
+        let l = line.map_err(|e| anyhow!(e))?;
+
+        output.push_str(&l);
+        output.push('\n');
+
+        slog::info!(crate::core::LOGGER, "{}", l;"cmd" => cmd.to_string(), "saga_id" => saga_id.to_string());
+
+        // Only save the logs when we have time, just do it async and don't
+        // wait on it, else we will be waiting forever.
+        // Update our start time after saving.
+        if start.elapsed() > Duration::from_secs(15) {
+            // Save the logs.
+            Function::add_logs(db, saga_id, output).await?;
+
+            // Reset our start time to now.
+            start = Instant::now();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_job() {
+        let id = uuid::Uuid::new_v4();
+
+        // We can't stop a job that is not running here.
+        assert!(!is_running(&id));
+        assert!(!stop_job(&id, StopReason::Cancelled).unwrap());
+
+        // A job that has not started its process yet is only marked as stopped, the
+        // runner sees that before it starts the process.
+        RUNNING_JOBS.lock().unwrap().insert(id, Default::default());
+        assert!(is_running(&id));
+        assert!(stop_job(&id, StopReason::Cancelled).unwrap());
+        assert_eq!(RUNNING_JOBS.lock().unwrap()[&id].stopped, Some(StopReason::Cancelled));
+
+        // Stopping it again keeps the first reason.
+        assert!(stop_job(&id, StopReason::TimedOut).unwrap());
+        assert_eq!(RUNNING_JOBS.lock().unwrap()[&id].stopped, Some(StopReason::Cancelled));
+
+        RUNNING_JOBS.lock().unwrap().remove(&id);
+        assert!(!is_running(&id));
+    }
+
+    #[test]
+    fn test_stop_reason_conclusion() {
+        assert_eq!(
+            StopReason::Cancelled.conclusion().to_string(),
+            octorust::types::Conclusion::Cancelled.to_string()
+        );
+        assert_eq!(
+            StopReason::TimedOut.conclusion().to_string(),
+            octorust::types::Conclusion::TimedOut.to_string()
+        );
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use cio_api::functions::{FunctionStats, FunctionSummary};
+use log::{info, warn};
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::server::Context;
+
+/// The schedule we use if no schedule file is given.
+const DEFAULT_SCHEDULE: &str = include_str!("schedule.toml");
+
+fn default_timezone() -> String {
+    "US/Pacific".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// The schedule for all our background jobs.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct ScheduleConfig {
+    /// The default timezone cron expressions are evaluated in.
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+
+    /// The jobs keyed by the name of their subcommand, for example `sync-finance`.
+    #[serde(default)]
+    pub jobs: BTreeMap<String, JobSchedule>,
+}
+
+/// The schedule for a single background job.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct JobSchedule {
+    /// A cron expression with a seconds field, for example `0 0 */4 * * *`.
+    pub cron: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// A random delay of up to this many seconds added to every run.
+    #[serde(default)]
+    pub jitter_secs: u64,
+    /// The timezone the cron expression is evaluated in, defaults to the timezone
+    /// of the schedule.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub timezone: String,
+    /// The longest a single run may take, 0 means there is no limit.
+    #[serde(default)]
+    pub max_runtime_secs: u64,
+}
+
+impl ScheduleConfig {
+    /// Parse and validate a schedule.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut config: ScheduleConfig = toml::from_str(s)?;
+
+        // Fill in the default timezone so every job has one.
+        for job in config.jobs.values_mut() {
+            if job.timezone.is_empty() {
+                job.timezone = config.timezone.to_string();
+            }
+        }
+
+        // Make sure everything is valid before we start using it.
+        for (name, job) in &config.jobs {
+            job.cron_schedule()
+                .map_err(|e| anyhow!("invalid cron expression for job `{}`: {}", name, e))?;
+            job.tz()
+                .map_err(|e| anyhow!("invalid timezone for job `{}`: {}", name, e))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Load the schedule from a file, or the default schedule if no file is given.
+    pub fn load(file: Option<&Path>) -> Result<Self> {
+        match file {
+            Some(f) => {
+                info!("loading job schedule from {}", f.display());
+                ScheduleConfig::parse(&std::fs::read_to_string(f)?)
+            }
+            None => ScheduleConfig::parse(DEFAULT_SCHEDULE),
+        }
+    }
+}
+
+impl JobSchedule {
+    pub fn cron_schedule(&self) -> Result<cron::Schedule> {
+        cron::Schedule::from_str(&self.cron).map_err(|e| anyhow!("{}", e))
+    }
+
+    pub fn tz(&self) -> Result<Tz> {
+        Tz::from_str(&self.timezone).map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Return the next time the cron expression fires after the given time,
+    /// not including any jitter.
+    pub fn next_run_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let schedule = self.cron_schedule().ok()?;
+        let tz = self.tz().ok()?;
+
+        schedule
+            .after(&after.with_timezone(&tz))
+            .next()
+            .map(|t| t.with_timezone(&Utc))
+    }
+
+    /// Return the next time the job should run after the given time, including jitter.
+    pub fn next_run_with_jitter(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let next = self.next_run_after(after)?;
+
+        if self.jitter_secs == 0 {
+            return Some(next);
+        }
+
+        let jitter = rand::thread_rng().gen_range(0..=self.jitter_secs);
+        Some(next + chrono::Duration::seconds(jitter as i64))
+    }
+
+    /// Return the time between the next two runs after the given time, not including
+    /// any jitter. This is how often we expect the job to run.
+    pub fn interval_after(&self, after: DateTime<Utc>) -> Option<chrono::Duration> {
+        let next = self.next_run_after(after)?;
+        let following = self.next_run_after(next)?;
+
+        Some(following - next)
+    }
+
+    pub fn max_runtime(&self) -> Option<Duration> {
+        if self.max_runtime_secs == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.max_runtime_secs))
+        }
+    }
+}
+
+/// The schedule the server is currently running with, and when each job will next run.
+#[derive(Debug, Clone)]
+pub struct ScheduleState {
+    pub file: Option<PathBuf>,
+    pub config: ScheduleConfig,
+    pub next_runs: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl ScheduleState {
+    pub fn new(file: Option<PathBuf>) -> Result<Self> {
+        let config = ScheduleConfig::load(file.as_deref())?;
+
+        Ok(ScheduleState {
+            file,
+            config,
+            next_runs: Default::default(),
+        })
+    }
+
+    /// Re-read the schedule from its file. Jobs whose schedule changed will have their
+    /// next run recomputed by the scheduler.
+    pub fn reload(&mut self) -> Result<()> {
+        let config = ScheduleConfig::load(self.file.as_deref())?;
+
+        // Forget the next run of any job that changed, so we recompute it.
+        let old = std::mem::replace(&mut self.config, config);
+        self.next_runs
+            .retain(|name, _| old.jobs.get(name) == self.config.jobs.get(name));
+
+        Ok(())
+    }
+}
+
+/// A job in the schedule, with when it will next run.
+#[derive(Debug, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ScheduledJob {
+    pub name: String,
+    #[serde(flatten)]
+    pub schedule: JobSchedule,
+    /// When the job will next run, this is empty if the job is disabled.
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// List the jobs in the schedule with their next run times.
+pub async fn list_scheduled_jobs(ctx: &Context) -> Vec<ScheduledJob> {
+    let state = ctx.schedule.read().await;
+    let now = Utc::now();
+
+    state
+        .config
+        .jobs
+        .iter()
+        .map(|(name, job)| {
+            let next_run = if !job.enabled {
+                None
+            } else if let Some(next) = state.next_runs.get(name) {
+                Some(*next)
+            } else {
+                // The scheduler isn't running this job (yet), so give the time
+                // it would run next without jitter.
+                job.next_run_after(now)
+            };
+
+            ScheduledJob {
+                name: name.to_string(),
+                schedule: job.clone(),
+                next_run,
+            }
+        })
+        .collect()
+}
+
+/// The last successful run of a scheduled job, and whether it is overdue.
+#[derive(Debug, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct JobHealth {
+    pub name: String,
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<FunctionSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<FunctionSummary>,
+    /// The fraction of completed runs that succeeded, from 0 to 1.
+    pub success_rate: f64,
+    /// True if the job is enabled and has not succeeded within twice its
+    /// scheduled interval. Alert on this.
+    pub overdue: bool,
+}
+
+/// Combine the statistics for each job with its schedule, so we can tell when
+/// a job silently stops succeeding.
+pub fn job_health(config: &ScheduleConfig, stats: &[FunctionStats], now: DateTime<Utc>) -> Vec<JobHealth> {
+    config
+        .jobs
+        .iter()
+        .map(|(name, job)| {
+            let stats = stats.iter().find(|s| &s.name == name);
+            let last_success = stats.and_then(|s| s.last_success.clone());
+
+            let overdue = job.enabled
+                && match job.interval_after(now) {
+                    Some(interval) => match &last_success {
+                        Some(l) => now - l.created_at > interval * 2,
+                        // It has never succeeded.
+                        None => true,
+                    },
+                    None => false,
+                };
+
+            JobHealth {
+                name: name.to_string(),
+                enabled: job.enabled,
+                last_run: stats.and_then(|s| s.last_run.clone()),
+                last_success,
+                success_rate: stats.map(|s| s.success_rate).unwrap_or_default(),
+                overdue,
+            }
+        })
+        .collect()
+}
+
+/// Run the scheduler forever, triggering each job when it is due.
+pub async fn run_scheduler(ctx: Context) {
+    info!("starting cron job scheduler...");
+
+    loop {
+        let now = Utc::now();
+        let mut due: Vec<String> = Default::default();
+
+        {
+            let mut state = ctx.schedule.write().await;
+            let jobs = state.config.jobs.clone();
+
+            // Forget any jobs that were removed or disabled.
+            state
+                .next_runs
+                .retain(|name, _| jobs.get(name).map(|j| j.enabled).unwrap_or(false));
+
+            for (name, job) in jobs {
+                if !job.enabled {
+                    continue;
+                }
+
+                let next = match state.next_runs.get(&name) {
+                    Some(next) => *next,
+                    None => match job.next_run_with_jitter(now) {
+                        Some(next) => {
+                            info!("scheduled cron job `{}` to next run at {}", name, next);
+                            state.next_runs.insert(name.to_string(), next);
+                            next
+                        }
+                        None => {
+                            warn!("cron job `{}` will never run with schedule `{}`", name, job.cron);
+                            continue;
+                        }
+                    },
+                };
+
+                if next <= now {
+                    // Compute the next run before we trigger this one.
+                    match job.next_run_with_jitter(now) {
+                        Some(n) => {
+                            state.next_runs.insert(name.to_string(), n);
+                        }
+                        None => {
+                            state.next_runs.remove(&name);
+                        }
+                    }
+
+                    due.push(name);
+                }
+            }
+        }
+
+        for name in due {
+            tokio::spawn(crate::server::do_job(ctx.clone(), name));
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_default_schedule_parses() {
+        let config = ScheduleConfig::parse(DEFAULT_SCHEDULE).unwrap();
+
+        assert_eq!(config.timezone, "US/Pacific");
+        assert!(config.jobs.contains_key("sync-finance"));
+        assert_eq!(
+            config.jobs["sync-shipments"].max_runtime(),
+            Some(Duration::from_secs(3600))
+        );
+        for job in config.jobs.values() {
+            assert_eq!(job.timezone, "US/Pacific");
+            assert!(job.enabled);
+        }
+    }
+
+    #[test]
+    fn test_next_run_after() {
+        let config = ScheduleConfig::parse(
+            r#"
+timezone = "UTC"
+
+[jobs.sync-finance]
+cron = "0 0 */6 * * *"
+
+[jobs.send-rfd-changelog]
+cron = "0 0 8 * * Mon"
+timezone = "US/Pacific"
+enabled = false
+"#,
+        )
+        .unwrap();
+
+        // 2022-04-06 was a Wednesday.
+        let now = Utc.ymd(2022, 4, 6).and_hms(7, 30, 0);
+
+        let finance = config.jobs.get("sync-finance").unwrap();
+        assert_eq!(finance.next_run_after(now), Some(Utc.ymd(2022, 4, 6).and_hms(12, 0, 0)));
+
+        // 8am Pacific on the following Monday is 3pm UTC.
+        let changelog = config.jobs.get("send-rfd-changelog").unwrap();
+        assert!(!changelog.enabled);
+        assert_eq!(
+            changelog.next_run_after(now),
+            Some(Utc.ymd(2022, 4, 11).and_hms(15, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_job_health() {
+        let config = ScheduleConfig::parse(
+            r#"
+timezone = "UTC"
+
+[jobs.sync-finance]
+cron = "0 0 */6 * * *"
+
+[jobs.sync-rfds]
+cron = "0 0 * * * *"
+"#,
+        )
+        .unwrap();
+
+        let now = Utc.ymd(2022, 4, 6).and_hms(7, 30, 0);
+        let success = |name: &str, created_at: DateTime<Utc>| {
+            let run = FunctionSummary {
+                id: 1,
+                name: name.to_string(),
+                status: octorust::types::JobStatus::Completed.to_string(),
+                conclusion: octorust::types::Conclusion::Success.to_string(),
+                created_at,
+                completed_at: Some(created_at),
+                saga_id: "".to_string(),
+            };
+
+            FunctionStats {
+                name: name.to_string(),
+                runs: 1,
+                in_progress: 0,
+                successes: 1,
+                failures: 0,
+                success_rate: 1.0,
+                avg_duration_secs: Some(0),
+                max_duration_secs: Some(0),
+                last_run: Some(run.clone()),
+                last_success: Some(run),
+            }
+        };
+
+        let stats = vec![
+            success("sync-finance", now - chrono::Duration::hours(7)),
+            success("sync-rfds", now - chrono::Duration::hours(3)),
+        ];
+
+        let health = job_health(&config, &stats, now);
+        assert_eq!(health.len(), 2);
+
+        // Runs every 6 hours and last succeeded 7 hours ago.
+        assert_eq!(health[0].name, "sync-finance");
+        assert!(!health[0].overdue);
+        // Runs every hour and last succeeded 3 hours ago.
+        assert_eq!(health[1].name, "sync-rfds");
+        assert!(health[1].overdue);
+    }
+
+    #[test]
+    fn test_invalid_cron_expression() {
+        let result = ScheduleConfig::parse(
+            r#"
+[jobs.sync-finance]
+cron = "every six hours"
+"#,
+        );
+
+        assert!(result.is_err());
+    }
+}
@@ -1,9 +1,18 @@
-use std::{collections::HashMap, env, fs::File, pin::Pin, sync::Arc};
+use std::{collections::HashMap, env, fs::File, sync::Arc};
 
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
-use cio_api::{analytics::NewPageView, db::Database, functions::Function, swag_store::Order};
-use clokwerk::{AsyncScheduler, Job, TimeUnits};
+use cio_api::{
+    analytics::NewPageView,
+    db::Database,
+    functions::{Function, FunctionFilter, FunctionStats, FunctionSummary},
+    rfd_changelogs::{NewRfdChangelogSubscription, RfdChangelogTokenParams},
+    rfd_diffs::{RfdDiff, RfdDiffParams},
+    rfd_references::RfdCitations,
+    rfd_reservations::{RfdReservation, RfdReservationRequest},
+    rfd_search::{RFDSearchParams, RFDSearchResult},
+    swag_store::Order,
+};
 use docusign::DocuSign;
 use dropshot::{
     endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseAccepted,
@@ -12,6 +21,7 @@ use dropshot::{
 use google_drive::Client as GoogleDrive;
 use gusto_api::Client as Gusto;
 use http::StatusCode;
+use hyper::{Body, Response};
 use log::{info, warn};
 use mailchimp_api::MailChimp;
 use quickbooks::QuickBooks;
@@ -26,7 +36,11 @@ use signal_hook::{
 use slack_chat_api::Slack;
 use zoom_api::Client as Zoom;
 
-use crate::github_types::GitHubWebhook;
+use crate::{
+    github_types::GitHubWebhook,
+    onboarding::OnboardingParams,
+    schedule::{JobHealth, ScheduleState, ScheduledJob},
+};
 
 pub async fn create_server(
     s: &crate::core::Server,
@@ -71,6 +85,10 @@ pub async fn create_server(
         .unwrap();
     api.register(listen_airtable_employees_print_home_address_label_webhooks)
         .unwrap();
+    api.register(listen_airtable_employees_create_return_label_webhooks)
+        .unwrap();
+    api.register(listen_onboard_user).unwrap();
+    api.register(listen_offboard_user).unwrap();
     api.register(listen_airtable_certificates_renew_webhooks).unwrap();
     api.register(listen_airtable_shipments_inbound_create_webhooks).unwrap();
     api.register(listen_airtable_shipments_outbound_create_webhooks)
@@ -83,6 +101,10 @@ pub async fn create_server(
         .unwrap();
     api.register(listen_airtable_shipments_outbound_schedule_pickup_webhooks)
         .unwrap();
+    api.register(listen_airtable_shipments_outbound_void_label_webhooks)
+        .unwrap();
+    api.register(listen_airtable_shipments_outbound_create_return_label_webhooks)
+        .unwrap();
     api.register(listen_airtable_swag_inventory_items_print_barcode_labels_webhooks)
         .unwrap();
     api.register(listen_analytics_page_view_webhooks).unwrap();
@@ -118,6 +140,7 @@ pub async fn create_server(
     api.register(listen_products_sold_count_requests).unwrap();
     api.register(listen_shippo_tracking_update_webhooks).unwrap();
     api.register(listen_easypost_tracking_update_webhooks).unwrap();
+    api.register(listen_tracking_page_requests).unwrap();
     api.register(listen_slack_commands_webhooks).unwrap();
     api.register(listen_slack_interactive_webhooks).unwrap();
     api.register(listen_shipbob_webhooks).unwrap();
@@ -125,6 +148,13 @@ pub async fn create_server(
     api.register(ping_mailchimp_mailing_list_webhooks).unwrap();
     api.register(ping_mailchimp_rack_line_webhooks).unwrap();
     api.register(trigger_rfd_update_by_number).unwrap();
+    api.register(listen_rfd_search).unwrap();
+    api.register(listen_rfd_references).unwrap();
+    api.register(listen_rfd_diff).unwrap();
+    api.register(listen_rfd_changelog_subscribe_requests).unwrap();
+    api.register(listen_rfd_changelog_confirm_requests).unwrap();
+    api.register(listen_rfd_changelog_unsubscribe_requests).unwrap();
+    api.register(listen_rfd_reserve_requests).unwrap();
     api.register(trigger_cleanup_create).unwrap();
 
     api.register(trigger_sync_analytics_create).unwrap();
@@ -148,8 +178,14 @@ pub async fn create_server(
     api.register(trigger_sync_swag_inventory_create).unwrap();
     api.register(trigger_sync_travel_create).unwrap();
 
+    api.register(listen_list_functions).unwrap();
     api.register(listen_get_function_by_uuid).unwrap();
     api.register(listen_get_function_logs_by_uuid).unwrap();
+    api.register(listen_cancel_function_by_uuid).unwrap();
+    api.register(listen_get_schedule).unwrap();
+    api.register(listen_reload_schedule).unwrap();
+    api.register(listen_get_function_stats).unwrap();
+    api.register(listen_get_function_last_success).unwrap();
     api.register(api_get_schema).unwrap();
 
     // Create the API schema.
@@ -169,7 +205,7 @@ pub async fn create_server(
     /*
      * The functions that implement our API endpoints will share this context.
      */
-    let api_context = Context::new(schema, logger).await;
+    let api_context = Context::new(schema, logger, s.schedule_file.clone()).await?;
 
     /*
      * Set up the server.
@@ -184,82 +220,6 @@ pub async fn create_server(
 pub async fn server(s: crate::core::Server, logger: slog::Logger, debug: bool) -> Result<()> {
     let (server, api_context) = create_server(&s, logger, debug).await?;
 
-    // This really only applied for when we are running with `do-cron` but we need the variable
-    // for the scheduler to be in the top level so we can run as async later based on the options.
-    let mut scheduler = AsyncScheduler::with_tz(chrono_tz::US::Pacific);
-
-    // Copy the Server struct so we can move it into our loop.
-    if s.do_cron {
-        /*
-         * Setup our cron jobs, with our timezone.
-         */
-        scheduler
-            .every(1.day())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-analytics")});
-        scheduler
-            .every(23.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-api-tokens")});
-        scheduler
-            .every(6.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-applications")});
-        scheduler
-            .every(2.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-asset-inventory")});
-        scheduler
-            .every(12.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-companies")});
-        scheduler
-            .every(4.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-configs")});
-        scheduler
-            .every(6.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-finance")});
-        scheduler
-            .every(12.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-functions")});
-        scheduler
-            .every(1.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-huddles")});
-        scheduler
-            .every(4.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-interviews")});
-        scheduler
-            .every(12.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-journal-clubs")});
-        scheduler
-            .every(20.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-mailing-lists")});
-        scheduler
-            .every(18.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-other")});
-        scheduler
-            .every(2.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-recorded-meetings")});
-        scheduler
-            .every(16.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-repos")});
-        scheduler
-            .every(14.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-rfds")});
-        scheduler
-            .every(2.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-shipments")});
-        scheduler
-            .every(3.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-shorturls")});
-        scheduler
-            .every(9.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-swag-inventory")});
-        scheduler
-            .every(5.hours())
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("sync-travel")});
-        // Run the RFD changelog.
-        scheduler
-            .every(clokwerk::Interval::Monday)
-            .at("8:00 am")
-            .run(enclose! { (api_context) move || api_context.create_do_job_fn("send-rfd-changelog")});
-    }
-
     // For Cloud run & ctrl+c, shutdown gracefully.
     // "The main process inside the container will receive SIGTERM, and after a grace period,
     // SIGKILL."
@@ -287,13 +247,8 @@ pub async fn server(s: crate::core::Server, logger: slog::Logger, debug: bool) -
             server.await.unwrap();
         });
 
-        info!("starting cron job scheduler...");
-
         // Loop the scheduler.
-        loop {
-            scheduler.run_pending().await;
-            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
-        }
+        crate::schedule::run_scheduler(api_context).await;
     } else {
         server.await.unwrap();
     }
@@ -311,27 +266,32 @@ pub struct Context {
     pub sec: Arc<steno::SecClient>,
 
     pub schema: serde_json::Value,
+
+    pub schedule: Arc<tokio::sync::RwLock<ScheduleState>>,
 }
 
 impl Context {
     /**
      * Return a new Context.
      */
-    pub async fn new(schema: serde_json::Value, logger: slog::Logger) -> Context {
+    pub async fn new(
+        schema: serde_json::Value,
+        logger: slog::Logger,
+        schedule_file: Option<std::path::PathBuf>,
+    ) -> Result<Context> {
         let db = Database::new().await;
 
         let sec = steno::sec(logger, Arc::new(db.clone()));
 
+        let schedule = ScheduleState::new(schedule_file)?;
+
         // Create the context.
-        Context {
+        Ok(Context {
             db,
             sec: Arc::new(sec),
             schema,
-        }
-    }
-
-    pub fn create_do_job_fn(&self, job: &str) -> Pin<Box<dyn std::future::Future<Output = ()> + Send>> {
-        Box::pin(do_job(self.clone(), job.to_string()))
+            schedule: Arc::new(tokio::sync::RwLock::new(schedule)),
+        })
     }
 }
 
@@ -472,6 +432,196 @@ async fn trigger_rfd_update_by_number(
     Ok(HttpResponseAccepted("ok".to_string()))
 }
 
+/** Search the RFDs, the best matches come first. */
+#[endpoint {
+    method = GET,
+    path = "/rfds/search",
+}]
+async fn listen_rfd_search(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<RFDSearchParams>,
+) -> Result<HttpResponseOk<Vec<RFDSearchResult>>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn.run(|| crate::handlers::handle_rfd_search(rqctx, query_args)).await {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Get the RFDs an RFD cites and the RFDs that cite it. */
+#[endpoint {
+    method = GET,
+    path = "/rfd/{num}/references",
+}]
+async fn listen_rfd_references(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<RFDPathParams>,
+) -> Result<HttpResponseOk<RfdCitations>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn
+        .run(|| crate::handlers::handle_rfd_references(rqctx, path_params))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Get the rendered diff of an RFD between two commits or for a pull request. */
+#[endpoint {
+    method = GET,
+    path = "/rfd/{num}/diff",
+}]
+async fn listen_rfd_diff(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<RFDPathParams>,
+    query_args: Query<RfdDiffParams>,
+) -> Result<HttpResponseOk<RfdDiff>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn
+        .run(|| crate::handlers::handle_rfd_diff(rqctx, path_params, query_args))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Subscribe to a digest of the changes to the RFDs. */
+#[endpoint {
+    method = POST,
+    path = "/rfd/changelog/subscribe",
+}]
+async fn listen_rfd_changelog_subscribe_requests(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<NewRfdChangelogSubscription>,
+) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let mut txn =
+        start_sentry_http_transaction(rqctx.clone(), Some(TypedOrUntypedBody::TypedBody(body_param.clone()))).await;
+
+    if let Err(e) = txn
+        .run(|| crate::handlers::handle_rfd_changelog_subscribe(rqctx, body_param))
+        .await
+    {
+        // Send the error to sentry.
+        txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(handle_anyhow_err_as_http_err(e));
+    }
+
+    txn.finish(http::StatusCode::ACCEPTED);
+
+    Ok(HttpResponseAccepted("ok".to_string()))
+}
+
+/** Confirm a subscription to the RFD changelog, from the link in the email we sent. */
+#[endpoint {
+    method = GET,
+    path = "/rfd/changelog/confirm",
+}]
+async fn listen_rfd_changelog_confirm_requests(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<RfdChangelogTokenParams>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn
+        .run(|| crate::handlers::handle_rfd_changelog_confirm(rqctx, query_args))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Unsubscribe from the RFD changelog, from the link in a digest. */
+#[endpoint {
+    method = GET,
+    path = "/rfd/changelog/unsubscribe",
+}]
+async fn listen_rfd_changelog_unsubscribe_requests(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<RfdChangelogTokenParams>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn
+        .run(|| crate::handlers::handle_rfd_changelog_unsubscribe(rqctx, query_args))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Reserve the next RFD number, with a branch and a draft pull request for it. */
+#[endpoint {
+    method = POST,
+    path = "/rfd/reserve",
+}]
+async fn listen_rfd_reserve_requests(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<RfdReservationRequest>,
+) -> Result<HttpResponseOk<RfdReservation>, HttpError> {
+    let mut txn =
+        start_sentry_http_transaction(rqctx.clone(), Some(TypedOrUntypedBody::TypedBody(body_param.clone()))).await;
+
+    match txn.run(|| crate::handlers::handle_rfd_reserve(rqctx, body_param)).await {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
 /** Get our current GitHub rate limit. */
 #[endpoint {
     method = GET,
@@ -533,6 +683,101 @@ async fn listen_airtable_employees_print_home_address_label_webhooks(
     Ok(HttpResponseAccepted("ok".to_string()))
 }
 
+/**
+ * Listen for a button pressed to create a return label from an employee's home address.
+ */
+#[endpoint {
+    method = POST,
+    path = "/airtable/employees/create_return_label",
+}]
+async fn listen_airtable_employees_create_return_label_webhooks(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<AirtableRowEvent>,
+) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let mut txn =
+        start_sentry_http_transaction(rqctx.clone(), Some(TypedOrUntypedBody::TypedBody(body_param.clone()))).await;
+
+    if let Err(e) = txn
+        .run(|| crate::handlers::handle_airtable_employees_create_return_label(rqctx, body_param))
+        .await
+    {
+        // Send the error to sentry.
+        txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(handle_anyhow_err_as_http_err(e));
+    }
+
+    txn.finish(http::StatusCode::ACCEPTED);
+
+    Ok(HttpResponseAccepted("ok".to_string()))
+}
+
+/**
+ * Onboard a user to all our providers, rolling everything back if a step fails.
+ * This returns the uuid of the function for the saga.
+ */
+#[endpoint {
+    method = POST,
+    path = "/users/onboard",
+}]
+async fn listen_onboard_user(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<OnboardingParams>,
+) -> Result<HttpResponseAccepted<uuid::Uuid>, HttpError> {
+    let mut txn =
+        start_sentry_http_transaction(rqctx.clone(), Some(TypedOrUntypedBody::TypedBody(body_param.clone()))).await;
+
+    match txn
+        .run(|| crate::handlers::handle_onboard_user(rqctx, body_param))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::ACCEPTED);
+
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/**
+ * Offboard a user from every provider. This runs in the background, the audit
+ * trail of each step is recorded as it runs.
+ */
+#[endpoint {
+    method = POST,
+    path = "/users/offboard",
+}]
+async fn listen_offboard_user(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<OffboardUserParams>,
+) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let mut txn =
+        start_sentry_http_transaction(rqctx.clone(), Some(TypedOrUntypedBody::TypedBody(body_param.clone()))).await;
+
+    if let Err(e) = txn
+        .run(|| crate::handlers::handle_offboard_user(rqctx, body_param))
+        .await
+    {
+        // Send the error to sentry.
+        txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(handle_anyhow_err_as_http_err(e));
+    }
+
+    txn.finish(http::StatusCode::ACCEPTED);
+
+    Ok(HttpResponseAccepted("ok".to_string()))
+}
+
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct OffboardUserParams {
+    pub cio_company_id: i32,
+    pub username: String,
+}
+
 /**
  * Listen for a button pressed to renew a certificate.
  */
@@ -828,6 +1073,62 @@ async fn listen_airtable_shipments_outbound_schedule_pickup_webhooks(
     Ok(HttpResponseAccepted("ok".to_string()))
 }
 
+/**
+ * Listen for a button pressed to void the label for an outbound shipment.
+ */
+#[endpoint {
+    method = POST,
+    path = "/airtable/shipments/outbound/void_label",
+}]
+async fn listen_airtable_shipments_outbound_void_label_webhooks(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<AirtableRowEvent>,
+) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let mut txn =
+        start_sentry_http_transaction(rqctx.clone(), Some(TypedOrUntypedBody::TypedBody(body_param.clone()))).await;
+
+    if let Err(e) = txn
+        .run(|| crate::handlers::handle_airtable_shipments_outbound_void_label(rqctx, body_param))
+        .await
+    {
+        // Send the error to sentry.
+        txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(handle_anyhow_err_as_http_err(e));
+    }
+
+    txn.finish(http::StatusCode::ACCEPTED);
+
+    Ok(HttpResponseAccepted("ok".to_string()))
+}
+
+/**
+ * Listen for a button pressed to create a return label for an outbound shipment.
+ */
+#[endpoint {
+    method = POST,
+    path = "/airtable/shipments/outbound/create_return_label",
+}]
+async fn listen_airtable_shipments_outbound_create_return_label_webhooks(
+    rqctx: Arc<RequestContext<Context>>,
+    body_param: TypedBody<AirtableRowEvent>,
+) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let mut txn =
+        start_sentry_http_transaction(rqctx.clone(), Some(TypedOrUntypedBody::TypedBody(body_param.clone()))).await;
+
+    if let Err(e) = txn
+        .run(|| crate::handlers::handle_airtable_shipments_outbound_create_return_label(rqctx, body_param))
+        .await
+    {
+        // Send the error to sentry.
+        txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(handle_anyhow_err_as_http_err(e));
+    }
+
+    txn.finish(http::StatusCode::ACCEPTED);
+
+    Ok(HttpResponseAccepted("ok".to_string()))
+}
+
 /// A SendGrid incoming email event.
 #[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
 pub struct IncomingEmail {
@@ -1113,12 +1414,12 @@ pub struct EasyPostTrackingUpdateEvent {
     pub description: String,
     /* /// Previous values of relevant result attributes.
     #[serde(default)]
-    pub previous_attributes: serde_json::Value,
+    pub previous_attributes: serde_json::Value,*/
     /// The object associated with the Event. See the "object" attribute on the result to determine
     /// its specific type. This field will not be returned when retrieving events directly from the
     /// API.
     #[serde(default)]
-    pub result: serde_json::Value,*/
+    pub result: serde_json::Value,
     /// The current status of the event. Possible values are "completed", "failed", "in_queue",
     /// "retrying", or "pending" (deprecated).
     #[serde(default, skip_serializing_if = "String::is_empty")]
@@ -1177,6 +1478,51 @@ pub struct ShippoTrackingUpdateEvent {
     pub test: bool,
 }
 
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct TrackingPathParams {
+    /// The carrier of the shipment.
+    pub carrier: String,
+    /// The tracking number of the shipment.
+    pub id: String,
+}
+
+/** Show the public tracking page for a shipment. */
+#[endpoint {
+    method = GET,
+    path = "/track/{carrier}/{id}",
+}]
+async fn listen_tracking_page_requests(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<TrackingPathParams>,
+) -> Result<Response<Body>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn
+        .run(|| crate::handlers::handle_tracking_page(rqctx, path_params))
+        .await
+    {
+        Ok(Some(html)) => {
+            txn.finish(http::StatusCode::OK);
+
+            Response::builder()
+                .status(http::StatusCode::OK)
+                .header(http::header::CONTENT_TYPE, "text/html; charset=utf-8")
+                .body(html.into())
+                .map_err(|e| HttpError::for_internal_error(e.to_string()))
+        }
+        Ok(None) => {
+            txn.finish(http::StatusCode::NOT_FOUND);
+
+            Err(HttpError::for_not_found(None, "shipment not found".to_string()))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
 /** Listen for updates to our checkr background checks. */
 #[endpoint {
     method = POST,
@@ -1898,6 +2244,34 @@ pub struct FunctionPathParams {
     pub uuid: String,
 }
 
+/** List function runs, newest first, filtered by name, status, conclusion and time range. */
+#[endpoint {
+    method = GET,
+    path = "/functions",
+}]
+async fn listen_list_functions(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<FunctionFilter>,
+) -> Result<HttpResponseOk<Vec<FunctionSummary>>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_list_functions(rqctx, query_args))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
 /** Get information about a function by its uuid. */
 #[endpoint {
     method = GET,
@@ -1954,6 +2328,137 @@ async fn listen_get_function_logs_by_uuid(
     }
 }
 
+/** Cancel a running function by its uuid. */
+#[endpoint {
+    method = POST,
+    path = "/functions/{uuid}/cancel",
+}]
+async fn listen_cancel_function_by_uuid(
+    rqctx: Arc<RequestContext<Context>>,
+    path_params: Path<FunctionPathParams>,
+) -> Result<HttpResponseAccepted<Function>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_cancel_function(rqctx, path_params))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::ACCEPTED);
+
+            Ok(HttpResponseAccepted(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** List the cron job schedule with the next run time for each job. */
+#[endpoint {
+    method = GET,
+    path = "/schedule",
+}]
+async fn listen_get_schedule(
+    rqctx: Arc<RequestContext<Context>>,
+) -> Result<HttpResponseOk<Vec<ScheduledJob>>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn.run(|| crate::handlers_cron::handle_get_schedule(rqctx)).await {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Reload the cron job schedule from its file. */
+#[endpoint {
+    method = POST,
+    path = "/schedule/reload",
+}]
+async fn listen_reload_schedule(
+    rqctx: Arc<RequestContext<Context>>,
+) -> Result<HttpResponseOk<Vec<ScheduledJob>>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn.run(|| crate::handlers_cron::handle_reload_schedule(rqctx)).await {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Get the success rate and duration statistics for each function. */
+#[endpoint {
+    method = GET,
+    path = "/stats/functions",
+}]
+async fn listen_get_function_stats(
+    rqctx: Arc<RequestContext<Context>>,
+    query_args: Query<FunctionFilter>,
+) -> Result<HttpResponseOk<Vec<FunctionStats>>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_get_function_stats(rqctx, query_args))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
+/** Get the last successful run of each scheduled job, and whether it is overdue. */
+#[endpoint {
+    method = GET,
+    path = "/stats/functions/last_success",
+}]
+async fn listen_get_function_last_success(
+    rqctx: Arc<RequestContext<Context>>,
+) -> Result<HttpResponseOk<Vec<JobHealth>>, HttpError> {
+    let mut txn = start_sentry_http_transaction(rqctx.clone(), None::<TypedOrUntypedBody<()>>).await;
+
+    match txn
+        .run(|| crate::handlers_cron::handle_get_function_last_success(rqctx))
+        .await
+    {
+        Ok(r) => {
+            txn.finish(http::StatusCode::OK);
+
+            Ok(HttpResponseOk(r))
+        }
+        // Send the error to sentry.
+        Err(e) => {
+            txn.finish(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(handle_anyhow_err_as_http_err(e))
+        }
+    }
+}
+
 /** Listen for triggering a function run of sync repos. */
 #[endpoint {
     method = POST,
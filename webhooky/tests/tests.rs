@@ -9,6 +9,7 @@ lazy_static! {
         address: "127.0.0.1:12345".to_string(),
         spec_file: None,
         do_cron: false,
+        schedule_file: None,
     };
     pub static ref LOGGER: slog::Logger = {
         let decorator = slog_term::PlainSyncDecorator::new(slog_term::TestStdoutWriter);